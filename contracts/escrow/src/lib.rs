@@ -1,11 +1,48 @@
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::store::{IterableMap, LookupMap};
-use near_sdk::{env, ext_contract, near, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseOrValue, NearSchema};
+use near_sdk::store::{IterableMap, IterableSet, LookupMap, Vector};
+use near_sdk::{env, ext_contract, near, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError, PromiseOrValue, NearSchema};
+use events::{
+    ensure, AdelanteEvent, ContractError, DebtorPaymentReceived, DisputeOpened,
+    DisputeResolved, EscrowCreated, EscrowSettled, KeeperRewards, OrFail, PaginatedResult,
+    TokensRecovered, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+};
 
+/// Default gas for calls to the sibling contracts, until the admin tunes it
 const GAS_FOR_CROSS_CONTRACT: Gas = Gas::from_tgas(10);
+/// Default gas reserved for our own callbacks
+const GAS_FOR_CALLBACK: Gas = Gas::from_tgas(10);
+/// Default gas for each ft_transfer
 const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(15);
+/// Lowest value set_gas_config accepts for any of the three amounts
+const MIN_GAS_CONFIG: Gas = Gas::from_tgas(5);
+/// Covers the buyer's and seller's payouts and mark_settled; split invoices draw on unused gas
+const GAS_FOR_SETTLE_PAYOUT: Gas = Gas::from_tgas(45);
+/// Most escrows settle_due will settle in one call, gas permitting
+const MAX_SETTLE_BATCH: usize = 5;
+/// Most payouts one settlement makes: up to 10 shareholders and the seller
+const MAX_SETTLE_PAYOUTS: u64 = 11;
+/// Most escrows settle_due will scan in one call
+const MAX_SETTLE_SCAN: u64 = 200;
+
+/// Length of the due-date buckets behind the running overdue count
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+/// Overdue escrows tolerated before health reports not ok
+const HEALTH_MAX_OVERDUE: u64 = 5;
+/// Open disputes tolerated before health reports not ok
+const HEALTH_MAX_DISPUTED: u64 = 10;
+
+/// Default time after the due date before an unpaid escrow can be refunded to the buyer
+const DEFAULT_GRACE_PERIOD_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Most entries kept in the on-chain audit log; the oldest is overwritten once full
+const AUDIT_LOG_CAPACITY: u32 = 256;
+
+/// Most TVL snapshots kept on-chain; the oldest is overwritten once full
+const TVL_HISTORY_CAPACITY: u32 = 365;
+/// Shortest gap between two TVL snapshots, so the history can't be flooded
+const TVL_SNAPSHOT_INTERVAL_MS: u64 = 60 * 60 * 1000;
 
 /// Escrow status
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
@@ -16,6 +53,49 @@ pub enum EscrowStatus {
     Released,
     Disputed,
     Refunded,
+    /// Dispute settled by splitting the sale amount between buyer and seller
+    PartiallyResolved,
+}
+
+/// Escrow lifecycle actions recorded in the audit log
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum AuditAction {
+    Created,
+    Settled,
+    Disputed,
+    Resolved,
+}
+
+/// One audit log record: who did what to which escrow, and when
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub actor: AccountId,
+    pub action: AuditAction,
+    pub escrow_id: String,
+}
+
+/// Total value locked in active escrows at a point in time
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct TvlSnapshot {
+    pub timestamp: u64,
+    pub tvl: U128,
+}
+
+impl EscrowStatus {
+    /// Whether the escrow has reached a final state and holds no funds
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            EscrowStatus::Released | EscrowStatus::Refunded | EscrowStatus::PartiallyResolved
+        )
+    }
 }
 
 /// Escrow entry
@@ -37,9 +117,75 @@ pub struct EscrowEntry {
     /// Whether USDC funds have been deposited into this escrow
     #[serde(default)]
     pub funds_deposited: bool,
-    /// Whether the debtor has paid the invoice (confirmed by admin/oracle)
+    /// Purchase funds actually received; the seller's payout on settlement
+    #[serde(default)]
+    pub deposited_amount: U128,
+    /// Whether the debtor's payment has arrived in escrow
     #[serde(default)]
     pub debtor_paid: bool,
+    /// Paid by the debtor so far, across installments; paid out to the buyer on settlement
+    #[serde(default)]
+    pub amount_paid: U128,
+    /// Installments already forwarded to the buyer ahead of settlement
+    #[serde(default)]
+    pub amount_forwarded: U128,
+    /// When the current dispute was opened
+    #[serde(default)]
+    pub dispute_opened_at: Option<u64>,
+    /// Whether invoice_amount and due_date were last confirmed against the invoice contract
+    #[serde(default)]
+    pub verified_against_invoice: bool,
+    /// Token the escrow is funded in, fixed by the first deposit; payouts use the same token
+    #[serde(default)]
+    pub token: Option<AccountId>,
+    /// Buyer and seller consents to an early release via mutual_release
+    #[serde(default)]
+    pub buyer_release_consent: bool,
+    #[serde(default)]
+    pub seller_release_consent: bool,
+    /// When the debtor's payment was completed, starting the dispute window
+    #[serde(default)]
+    pub debtor_paid_at: Option<u64>,
+    /// Invoice beneficiary paid the seller's share instead of the seller, when set
+    #[serde(default)]
+    pub payout_account: Option<AccountId>,
+}
+
+impl EscrowEntry {
+    /// Account the seller's share is paid to: the payout account if set, else the seller
+    pub fn payout_recipient(&self) -> AccountId {
+        self.payout_account.clone().unwrap_or_else(|| self.seller.clone())
+    }
+}
+
+/// Arbiter vote on a disputed escrow
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct DisputeVote {
+    pub arbiter: AccountId,
+    pub winner: AccountId,
+    pub voted_at: u64,
+}
+
+/// Gas attached to cross-contract calls, callbacks and token transfers
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct GasConfig {
+    pub cross_contract: Gas,
+    pub callback: Gas,
+    pub ft_transfer: Gas,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            cross_contract: GAS_FOR_CROSS_CONTRACT,
+            callback: GAS_FOR_CALLBACK,
+            ft_transfer: GAS_FOR_FT_TRANSFER,
+        }
+    }
 }
 
 /// Escrow statistics view
@@ -53,10 +199,169 @@ pub struct EscrowStats {
     pub total_disputed: u64,
 }
 
+/// Evidence reference submitted by a dispute party (e.g. an IPFS hash)
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct DisputeEvidence {
+    pub submitted_by: AccountId,
+    pub evidence_hash: String,
+    pub submitted_at: u64,
+}
+
+/// Public status of a dispute and its arbiter votes
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeDetails {
+    pub escrow_id: String,
+    pub status: EscrowStatus,
+    pub reason: Option<String>,
+    pub evidence: Vec<DisputeEvidence>,
+    pub votes: Vec<DisputeVote>,
+    pub buyer_votes: u32,
+    pub seller_votes: u32,
+    /// Votes required for a majority of registered arbiters
+    pub quorum: u32,
+    pub opened_at: Option<u64>,
+    /// Milliseconds left to vote, None when no voting period is configured
+    pub time_remaining_ms: Option<u64>,
+}
+
+/// Debtor payments received against an escrow's invoice amount
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PaymentProgress {
+    pub invoice_amount: U128,
+    pub amount_paid: U128,
+    pub outstanding: U128,
+    /// Portion of amount_paid already forwarded to the buyer
+    pub amount_forwarded: U128,
+}
+
+/// Outcome of a settle_due sweep
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SettleDueResult {
+    /// Escrows whose settlement was started in this call
+    pub settled: u64,
+    /// Ready escrows in the scanned range left for a later call
+    pub remaining: u64,
+}
+
+/// One page of active escrows from get_active_escrows_after
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ActiveEscrowsPage {
+    pub items: Vec<EscrowEntry>,
+    /// Last escrow id examined, to pass as `last_id` for the next page; None once all are seen
+    pub next_cursor: Option<String>,
+}
+
+/// Health check view for monitoring
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HealthStatus {
+    pub ok: bool,
+    pub paused: bool,
+    pub admin: AccountId,
+    pub disputed_escrows: u64,
+    /// Active escrows whose due day has ended
+    pub overdue_escrows: u64,
+    pub storage_usage: u64,
+}
+
+/// Invoice fields read from the invoice contract for overviews and verification
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceSummary {
+    pub id: String,
+    pub amount: U128,
+    pub due_date: u64,
+    pub status: String,
+}
+
+/// A holder's stake in a fractionally owned invoice, read from the invoice contract
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ShareHolding {
+    pub account_id: AccountId,
+    pub shares: u64,
+}
+
+/// A senior-to-junior slice of a structured invoice, read from the invoice contract
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Tranche {
+    pub amount: U128,
+    pub yield_bps: u16,
+    pub holder: AccountId,
+}
+
+/// Listing fields read from the marketplace contract for account overviews
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingSummary {
+    pub id: String,
+    pub invoice_id: String,
+    pub asking_price: U128,
+    pub active: bool,
+}
+
+/// Bid fields read from the marketplace contract for account overviews
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BidSummary {
+    pub id: String,
+    pub listing_id: String,
+    pub amount: U128,
+}
+
+/// An account's position across the invoice, marketplace and escrow contracts
+/// The `*_available` flags are false when the corresponding cross-contract call failed
+/// Invoices, listings and bids are limited to the first MAX_PAGE_LIMIT entries of each
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountOverview {
+    pub account: AccountId,
+    pub invoices_owned: Vec<InvoiceSummary>,
+    pub active_listings: Vec<ListingSummary>,
+    pub active_bids: Vec<BidSummary>,
+    pub active_escrows: Vec<EscrowEntry>,
+    pub invoices_available: bool,
+    pub listings_available: bool,
+    pub bids_available: bool,
+}
+
 /// Cross-contract interface for Invoice contract
 #[ext_contract(ext_invoice)]
 pub trait InvoiceContract {
     fn mark_settled(&mut self, invoice_id: String);
+    fn get_invoices_by_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<InvoiceSummary>;
+    fn get_invoice(&self, invoice_id: String) -> Option<InvoiceSummary>;
+    fn get_shareholders(&self, invoice_id: String) -> Vec<ShareHolding>;
+    fn get_tranches(&self, invoice_id: String) -> Vec<Tranche>;
+}
+
+/// Cross-contract interface for Marketplace contract
+#[ext_contract(ext_marketplace)]
+pub trait MarketplaceContract {
+    fn get_listings_by_seller(
+        &self,
+        seller: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<ListingSummary>;
+    fn get_bids_by_bidder(
+        &self,
+        bidder: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<BidSummary>;
 }
 
 /// Cross-contract interface for USDC (NEP-141 Fungible Token)
@@ -65,6 +370,72 @@ pub trait FungibleToken {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 }
 
+/// Escrow entry as stored by the original release, before installments and multi-token support
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct OldEscrowEntry {
+    id: String,
+    invoice_id: String,
+    seller: AccountId,
+    buyer: AccountId,
+    sale_amount: U128,
+    invoice_amount: U128,
+    created_at: u64,
+    due_date: u64,
+    status: EscrowStatus,
+    settled_at: Option<u64>,
+    dispute_reason: Option<String>,
+    funds_deposited: bool,
+    debtor_paid: bool,
+}
+
+impl From<OldEscrowEntry> for EscrowEntry {
+    fn from(old: OldEscrowEntry) -> Self {
+        // The original release only marked debtor payment in full
+        let amount_paid = if old.debtor_paid { old.invoice_amount } else { U128(0) };
+        EscrowEntry {
+            id: old.id,
+            invoice_id: old.invoice_id,
+            seller: old.seller,
+            buyer: old.buyer,
+            sale_amount: old.sale_amount,
+            invoice_amount: old.invoice_amount,
+            created_at: old.created_at,
+            due_date: old.due_date,
+            status: old.status,
+            settled_at: old.settled_at,
+            dispute_reason: old.dispute_reason,
+            funds_deposited: old.funds_deposited,
+            deposited_amount: if old.funds_deposited { old.sale_amount } else { U128(0) },
+            debtor_paid: old.debtor_paid,
+            amount_paid,
+            amount_forwarded: U128(0),
+            dispute_opened_at: None,
+            verified_against_invoice: false,
+            token: None,
+            buyer_release_consent: false,
+            seller_release_consent: false,
+            debtor_paid_at: None,
+            payout_account: None,
+        }
+    }
+}
+
+/// Old contract state (for migration from the original release)
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct OldEscrowContract {
+    escrows: IterableMap<String, OldEscrowEntry>,
+    escrows_by_invoice: LookupMap<String, String>,
+    escrows_by_buyer: LookupMap<AccountId, Vec<String>>,
+    escrows_by_seller: LookupMap<AccountId, Vec<String>>,
+    escrow_count: u64,
+    invoice_contract: AccountId,
+    marketplace_contract: AccountId,
+    usdc_contract: AccountId,
+    admin: AccountId,
+}
+
 /// Escrow Contract
 #[near(contract_state)]
 #[derive(PanicOnDefault)]
@@ -79,6 +450,55 @@ pub struct EscrowContract {
     marketplace_contract: AccountId,
     usdc_contract: AccountId,
     admin: AccountId,
+
+    arbiters: IterableMap<AccountId, bool>,
+    dispute_votes: LookupMap<String, Vec<DisputeVote>>,
+    paused: bool,
+    operators: LookupMap<AccountId, bool>,
+
+    disputes_resolved: u64,
+    total_dispute_resolution_ms: u64,
+    keeper_rewards: KeeperRewards,
+
+    dispute_evidence: LookupMap<String, Vec<DisputeEvidence>>,
+    /// Time arbiters have to vote once a dispute opens (0 = no deadline)
+    dispute_voting_period_ms: u64,
+    /// Lifetime USDC paid out per recipient
+    total_received: LookupMap<AccountId, u128>,
+    /// Require verify_against_invoice to pass before settlement
+    strict_verification: bool,
+    /// Every escrow ever created for an invoice, oldest first
+    escrow_history_by_invoice: LookupMap<String, Vec<String>>,
+    /// Fungible tokens accepted for deposits and debtor payments
+    accepted_tokens: IterableMap<AccountId, bool>,
+    /// Forward debtor installments to the buyer as they arrive instead of holding them until paid in full
+    forward_partial_payments: bool,
+    /// Gas amounts attached to outgoing calls
+    gas_config: GasConfig,
+    /// Time after the due date the debtor has to pay before the buyer may claim a refund
+    grace_period_ms: u64,
+    /// Time after the debtor pays during which settlement waits so either party can dispute
+    dispute_window_ms: u64,
+    /// Ring buffer of the latest lifecycle actions, at most AUDIT_LOG_CAPACITY entries
+    recent_events: Vector<AuditEntry>,
+    /// Actions ever recorded; the next one lands in slot audit_count % AUDIT_LOG_CAPACITY
+    audit_count: u64,
+    /// Sale amount of every active escrow, kept in step with status changes
+    active_value_locked: u128,
+    /// Ring buffer of TVL snapshots, at most TVL_HISTORY_CAPACITY entries
+    tvl_history: Vector<TvlSnapshot>,
+    /// Snapshots ever recorded; the next lands in slot tvl_snapshot_count % TVL_HISTORY_CAPACITY
+    tvl_snapshot_count: u64,
+    /// Per token, transfers received without a matching escrow and not yet withdrawn
+    orphaned_tokens: LookupMap<AccountId, u128>,
+    /// Ids of escrows currently in dispute
+    disputed_escrows: IterableSet<String>,
+    /// Active escrows per due day (due_date / DAY_MS), kept in step with status changes
+    active_due_days: LookupMap<u64, u64>,
+    /// Active escrows due before overdue_day
+    overdue_count: u64,
+    /// First day whose active_due_days bucket is not yet folded into overdue_count
+    overdue_day: u64,
 }
 
 #[near]
@@ -91,6 +511,9 @@ impl EscrowContract {
         usdc_contract: AccountId,
         admin: AccountId,
     ) -> Self {
+        let mut accepted_tokens = IterableMap::new(b"t");
+        accepted_tokens.insert(usdc_contract.clone(), true);
+
         Self {
             escrows: IterableMap::new(b"e"),
             escrows_by_invoice: LookupMap::new(b"i"),
@@ -101,32 +524,155 @@ impl EscrowContract {
             marketplace_contract,
             usdc_contract,
             admin,
+            arbiters: IterableMap::new(b"a"),
+            dispute_votes: LookupMap::new(b"v"),
+            paused: false,
+            operators: LookupMap::new(b"p"),
+            disputes_resolved: 0,
+            total_dispute_resolution_ms: 0,
+            keeper_rewards: KeeperRewards::new(),
+            dispute_evidence: LookupMap::new(b"x"),
+            dispute_voting_period_ms: 0,
+            total_received: LookupMap::new(b"r"),
+            strict_verification: false,
+            escrow_history_by_invoice: LookupMap::new(b"h"),
+            accepted_tokens,
+            forward_partial_payments: false,
+            gas_config: GasConfig::default(),
+            grace_period_ms: DEFAULT_GRACE_PERIOD_MS,
+            dispute_window_ms: 0,
+            recent_events: Vector::new(b"l"),
+            audit_count: 0,
+            active_value_locked: 0,
+            tvl_history: Vector::new(b"y"),
+            tvl_snapshot_count: 0,
+            orphaned_tokens: LookupMap::new(b"o"),
+            disputed_escrows: IterableSet::new(b"d"),
+            active_due_days: LookupMap::new(b"u"),
+            overdue_count: 0,
+            overdue_day: env::block_timestamp_ms() / DAY_MS,
         }
     }
 
-    /// NEP-141 callback: Receive USDC tokens from marketplace
-    /// Message format: "escrow_deposit:INV-000001"
+    /// Migrate from the original release's state (see OldEscrowContract)
+    /// That layout held only the escrow maps, escrow_count, the contract accounts and admin;
+    /// every entry is rewritten in the current layout, so gas grows with the number of escrows
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old: OldEscrowContract = env::state_read()
+            .or_fail(ContractError::InvalidState, "Failed to read old state");
+        let old_entries: Vec<(String, OldEscrowEntry)> = old.escrows.drain().collect();
+        old.escrows.flush();
+
+        let mut escrows = IterableMap::new(b"e");
+        let mut escrow_history_by_invoice: LookupMap<String, Vec<String>> = LookupMap::new(b"h");
+        let mut active_value_locked = 0u128;
+        let mut disputed_escrows = IterableSet::new(b"d");
+        let mut active_due_days: LookupMap<u64, u64> = LookupMap::new(b"u");
+        let overdue_day = env::block_timestamp_ms() / DAY_MS;
+        let mut overdue_count = 0;
+        for (escrow_id, entry) in old_entries {
+            let mut history = escrow_history_by_invoice
+                .get(&entry.invoice_id)
+                .cloned()
+                .unwrap_or_default();
+            history.push(escrow_id.clone());
+            escrow_history_by_invoice.insert(entry.invoice_id.clone(), history);
+            let entry = EscrowEntry::from(entry);
+            if entry.status == EscrowStatus::Active {
+                active_value_locked += entry.sale_amount.0;
+                let due_day = entry.due_date / DAY_MS;
+                *active_due_days.entry(due_day).or_insert(0) += 1;
+                if due_day < overdue_day {
+                    overdue_count += 1;
+                }
+            }
+            if entry.status == EscrowStatus::Disputed {
+                disputed_escrows.insert(escrow_id.clone());
+            }
+            escrows.insert(escrow_id, entry);
+        }
+
+        let mut accepted_tokens = IterableMap::new(b"t");
+        accepted_tokens.insert(old.usdc_contract.clone(), true);
+
+        Self {
+            escrows,
+            escrows_by_invoice: old.escrows_by_invoice,
+            escrows_by_buyer: old.escrows_by_buyer,
+            escrows_by_seller: old.escrows_by_seller,
+            escrow_count: old.escrow_count,
+            invoice_contract: old.invoice_contract,
+            marketplace_contract: old.marketplace_contract,
+            usdc_contract: old.usdc_contract,
+            admin: old.admin,
+            arbiters: IterableMap::new(b"a"),
+            dispute_votes: LookupMap::new(b"v"),
+            paused: false,
+            operators: LookupMap::new(b"p"),
+            disputes_resolved: 0,
+            total_dispute_resolution_ms: 0,
+            keeper_rewards: KeeperRewards::new(),
+            dispute_evidence: LookupMap::new(b"x"),
+            dispute_voting_period_ms: 0,
+            total_received: LookupMap::new(b"r"),
+            strict_verification: false,
+            escrow_history_by_invoice,
+            accepted_tokens,
+            forward_partial_payments: false,
+            gas_config: GasConfig::default(),
+            grace_period_ms: DEFAULT_GRACE_PERIOD_MS,
+            dispute_window_ms: 0,
+            recent_events: Vector::new(b"l"),
+            audit_count: 0,
+            active_value_locked,
+            tvl_history: Vector::new(b"y"),
+            tvl_snapshot_count: 0,
+            orphaned_tokens: LookupMap::new(b"o"),
+            disputed_escrows,
+            active_due_days,
+            overdue_count,
+            overdue_day,
+        }
+    }
+
+    /// NEP-141 callback: Receive accepted tokens
+    /// Message formats:
+    /// - "escrow_deposit:INV-000001" - buyer's purchase funds, sent by the marketplace
+    /// - "debtor_payment:INV-000001" - the debtor paying the invoice, sent by anyone
+    /// - "partial_payment:ESC-000001" - an installment toward the invoice amount, sent by anyone
     pub fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
         amount: U128,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        // Verify the caller is the USDC contract
+        // Verify the caller is an accepted token contract
         let token_contract = env::predecessor_account_id();
-        assert!(
-            token_contract == self.usdc_contract,
-            "Only USDC token transfers accepted"
+        ensure!(
+            self.is_accepted_token(&token_contract),
+            ContractError::TokenNotAccepted,
+            "Token {} is not accepted",
+            token_contract
         );
 
+        // Parse the message to get invoice ID
+        let parts: Vec<&str> = msg.split(':').collect();
+        if parts.len() >= 2 && parts[0] == "debtor_payment" {
+            return self.take_debtor_payment(token_contract, sender_id, parts[1], amount);
+        }
+        if parts.len() >= 2 && parts[0] == "partial_payment" {
+            return self.record_partial_payment(token_contract, sender_id, parts[1].to_string(), amount);
+        }
+
         // Verify the sender is the marketplace
-        assert!(
+        ensure!(
             sender_id == self.marketplace_contract,
+            ContractError::Unauthorized,
             "Only marketplace can deposit to escrow"
         );
 
-        // Parse the message to get invoice ID
-        let parts: Vec<&str> = msg.split(':').collect();
         if parts.len() >= 2 && parts[0] == "escrow_deposit" {
             let invoice_id = parts[1];
 
@@ -134,27 +680,136 @@ impl EscrowContract {
             if let Some(escrow_id) = self.escrows_by_invoice.get(invoice_id).cloned() {
                 if let Some(mut escrow) = self.escrows.get(&escrow_id).cloned() {
                     // Verify amount matches expected
-                    assert!(
+                    ensure!(
                         amount.0 >= escrow.sale_amount.0,
+                        ContractError::InsufficientPayment,
                         "Insufficient deposit amount"
                     );
 
+                    Self::assert_escrow_token(&mut escrow, &token_contract);
                     escrow.funds_deposited = true;
+                    escrow.deposited_amount = U128(escrow.deposited_amount.0 + amount.0);
                     self.escrows.insert(escrow_id.clone(), escrow);
 
                     env::log_str(&format!(
-                        "Escrow {} funded with {} USDC",
-                        escrow_id, amount.0
+                        "Escrow {} funded with {} {}",
+                        escrow_id, amount.0, token_contract
                     ));
+                    return PromiseOrValue::Value(U128(0));
                 }
             }
         }
 
-        // Accept all funds (return 0 to keep everything)
+        // No escrow to credit: keep the funds aside so admin_withdraw_token can return them
+        let orphaned = self.orphaned_tokens.get(&token_contract).copied().unwrap_or(0);
+        self.orphaned_tokens.insert(token_contract.clone(), orphaned + amount.0);
+        env::log_str(&format!(
+            "Unmatched transfer of {} {} from {} held for recovery",
+            amount.0, token_contract, sender_id
+        ));
         PromiseOrValue::Value(U128(0))
     }
 
+    /// Take the debtor's payment of the full outstanding invoice amount, returning any excess
+    fn take_debtor_payment(
+        &mut self,
+        token: AccountId,
+        payer: AccountId,
+        invoice_id: &str,
+        amount: U128,
+    ) -> PromiseOrValue<U128> {
+        let escrow_id = self
+            .escrows_by_invoice
+            .get(invoice_id)
+            .cloned()
+            .or_fail(ContractError::EscrowNotFound, "No escrow for invoice");
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+        let outstanding = entry.invoice_amount.0.saturating_sub(entry.amount_paid.0);
+        ensure!(
+            amount.0 >= outstanding,
+            ContractError::InsufficientPayment,
+            "Debtor payment below invoice amount. Required: {}, Received: {}",
+            outstanding,
+            amount.0
+        );
+
+        self.record_partial_payment(token, payer, escrow_id, amount)
+    }
+
+    /// Accumulate a debtor payment toward the invoice amount, returning any excess
+    /// Installments are held until the invoice is paid in full, or forwarded to the buyer when
+    /// forward_partial_payments is set; payout waits for a separate settle call
+    fn record_partial_payment(
+        &mut self,
+        token: AccountId,
+        payer: AccountId,
+        escrow_id: String,
+        amount: U128,
+    ) -> PromiseOrValue<U128> {
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        // An off-chain receipt (record_debtor_payment) still leaves the invoice amount owed
+        ensure!(
+            entry.amount_paid.0 < entry.invoice_amount.0,
+            ContractError::InvalidState,
+            "Debtor payment already received"
+        );
+        ensure!(amount.0 > 0, ContractError::InvalidAmount, "Payment must be greater than 0");
+        Self::assert_escrow_token(&mut entry, &token);
+
+        let outstanding = entry.invoice_amount.0 - entry.amount_paid.0;
+        let accepted = amount.0.min(outstanding);
+        entry.amount_paid = U128(entry.amount_paid.0 + accepted);
+        let paid_in_full = entry.amount_paid.0 >= entry.invoice_amount.0;
+        if paid_in_full && !entry.debtor_paid {
+            entry.debtor_paid = true;
+            entry.debtor_paid_at = Some(env::block_timestamp_ms());
+        }
+
+        env::log_str(&format!(
+            "Debtor payment of {} {} received from {} for escrow {} ({} of {} paid)",
+            accepted, token, payer, escrow_id, entry.amount_paid.0, entry.invoice_amount.0
+        ));
+
+        if !paid_in_full && self.forward_partial_payments {
+            entry.amount_forwarded = U128(entry.amount_forwarded.0 + accepted);
+            self.record_payout(&entry.buyer, accepted);
+            env::log_str(&format!(
+                "Installment of {} {} forwarded to buyer {}",
+                accepted, token, entry.buyer
+            ));
+            let _ = ext_ft::ext(token)
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(
+                    entry.buyer.clone(),
+                    U128(accepted),
+                    Some(format!("installment:{}", escrow_id)),
+                );
+        }
+        if paid_in_full {
+            Self::emit_debtor_payment_received(&entry, payer, entry.amount_paid);
+        }
+        self.escrows.insert(escrow_id, entry);
+
+        PromiseOrValue::Value(U128(amount.0 - accepted))
+    }
+
     /// Create escrow entry (called by marketplace after sale)
+    /// A retry with the same terms returns the existing escrow's ID rather than failing
+    #[allow(clippy::too_many_arguments)]
     pub fn create_escrow(
         &mut self,
         invoice_id: String,
@@ -163,477 +818,4219 @@ impl EscrowContract {
         sale_amount: U128,
         invoice_amount: U128,
         due_date: u64,
+        payout_account: Option<AccountId>,
     ) -> String {
+        self.assert_not_paused();
         let caller = env::predecessor_account_id();
-        assert!(
+        ensure!(
             caller == self.marketplace_contract || caller == self.admin,
+            ContractError::Unauthorized,
             "Only marketplace can create escrow"
         );
 
-        // Check if escrow already exists for this invoice
-        assert!(
-            self.escrows_by_invoice.get(&invoice_id).is_none(),
-            "Escrow already exists for this invoice"
-        );
-
-        self.escrow_count += 1;
-        let id = format!("ESC-{:06}", self.escrow_count);
+        // The marketplace's purchase chain is detached, so a retried flow can land here twice
+        // A terminal escrow is not a retry: the invoice was sold again, so it gets a new escrow
+        let existing = self
+            .escrows_by_invoice
+            .get(&invoice_id)
+            .and_then(|existing_id| self.escrows.get(existing_id))
+            .filter(|existing| !existing.status.is_terminal());
+        if let Some(existing) = existing {
+            ensure!(
+                existing.seller == seller
+                    && existing.buyer == buyer
+                    && existing.sale_amount == sale_amount
+                    && existing.invoice_amount == invoice_amount
+                    && existing.due_date == due_date
+                    && existing.payout_account == payout_account,
+                ContractError::AlreadyExists,
+                "Escrow already exists for this invoice with different terms"
+            );
+            env::log_str(&format!("Escrow {} already exists for {}", existing.id, invoice_id));
+            return existing.id.clone();
+        }
 
-        let entry = EscrowEntry {
-            id: id.clone(),
-            invoice_id: invoice_id.clone(),
-            seller: seller.clone(),
-            buyer: buyer.clone(),
+        self.internal_create_escrow(
+            invoice_id,
+            seller,
+            buyer,
             sale_amount,
             invoice_amount,
-            created_at: env::block_timestamp_ms(),
             due_date,
-            status: EscrowStatus::Active,
-            settled_at: None,
-            dispute_reason: None,
-            funds_deposited: false, // Will be set to true when USDC arrives via ft_on_transfer
-            debtor_paid: false, // Will be set to true when admin confirms debtor payment
-        };
-
-        self.escrows.insert(id.clone(), entry);
-        self.escrows_by_invoice.insert(invoice_id, id.clone());
-
-        // Update buyer index
-        let mut buyer_escrows = self
-            .escrows_by_buyer
-            .get(&buyer)
-            .cloned()
-            .unwrap_or_default();
-        buyer_escrows.push(id.clone());
-        self.escrows_by_buyer.insert(buyer, buyer_escrows);
-
-        // Update seller index
-        let mut seller_escrows = self
-            .escrows_by_seller
-            .get(&seller)
-            .cloned()
-            .unwrap_or_default();
-        seller_escrows.push(id.clone());
-        self.escrows_by_seller.insert(seller, seller_escrows);
-
-        env::log_str(&format!("Escrow {} created", id));
-        id
+            payout_account,
+        )
     }
 
-    /// Confirm that the debtor has paid (admin only)
-    /// In production, this would be called by an oracle or payment processor
-    pub fn confirm_debtor_payment(&mut self, escrow_id: String) {
+    /// Create an escrow for a resold invoice, reusing the invoice terms of its last (refunded) escrow
+    pub fn create_escrow_from_template(
+        &mut self,
+        invoice_id: String,
+        buyer: AccountId,
+        sale_amount: U128,
+    ) -> String {
+        self.assert_not_paused();
         let caller = env::predecessor_account_id();
-        assert!(
-            caller == self.admin,
-            "Only admin can confirm debtor payments"
+        ensure!(
+            caller == self.marketplace_contract || caller == self.admin,
+            ContractError::Unauthorized,
+            "Only marketplace can create escrow"
         );
 
-        let mut entry = self
+        let prior_id = self
+            .escrow_history_by_invoice
+            .get(&invoice_id)
+            .and_then(|history| history.last().cloned())
+            .or_fail(ContractError::EscrowNotFound, "No prior escrow for invoice");
+        let prior = self
             .escrows
-            .get(&escrow_id)
-            .expect("Escrow not found")
+            .get(&prior_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
             .clone();
-
-        assert!(
-            entry.status == EscrowStatus::Active,
-            "Escrow is not active"
+        ensure!(
+            prior.status == EscrowStatus::Refunded,
+            ContractError::InvalidState,
+            "Invoice is not relistable"
         );
-        assert!(
-            !entry.debtor_paid,
-            "Debtor payment already confirmed"
-        );
-
-        entry.debtor_paid = true;
-        self.escrows.insert(escrow_id.clone(), entry);
 
-        env::log_str(&format!(
-            "Debtor payment confirmed for escrow {}",
-            escrow_id
-        ));
+        self.internal_create_escrow(
+            invoice_id,
+            prior.seller,
+            buyer,
+            sale_amount,
+            prior.invoice_amount,
+            prior.due_date,
+            prior.payout_account,
+        )
     }
 
-    /// Settle escrow - release funds to investor (buyer)
-    /// Requires debtor payment to be confirmed first
+    /// Settle escrow - pay the debtor's invoice_amount to the investor (buyer) and the deposit to the seller
+    /// Requires the debtor's payment to have arrived
     pub fn settle(&mut self, escrow_id: String) -> Promise {
+        self.assert_not_paused();
         let caller = env::predecessor_account_id();
-        let mut entry = self
+        let entry = self
             .escrows
             .get(&escrow_id)
-            .expect("Escrow not found")
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
             .clone();
 
-        assert!(
+        ensure!(
             entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
             "Escrow is not active"
         );
 
-        assert!(
+        ensure!(
             caller == entry.seller || caller == entry.buyer || caller == self.admin,
+            ContractError::Unauthorized,
             "Unauthorized"
         );
 
-        assert!(
+        ensure!(
             entry.funds_deposited,
+            ContractError::InvalidState,
             "No funds deposited in escrow"
         );
 
-        assert!(
-            entry.debtor_paid,
-            "Debtor payment has not been confirmed"
+        ensure!(
+            entry.debtor_paid && entry.amount_paid.0 >= entry.invoice_amount.0,
+            ContractError::InvalidState,
+            "Debtor payment has not been received"
         );
 
-        entry.status = EscrowStatus::Released;
-        entry.settled_at = Some(env::block_timestamp_ms());
-        self.escrows.insert(escrow_id.clone(), entry.clone());
+        // Either party may still dispute until the window closes, unless both agreed to release
+        let eligible_at = self.settlement_eligible_at(&entry).unwrap_or(0);
+        ensure!(
+            env::block_timestamp_ms() >= eligible_at,
+            ContractError::InvalidState,
+            "Dispute window is open until {}",
+            eligible_at
+        );
 
-        env::log_str(&format!(
-            "Escrow {} settled: {} USDC released to buyer {}",
-            escrow_id, entry.sale_amount.0, entry.buyer
-        ));
+        ensure!(
+            entry.deposited_amount.0 >= entry.sale_amount.0,
+            ContractError::InsufficientBalance,
+            "Deposited amount {} is below the sale amount {}",
+            entry.deposited_amount.0,
+            entry.sale_amount.0
+        );
 
-        ext_ft::ext(self.usdc_contract.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(
-                entry.buyer.clone(),
-                entry.sale_amount,
-                Some(format!("settlement:{}", escrow_id)),
-            )
-            .then(
-                ext_invoice::ext(self.invoice_contract.clone())
-                    .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-                    .mark_settled(entry.invoice_id)
-            )
+        if self.strict_verification {
+            ensure!(
+                entry.verified_against_invoice,
+                ContractError::InvalidState,
+                "Escrow has not been verified against the invoice"
+            );
+        }
+
+        self.request_settlement(escrow_id, &entry)
     }
 
-    /// Open a dispute
-    pub fn open_dispute(&mut self, escrow_id: String, reason: String) {
+    /// Record that the debtor paid the invoice off-chain (admin only)
+    /// Informational: settlement still waits for the invoice amount to arrive on-chain, and the
+    /// buyer keeps their timeout refund until it does
+    pub fn record_debtor_payment(&mut self, escrow_id: String) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can record debtor payments"
+        );
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        ensure!(!entry.debtor_paid, ContractError::InvalidState, "Debtor payment already received");
+
+        let outstanding = entry.invoice_amount.0.saturating_sub(entry.amount_paid.0);
+        entry.debtor_paid = true;
+        entry.debtor_paid_at = Some(env::block_timestamp_ms());
+        Self::emit_debtor_payment_received(&entry, caller, U128(outstanding));
+        self.escrows.insert(escrow_id, entry);
+    }
+
+    /// Consent to releasing a funded escrow early (buyer or seller)
+    /// Settles as soon as both parties have consented, without waiting for the debtor;
+    /// returns whether settlement was started
+    pub fn mutual_release(&mut self, escrow_id: String) -> bool {
+        self.assert_not_paused();
         let caller = env::predecessor_account_id();
         let mut entry = self
             .escrows
             .get(&escrow_id)
-            .expect("Escrow not found")
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
             .clone();
 
-        assert!(
+        ensure!(
             entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
             "Escrow is not active"
         );
-        assert!(
-            caller == entry.buyer || caller == entry.seller,
-            "Only buyer or seller can open dispute"
+        ensure!(
+            entry.funds_deposited,
+            ContractError::InvalidState,
+            "No funds deposited in escrow"
         );
-        assert!(!reason.is_empty(), "Dispute reason required");
 
-        entry.status = EscrowStatus::Disputed;
-        entry.dispute_reason = Some(reason.clone());
-        self.escrows.insert(escrow_id.clone(), entry);
+        let consent = if caller == entry.buyer {
+            &mut entry.buyer_release_consent
+        } else if caller == entry.seller {
+            &mut entry.seller_release_consent
+        } else {
+            ContractError::Unauthorized.panic("Only buyer or seller can consent to release")
+        };
+        ensure!(
+            !*consent,
+            ContractError::AlreadyExists,
+            "{} already consented to release escrow {}",
+            caller,
+            escrow_id
+        );
+        *consent = true;
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+        env::log_str(&format!("{} consented to early release of escrow {}", caller, escrow_id));
+
+        if !(entry.buyer_release_consent && entry.seller_release_consent) {
+            return false;
+        }
+        env::log_str(&format!("Escrow {} released early by mutual consent", escrow_id));
+        let _ = self.request_settlement(escrow_id, &entry);
+        true
+    }
+
+    /// Settle the escrow holding an invoice; same checks as `settle`
+    pub fn settle_by_invoice(&mut self, invoice_id: String) -> Promise {
+        let escrow_id = self
+            .escrows_by_invoice
+            .get(&invoice_id)
+            .cloned()
+            .or_fail(ContractError::EscrowNotFound, "No escrow for invoice");
+        self.settle(escrow_id)
+    }
+
+    /// Settle ready escrows past their due date (callable by anyone, e.g. a keeper bot).
+    /// Scans `limit` escrows from `from_index`, settles as many as the attached gas covers (at most
+    /// MAX_SETTLE_BATCH) and reports how many ready escrows in the range remain
+    pub fn settle_due(&mut self, from_index: Option<u64>, limit: Option<u64>) -> SettleDueResult {
+        self.assert_not_paused();
+        let now = env::block_timestamp_ms();
+        let limit = limit.unwrap_or(MAX_SETTLE_SCAN).min(MAX_SETTLE_SCAN);
+
+        let ready: Vec<(String, EscrowEntry)> = self
+            .escrows
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit as usize)
+            .filter(|(_, entry)| entry.due_date <= now && self.is_settleable(entry))
+            .map(|(id, entry)| (id.clone(), entry.clone()))
+            .collect();
+
+        // Settlement callbacks share the unused gas, so start only as many as it fully covers
+        let available = env::prepaid_gas()
+            .as_gas()
+            .saturating_sub(env::used_gas().as_gas())
+            .saturating_sub(self.gas_config.callback.as_gas());
+        let affordable = (available / self.settlement_gas().as_gas()) as usize;
+        let settled = ready.len().min(MAX_SETTLE_BATCH).min(affordable);
+        for (escrow_id, entry) in ready.iter().take(settled) {
+            let _ = self.request_settlement(escrow_id.clone(), entry);
+        }
 
         env::log_str(&format!(
-            "Dispute opened for escrow {}: {}",
-            escrow_id, reason
+            "settle_due: {} escrows settling, {} remaining",
+            settled,
+            ready.len() - settled
         ));
+
+        SettleDueResult {
+            settled: settled as u64,
+            remaining: (ready.len() - settled) as u64,
+        }
+    }
+
+    /// Release settlement funds: the invoice amount to the buyer (pro rata to shareholders when the
+    /// invoice is split, senior first when it is tranched) and the escrowed price to the seller
+    #[private]
+    pub fn on_settle_callback(
+        &mut self,
+        escrow_id: String,
+        #[callback_result] holders: Result<Vec<ShareHolding>, PromiseError>,
+        #[callback_result] tranches: Result<Vec<Tranche>, PromiseError>,
+    ) -> Promise {
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        // A dispute or an earlier settlement may have landed since the request
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+
+        // A whole invoice (or an unreadable cap table) settles to the buyer
+        // Installments already forwarded to the buyer are not paid again
+        let held = entry.amount_paid.0 - entry.amount_forwarded.0;
+        let (holder_role, holder_payouts) = match (tranches, holders) {
+            (Ok(tranches), _) if !tranches.is_empty() => (
+                "tranche holder",
+                Self::waterfall(held, entry.amount_forwarded.0, &tranches),
+            ),
+            (_, Ok(holders)) if holders.len() > 1 => ("shareholder", Self::pro_rata(held, &holders)),
+            _ => ("buyer", vec![(entry.buyer.clone(), held)]),
+        };
+        let payouts = holder_payouts
+            .into_iter()
+            .map(|(recipient, amount)| (holder_role, recipient, amount))
+            .chain([("seller", entry.payout_recipient(), entry.deposited_amount.0)])
+            .filter(|(_, _, amount)| *amount > 0)
+            .collect::<Vec<_>>();
+
+        // Running out of gas here leaves the escrow active so settlement can be retried
+        let required = self.gas_config.ft_transfer.as_gas() * payouts.len() as u64
+            + self.gas_config.cross_contract.as_gas();
+        ensure!(
+            env::prepaid_gas().as_gas().saturating_sub(env::used_gas().as_gas()) >= required,
+            ContractError::InsufficientGas,
+            "Not enough gas to settle {} payouts",
+            payouts.len()
+        );
+
+        entry.status = EscrowStatus::Released;
+        entry.settled_at = Some(env::block_timestamp_ms());
+        self.unlock_value(&entry);
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+        // The callback runs as this contract, so credit whoever signed the settlement
+        self.record_audit(AuditAction::Settled, env::signer_account_id(), &escrow_id);
+
+        let mut transfers: Option<Promise> = None;
+        for (role, recipient, amount) in payouts {
+            self.record_payout(&recipient, amount);
+
+            env::log_str(&format!(
+                "Escrow {} settled: {} {} released to {} {}",
+                escrow_id,
+                amount,
+                self.escrow_token(&entry),
+                role,
+                recipient
+            ));
+            AdelanteEvent::EscrowSettled(vec![EscrowSettled {
+                escrow_id: escrow_id.clone(),
+                invoice_id: entry.invoice_id.clone(),
+                recipient: recipient.clone(),
+                amount: U128(amount),
+            }])
+            .emit();
+
+            let transfer = ext_ft::ext(self.escrow_token(&entry))
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(recipient, U128(amount), Some(format!("settlement:{}", escrow_id)));
+            transfers = Some(match transfers {
+                Some(previous) => previous.and(transfer),
+                None => transfer,
+            });
+        }
+
+        transfers.or_fail(ContractError::InvalidState, "No settlement payouts").then(
+            ext_invoice::ext(self.invoice_contract.clone())
+                .with_static_gas(self.gas_config.cross_contract)
+                .mark_settled(entry.invoice_id),
+        )
+    }
+
+    /// Transfer the right to receive settlement to a new holder (current buyer only)
+    pub fn transfer_escrow_beneficiary(&mut self, escrow_id: String, new_buyer: AccountId) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        ensure!(
+            caller == entry.buyer,
+            ContractError::Unauthorized,
+            "Only buyer can transfer escrow position"
+        );
+
+        self.move_position(escrow_id, entry, new_buyer);
+    }
+
+    /// Hand an invoice's active escrow position to the buyer of a resale, returning the escrow id
+    /// (marketplace only). Funds and terms stay as they are; only the buyer changes
+    pub fn resell_position(&mut self, invoice_id: String, new_buyer: AccountId) -> String {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Only marketplace can resell escrow positions"
+        );
+        let escrow_id = self
+            .escrows_by_invoice
+            .get(&invoice_id)
+            .cloned()
+            .or_fail(ContractError::EscrowNotFound, "No escrow for invoice");
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+
+        self.move_position(escrow_id.clone(), entry, new_buyer);
+        escrow_id
+    }
+
+
+    /// Open a dispute
+    pub fn open_dispute(&mut self, escrow_id: String, reason: String) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+
+        ensure!(
+            caller == entry.buyer || caller == entry.seller,
+            ContractError::Unauthorized,
+            "Only buyer or seller can open dispute"
+        );
+
+        self.internal_open_dispute(escrow_id, reason);
     }
 
     /// Resolve dispute (admin only) - transfers USDC to winner
     pub fn resolve_dispute(&mut self, escrow_id: String, winner: AccountId) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can resolve disputes"
+        );
+
+        self.internal_resolve_dispute(escrow_id, winner)
+    }
+
+    /// Resolve a dispute by splitting the escrowed funds between buyer and seller (admin only)
+    /// The split covers the deposit and any debtor payment still held in escrow
+    pub fn resolve_dispute_split(
+        &mut self,
+        escrow_id: String,
+        buyer_amount: U128,
+        seller_amount: U128,
+    ) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can resolve disputes"
+        );
+
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+        ensure!(
+            entry.status == EscrowStatus::Disputed,
+            ContractError::InvalidState,
+            "Escrow is not disputed"
+        );
+        ensure!(
+            entry.funds_deposited,
+            ContractError::InvalidState,
+            "No funds deposited in escrow"
+        );
+        let escrowed = entry.deposited_amount.0 + entry.amount_paid.0 - entry.amount_forwarded.0;
+        ensure!(
+            buyer_amount.0.checked_add(seller_amount.0) == Some(escrowed),
+            ContractError::InvalidAmount,
+            "Split must sum to the escrowed amount of {}",
+            escrowed
+        );
+
+        entry.status = EscrowStatus::PartiallyResolved;
+        entry.settled_at = Some(env::block_timestamp_ms());
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+
+        // The event names the party awarded the larger share
+        let majority = if buyer_amount.0 >= seller_amount.0 {
+            entry.buyer.clone()
+        } else {
+            entry.seller.clone()
+        };
+        self.record_dispute_resolution(&entry, &majority);
+        let token = self.escrow_token(&entry);
+        env::log_str(&format!(
+            "Dispute split: {} {} to buyer {}, {} {} to seller {}",
+            buyer_amount.0, token, entry.buyer, seller_amount.0, token, entry.seller
+        ));
+
+        let mut transfers: Option<Promise> = None;
+        let shares = [
+            (entry.buyer.clone(), buyer_amount),
+            (entry.payout_recipient(), seller_amount),
+        ];
+        for (recipient, amount) in shares {
+            if amount.0 == 0 {
+                continue;
+            }
+            self.record_payout(&recipient, amount.0);
+            let transfer = ext_ft::ext(self.escrow_token(&entry))
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(
+                    recipient,
+                    amount,
+                    Some(format!("dispute_resolution:{}", escrow_id)),
+                );
+            transfers = Some(match transfers {
+                Some(previous) => previous.and(transfer),
+                None => transfer,
+            });
+        }
+
+        transfers.or_fail(ContractError::InvalidState, "No dispute payouts").then(
+            ext_invoice::ext(self.invoice_contract.clone())
+                .with_static_gas(self.gas_config.cross_contract)
+                .mark_settled(entry.invoice_id.clone()),
+        )
+    }
+
+    /// Cast an arbiter vote on a disputed escrow; a majority of arbiters resolves it
+    pub fn vote_dispute(&mut self, escrow_id: String, winner: AccountId) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        ensure!(
+            self.arbiters.contains_key(&caller),
+            ContractError::Unauthorized,
+            "Only arbiters can vote on disputes"
+        );
+
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+        ensure!(
+            entry.status == EscrowStatus::Disputed,
+            ContractError::InvalidState,
+            "Escrow is not disputed"
+        );
+        ensure!(
+            winner == entry.buyer || winner == entry.seller,
+            ContractError::InvalidArgument,
+            "Winner must be buyer or seller"
+        );
+        ensure!(
+            self.voting_time_remaining(&entry) != Some(0),
+            ContractError::Expired,
+            "Voting period has ended"
+        );
+
+        let mut votes = self
+            .dispute_votes
+            .get(&escrow_id)
+            .cloned()
+            .unwrap_or_default();
+        ensure!(
+            !votes.iter().any(|vote| vote.arbiter == caller),
+            ContractError::InvalidState,
+            "Arbiter has already voted"
+        );
+
+        votes.push(DisputeVote {
+            arbiter: caller.clone(),
+            winner: winner.clone(),
+            voted_at: env::block_timestamp_ms(),
+        });
+        let winner_votes = votes.iter().filter(|vote| vote.winner == winner).count() as u32;
+        self.dispute_votes.insert(escrow_id.clone(), votes);
+
+        env::log_str(&format!(
+            "Arbiter {} voted for {} on escrow {}",
+            caller, winner, escrow_id
+        ));
+
+        if winner_votes >= self.dispute_quorum() {
+            if entry.funds_deposited {
+                env::log_str(&format!(
+                    "Arbiter majority reached on escrow {}: resolving for {}",
+                    escrow_id, winner
+                ));
+                let _ = self.internal_resolve_dispute(escrow_id, winner);
+            } else {
+                env::log_str(&format!(
+                    "Arbiter majority reached on escrow {}, awaiting deposit before resolution",
+                    escrow_id
+                ));
+            }
+        }
+    }
+
+    /// Attach an evidence reference to an open dispute (buyer or seller)
+    pub fn submit_dispute_evidence(&mut self, escrow_id: String, evidence_hash: String) {
         let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can resolve disputes");
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+
+        ensure!(
+            entry.status == EscrowStatus::Disputed,
+            ContractError::InvalidState,
+            "Escrow is not disputed"
+        );
+        ensure!(
+            caller == entry.buyer || caller == entry.seller,
+            ContractError::Unauthorized,
+            "Only buyer or seller can submit evidence"
+        );
+        ensure!(
+            !evidence_hash.is_empty(),
+            ContractError::InvalidArgument,
+            "Evidence hash required"
+        );
+
+        let mut evidence = self
+            .dispute_evidence
+            .get(&escrow_id)
+            .cloned()
+            .unwrap_or_default();
+        evidence.push(DisputeEvidence {
+            submitted_by: caller,
+            evidence_hash,
+            submitted_at: env::block_timestamp_ms(),
+        });
+        self.dispute_evidence.insert(escrow_id.clone(), evidence);
+
+        env::log_str(&format!("Evidence submitted for escrow {}", escrow_id));
+    }
+
+    /// Gather an account's invoices, listings, bids and escrows in one call
+    /// Invoice and marketplace data are fetched cross-contract and merged in a callback
+    pub fn get_account_overview(&mut self, account: AccountId) -> Promise {
+        let invoices = ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .get_invoices_by_owner(account.clone(), None, Some(MAX_PAGE_LIMIT));
+        let listings = ext_marketplace::ext(self.marketplace_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .get_listings_by_seller(account.clone(), None, Some(MAX_PAGE_LIMIT));
+        let bids = ext_marketplace::ext(self.marketplace_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .get_bids_by_bidder(account.clone(), None, Some(MAX_PAGE_LIMIT));
+
+        invoices.and(listings).and(bids).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(self.gas_config.callback)
+                .on_account_overview(account),
+        )
+    }
+
+    #[private]
+    pub fn on_account_overview(
+        &self,
+        account: AccountId,
+        #[callback_result] invoices: Result<PaginatedResult<InvoiceSummary>, PromiseError>,
+        #[callback_result] listings: Result<PaginatedResult<ListingSummary>, PromiseError>,
+        #[callback_result] bids: Result<PaginatedResult<BidSummary>, PromiseError>,
+    ) -> AccountOverview {
+        // Only the account's own escrows are read, through the buyer and seller indexes
+        let lookup = |index: &LookupMap<AccountId, Vec<String>>| {
+            index
+                .get(&account)
+                .into_iter()
+                .flatten()
+                .filter_map(|id| self.escrows.get(id))
+                .collect::<Vec<_>>()
+        };
+        let as_buyer = lookup(&self.escrows_by_buyer);
+        // An escrow the account is both buyer and seller of is already in as_buyer
+        let as_seller = lookup(&self.escrows_by_seller)
+            .into_iter()
+            .filter(|entry| entry.buyer != account);
+        let active_escrows = as_buyer
+            .into_iter()
+            .chain(as_seller)
+            .filter(|entry| !entry.status.is_terminal())
+            .take(MAX_PAGE_LIMIT as usize)
+            .cloned()
+            .collect();
+
+        AccountOverview {
+            invoices_available: invoices.is_ok(),
+            listings_available: listings.is_ok(),
+            bids_available: bids.is_ok(),
+            invoices_owned: invoices.map(|page| page.items).unwrap_or_default(),
+            active_listings: listings
+                .map(|page| page.items)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|listing| listing.active)
+                .collect(),
+            active_bids: bids.map(|page| page.items).unwrap_or_default(),
+            active_escrows,
+            account,
+        }
+    }
+
+    /// Compare the escrow's recorded invoice terms with the invoice contract
+    pub fn verify_against_invoice(&mut self, escrow_id: String) -> Promise {
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+
+        ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .get_invoice(entry.invoice_id.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(self.gas_config.callback)
+                    .on_verify_callback(escrow_id),
+            )
+    }
+
+    #[private]
+    pub fn on_verify_callback(
+        &mut self,
+        escrow_id: String,
+        #[callback_result] invoice: Result<Option<InvoiceSummary>, PromiseError>,
+    ) -> bool {
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        let verified = match invoice {
+            Ok(Some(invoice)) => {
+                let matches =
+                    invoice.amount == entry.invoice_amount && invoice.due_date == entry.due_date;
+                if !matches {
+                    env::log_str(&format!(
+                        "Escrow {} mismatch: recorded {} due {}, invoice {} due {}",
+                        escrow_id,
+                        entry.invoice_amount.0,
+                        entry.due_date,
+                        invoice.amount.0,
+                        invoice.due_date
+                    ));
+                }
+                matches
+            }
+            Ok(None) => {
+                env::log_str(&format!(
+                    "Escrow {} invoice {} not found",
+                    escrow_id, entry.invoice_id
+                ));
+                false
+            }
+            // Leave the previous result in place when the invoice contract can't be reached
+            Err(_) => return entry.verified_against_invoice,
+        };
+
+        entry.verified_against_invoice = verified;
+        self.escrows.insert(escrow_id, entry);
+        verified
+    }
+
+    /// Purchase funds actually received by the escrow
+    pub fn get_deposited_amount(&self, escrow_id: String) -> U128 {
+        self.escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .deposited_amount
+    }
+
+    /// Whether the debtor's payment has been received for the escrow
+    pub fn is_debtor_paid(&self, escrow_id: String) -> bool {
+        self.escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .debtor_paid
+    }
+
+    /// Check if escrow is past due date
+    pub fn check_overdue(&self, escrow_id: String) -> bool {
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+        entry.status == EscrowStatus::Active && env::block_timestamp_ms() > entry.due_date
+    }
+
+    /// Append the current TVL to the on-chain history (permissionless, e.g. a keeper bot)
+    /// At most one snapshot per TVL_SNAPSHOT_INTERVAL_MS; the oldest is overwritten once full
+    pub fn record_tvl_snapshot(&mut self) -> TvlSnapshot {
+        let now = env::block_timestamp_ms();
+        if let Some(last) = self.get_tvl_history(Some(1)).pop() {
+            ensure!(
+                now >= last.timestamp + TVL_SNAPSHOT_INTERVAL_MS,
+                ContractError::InvalidState,
+                "Next TVL snapshot allowed at {}",
+                last.timestamp + TVL_SNAPSHOT_INTERVAL_MS
+            );
+        }
+
+        let snapshot = TvlSnapshot {
+            timestamp: now,
+            tvl: U128(self.active_value_locked),
+        };
+        let slot = (self.tvl_snapshot_count % TVL_HISTORY_CAPACITY as u64) as u32;
+        if slot < self.tvl_history.len() {
+            self.tvl_history.replace(slot, snapshot.clone());
+        } else {
+            self.tvl_history.push(snapshot.clone());
+        }
+        self.tvl_snapshot_count += 1;
+        env::log_str(&format!("TVL snapshot: {}", self.active_value_locked));
+        snapshot
+    }
+
+    /// Mark an unpaid escrow as overdue (permissionless, pays the keeper bounty)
+    pub fn mark_overdue(&mut self, escrow_id: String) {
+        self.assert_not_paused();
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        ensure!(
+            env::block_timestamp_ms() > entry.due_date,
+            ContractError::InvalidState,
+            "Escrow is not overdue"
+        );
+        // A paid escrow waiting out its dispute window is not overdue
+        ensure!(
+            !entry.debtor_paid && entry.amount_paid.0 < entry.invoice_amount.0,
+            ContractError::InvalidState,
+            "Debtor payment already received"
+        );
+
+        // Auto-open dispute for overdue escrow
+        self.internal_open_dispute(
+            escrow_id,
+            format!("Auto-dispute: Payment overdue since {}", entry.due_date),
+        );
+        self.reward_keeper();
+    }
+
+    /// Refund the buyer once the grace period after the due date passes without debtor payment
+    /// Installments held for the buyer are returned along with the deposit (buyer only)
+    pub fn claim_timeout_refund(&mut self, escrow_id: String) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        ensure!(
+            caller == entry.buyer,
+            ContractError::Unauthorized,
+            "Only buyer can claim a timeout refund"
+        );
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        ensure!(
+            entry.funds_deposited,
+            ContractError::InvalidState,
+            "No funds deposited in escrow"
+        );
+        ensure!(
+            entry.amount_paid.0 < entry.invoice_amount.0,
+            ContractError::InvalidState,
+            "Debtor payment already received"
+        );
+        let refundable_at = entry.due_date.saturating_add(self.grace_period_ms);
+        ensure!(
+            env::block_timestamp_ms() > refundable_at,
+            ContractError::InvalidState,
+            "Grace period ends at {}",
+            refundable_at
+        );
+
+        let held = entry.amount_paid.0 - entry.amount_forwarded.0;
+        let refund = entry.deposited_amount.0 + held;
+        entry.status = EscrowStatus::Refunded;
+        entry.settled_at = Some(env::block_timestamp_ms());
+        self.unlock_value(&entry);
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+        self.record_payout(&entry.buyer, refund);
+
+        env::log_str(&format!(
+            "Escrow {} timed out unpaid: {} {} refunded to buyer {}",
+            escrow_id,
+            refund,
+            self.escrow_token(&entry),
+            entry.buyer
+        ));
+
+        ext_ft::ext(self.escrow_token(&entry))
+            .with_static_gas(self.gas_config.ft_transfer)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(
+                entry.buyer.clone(),
+                U128(refund),
+                Some(format!("timeout_refund:{}", escrow_id)),
+            )
+    }
+
+    /// Add NEAR to the keeper bounty pool
+    #[payable]
+    pub fn fund_keeper_pool(&mut self) {
+        self.keeper_rewards.fund(env::attached_deposit());
+    }
+
+    /// Configure the keeper bounty and the cap on total bounties paid (admin only)
+    pub fn set_keeper_bounty(&mut self, bounty: NearToken, max_total_paid: NearToken) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can configure keeper bounties"
+        );
+        self.keeper_rewards.configure(bounty, max_total_paid);
+    }
+
+    /// Set the gas attached to cross-contract calls, callbacks and ft_transfers (admin only)
+    pub fn set_gas_config(&mut self, cross_contract: Gas, callback: Gas, ft_transfer: Gas) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update gas config"
+        );
+        for (name, gas) in [
+            ("cross_contract", cross_contract),
+            ("callback", callback),
+            ("ft_transfer", ft_transfer),
+        ] {
+            ensure!(
+                gas >= MIN_GAS_CONFIG,
+                ContractError::InvalidArgument,
+                "Gas for {} below minimum of {} Tgas",
+                name,
+                MIN_GAS_CONFIG.as_tgas()
+            );
+        }
+
+        self.gas_config = GasConfig { cross_contract, callback, ft_transfer };
+        env::log_str(&format!(
+            "Gas config set: cross_contract {} Tgas, callback {} Tgas, ft_transfer {} Tgas",
+            cross_contract.as_tgas(),
+            callback.as_tgas(),
+            ft_transfer.as_tgas()
+        ));
+    }
+
+
+    /// Remove settled escrows from an account's buyer and seller indexes (permissionless)
+    /// Entries stay in storage and remain retrievable by id; returns how many ids were pruned
+    pub fn prune_settled_from_indexes(&mut self, account: AccountId, limit: u64) -> u64 {
+        let mut pruned = 0u64;
+
+        if let Some(mut ids) = self.escrows_by_buyer.get(&account).cloned() {
+            pruned += self.prune_terminal_ids(&mut ids, limit);
+            self.escrows_by_buyer.insert(account.clone(), ids);
+        }
+
+        if let Some(mut ids) = self.escrows_by_seller.get(&account).cloned() {
+            pruned += self.prune_terminal_ids(&mut ids, limit - pruned);
+            self.escrows_by_seller.insert(account.clone(), ids);
+        }
+
+        env::log_str(&format!(
+            "Pruned {} settled escrows from indexes of {}",
+            pruned, account
+        ));
+        pruned
+    }
+
+    /// Raise escrow_count past every stored escrow id so new ids never collide (admin or operator)
+    pub fn recompute_counters(&mut self) -> u64 {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can recompute counters"
+        );
+
+        let max_sequence = self.escrows.keys().map(|id| id_sequence(id)).max().unwrap_or(0);
+        let previous = self.escrow_count;
+        self.escrow_count = previous.max(max_sequence).max(self.escrows.len() as u64);
+
+        env::log_str(&format!(
+            "escrow_count recomputed: {} -> {}",
+            previous, self.escrow_count
+        ));
+        self.escrow_count
+    }
+
+    /// Update admin (current admin only)
+    pub fn set_admin(&mut self, new_admin: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can change admin");
+        self.admin = new_admin;
+    }
+
+    /// Pause or unpause escrow operations (admin or operator)
+    pub fn set_paused(&mut self, paused: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can pause the contract"
+        );
+        self.paused = paused;
+        env::log_str(&format!("Escrow contract paused: {}", paused));
+    }
+
+    /// Add an operator for routine maintenance (admin only)
+    pub fn add_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.insert(operator, true);
+    }
+
+    /// Remove an operator (admin only)
+    pub fn remove_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.remove(&operator);
+    }
+
+    /// Require invoice verification before settlement (admin only)
+    pub fn set_strict_verification(&mut self, strict: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can set strict verification"
+        );
+        self.strict_verification = strict;
+    }
+
+    /// Choose whether debtor installments are forwarded to the buyer as they arrive (admin only)
+    pub fn set_forward_partial_payments(&mut self, forward: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can configure partial payments"
+        );
+        self.forward_partial_payments = forward;
+    }
+
+    /// Set how long after the due date the debtor has before timeout refunds open (admin only)
+    pub fn set_grace_period(&mut self, period_ms: u64) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can set grace period"
+        );
+        self.grace_period_ms = period_ms;
+    }
+
+    /// Set how long arbiters have to vote on a dispute, 0 for no deadline (admin only)
+    pub fn set_dispute_voting_period(&mut self, period_ms: u64) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can set voting period"
+        );
+        self.dispute_voting_period_ms = period_ms;
+    }
+
+    /// Set how long settlement waits after the debtor pays, 0 to settle at once (admin only)
+    pub fn set_dispute_window(&mut self, window_ms: u64) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can set dispute window"
+        );
+        self.dispute_window_ms = window_ms;
+    }
+
+    /// Register a dispute arbiter (admin only)
+    pub fn add_arbiter(&mut self, arbiter: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage arbiters"
+        );
+        self.arbiters.insert(arbiter, true);
+    }
+
+    /// Remove a dispute arbiter (admin only)
+    pub fn remove_arbiter(&mut self, arbiter: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage arbiters"
+        );
+        self.arbiters.remove(&arbiter);
+    }
+
+    /// Accept a fungible token for deposits and debtor payments (admin only)
+    pub fn add_accepted_token(&mut self, token: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage accepted tokens"
+        );
+        self.accepted_tokens.insert(token, true);
+    }
+
+    /// Stop accepting a fungible token; escrows already funded in it still pay out in it (admin only)
+    pub fn remove_accepted_token(&mut self, token: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage accepted tokens"
+        );
+        self.accepted_tokens.remove(&token);
+    }
+
+    /// Send tokens that reached the contract without a matching escrow to `to` (admin only)
+    /// Limited to the unmatched transfers ft_on_transfer recorded, so escrowed funds can't be taken
+    pub fn admin_withdraw_token(&mut self, token: AccountId, to: AccountId, amount: U128) -> Promise {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can withdraw tokens"
+        );
+        ensure!(amount.0 > 0, ContractError::InvalidAmount, "Amount must be greater than 0");
+        let orphaned = self.orphaned_tokens.get(&token).copied().unwrap_or(0);
+        ensure!(
+            amount.0 <= orphaned,
+            ContractError::InsufficientBalance,
+            "Only {} of {} is unclaimed by escrows",
+            orphaned,
+            token
+        );
+        self.orphaned_tokens.insert(token.clone(), orphaned - amount.0);
+
+        env::log_str(&format!("Recovering {} of {} to {}", amount.0, token, to));
+        AdelanteEvent::TokensRecovered(vec![TokensRecovered {
+            token: token.clone(),
+            recipient: to.clone(),
+            amount,
+        }])
+        .emit();
+
+        ext_ft::ext(token.clone())
+            .with_static_gas(self.gas_config.ft_transfer)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(to, amount, Some("admin_withdraw".to_string()))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(self.gas_config.callback)
+                    .on_withdraw_callback(token, amount),
+            )
+    }
+
+    /// Return a failed recovery to the orphaned balance so it can be withdrawn again
+    #[private]
+    pub fn on_withdraw_callback(
+        &mut self,
+        token: AccountId,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        if result.is_err() {
+            let orphaned = self.orphaned_tokens.get(&token).copied().unwrap_or(0);
+            self.orphaned_tokens.insert(token.clone(), orphaned + amount.0);
+            env::log_str(&format!("Recovery of {} {} failed, restored", amount.0, token));
+        }
+        result.is_ok()
+    }
+
+    /// Tokens received without a matching escrow, available to admin_withdraw_token
+    pub fn get_orphaned_balance(&self, token: AccountId) -> U128 {
+        U128(self.orphaned_tokens.get(&token).copied().unwrap_or(0))
+    }
+
+    /// Update contract addresses (admin only)
+    pub fn set_contract_addresses(
+        &mut self,
+        invoice_contract: Option<AccountId>,
+        marketplace_contract: Option<AccountId>,
+        usdc_contract: Option<AccountId>,
+    ) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update contracts"
+        );
+
+        if let Some(addr) = invoice_contract {
+            self.invoice_contract = addr;
+        }
+        if let Some(addr) = marketplace_contract {
+            self.marketplace_contract = addr;
+        }
+        if let Some(addr) = usdc_contract {
+            self.usdc_contract = addr;
+        }
+    }
+
+    // ============ VIEW METHODS ============
+
+    /// Get escrow by ID
+    pub fn get_escrow(&self, escrow_id: String) -> Option<EscrowEntry> {
+        self.escrows.get(&escrow_id).cloned()
+    }
+
+    /// Get escrow by invoice
+    pub fn get_escrow_by_invoice(&self, invoice_id: String) -> Option<EscrowEntry> {
+        self.escrows_by_invoice
+            .get(&invoice_id)
+            .and_then(|id| self.escrows.get(id).cloned())
+    }
+
+    /// Get the status of the escrow holding an invoice
+    pub fn get_escrow_status_by_invoice(&self, invoice_id: String) -> Option<EscrowStatus> {
+        self.escrows_by_invoice
+            .get(&invoice_id)
+            .and_then(|id| self.escrows.get(id))
+            .map(|entry| entry.status.clone())
+    }
+
+    /// Get escrows by buyer (paginated)
+    pub fn get_escrows_by_buyer(
+        &self,
+        buyer: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<EscrowEntry> {
+        let ids = self.escrows_by_buyer.get(&buyer).into_iter().flatten();
+        PaginatedResult::paginate(
+            ids.filter_map(|id| self.escrows.get(id).cloned()),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get escrows by seller (paginated)
+    pub fn get_escrows_by_seller(
+        &self,
+        seller: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<EscrowEntry> {
+        let ids = self.escrows_by_seller.get(&seller).into_iter().flatten();
+        PaginatedResult::paginate(
+            ids.filter_map(|id| self.escrows.get(id).cloned()),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get all active escrows
+    pub fn get_active_escrows(&self, from_index: u64, limit: u64) -> Vec<EscrowEntry> {
+        self.escrows
+            .iter()
+            .filter(|(_, entry)| entry.status == EscrowStatus::Active)
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+
+    /// Get active escrows with ids after `last_id`, in ascending escrow id (creation) order
+    /// Looks up the ids after the cursor directly, so deep pages stay as cheap as the first
+    /// At most MAX_VIEW_SCAN ids are examined per call, so a page may come back short; keep
+    /// following `next_cursor` until it is None
+    pub fn get_active_escrows_after(
+        &self,
+        last_id: Option<String>,
+        limit: Option<u64>,
+    ) -> ActiveEscrowsPage {
+        let start = last_id.map_or(0, |id| id_sequence(&id)) + 1;
+
+        let page = PaginatedResult::scan(start..=self.escrow_count, None, limit, |sequence| {
+            self.escrows
+                .get(&format!("ESC-{:06}", sequence))
+                .filter(|entry| entry.status == EscrowStatus::Active)
+                .cloned()
+        });
+        ActiveEscrowsPage {
+            items: page.items,
+            next_cursor: page
+                .next_index
+                .map(|examined| format!("ESC-{:06}", start + examined - 1)),
+        }
+    }
+
+    /// Get disputed escrows (admin view, paginated over the disputed index)
+    pub fn get_disputed_escrows(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<EscrowEntry> {
+        PaginatedResult::paginate(
+            self.disputed_escrows
+                .iter()
+                .filter_map(|id| self.escrows.get(id).cloned()),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get disputed escrows the arbiter has not voted on yet (arbiter work queue)
+    /// `from_index` and `next_index` are positions in the disputed index, so keep following
+    /// `next_index` until it is None even when a page comes back short
+    pub fn get_disputes_pending_vote(
+        &self,
+        arbitrator: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<EscrowEntry> {
+        if !self.arbiters.contains_key(&arbitrator) {
+            return PaginatedResult { items: vec![], next_index: None };
+        }
+
+        PaginatedResult::scan(self.disputed_escrows.iter(), from_index, limit, |id| {
+            let voted = self
+                .dispute_votes
+                .get(id)
+                .is_some_and(|votes| votes.iter().any(|vote| vote.arbiter == arbitrator));
+            if voted {
+                return None;
+            }
+            self.escrows.get(id).cloned()
+        })
+    }
+
+    /// Get a dispute's reason, evidence, votes, tally and voting deadline
+    pub fn get_dispute_details(&self, escrow_id: String) -> DisputeDetails {
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+        let votes = self
+            .dispute_votes
+            .get(&escrow_id)
+            .cloned()
+            .unwrap_or_default();
+
+        let buyer_votes = votes.iter().filter(|vote| vote.winner == entry.buyer).count() as u32;
+        let seller_votes = votes.iter().filter(|vote| vote.winner == entry.seller).count() as u32;
+
+        DisputeDetails {
+            escrow_id: escrow_id.clone(),
+            status: entry.status.clone(),
+            reason: entry.dispute_reason.clone(),
+            evidence: self
+                .dispute_evidence
+                .get(&escrow_id)
+                .cloned()
+                .unwrap_or_default(),
+            votes,
+            buyer_votes,
+            seller_votes,
+            quorum: self.dispute_quorum(),
+            opened_at: entry.dispute_opened_at,
+            time_remaining_ms: self.voting_time_remaining(entry),
+        }
+    }
+
+    /// Get votes cast on a dispute
+    pub fn get_dispute_votes(&self, escrow_id: String) -> Vec<DisputeVote> {
+        self.dispute_votes
+            .get(&escrow_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get registered arbiters
+    pub fn get_arbiters(&self) -> Vec<AccountId> {
+        self.arbiters.keys().cloned().collect()
+    }
+
+    /// Get the tokens accepted for deposits and debtor payments
+    pub fn get_accepted_tokens(&self) -> Vec<AccountId> {
+        self.accepted_tokens.keys().cloned().collect()
+    }
+
+    /// Get overdue escrows, examining at most MAX_VIEW_SCAN escrows per call
+    /// `from_index` and `next_index` are positions in the escrow map, so keep following
+    /// `next_index` until it is None even when a page comes back short
+    pub fn get_overdue_escrows(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<EscrowEntry> {
+        let now = env::block_timestamp_ms();
+        PaginatedResult::scan(self.escrows.values(), from_index, limit, |entry| {
+            let overdue = entry.status == EscrowStatus::Active
+                && now > entry.due_date
+                && !entry.debtor_paid;
+            overdue.then(|| entry.clone())
+        })
+    }
+
+    /// Get escrow statistics
+    pub fn get_stats(&self) -> EscrowStats {
+        let mut active_count = 0u64;
+        let mut active_value = 0u128;
+        let mut settled_count = 0u64;
+        let mut disputed_count = 0u64;
+
+        for (_, entry) in self.escrows.iter() {
+            match entry.status {
+                EscrowStatus::Active => {
+                    active_count += 1;
+                    active_value += entry.sale_amount.0;
+                }
+                EscrowStatus::Released => settled_count += 1,
+                EscrowStatus::Disputed => disputed_count += 1,
+                EscrowStatus::Refunded => settled_count += 1,
+                EscrowStatus::PartiallyResolved => settled_count += 1,
+            }
+        }
+
+        EscrowStats {
+            total_escrows: self.escrow_count,
+            active_escrows: active_count,
+            total_value_locked: U128(active_value),
+            total_settled: settled_count,
+            total_disputed: disputed_count,
+        }
+    }
+
+    /// Composite health check for monitoring, read from the running dispute and overdue counts
+    pub fn health(&self) -> HealthStatus {
+        let disputed = self.disputed_escrows.len() as u64;
+        let overdue = self.overdue_escrow_count();
+
+        HealthStatus {
+            ok: !self.paused && overdue <= HEALTH_MAX_OVERDUE && disputed <= HEALTH_MAX_DISPUTED,
+            paused: self.paused,
+            admin: self.admin.clone(),
+            disputed_escrows: disputed,
+            overdue_escrows: overdue,
+            storage_usage: env::storage_usage(),
+        }
+    }
+
+    /// Check whether an account is an operator
+    pub fn is_operator(&self, account_id: AccountId) -> bool {
+        self.operators.get(&account_id).copied().unwrap_or(false)
+    }
+
+    /// Check whether the contract is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Get average time from dispute opening to resolution, in milliseconds
+    pub fn get_avg_dispute_resolution_time(&self) -> u64 {
+        if self.disputes_resolved == 0 {
+            return 0;
+        }
+        self.total_dispute_resolution_ms / self.disputes_resolved
+    }
+
+    /// Get the time after the due date before unpaid escrows can be refunded
+    pub fn get_grace_period(&self) -> u64 {
+        self.grace_period_ms
+    }
+
+    /// Get the time settlement waits after the debtor pays
+    pub fn get_dispute_window(&self) -> u64 {
+        self.dispute_window_ms
+    }
+
+    /// Get when an escrow's dispute window closes and settle may release its funds
+    /// None if the escrow is unknown or the debtor has not paid yet
+    pub fn get_settlement_eligible_at(&self, escrow_id: String) -> Option<u64> {
+        self.escrows
+            .get(&escrow_id)
+            .and_then(|entry| self.settlement_eligible_at(entry))
+    }
+
+    /// Get the latest audit log entries, newest first
+    /// Only the last AUDIT_LOG_CAPACITY actions are kept on-chain; NEP-297 logs hold the rest
+    pub fn get_recent_events(&self, limit: Option<u64>) -> Vec<AuditEntry> {
+        let limit = limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .min(self.recent_events.len() as u64);
+        (1..=limit)
+            .map(|back| {
+                let slot = ((self.audit_count - back) % AUDIT_LOG_CAPACITY as u64) as u32;
+                self.recent_events[slot].clone()
+            })
+            .collect()
+    }
+
+    /// Get the latest TVL snapshots, newest first
+    pub fn get_tvl_history(&self, limit: Option<u64>) -> Vec<TvlSnapshot> {
+        let limit = limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .min(self.tvl_history.len() as u64);
+        (1..=limit)
+            .map(|back| {
+                let slot = ((self.tvl_snapshot_count - back) % TVL_HISTORY_CAPACITY as u64) as u32;
+                self.tvl_history[slot].clone()
+            })
+            .collect()
+    }
+
+    /// Get keeper bounty configuration and totals
+    pub fn get_keeper_rewards(&self) -> KeeperRewards {
+        self.keeper_rewards.clone()
+    }
+
+    /// Get the gas attached to outgoing calls
+    pub fn get_gas_config(&self) -> GasConfig {
+        self.gas_config.clone()
+    }
+
+    /// Get how much of an escrow's invoice the debtor has paid
+    pub fn get_payment_progress(&self, escrow_id: String) -> PaymentProgress {
+        let entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found");
+        PaymentProgress {
+            invoice_amount: entry.invoice_amount,
+            amount_paid: entry.amount_paid,
+            outstanding: U128(entry.invoice_amount.0.saturating_sub(entry.amount_paid.0)),
+            amount_forwarded: entry.amount_forwarded,
+        }
+    }
+
+    /// Get lifetime USDC paid out to an account across settlements and dispute resolutions
+    pub fn get_total_received(&self, account: AccountId) -> U128 {
+        U128(self.total_received.get(&account).copied().unwrap_or(0))
+    }
+
+    /// Get escrow count
+    pub fn get_escrow_count(&self) -> u64 {
+        self.escrow_count
+    }
+
+    /// Get admin address
+    pub fn get_admin(&self) -> AccountId {
+        self.admin.clone()
+    }
+
+    /// Get contract addresses
+    pub fn get_contract_addresses(&self) -> (AccountId, AccountId, AccountId) {
+        (
+            self.invoice_contract.clone(),
+            self.marketplace_contract.clone(),
+            self.usdc_contract.clone(),
+        )
+    }
+}
+
+impl EscrowContract {
+    fn is_accepted_token(&self, token: &AccountId) -> bool {
+        self.accepted_tokens.get(token).copied().unwrap_or(false)
+    }
+
+    /// Token an escrow pays out in (escrows funded before multi-token support hold USDC)
+    fn escrow_token(&self, entry: &EscrowEntry) -> AccountId {
+        entry.token.clone().unwrap_or_else(|| self.usdc_contract.clone())
+    }
+
+    /// Fix the escrow's token on its first deposit and require later deposits to match
+    fn assert_escrow_token(entry: &mut EscrowEntry, token: &AccountId) {
+        match &entry.token {
+            Some(escrow_token) => ensure!(
+                escrow_token == token,
+                ContractError::TokenMismatch,
+                "Escrow {} is funded in {}",
+                entry.id,
+                escrow_token
+            ),
+            None => entry.token = Some(token.clone()),
+        }
+    }
+
+    /// Whether an active escrow holds both the purchase funds and the debtor's full payment,
+    /// with its dispute window closed
+    fn is_settleable(&self, entry: &EscrowEntry) -> bool {
+        entry.status == EscrowStatus::Active
+            && entry.funds_deposited
+            && entry.deposited_amount.0 >= entry.sale_amount.0
+            && entry.debtor_paid
+            && entry.amount_paid.0 >= entry.invoice_amount.0
+            && self
+                .settlement_eligible_at(entry)
+                .is_some_and(|eligible_at| env::block_timestamp_ms() >= eligible_at)
+            && (!self.strict_verification || entry.verified_against_invoice)
+    }
+
+    /// When a paid escrow's dispute window closes; None until the debtor has paid
+    /// Mutual consent to release waives the window, and escrows paid before it existed have none
+    fn settlement_eligible_at(&self, entry: &EscrowEntry) -> Option<u64> {
+        if !entry.debtor_paid {
+            return None;
+        }
+        let Some(paid_at) = entry.debtor_paid_at else {
+            return Some(0);
+        };
+        if entry.buyer_release_consent && entry.seller_release_consent {
+            return Some(paid_at);
+        }
+        Some(paid_at.saturating_add(self.dispute_window_ms))
+    }
+
+    /// Log the debtor payment and emit the debtor_payment_received event
+    fn emit_debtor_payment_received(entry: &EscrowEntry, payer: AccountId, amount: U128) {
+        env::log_str(&format!(
+            "Escrow {} paid in full, awaiting settlement",
+            entry.id
+        ));
+        AdelanteEvent::DebtorPaymentReceived(vec![DebtorPaymentReceived {
+            escrow_id: entry.id.clone(),
+            invoice_id: entry.invoice_id.clone(),
+            payer,
+            amount,
+        }])
+        .emit();
+    }
+
+    /// Look up the invoice's shareholders and tranches and pay out in on_settle_callback
+    /// Gas one settlement needs: the two invoice reads, then a callback that pays out the largest
+    /// cap table and marks the invoice settled
+    fn settlement_gas(&self) -> Gas {
+        let cross_contract = self.gas_config.cross_contract.as_gas();
+        let payouts = self.gas_config.ft_transfer.as_gas() * MAX_SETTLE_PAYOUTS;
+        Gas::from_gas(GAS_FOR_SETTLE_PAYOUT.as_gas() + 3 * cross_contract + payouts)
+    }
+
+    fn request_settlement(&self, escrow_id: String, entry: &EscrowEntry) -> Promise {
+        // Fractionally owned invoices pay out to every shareholder. The reads take no unused gas
+        // so all of it reaches the callback that makes the payouts
+        ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .with_unused_gas_weight(0)
+            .get_shareholders(entry.invoice_id.clone())
+            .and(
+                ext_invoice::ext(self.invoice_contract.clone())
+                    .with_static_gas(self.gas_config.cross_contract)
+                    .with_unused_gas_weight(0)
+                    .get_tranches(entry.invoice_id.clone()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_SETTLE_PAYOUT)
+                    .with_unused_gas_weight(1)
+                    .on_settle_callback(escrow_id),
+            )
+    }
+
+    /// Reassign an escrow to a new buyer, keeping the buyer indexes in step
+    fn move_position(&mut self, escrow_id: String, mut entry: EscrowEntry, new_buyer: AccountId) {
+        ensure!(
+            new_buyer != entry.buyer,
+            ContractError::InvalidArgument,
+            "New buyer must differ from current buyer"
+        );
+        ensure!(
+            new_buyer != entry.seller,
+            ContractError::InvalidArgument,
+            "Seller cannot hold the escrow position"
+        );
+
+        let old_buyer = entry.buyer.clone();
+        entry.buyer = new_buyer.clone();
+        // The previous buyer's consent to an early release does not bind the new one
+        entry.buyer_release_consent = false;
+        self.escrows.insert(escrow_id.clone(), entry);
+
+        // Move the escrow between buyer indexes
+        if let Some(mut old_buyer_escrows) = self.escrows_by_buyer.get(&old_buyer).cloned() {
+            old_buyer_escrows.retain(|id| id != &escrow_id);
+            self.escrows_by_buyer.insert(old_buyer.clone(), old_buyer_escrows);
+        }
+
+        let mut new_buyer_escrows = self
+            .escrows_by_buyer
+            .get(&new_buyer)
+            .cloned()
+            .unwrap_or_default();
+        new_buyer_escrows.push(escrow_id.clone());
+        self.escrows_by_buyer.insert(new_buyer.clone(), new_buyer_escrows);
+
+        env::log_str(&format!(
+            "Escrow {} position transferred from {} to {}",
+            escrow_id, old_buyer, new_buyer
+        ));
+    }
+
+    fn assert_not_paused(&self) {
+        ensure!(!self.paused, ContractError::Paused, "Contract is paused");
+    }
+
+    fn internal_open_dispute(&mut self, escrow_id: String, reason: String) {
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+
+        ensure!(
+            entry.status == EscrowStatus::Active,
+            ContractError::InvalidState,
+            "Escrow is not active"
+        );
+        ensure!(!reason.is_empty(), ContractError::InvalidArgument, "Dispute reason required");
+
+        entry.status = EscrowStatus::Disputed;
+        self.unlock_value(&entry);
+        self.disputed_escrows.insert(escrow_id.clone());
+        entry.dispute_reason = Some(reason.clone());
+        entry.dispute_opened_at = Some(env::block_timestamp_ms());
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+        self.record_audit(AuditAction::Disputed, env::predecessor_account_id(), &escrow_id);
+        // Every dispute starts with a fresh tally
+        self.dispute_votes.remove(&escrow_id);
+
+        env::log_str(&format!(
+            "Dispute opened for escrow {}: {}",
+            escrow_id, reason
+        ));
+        AdelanteEvent::DisputeOpened(vec![DisputeOpened {
+            escrow_id,
+            invoice_id: entry.invoice_id,
+            opened_by: env::predecessor_account_id(),
+            reason,
+        }])
+        .emit();
+    }
+
+    /// Pay the escrowed sale amount to the dispute winner
+    /// Debtor payment still held in escrow goes to the buyer either way, as on settlement or a
+    /// timeout refund
+    fn internal_resolve_dispute(&mut self, escrow_id: String, winner: AccountId) -> Promise {
+        let mut entry = self
+            .escrows
+            .get(&escrow_id)
+            .or_fail(ContractError::EscrowNotFound, "Escrow not found")
+            .clone();
+        ensure!(
+            entry.status == EscrowStatus::Disputed,
+            ContractError::InvalidState,
+            "Escrow is not disputed"
+        );
+        ensure!(
+            winner == entry.buyer || winner == entry.seller,
+            ContractError::InvalidArgument,
+            "Winner must be buyer or seller"
+        );
+
+        // Verify funds were deposited
+        ensure!(
+            entry.funds_deposited,
+            ContractError::InvalidState,
+            "No funds deposited in escrow"
+        );
+
+        let invoice_id = entry.invoice_id.clone();
+        let seller = entry.seller.clone();
+        let buyer = entry.buyer.clone();
+        let buyer_wins = winner == buyer;
+        let held = entry.amount_paid.0 - entry.amount_forwarded.0;
+        let token = self.escrow_token(&entry);
+
+        if buyer_wins {
+            // Refund buyer - they get their deposit back
+            entry.status = EscrowStatus::Refunded;
+            env::log_str(&format!(
+                "Dispute resolved: {} {} refunded to buyer {}",
+                entry.deposited_amount.0, token, buyer
+            ));
+        } else {
+            // Release to seller - buyer's payment goes to seller
+            entry.status = EscrowStatus::Released;
+            env::log_str(&format!(
+                "Dispute resolved: {} {} released to seller {}",
+                entry.deposited_amount.0, token, seller
+            ));
+        }
+
+        entry.settled_at = Some(env::block_timestamp_ms());
+        self.escrows.insert(escrow_id.clone(), entry.clone());
+
+        // Transfer the deposit to the winner (the seller's payout account if it has one)
+        self.record_dispute_resolution(&entry, if buyer_wins { &buyer } else { &seller });
+        let shares = if buyer_wins {
+            vec![(buyer.clone(), entry.deposited_amount.0 + held)]
+        } else {
+            vec![(entry.payout_recipient(), entry.deposited_amount.0), (buyer.clone(), held)]
+        };
+
+        let mut transfers: Option<Promise> = None;
+        for (recipient, amount) in shares {
+            if amount == 0 {
+                continue;
+            }
+            self.record_payout(&recipient, amount);
+            let transfer = ext_ft::ext(self.escrow_token(&entry))
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(
+                    recipient,
+                    U128(amount),
+                    Some(format!("dispute_resolution:{}", escrow_id)),
+                );
+            transfers = Some(match transfers {
+                Some(previous) => previous.and(transfer),
+                None => transfer,
+            });
+        }
+
+        transfers
+            .or_fail(ContractError::InvalidState, "No dispute payouts")
+            .then(
+                // Update invoice status based on resolution
+                if !buyer_wins {
+                    ext_invoice::ext(self.invoice_contract.clone())
+                        .with_static_gas(self.gas_config.cross_contract)
+                        .mark_settled(invoice_id)
+                } else {
+                    // If buyer wins, create a no-op promise
+                    Promise::new(env::current_account_id())
+                }
+            )
+    }
+
+    /// Votes needed for a majority of registered arbiters
+    fn dispute_quorum(&self) -> u32 {
+        self.arbiters.len() / 2 + 1
+    }
+
+    /// Milliseconds left to vote on a dispute, None when voting has no deadline
+    fn voting_time_remaining(&self, entry: &EscrowEntry) -> Option<u64> {
+        if self.dispute_voting_period_ms == 0 {
+            return None;
+        }
+        let deadline = entry.dispute_opened_at?.saturating_add(self.dispute_voting_period_ms);
+        Some(deadline.saturating_sub(env::block_timestamp_ms()))
+    }
+
+    /// Pay the keeper bounty to the caller of a maintenance action, if available
+    fn reward_keeper(&mut self) {
+        self.keeper_rewards.reward(env::predecessor_account_id());
+    }
+
+    /// Record a new active escrow and index it by invoice, buyer and seller
+    #[allow(clippy::too_many_arguments)]
+    fn internal_create_escrow(
+        &mut self,
+        invoice_id: String,
+        seller: AccountId,
+        buyer: AccountId,
+        sale_amount: U128,
+        invoice_amount: U128,
+        due_date: u64,
+        payout_account: Option<AccountId>,
+    ) -> String {
+        self.escrow_count += 1;
+        let id = format!("ESC-{:06}", self.escrow_count);
+
+        let entry = EscrowEntry {
+            id: id.clone(),
+            invoice_id: invoice_id.clone(),
+            seller: seller.clone(),
+            buyer: buyer.clone(),
+            sale_amount,
+            invoice_amount,
+            created_at: env::block_timestamp_ms(),
+            due_date,
+            status: EscrowStatus::Active,
+            settled_at: None,
+            dispute_reason: None,
+            funds_deposited: false, // Will be set to true when USDC arrives via ft_on_transfer
+            deposited_amount: U128(0),
+            debtor_paid: false, // Will be set to true when the debtor pays via ft_on_transfer
+            amount_paid: U128(0),
+            amount_forwarded: U128(0),
+            dispute_opened_at: None,
+            verified_against_invoice: false,
+            token: None,
+            buyer_release_consent: false,
+            seller_release_consent: false,
+            debtor_paid_at: None,
+            payout_account,
+        };
+
+        self.escrows.insert(id.clone(), entry);
+        self.escrows_by_invoice.insert(invoice_id.clone(), id.clone());
+        self.active_value_locked += sale_amount.0;
+        self.roll_overdue();
+        *self.active_due_days.entry(due_date / DAY_MS).or_insert(0) += 1;
+        if due_date / DAY_MS < self.overdue_day {
+            self.overdue_count += 1;
+        }
+
+        let mut history = self
+            .escrow_history_by_invoice
+            .get(&invoice_id)
+            .cloned()
+            .unwrap_or_default();
+        history.push(id.clone());
+        self.escrow_history_by_invoice.insert(invoice_id.clone(), history);
+
+        // Update buyer index
+        let mut buyer_escrows = self
+            .escrows_by_buyer
+            .get(&buyer)
+            .cloned()
+            .unwrap_or_default();
+        buyer_escrows.push(id.clone());
+        self.escrows_by_buyer.insert(buyer.clone(), buyer_escrows);
+
+        // Update seller index
+        let mut seller_escrows = self
+            .escrows_by_seller
+            .get(&seller)
+            .cloned()
+            .unwrap_or_default();
+        seller_escrows.push(id.clone());
+        self.escrows_by_seller.insert(seller.clone(), seller_escrows);
+
+        env::log_str(&format!("Escrow {} created", id));
+        self.record_audit(AuditAction::Created, env::predecessor_account_id(), &id);
+        AdelanteEvent::EscrowCreated(vec![EscrowCreated {
+            escrow_id: id.clone(),
+            invoice_id,
+            seller,
+            buyer,
+            sale_amount,
+        }])
+        .emit();
+        id
+    }
+
+    /// Split an amount by share count; the rounding remainder goes to the first holder
+    fn pro_rata(amount: u128, holders: &[ShareHolding]) -> Vec<(AccountId, u128)> {
+        let total_shares: u128 = holders.iter().map(|h| h.shares as u128).sum();
+        let mut payouts: Vec<(AccountId, u128)> = holders
+            .iter()
+            .map(|h| (h.account_id.clone(), amount * h.shares as u128 / total_shares))
+            .collect();
+        let distributed: u128 = payouts.iter().map(|(_, paid)| paid).sum();
+        payouts[0].1 += amount - distributed;
+        payouts
+    }
+
+    /// Pay `amount` down the tranches, filling each senior tranche before the next
+    /// `already_paid` (installments forwarded earlier) has used up the most senior capacity;
+    /// anything beyond the last tranche's face value goes to its junior holder
+    fn waterfall(amount: u128, already_paid: u128, tranches: &[Tranche]) -> Vec<(AccountId, u128)> {
+        let mut covered = already_paid;
+        let mut remaining = amount;
+        let mut payouts: Vec<(AccountId, u128)> = tranches
+            .iter()
+            .map(|tranche| {
+                let capacity = tranche.amount.0.saturating_sub(covered);
+                covered = covered.saturating_sub(tranche.amount.0);
+                let paid = remaining.min(capacity);
+                remaining -= paid;
+                (tranche.holder.clone(), paid)
+            })
+            .collect();
+        if let Some(junior) = payouts.last_mut() {
+            junior.1 += remaining;
+        }
+        payouts
+    }
+
+    /// Add a payout to the recipient's lifetime total
+    fn record_payout(&mut self, recipient: &AccountId, amount: u128) {
+        let total = self.total_received.get(recipient).copied().unwrap_or(0);
+        self.total_received
+            .insert(recipient.clone(), total.saturating_add(amount));
+    }
+
+    /// Append to the audit log, overwriting the oldest entry once it is full
+    fn record_audit(&mut self, action: AuditAction, actor: AccountId, escrow_id: &str) {
+        let entry = AuditEntry {
+            timestamp: env::block_timestamp_ms(),
+            actor,
+            action,
+            escrow_id: escrow_id.to_string(),
+        };
+        let slot = (self.audit_count % AUDIT_LOG_CAPACITY as u64) as u32;
+        if slot < self.recent_events.len() {
+            self.recent_events.replace(slot, entry);
+        } else {
+            self.recent_events.push(entry);
+        }
+        self.audit_count += 1;
+    }
+
+    /// Drop an escrow that just left the Active state from the running TVL and overdue count
+    fn unlock_value(&mut self, entry: &EscrowEntry) {
+        self.active_value_locked = self.active_value_locked.saturating_sub(entry.sale_amount.0);
+
+        self.roll_overdue();
+        let due_day = entry.due_date / DAY_MS;
+        if let Some(count) = self.active_due_days.get_mut(&due_day) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.active_due_days.remove(&due_day);
+            }
+        }
+        if due_day < self.overdue_day {
+            self.overdue_count = self.overdue_count.saturating_sub(1);
+        }
+    }
+
+    /// Active escrows due before today: the running count plus the days not yet folded into it
+    /// Reads one bucket per day since the last status change
+    fn overdue_escrow_count(&self) -> u64 {
+        let today = env::block_timestamp_ms() / DAY_MS;
+        (self.overdue_day..today).fold(self.overdue_count, |count, day| {
+            count + self.active_due_days.get(&day).copied().unwrap_or(0)
+        })
+    }
+
+    /// Fold the days that ended since the last status change into overdue_count
+    fn roll_overdue(&mut self) {
+        self.overdue_count = self.overdue_escrow_count();
+        self.overdue_day = self.overdue_day.max(env::block_timestamp_ms() / DAY_MS);
+    }
+
+    /// Track dispute resolution latency and emit a dispute_resolved event
+    fn record_dispute_resolution(&mut self, entry: &EscrowEntry, winner: &AccountId) {
+        let now = env::block_timestamp_ms();
+        let latency = entry
+            .dispute_opened_at
+            .map(|opened_at| now.saturating_sub(opened_at))
+            .unwrap_or(0);
+
+        self.disputes_resolved += 1;
+        self.total_dispute_resolution_ms += latency;
+        self.disputed_escrows.remove(&entry.id);
+        self.record_audit(AuditAction::Resolved, env::predecessor_account_id(), &entry.id);
+
+        env::log_str(&format!(
+            "DisputeResolved: escrow {} resolved in {} ms",
+            entry.id, latency
+        ));
+        AdelanteEvent::DisputeResolved(vec![DisputeResolved {
+            escrow_id: entry.id.clone(),
+            invoice_id: entry.invoice_id.clone(),
+            winner: winner.clone(),
+            resolution_ms: latency,
+        }])
+        .emit();
+    }
+
+    /// Drop up to `limit` ids of terminal escrows from an index vector
+    fn prune_terminal_ids(&self, ids: &mut Vec<String>, limit: u64) -> u64 {
+        let mut pruned = 0u64;
+        ids.retain(|id| {
+            let terminal = self
+                .escrows
+                .get(id)
+                .map(|entry| entry.status.is_terminal())
+                .unwrap_or(false);
+            if terminal && pruned < limit {
+                pruned += 1;
+                false
+            } else {
+                true
+            }
+        });
+        pruned
+    }
+}
+
+/// Numeric sequence of a generated id such as "INV-000042" (0 if unparseable)
+fn id_sequence(id: &str) -> u64 {
+    id.rsplit('-')
+        .next()
+        .and_then(|suffix| suffix.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use events::MAX_VIEW_SCAN;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn get_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(predecessor);
+        builder
+    }
+
+    /// Pay an escrow's invoice in full from the debtor through the USDC contract
+    fn pay_debtor(contract: &mut EscrowContract, escrow_id: &str) {
+        let entry = contract.get_escrow(escrow_id.to_string()).unwrap();
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "debtor.testnet".parse().unwrap(),
+            entry.invoice_amount,
+            format!("debtor_payment:{}", entry.invoice_id),
+        );
+    }
+
+    #[test]
+    fn test_init() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+
+        let context = get_context(admin.clone());
+        testing_env!(context.build());
+
+        let contract = EscrowContract::new(invoice, marketplace, usdc, admin.clone());
+
+        assert_eq!(contract.get_escrow_count(), 0);
+        assert_eq!(contract.get_admin(), admin);
+    }
+
+    #[test]
+    fn test_create_escrow() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        let context = get_context(marketplace.clone());
+        testing_env!(context.build());
+
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(1_850_000_000), // $1,850
+            U128(2_000_000_000), // $2,000
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        assert_eq!(escrow_id, "ESC-000001");
+
+        let escrow = contract.get_escrow(escrow_id).unwrap();
+        assert_eq!(escrow.seller, seller);
+        assert_eq!(escrow.buyer, buyer);
+        assert_eq!(escrow.status, EscrowStatus::Active);
+        assert!(!escrow.funds_deposited); // Funds not deposited yet
+    }
+
+    #[test]
+    fn test_create_escrow_retry_returns_existing_id() {
+        let (mut contract, escrow_id) = funded_escrow();
+
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        let retried = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        assert_eq!(retried, escrow_id);
+        assert_eq!(contract.get_escrow_count(), 1);
+        // The retry leaves the deposited escrow untouched
+        assert!(contract.get_escrow(escrow_id).unwrap().funds_deposited);
+    }
+
+    #[test]
+    fn test_create_escrow_after_terminal_escrow_creates_new_one() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let refundable_at = due_date + contract.get_grace_period();
+
+        testing_env!(get_context("buyer.testnet".parse().unwrap())
+            .block_timestamp((refundable_at + 1) * 1_000_000)
+            .build());
+        let _ = contract.claim_timeout_refund(escrow_id.clone());
+
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        let created = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+        );
+
+        assert_eq!(created, "ESC-000002");
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Refunded);
+        let escrow = contract.get_escrow(created).unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Active);
+        assert!(!escrow.funds_deposited);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow already exists for this invoice with different terms")]
+    fn test_create_escrow_retry_with_different_terms_panics() {
+        let (mut contract, _) = funded_escrow();
+
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "other-buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_disputes_pending_vote_excludes_voted() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let arbiter: AccountId = "arbiter.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin.clone());
+
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let first = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+        );
+        let second = contract.create_escrow(
+            "INV-000002".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(900_000_000),
+            U128(1_000_000_000),
+            due_date,
+            None,
+        );
+
+        testing_env!(get_context(admin).build());
+        contract.add_arbiter(arbiter.clone());
+
+        testing_env!(get_context(buyer.clone()).build());
+        contract.open_dispute(first.clone(), "Debtor disputes delivery".to_string());
+        contract.open_dispute(second.clone(), "Wrong invoice amount".to_string());
+
+        testing_env!(get_context(arbiter.clone()).build());
+        let pending = contract.get_disputes_pending_vote(arbiter.clone(), None, None);
+        assert_eq!(pending.items.len(), 2);
+        assert_eq!(pending.next_index, None);
+
+        contract.vote_dispute(first, buyer);
+
+        let pending = contract.get_disputes_pending_vote(arbiter.clone(), None, None);
+        assert_eq!(pending.items.len(), 1);
+        assert_eq!(pending.items[0].id, second);
+
+        // Positions are in the disputed index, so the voted dispute still takes up a slot
+        let page = contract.get_disputes_pending_vote(arbiter, Some(1), Some(1));
+        assert_eq!(page.items[0].id, second);
+        assert_eq!(page.next_index, None);
+    }
+
+    #[test]
+    fn test_prune_settled_from_buyer_index() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let settled = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+        );
+        let active = contract.create_escrow(
+            "INV-000002".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(900_000_000),
+            U128(1_000_000_000),
+            due_date,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        pay_debtor(&mut contract, &settled);
+        testing_env!(get_context(admin).build());
+        let _ = contract.settle(settled.clone());
+        let _ = contract.on_settle_callback(
+            settled.clone(),
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+
+        let pruned = contract.prune_settled_from_indexes(buyer.clone(), 10);
+        assert_eq!(pruned, 1);
+
+        let buyer_escrows = contract.get_escrows_by_buyer(buyer, None, None).items;
+        assert_eq!(buyer_escrows.len(), 1);
+        assert_eq!(buyer_escrows[0].id, active);
+        assert_eq!(contract.get_escrows_by_seller(seller, None, None).items.len(), 2);
+
+        let entry = contract.get_escrow(settled).unwrap();
+        assert_eq!(entry.status, EscrowStatus::Released);
+    }
+
+    #[test]
+    fn test_health_reports_overdue_escrows() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc, admin);
+
+        let due_date = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        for i in 0..=HEALTH_MAX_OVERDUE {
+            contract.create_escrow(
+                format!("INV-{:06}", i + 1),
+                seller.clone(),
+                buyer.clone(),
+                U128(900_000_000),
+                U128(1_000_000_000),
+                due_date,
+                None,
+            );
+        }
+        assert!(contract.health().ok);
+
+        // Move past the due day so every escrow is overdue
+        let mut context = get_context(marketplace);
+        context.block_timestamp((due_date + DAY_MS) * 1_000_000);
+        testing_env!(context.build());
+
+        let health = contract.health();
+        assert!(!health.ok);
+        assert!(!health.paused);
+        assert_eq!(health.overdue_escrows, HEALTH_MAX_OVERDUE + 1);
+
+        // Disputing an overdue escrow moves it from one running count to the other
+        contract.mark_overdue("ESC-000001".to_string());
+        let health = contract.health();
+        assert_eq!(health.overdue_escrows, HEALTH_MAX_OVERDUE);
+        assert_eq!(health.disputed_escrows, 1);
+        assert!(health.ok);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can update contracts")]
+    fn test_operator_can_pause_but_not_govern() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let operator: AccountId = "operator.testnet".parse().unwrap();
+
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc, admin);
+        contract.add_operator(operator.clone());
+
+        testing_env!(get_context(operator).build());
+        contract.set_paused(true);
+        assert!(contract.is_paused());
+        assert_eq!(contract.recompute_counters(), 0);
+
+        contract.set_contract_addresses(None, Some(marketplace), None);
+    }
+
+    #[test]
+    fn test_dispute_resolution_latency() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller,
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        let opened_at = 1_000_000u64;
+        let mut context = get_context(buyer.clone());
+        context.block_timestamp(opened_at * 1_000_000);
+        testing_env!(context.build());
+        contract.open_dispute(escrow_id.clone(), "Goods not delivered".to_string());
+        assert_eq!(
+            contract.get_escrow(escrow_id.clone()).unwrap().dispute_opened_at,
+            Some(opened_at)
+        );
+
+        let latency = 2 * 60 * 60 * 1000;
+        let mut context = get_context(admin);
+        context.block_timestamp((opened_at + latency) * 1_000_000);
+        testing_env!(context.build());
+        let _ = contract.resolve_dispute(escrow_id, buyer);
+
+        assert_eq!(contract.get_avg_dispute_resolution_time(), latency);
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains(&format!("resolved in {} ms", latency))));
+    }
+
+    #[test]
+    fn test_account_overview_includes_escrows_and_listings() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer,
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        let listings = vec![
+            ListingSummary {
+                id: "LST-000002".to_string(),
+                invoice_id: "INV-000002".to_string(),
+                asking_price: U128(900_000_000),
+                active: true,
+            },
+            ListingSummary {
+                id: "LST-000001".to_string(),
+                invoice_id: "INV-000001".to_string(),
+                asking_price: U128(1_850_000_000),
+                active: false,
+            },
+        ];
+
+        let overview = contract.on_account_overview(
+            seller.clone(),
+            Ok(PaginatedResult { items: vec![], next_index: None }),
+            Ok(PaginatedResult { items: listings, next_index: None }),
+            Err(PromiseError::Failed),
+        );
+
+        assert_eq!(overview.account, seller);
+        assert_eq!(overview.active_escrows.len(), 1);
+        assert_eq!(overview.active_escrows[0].id, escrow_id);
+        assert_eq!(overview.active_listings.len(), 1);
+        assert_eq!(overview.active_listings[0].id, "LST-000002");
+        assert!(overview.listings_available);
+        assert!(!overview.bids_available);
+        assert!(overview.active_bids.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_beneficiary_settles_to_new_holder() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let new_buyer: AccountId = "carol.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller,
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        testing_env!(get_context(buyer.clone()).build());
+        contract.transfer_escrow_beneficiary(escrow_id.clone(), new_buyer.clone());
+
+        assert!(contract.get_escrows_by_buyer(buyer, None, None).items.is_empty());
+        assert_eq!(contract.get_escrows_by_buyer(new_buyer.clone(), None, None).items.len(), 1);
+
+        pay_debtor(&mut contract, &escrow_id);
+        testing_env!(get_context(admin).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id.clone(),
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+
+        let entry = contract.get_escrow(escrow_id).unwrap();
+        assert_eq!(entry.buyer, new_buyer);
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains(&format!("released to buyer {}", new_buyer))));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Debtor payment already received")]
+    fn test_mark_overdue_rejects_paid_escrow() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_dispute_window(60 * 24 * 60 * 60 * 1000);
+        pay_debtor(&mut contract, &escrow_id);
+
+        // Past the due date but still inside the dispute window
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        testing_env!(context.block_timestamp((due_date + 1) * 1_000_000).build());
+        assert!(contract.get_overdue_escrows(None, None).items.is_empty());
+        contract.mark_overdue(escrow_id);
+    }
+
+    #[test]
+    fn test_mark_overdue_by_keeper_pays_bounty() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let keeper: AccountId = "keeper.testnet".parse().unwrap();
+
+        let mut context = get_context(admin.clone());
+        context.attached_deposit(NearToken::from_millinear(100));
+        testing_env!(context.build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc, admin.clone());
+        contract.set_keeper_bounty(NearToken::from_millinear(10), NearToken::from_millinear(10));
+        contract.fund_keeper_pool();
+
+        testing_env!(get_context(marketplace).build());
+        let due_date = env::block_timestamp_ms() + 1000;
+        for invoice_id in ["INV-000001", "INV-000002"] {
+            contract.create_escrow(
+                invoice_id.to_string(),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(900_000_000),
+                U128(1_000_000_000),
+                due_date,
+                None,
+            );
+        }
+
+        let mut context = get_context(keeper);
+        context.block_timestamp((due_date + 1) * 1_000_000);
+        testing_env!(context.build());
+        contract.mark_overdue("ESC-000001".to_string());
+        contract.mark_overdue("ESC-000002".to_string());
+
+        assert_eq!(
+            contract.get_escrow("ESC-000001".to_string()).unwrap().status,
+            EscrowStatus::Disputed
+        );
+        // Cap allows a single bounty
+        let rewards = contract.get_keeper_rewards();
+        assert_eq!(rewards.total_paid, NearToken::from_millinear(10));
+        assert_eq!(rewards.pool, NearToken::from_millinear(90));
+    }
+
+    #[test]
+    fn test_dispute_details_tally_and_quorum() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let arbiters: Vec<AccountId> = ["arb1.testnet", "arb2.testnet", "arb3.testnet"]
+            .iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin.clone());
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(admin).build());
+        for arbiter in &arbiters {
+            contract.add_arbiter(arbiter.clone());
+        }
+        contract.set_dispute_voting_period(24 * 60 * 60 * 1000);
+
+        testing_env!(get_context(buyer.clone()).build());
+        contract.open_dispute(escrow_id.clone(), "Goods not delivered".to_string());
+        contract.submit_dispute_evidence(escrow_id.clone(), "QmEvidence".to_string());
+
+        testing_env!(get_context(arbiters[0].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), buyer.clone());
+        testing_env!(get_context(arbiters[1].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), seller);
+
+        let details = contract.get_dispute_details(escrow_id);
+        assert_eq!(details.reason, Some("Goods not delivered".to_string()));
+        assert_eq!(details.evidence.len(), 1);
+        assert_eq!(details.votes.len(), 2);
+        assert_eq!(details.buyer_votes, 1);
+        assert_eq!(details.seller_votes, 1);
+        assert_eq!(details.quorum, 2);
+        assert_eq!(details.time_remaining_ms, Some(24 * 60 * 60 * 1000));
+    }
+
+    #[test]
+    fn test_total_received_accumulates_settlements() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+
+        let mut escrow_ids = Vec::new();
+        for (invoice_id, sale_amount) in [("INV-000001", 1_850_000_000), ("INV-000002", 900_000_000)] {
+            testing_env!(get_context(marketplace.clone()).build());
+            escrow_ids.push(contract.create_escrow(
+                invoice_id.to_string(),
+                seller.clone(),
+                buyer.clone(),
+                U128(sale_amount),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+            ));
+
+            testing_env!(get_context(usdc.clone()).build());
+            let _ = contract.ft_on_transfer(
+                marketplace.clone(),
+                U128(sale_amount),
+                format!("escrow_deposit:{}", invoice_id),
+            );
+        }
+
+        for escrow_id in escrow_ids {
+            pay_debtor(&mut contract, &escrow_id);
+            testing_env!(get_context(admin.clone()).build());
+            let _ = contract.settle(escrow_id.clone());
+            let _ = contract.on_settle_callback(
+                escrow_id,
+                Ok(vec![ShareHolding { account_id: buyer.clone(), shares: 1 }]),
+                Err(PromiseError::Failed),
+            );
+        }
+
+        // The buyer collects both invoice amounts; the seller the purchase prices
+        assert_eq!(contract.get_total_received(buyer).0, 4_000_000_000);
+        assert_eq!(contract.get_total_received(seller).0, 2_750_000_000);
+    }
+
+    #[test]
+    fn test_verify_against_invoice_detects_mismatch() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+        );
+
+        // Invoice was amended to a lower amount after the escrow was created
+        let amended = InvoiceSummary {
+            id: "INV-000001".to_string(),
+            amount: U128(1_500_000_000),
+            due_date,
+            status: "Sold".to_string(),
+        };
+        assert!(!contract.on_verify_callback(escrow_id.clone(), Ok(Some(amended.clone()))));
+        assert!(!contract.get_escrow(escrow_id.clone()).unwrap().verified_against_invoice);
+
+        let matching = InvoiceSummary {
+            amount: U128(2_000_000_000),
+            ..amended
+        };
+        assert!(contract.on_verify_callback(escrow_id.clone(), Ok(Some(matching))));
+        assert!(contract.get_escrow(escrow_id).unwrap().verified_against_invoice);
+    }
+
+    #[test]
+    fn test_create_escrow_from_template_inherits_terms() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let new_buyer: AccountId = "carol.testnet".parse().unwrap();
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+        let first_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace.clone(),
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        // Unwind the first sale back to the buyer
+        testing_env!(get_context(buyer.clone()).build());
+        contract.open_dispute(first_id.clone(), "Invoice disputed by debtor".to_string());
+        testing_env!(get_context(admin).build());
+        let _ = contract.resolve_dispute(first_id, buyer);
+
+        testing_env!(get_context(marketplace).build());
+        let second_id = contract.create_escrow_from_template(
+            "INV-000001".to_string(),
+            new_buyer.clone(),
+            U128(1_800_000_000),
+        );
+
+        let entry = contract.get_escrow(second_id.clone()).unwrap();
+        assert_eq!(entry.invoice_amount.0, 2_000_000_000);
+        assert_eq!(entry.due_date, due_date);
+        assert_eq!(entry.seller, seller);
+        assert_eq!(entry.buyer, new_buyer);
+        assert_eq!(entry.sale_amount.0, 1_800_000_000);
+        assert_eq!(
+            contract.get_escrow_by_invoice("INV-000001".to_string()).unwrap().id,
+            second_id
+        );
+    }
+
+    #[test]
+    fn test_escrow_and_dispute_events() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            buyer.clone(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+        assert!(near_sdk::test_utils::get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"escrow_created","data":[{"escrow_id":"ESC-000001","invoice_id":"INV-000001","seller":"seller.testnet","buyer":"buyer.testnet","sale_amount":"1850000000"}]}"#.to_string()
+        ));
+
+        testing_env!(get_context(buyer).build());
+        contract.open_dispute(escrow_id, "Goods not delivered".to_string());
+        assert!(near_sdk::test_utils::get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"dispute_opened","data":[{"escrow_id":"ESC-000001","invoice_id":"INV-000001","opened_by":"buyer.testnet","reason":"Goods not delivered"}]}"#.to_string()
+        ));
+    }
+
+    #[test]
+    fn test_buyer_view_paginates() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+
+        for i in 0..3 {
+            contract.create_escrow(
+                format!("INV-{:06}", i + 1),
+                seller.clone(),
+                buyer.clone(),
+                U128(900_000_000),
+                U128(1_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+            );
+        }
+
+        let first = contract.get_escrows_by_buyer(buyer.clone(), None, Some(2));
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.next_index, Some(2));
+
+        let last = contract.get_escrows_by_buyer(buyer.clone(), first.next_index, Some(2));
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.items[0].id, "ESC-000003");
+        assert_eq!(last.next_index, None);
+    }
+
+    #[test]
+    fn test_settle_splits_payout_between_shareholders() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let investor: AccountId = "investor.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller.clone(),
+            buyer.clone(),
+            U128(900_000_000),
+            U128(1_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(900_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        pay_debtor(&mut contract, &escrow_id);
+        testing_env!(get_context(admin).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id,
+            Ok(vec![
+                ShareHolding { account_id: buyer.clone(), shares: 2 },
+                ShareHolding { account_id: investor.clone(), shares: 1 },
+            ]),
+            Err(PromiseError::Failed),
+        );
+
+        // 1/3 of the invoice amount rounds down; the remainder goes to the first holder
+        assert_eq!(contract.get_total_received(buyer).0, 666_666_667);
+        assert_eq!(contract.get_total_received(investor).0, 333_333_333);
+        assert_eq!(contract.get_total_received(seller).0, 900_000_000);
+    }
+
+    #[test]
+    fn test_debtor_payment_refunds_excess_and_unlocks_settlement() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin);
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            seller,
+            buyer,
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let excess = contract.ft_on_transfer(
+            "debtor.testnet".parse().unwrap(),
+            U128(2_100_000_000),
+            "debtor_payment:INV-000001".to_string(),
+        );
+        assert!(matches!(excess, PromiseOrValue::Value(U128(100_000_000))));
+
+        let entry = contract.get_escrow(escrow_id).unwrap();
+        assert!(entry.debtor_paid);
+        assert_eq!(entry.amount_paid.0, 2_000_000_000);
+    }
+
+    #[test]
+    fn test_settle_due_batches_ready_escrows() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let keeper: AccountId = "keeper.testnet".parse().unwrap();
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin);
+
+        // Seven ready escrows, one the debtor has not paid and one not yet due
+        let mut escrow_ids = Vec::new();
+        for i in 1..=9 {
+            let invoice_id = format!("INV-{:06}", i);
+            testing_env!(get_context(marketplace.clone()).build());
+            escrow_ids.push(contract.create_escrow(
+                invoice_id.clone(),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                if i == 9 { due_date * 2 } else { due_date },
+                None,
+            ));
+
+            testing_env!(get_context(usdc.clone()).build());
+            let _ = contract.ft_on_transfer(
+                marketplace.clone(),
+                U128(1_850_000_000),
+                format!("escrow_deposit:{}", invoice_id),
+            );
+            if i != 8 {
+                pay_debtor(&mut contract, &escrow_ids[i - 1]);
+            }
+        }
+
+        // At the 300 Tgas transaction limit the default gas config covers one settlement per call
+        let mut context = get_context(keeper.clone());
+        context
+            .block_timestamp((due_date + 1) * 1_000_000)
+            .prepaid_gas(Gas::from_tgas(300));
+        for (settling, remaining) in (0..7u64).rev().enumerate() {
+            testing_env!(context.build());
+            let result = contract.settle_due(None, None);
+            assert_eq!(result.settled, 1);
+            assert_eq!(result.remaining, remaining);
+
+            testing_env!(context.build());
+            let _ = contract.on_settle_callback(
+                escrow_ids[settling].clone(),
+                Err(PromiseError::Failed),
+                Err(PromiseError::Failed),
+            );
+        }
+
+        let statuses: Vec<EscrowStatus> = escrow_ids
+            .into_iter()
+            .map(|id| contract.get_escrow(id).unwrap().status)
+            .collect();
+        assert!(statuses[..7].iter().all(|status| *status == EscrowStatus::Released));
+        assert_eq!(statuses[7], EscrowStatus::Active);
+        assert_eq!(statuses[8], EscrowStatus::Active);
+        assert_eq!(contract.settle_due(None, None).settled, 0);
+    }
+
+    #[test]
+    fn test_settle_due_leaves_each_callback_its_payout_gas() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = EscrowContract::new(
+            "invoice.testnet".parse().unwrap(),
+            marketplace.clone(),
+            usdc.clone(),
+            admin,
+        );
+        let ft_transfer = Gas::from_tgas(5);
+        contract.set_gas_config(Gas::from_tgas(5), Gas::from_tgas(5), ft_transfer);
+        for i in 1..=3 {
+            let invoice_id = format!("INV-{:06}", i);
+            testing_env!(get_context(marketplace.clone()).build());
+            let escrow_id = contract.create_escrow(
+                invoice_id.clone(),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                due_date,
+                None,
+            );
+            testing_env!(get_context(usdc.clone()).build());
+            let _ = contract.ft_on_transfer(
+                marketplace.clone(),
+                U128(1_850_000_000),
+                format!("escrow_deposit:{}", invoice_id),
+            );
+            pay_debtor(&mut contract, &escrow_id);
+        }
+
+        let prepaid = Gas::from_tgas(300);
+        testing_env!(get_context("keeper.testnet".parse().unwrap())
+            .block_timestamp((due_date + 1) * 1_000_000)
+            .prepaid_gas(prepaid)
+            .build());
+        let result = contract.settle_due(None, None);
+        assert_eq!(result.settled, 2);
+        assert_eq!(result.remaining, 1);
+
+        // Used gas includes the static gas attached to the receipts; what is left over is shared
+        // by weight, and every callback can still pay 11 holders and mark the invoice settled
+        let calls: Vec<(String, Gas, u64)> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::FunctionCallWeight {
+                    method_name, prepaid_gas, gas_weight, ..
+                } => Some((String::from_utf8(method_name).unwrap(), prepaid_gas, gas_weight.0)),
+                _ => None,
+            })
+            .collect();
+        let attached: u64 = calls.iter().map(|(_, gas, _)| gas.as_gas()).sum();
+        assert!(attached <= env::used_gas().as_gas());
+        let weights: u64 = calls.iter().map(|(_, _, weight)| weight).sum();
+        let unused = prepaid.as_gas() - env::used_gas().as_gas();
+        let callbacks: Vec<_> =
+            calls.iter().filter(|(method, _, _)| method == "on_settle_callback").collect();
+        assert_eq!(callbacks.len(), 2);
+        let (_, callback_static, callback_weight) = callbacks[0];
+        let callback_gas = callback_static.as_gas() + unused / weights * callback_weight;
+        let needed = GAS_FOR_SETTLE_PAYOUT.as_gas()
+            + ft_transfer.as_gas() * MAX_SETTLE_PAYOUTS
+            + Gas::from_tgas(5).as_gas();
+        assert!(callback_gas >= needed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Debtor payment has not been received")]
+    fn test_settle_requires_debtor_funds() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        testing_env!(get_context(admin).build());
+        let _ = contract.settle(escrow_id);
+    }
+
+    #[test]
+    fn test_settlement_pays_out_in_deposited_token() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let usdt: AccountId = "usdt.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+        contract.add_accepted_token(usdt.clone());
+
+        for (token, invoice_id) in [(&usdc, "INV-000001"), (&usdt, "INV-000002")] {
+            testing_env!(get_context(marketplace.clone()).build());
+            let escrow_id = contract.create_escrow(
+                invoice_id.to_string(),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+            );
+
+            testing_env!(get_context(token.clone()).build());
+            let _ = contract.ft_on_transfer(
+                marketplace.clone(),
+                U128(1_850_000_000),
+                format!("escrow_deposit:{}", invoice_id),
+            );
+            let _ = contract.ft_on_transfer(
+                "debtor.testnet".parse().unwrap(),
+                U128(2_000_000_000),
+                format!("debtor_payment:{}", invoice_id),
+            );
+            assert_eq!(contract.get_escrow(escrow_id.clone()).unwrap().token, Some(token.clone()));
+
+            testing_env!(get_context(admin.clone()).build());
+            let _ = contract.on_settle_callback(
+                escrow_id,
+                Err(PromiseError::Failed),
+                Err(PromiseError::Failed),
+            );
+
+            // Buyer and seller are both paid through the escrow's token contract
+            let token_receipts: Vec<AccountId> = near_sdk::test_utils::get_created_receipts()
+                .into_iter()
+                .filter(|receipt| receipt.receiver_id == usdc || receipt.receiver_id == usdt)
+                .map(|receipt| receipt.receiver_id)
+                .collect();
+            assert_eq!(token_receipts, vec![token.clone(), token.clone()]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow ESC-000001 is funded in usdc.testnet")]
+    fn test_debtor_payment_must_match_escrow_token() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let usdt: AccountId = "usdt.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin);
+        contract.add_accepted_token(usdt.clone());
+
+        testing_env!(get_context(marketplace.clone()).build());
+        contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        testing_env!(get_context(usdt).build());
+        let _ = contract.ft_on_transfer(
+            "debtor.testnet".parse().unwrap(),
+            U128(2_000_000_000),
+            "debtor_payment:INV-000001".to_string(),
+        );
+    }
+
+    /// A funded, disputed escrow with three registered arbiters
+    fn disputed_escrow_with_arbiters() -> (EscrowContract, String, Vec<AccountId>) {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let arbiters: Vec<AccountId> = ["arb1.testnet", "arb2.testnet", "arb3.testnet"]
+            .iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin.clone());
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+
+        testing_env!(get_context(admin).build());
+        for arbiter in &arbiters {
+            contract.add_arbiter(arbiter.clone());
+        }
+
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Goods not delivered".to_string());
+        (contract, escrow_id, arbiters)
+    }
+
+    #[test]
+    fn test_two_of_three_arbiters_resolve_dispute() {
+        let (mut contract, escrow_id, arbiters) = disputed_escrow_with_arbiters();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+
+        testing_env!(get_context(arbiters[0].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), buyer.clone());
+        assert_eq!(contract.get_escrow(escrow_id.clone()).unwrap().status, EscrowStatus::Disputed);
+
+        testing_env!(get_context(arbiters[1].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), buyer.clone());
+
+        // The second matching vote is a majority and refunds the buyer
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Refunded);
+        assert_eq!(contract.get_total_received(buyer).0, 1_850_000_000);
+    }
+
+    #[test]
+    fn test_split_votes_do_not_resolve_dispute() {
+        let (mut contract, escrow_id, arbiters) = disputed_escrow_with_arbiters();
+
+        testing_env!(get_context(arbiters[0].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), "buyer.testnet".parse().unwrap());
+        testing_env!(get_context(arbiters[1].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), "seller.testnet".parse().unwrap());
+
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Disputed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Arbiter has already voted")]
+    fn test_arbiter_cannot_vote_twice() {
+        let (mut contract, escrow_id, arbiters) = disputed_escrow_with_arbiters();
+
+        testing_env!(get_context(arbiters[0].clone()).build());
+        contract.vote_dispute(escrow_id.clone(), "buyer.testnet".parse().unwrap());
+        contract.vote_dispute(escrow_id, "buyer.testnet".parse().unwrap());
+    }
+
+    /// A funded escrow for INV-000001 awaiting the debtor's payment
+    fn funded_escrow() -> (EscrowContract, String) {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin);
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+        (contract, escrow_id)
+    }
+
+    fn pay_installment(contract: &mut EscrowContract, escrow_id: &str, amount: u128) -> PromiseOrValue<U128> {
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        contract.ft_on_transfer(
+            "debtor.testnet".parse().unwrap(),
+            U128(amount),
+            format!("partial_payment:{}", escrow_id),
+        )
+    }
+
+    #[test]
+    fn test_two_installments_complete_payment_and_settle() {
+        let (mut contract, escrow_id) = funded_escrow();
+
+        let _ = pay_installment(&mut contract, &escrow_id, 1_200_000_000);
+        let progress = contract.get_payment_progress(escrow_id.clone());
+        assert_eq!(progress.amount_paid.0, 1_200_000_000);
+        assert_eq!(progress.outstanding.0, 800_000_000);
+        assert!(!contract.get_escrow(escrow_id.clone()).unwrap().debtor_paid);
+        // Held until complete: nothing forwarded yet
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+
+        // The second installment overshoots; the excess is returned and the escrow awaits settle
+        let excess = pay_installment(&mut contract, &escrow_id, 900_000_000);
+        assert!(matches!(excess, PromiseOrValue::Value(U128(100_000_000))));
+        let progress = contract.get_payment_progress(escrow_id.clone());
+        assert_eq!(progress.amount_paid.0, 2_000_000_000);
+        assert_eq!(progress.outstanding.0, 0);
+        assert!(contract.get_escrow(escrow_id.clone()).unwrap().debtor_paid);
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log == &format!("Escrow {} paid in full, awaiting settlement", escrow_id)));
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id.clone(),
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Released);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 2_000_000_000);
+    }
+
+    #[test]
+    fn test_forwarded_installments_are_not_paid_twice() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_forward_partial_payments(true);
+
+        let _ = pay_installment(&mut contract, &escrow_id, 1_200_000_000);
+        assert_eq!(contract.get_payment_progress(escrow_id.clone()).amount_forwarded.0, 1_200_000_000);
+        assert_eq!(contract.get_total_received(buyer.clone()).0, 1_200_000_000);
+
+        let _ = pay_installment(&mut contract, &escrow_id, 800_000_000);
+        let _ = contract.on_settle_callback(
+            escrow_id,
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+
+        // Settlement pays the buyer only the held remainder
+        assert_eq!(contract.get_total_received(buyer).0, 2_000_000_000);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_850_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_ESCROW_NOT_FOUND: Escrow not found")]
+    fn test_error_code_for_missing_escrow() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute("ESC-999999".to_string(), "Late".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "E_TOKEN_NOT_ACCEPTED: Token dai.testnet is not accepted")]
+    fn test_error_code_for_unaccepted_token() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("dai.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "marketplace.testnet".parse().unwrap(),
+            U128(1),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_resell_position_keeps_escrow_funded() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        let resold = contract.resell_position("INV-000001".to_string(), carol.clone());
+
+        assert_eq!(resold, escrow_id);
+        let entry = contract.get_escrow(escrow_id.clone()).unwrap();
+        assert_eq!(entry.buyer, carol);
+        assert_eq!(entry.status, EscrowStatus::Active);
+        assert!(entry.funds_deposited);
+        assert_eq!(entry.sale_amount.0, 1_850_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only marketplace can resell escrow positions")]
+    fn test_resell_position_is_marketplace_only() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.resell_position("INV-000001".to_string(), "carol.testnet".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_ARGUMENT: Gas for ft_transfer below minimum of 5 Tgas")]
+    fn test_set_gas_config_rejects_values_below_floor() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_gas_config(Gas::from_tgas(10), Gas::from_tgas(10), Gas::from_tgas(4));
+    }
+
+    #[test]
+    fn test_migrate_from_original_layout() {
+        let mut context = VMContextBuilder::new();
+        context.predecessor_account_id("escrow.testnet".parse().unwrap());
+        context.current_account_id("escrow.testnet".parse().unwrap());
+        testing_env!(context.build());
+
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let mut old = OldEscrowContract {
+            escrows: IterableMap::new(b"e"),
+            escrows_by_invoice: LookupMap::new(b"i"),
+            escrows_by_buyer: LookupMap::new(b"b"),
+            escrows_by_seller: LookupMap::new(b"s"),
+            escrow_count: 1,
+            invoice_contract: "invoice.testnet".parse().unwrap(),
+            marketplace_contract: "marketplace.testnet".parse().unwrap(),
+            usdc_contract: "usdc.testnet".parse().unwrap(),
+            admin: "admin.testnet".parse().unwrap(),
+        };
+        old.escrows.insert(
+            "ESC-000001".to_string(),
+            OldEscrowEntry {
+                id: "ESC-000001".to_string(),
+                invoice_id: "INV-000001".to_string(),
+                seller: "seller.testnet".parse().unwrap(),
+                buyer: buyer.clone(),
+                sale_amount: U128(1_850_000_000),
+                invoice_amount: U128(2_000_000_000),
+                created_at: 0,
+                due_date: 1_000,
+                status: EscrowStatus::Active,
+                settled_at: None,
+                dispute_reason: None,
+                funds_deposited: true,
+                debtor_paid: true,
+            },
+        );
+        old.escrows_by_invoice.insert("INV-000001".to_string(), "ESC-000001".to_string());
+        old.escrows_by_buyer.insert(buyer.clone(), vec!["ESC-000001".to_string()]);
+        old.escrows.flush();
+        old.escrows_by_invoice.flush();
+        old.escrows_by_buyer.flush();
+        env::state_write(&old);
+
+        let contract = EscrowContract::migrate();
+        let entry = contract.get_escrow("ESC-000001".to_string()).unwrap();
+        assert_eq!(entry.buyer, buyer);
+        assert!(entry.funds_deposited);
+        assert_eq!(entry.amount_paid.0, 2_000_000_000);
+        assert_eq!(entry.token, None);
+        let by_invoice = contract.get_escrow_by_invoice("INV-000001".to_string()).unwrap();
+        assert_eq!(by_invoice.id, "ESC-000001");
+        assert_eq!(contract.get_admin(), "admin.testnet".parse::<AccountId>().unwrap());
+        assert!(contract.is_accepted_token(&"usdc.testnet".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_settle_by_invoice_matches_settle_by_escrow_id() {
+        let settle_receipts = |by_invoice: bool| {
+            // Each run starts from empty storage
+            near_sdk::mock::with_mocked_blockchain(|blockchain| blockchain.take_storage());
+            let (mut contract, escrow_id) = funded_escrow();
+            pay_debtor(&mut contract, &escrow_id);
+            testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+            if by_invoice {
+                let _ = contract.settle_by_invoice("INV-000001".to_string());
+            } else {
+                let _ = contract.settle(escrow_id);
+            }
+            let receipts: Vec<_> = near_sdk::test_utils::get_created_receipts()
+                .into_iter()
+                .map(|receipt| (receipt.receiver_id, format!("{:?}", receipt.actions)))
+                .collect();
+            (receipts, contract.get_escrow_status_by_invoice("INV-000001".to_string()))
+        };
+
+        let by_escrow = settle_receipts(false);
+        let by_invoice = settle_receipts(true);
+        assert!(!by_escrow.0.is_empty());
+        assert_eq!(by_escrow, by_invoice);
+        assert_eq!(by_invoice.1, Some(EscrowStatus::Active));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_ESCROW_NOT_FOUND: No escrow for invoice")]
+    fn test_settle_by_unknown_invoice() {
+        let (mut contract, _) = funded_escrow();
+        assert!(contract.get_escrow_status_by_invoice("INV-999999".to_string()).is_none());
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.settle_by_invoice("INV-999999".to_string());
+    }
+
+    #[test]
+    fn test_mutual_release_settles_once_both_consent() {
+        let (mut contract, escrow_id) = funded_escrow();
+
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        assert!(!contract.mutual_release(escrow_id.clone()));
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        assert!(contract.mutual_release(escrow_id.clone()));
+
+        let entry = contract.get_escrow(escrow_id).unwrap();
+        assert!(entry.buyer_release_consent && entry.seller_release_consent);
+        let settling = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .any(|receipt| receipt.receiver_id.as_str() == "invoice.testnet");
+        assert!(settling);
+    }
+
+    #[test]
+    fn test_mutual_release_needs_both_parties() {
+        let (mut contract, escrow_id) = funded_escrow();
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        assert!(!contract.mutual_release(escrow_id.clone()));
+        let repeat = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.mutual_release(escrow_id.clone())
+        }));
+        assert!(repeat.is_err());
+
+        let entry = contract.get_escrow(escrow_id).unwrap();
+        assert!(entry.seller_release_consent && !entry.buyer_release_consent);
+        assert_eq!(entry.status, EscrowStatus::Active);
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_dispute_split_pays_both_parties() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Partial delivery".to_string());
+        assert_eq!(contract.get_disputed_escrows(None, None).items[0].id, escrow_id);
+
+        // 60/40 of the 1,850 USDC sale amount
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.resolve_dispute_split(
+            escrow_id.clone(),
+            U128(1_110_000_000),
+            U128(740_000_000),
+        );
+
+        let entry = contract.get_escrow(escrow_id).unwrap();
+        assert_eq!(entry.status, EscrowStatus::PartiallyResolved);
+        assert!(entry.status.is_terminal());
+        assert!(contract.get_disputed_escrows(None, None).items.is_empty());
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 1_110_000_000);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 740_000_000);
+        let transfers = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id.as_str() == "usdc.testnet")
+            .count();
+        assert_eq!(transfers, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_AMOUNT: Split must sum to the escrowed amount of 1850000000")]
+    fn test_resolve_dispute_split_must_sum_to_deposited_amount() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Partial delivery".to_string());
+
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.resolve_dispute_split(escrow_id, U128(1_110_000_000), U128(700_000_000));
+    }
+
+    #[test]
+    fn test_dispute_after_debtor_paid_pays_out_held_funds() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_dispute_window(3 * 24 * 60 * 60 * 1000);
+        pay_debtor(&mut contract, &escrow_id);
+
+        // The dispute window is still open, so the buyer can dispute the paid escrow
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Goods not delivered".to_string());
+
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.resolve_dispute(escrow_id.clone(), "seller.testnet".parse().unwrap());
+
+        // The seller keeps the deposit and the buyer receives the debtor's payment
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Released);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_850_000_000);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 2_000_000_000);
+        let transfers = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id.as_str() == "usdc.testnet")
+            .count();
+        assert_eq!(transfers, 2);
+    }
+
+    #[test]
+    fn test_resolve_dispute_split_includes_held_debtor_funds() {
+        let (mut contract, escrow_id) = funded_escrow();
+        pay_debtor(&mut contract, &escrow_id);
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Partial delivery".to_string());
+
+        // 1,850 USDC deposit plus the 2,000 USDC debtor payment
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.resolve_dispute_split(
+            escrow_id,
+            U128(2_500_000_000),
+            U128(1_350_000_000),
+        );
+
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 2_500_000_000);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_350_000_000);
+    }
+
+    #[test]
+    fn test_debtor_payment_recorded_before_settlement() {
+        let (mut contract, escrow_id) = funded_escrow();
+        assert!(!contract.is_debtor_paid(escrow_id.clone()));
+
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.record_debtor_payment(escrow_id.clone());
+        assert!(contract.is_debtor_paid(escrow_id.clone()));
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"debtor_payment_received\"")));
+        // Receipt alone releases nothing, and settlement still waits for the money
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+        assert_eq!(contract.get_escrow(escrow_id.clone()).unwrap().status, EscrowStatus::Active);
+
+        pay_debtor(&mut contract, &escrow_id);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id.clone(),
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Released);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 2_000_000_000);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_850_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Debtor payment has not been received")]
+    fn test_settle_requires_debtor_payment() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: Only admin can record debtor payments")]
+    fn test_record_debtor_payment_requires_admin() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let operator: AccountId = "operator.testnet".parse().unwrap();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.add_operator(operator.clone());
+
+        testing_env!(get_context(operator).build());
+        contract.record_debtor_payment(escrow_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Debtor payment has not been received")]
+    fn test_recorded_debtor_payment_cannot_settle_without_funds() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.record_debtor_payment(escrow_id.clone());
 
-        let mut entry = self
-            .escrows
-            .get(&escrow_id)
-            .expect("Escrow not found")
-            .clone();
-        assert!(
-            entry.status == EscrowStatus::Disputed,
-            "Escrow is not disputed"
-        );
-        assert!(
-            winner == entry.buyer || winner == entry.seller,
-            "Winner must be buyer or seller"
-        );
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id);
+    }
 
-        // Verify funds were deposited
-        assert!(
-            entry.funds_deposited,
-            "No funds deposited in escrow"
+    /// Have the marketplace deposit `amount` for an invoice with no escrow
+    fn send_orphaned_deposit(contract: &mut EscrowContract, amount: u128) {
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "marketplace.testnet".parse().unwrap(),
+            U128(amount),
+            "escrow_deposit:INV-999999".to_string(),
         );
+    }
 
-        let invoice_id = entry.invoice_id.clone();
-        let seller = entry.seller.clone();
-        let buyer = entry.buyer.clone();
-        let buyer_wins = winner == buyer;
-
-        if buyer_wins {
-            // Refund buyer - they get their sale_amount back
-            entry.status = EscrowStatus::Refunded;
-            env::log_str(&format!(
-                "Dispute resolved: {} USDC refunded to buyer {}",
-                entry.sale_amount.0, buyer
-            ));
-        } else {
-            // Release to seller - buyer's payment goes to seller
-            entry.status = EscrowStatus::Released;
-            env::log_str(&format!(
-                "Dispute resolved: {} USDC released to seller {}",
-                entry.sale_amount.0, seller
-            ));
-        }
+    #[test]
+    fn test_admin_withdraws_orphaned_tokens() {
+        let (mut contract, _) = funded_escrow();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        send_orphaned_deposit(&mut contract, 500_000_000);
+        assert_eq!(contract.get_orphaned_balance(usdc.clone()).0, 500_000_000);
 
-        entry.settled_at = Some(env::block_timestamp_ms());
-        self.escrows.insert(escrow_id.clone(), entry.clone());
+        let rescuer: AccountId = "treasury.testnet".parse().unwrap();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.admin_withdraw_token(usdc.clone(), rescuer, U128(500_000_000));
 
-        // Transfer USDC to the winner
-        let recipient = if buyer_wins { buyer } else { seller.clone() };
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"tokens_recovered\"")));
+        assert!(near_sdk::test_utils::get_created_receipts()
+            .iter()
+            .any(|receipt| receipt.receiver_id == usdc));
+        assert_eq!(contract.get_orphaned_balance(usdc.clone()).0, 0);
 
-        ext_ft::ext(self.usdc_contract.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(
-                recipient,
-                entry.sale_amount,
-                Some(format!("dispute_resolution:{}", escrow_id)),
-            )
-            .then(
-                // Update invoice status based on resolution
-                if !buyer_wins {
-                    ext_invoice::ext(self.invoice_contract.clone())
-                        .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-                        .mark_settled(invoice_id)
-                } else {
-                    // If buyer wins, create a no-op promise
-                    Promise::new(env::current_account_id())
-                }
-            )
+        // A failed transfer puts the amount back up for recovery
+        let restored = contract.on_withdraw_callback(usdc.clone(), U128(500_000_000), Err(PromiseError::Failed));
+        assert!(!restored);
+        assert_eq!(contract.get_orphaned_balance(usdc).0, 500_000_000);
     }
 
-    /// Check if escrow is past due date
-    pub fn check_overdue(&self, escrow_id: String) -> bool {
-        let entry = self.escrows.get(&escrow_id).expect("Escrow not found");
-        entry.status == EscrowStatus::Active && env::block_timestamp_ms() > entry.due_date
+    #[test]
+    #[should_panic(expected = "E_INSUFFICIENT_BALANCE: Only 100000000 of usdc.testnet is unclaimed by escrows")]
+    fn test_admin_cannot_withdraw_escrowed_funds() {
+        let (mut contract, _) = funded_escrow();
+        send_orphaned_deposit(&mut contract, 100_000_000);
+
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.admin_withdraw_token(
+            "usdc.testnet".parse().unwrap(),
+            "treasury.testnet".parse().unwrap(),
+            U128(500_000_000),
+        );
     }
 
-    /// Mark escrow as overdue (can be used to auto-open disputes)
-    pub fn mark_overdue(&mut self, escrow_id: String) {
-        let entry = self.escrows.get(&escrow_id).expect("Escrow not found").clone();
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: Only admin can withdraw tokens")]
+    fn test_non_admin_cannot_withdraw_tokens() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.admin_withdraw_token(
+            "usdc.testnet".parse().unwrap(),
+            "seller.testnet".parse().unwrap(),
+            U128(500_000_000),
+        );
+    }
 
-        assert!(
-            entry.status == EscrowStatus::Active,
-            "Escrow is not active"
+    #[test]
+    fn test_overpaid_deposit_is_paid_out_in_full() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(
+            "invoice.testnet".parse().unwrap(),
+            marketplace.clone(),
+            usdc.clone(),
+            "admin.testnet".parse().unwrap(),
         );
-        assert!(
-            env::block_timestamp_ms() > entry.due_date,
-            "Escrow is not overdue"
+        let escrow_id = contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
         );
+        assert_eq!(contract.get_deposited_amount(escrow_id.clone()).0, 0);
 
-        // Auto-open dispute for overdue escrow
-        self.open_dispute(
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_900_000_000),
+            "escrow_deposit:INV-000001".to_string(),
+        );
+        assert_eq!(contract.get_deposited_amount(escrow_id.clone()).0, 1_900_000_000);
+
+        pay_debtor(&mut contract, &escrow_id);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
             escrow_id,
-            format!("Auto-dispute: Payment overdue since {}", entry.due_date),
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
         );
+
+        // The seller receives everything deposited, not just the sale amount
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_900_000_000);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 2_000_000_000);
     }
 
-    /// Update admin (current admin only)
-    pub fn set_admin(&mut self, new_admin: AccountId) {
-        let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can change admin");
-        self.admin = new_admin;
+    #[test]
+    fn test_active_escrow_cursor_matches_offset_paging() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(
+            "invoice.testnet".parse().unwrap(),
+            marketplace,
+            "usdc.testnet".parse().unwrap(),
+            "admin.testnet".parse().unwrap(),
+        );
+        for i in 1..=4 {
+            contract.create_escrow(
+                format!("INV-{:06}", i),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+            );
+        }
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute("ESC-000003".to_string(), "Goods not delivered".to_string());
+
+        let first = contract.get_active_escrows_after(None, Some(2));
+        assert_eq!(first.next_cursor.as_deref(), Some("ESC-000002"));
+        let second = contract.get_active_escrows_after(first.next_cursor, Some(2));
+        assert_eq!(second.next_cursor, None);
+        let paged: Vec<String> =
+            first.items.into_iter().chain(second.items).map(|entry| entry.id).collect();
+
+        let offset: Vec<String> =
+            contract.get_active_escrows(0, 10).into_iter().map(|entry| entry.id).collect();
+        assert_eq!(paged, vec!["ESC-000001", "ESC-000002", "ESC-000004"]);
+        assert_eq!(paged, offset);
     }
 
-    /// Update contract addresses (admin only)
-    pub fn set_contract_addresses(
-        &mut self,
-        invoice_contract: Option<AccountId>,
-        marketplace_contract: Option<AccountId>,
-        usdc_contract: Option<AccountId>,
-    ) {
-        let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can update contracts");
+    #[test]
+    fn test_active_escrow_cursor_caps_ids_examined() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(
+            "invoice.testnet".parse().unwrap(),
+            marketplace,
+            "usdc.testnet".parse().unwrap(),
+            "admin.testnet".parse().unwrap(),
+        );
+        contract.create_escrow(
+            "INV-000001".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+        // Ids past the cursor that no longer hold an escrow still count towards the scan
+        contract.escrow_count = MAX_VIEW_SCAN + 2;
 
-        if let Some(addr) = invoice_contract {
-            self.invoice_contract = addr;
-        }
-        if let Some(addr) = marketplace_contract {
-            self.marketplace_contract = addr;
-        }
-        if let Some(addr) = usdc_contract {
-            self.usdc_contract = addr;
-        }
+        let first = contract.get_active_escrows_after(None, None);
+        assert_eq!(first.items.len(), 1);
+        assert_eq!(first.next_cursor, Some(format!("ESC-{:06}", MAX_VIEW_SCAN)));
+
+        let second = contract.get_active_escrows_after(first.next_cursor, None);
+        assert!(second.items.is_empty());
+        assert_eq!(second.next_cursor, None);
     }
 
-    // ============ VIEW METHODS ============
+    #[test]
+    fn test_timeout_refund_after_grace_period() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let refundable_at = due_date + contract.get_grace_period();
 
-    /// Get escrow by ID
-    pub fn get_escrow(&self, escrow_id: String) -> Option<EscrowEntry> {
-        self.escrows.get(&escrow_id).cloned()
+        let mut context = get_context(buyer.clone());
+        testing_env!(context.block_timestamp((refundable_at + 1) * 1_000_000).build());
+        let _ = contract.claim_timeout_refund(escrow_id.clone());
+
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Refunded);
+        assert_eq!(contract.get_total_received(buyer).0, 1_850_000_000);
+        assert_eq!(near_sdk::test_utils::get_created_receipts().len(), 1);
     }
 
-    /// Get escrow by invoice
-    pub fn get_escrow_by_invoice(&self, invoice_id: String) -> Option<EscrowEntry> {
-        self.escrows_by_invoice
-            .get(&invoice_id)
-            .and_then(|id| self.escrows.get(id).cloned())
+    #[test]
+    fn test_timeout_refund_survives_recorded_debtor_payment() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.record_debtor_payment(escrow_id.clone());
+
+        // The recorded payment never arrives, so the buyer can still recover their deposit
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let refundable_at = due_date + contract.get_grace_period();
+        let mut context = get_context(buyer.clone());
+        testing_env!(context.block_timestamp((refundable_at + 1) * 1_000_000).build());
+        let _ = contract.claim_timeout_refund(escrow_id.clone());
+
+        assert_eq!(contract.get_escrow(escrow_id).unwrap().status, EscrowStatus::Refunded);
+        assert_eq!(contract.get_total_received(buyer).0, 1_850_000_000);
     }
 
-    /// Get escrows by buyer
-    pub fn get_escrows_by_buyer(&self, buyer: AccountId) -> Vec<EscrowEntry> {
-        self.escrows_by_buyer
-            .get(&buyer)
-            .cloned()
-            .unwrap_or_default()
-            .iter()
-            .filter_map(|id| self.escrows.get(id).cloned())
-            .collect()
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Grace period ends at")]
+    fn test_timeout_refund_rejected_at_grace_boundary() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let refundable_at = due_date + contract.get_grace_period();
+
+        let mut context = get_context("buyer.testnet".parse().unwrap());
+        testing_env!(context.block_timestamp(refundable_at * 1_000_000).build());
+        let _ = contract.claim_timeout_refund(escrow_id);
     }
 
-    /// Get escrows by seller
-    pub fn get_escrows_by_seller(&self, seller: AccountId) -> Vec<EscrowEntry> {
-        self.escrows_by_seller
-            .get(&seller)
-            .cloned()
-            .unwrap_or_default()
-            .iter()
-            .filter_map(|id| self.escrows.get(id).cloned())
-            .collect()
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Escrow is not active")]
+    fn test_timeout_refund_rejected_for_disputed_escrow() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        testing_env!(get_context(buyer.clone()).build());
+        contract.open_dispute(escrow_id.clone(), "Debtor unresponsive".to_string());
+
+        let due_date = contract.get_escrow(escrow_id.clone()).unwrap().due_date;
+        let refundable_at = due_date + contract.get_grace_period();
+        let mut context = get_context(buyer);
+        testing_env!(context.block_timestamp((refundable_at + 1) * 1_000_000).build());
+        let _ = contract.claim_timeout_refund(escrow_id);
     }
 
-    /// Get all active escrows
-    pub fn get_active_escrows(&self, from_index: u64, limit: u64) -> Vec<EscrowEntry> {
-        self.escrows
-            .iter()
-            .filter(|(_, entry)| entry.status == EscrowStatus::Active)
-            .skip(from_index as usize)
-            .take(limit as usize)
-            .map(|(_, entry)| entry.clone())
-            .collect()
+    fn senior_junior_tranches() -> Vec<Tranche> {
+        vec![
+            Tranche {
+                amount: U128(1_500_000_000),
+                yield_bps: 600,
+                holder: "senior.testnet".parse().unwrap(),
+            },
+            Tranche {
+                amount: U128(500_000_000),
+                yield_bps: 1_400,
+                holder: "buyer.testnet".parse().unwrap(),
+            },
+        ]
     }
 
-    /// Get disputed escrows (admin view)
-    pub fn get_disputed_escrows(&self) -> Vec<EscrowEntry> {
-        self.escrows
+    #[test]
+    fn test_tranched_settlement_pays_both_tranches_in_full() {
+        let (mut contract, escrow_id) = funded_escrow();
+        pay_debtor(&mut contract, &escrow_id);
+
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id,
+            Err(PromiseError::Failed),
+            Ok(senior_junior_tranches()),
+        );
+
+        assert_eq!(contract.get_total_received("senior.testnet".parse().unwrap()).0, 1_500_000_000);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 500_000_000);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_850_000_000);
+        assert!(near_sdk::test_utils::get_logs()
             .iter()
-            .filter(|(_, entry)| entry.status == EscrowStatus::Disputed)
-            .map(|(_, entry)| entry.clone())
-            .collect()
+            .any(|log| log.contains("released to tranche holder senior.testnet")));
     }
 
-    /// Get overdue escrows
-    pub fn get_overdue_escrows(&self) -> Vec<EscrowEntry> {
-        let now = env::block_timestamp_ms();
-        self.escrows
-            .iter()
-            .filter(|(_, entry)| entry.status == EscrowStatus::Active && now > entry.due_date)
-            .map(|(_, entry)| entry.clone())
-            .collect()
+    #[test]
+    fn test_tranched_shortfall_is_absorbed_by_junior() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let _ = pay_installment(&mut contract, &escrow_id, 1_200_000_000);
+
+        // The debtor defaults on the rest; both parties agree to close out what was collected
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.mutual_release(escrow_id.clone());
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        assert!(contract.mutual_release(escrow_id.clone()));
+        let _ = contract.on_settle_callback(
+            escrow_id,
+            Err(PromiseError::Failed),
+            Ok(senior_junior_tranches()),
+        );
+
+        assert_eq!(contract.get_total_received("senior.testnet".parse().unwrap()).0, 1_200_000_000);
+        assert_eq!(contract.get_total_received("buyer.testnet".parse().unwrap()).0, 0);
+        assert_eq!(contract.get_total_received("seller.testnet".parse().unwrap()).0, 1_850_000_000);
     }
 
-    /// Get escrow statistics
-    pub fn get_stats(&self) -> EscrowStats {
-        let mut active_count = 0u64;
-        let mut active_value = 0u128;
-        let mut settled_count = 0u64;
-        let mut disputed_count = 0u64;
+    #[test]
+    fn test_audit_log_wraps_past_capacity() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("buyer.testnet".parse().unwrap()).build());
+        contract.open_dispute(escrow_id.clone(), "Goods never arrived".to_string());
 
-        for (_, entry) in self.escrows.iter() {
-            match entry.status {
-                EscrowStatus::Active => {
-                    active_count += 1;
-                    active_value += entry.sale_amount.0;
-                }
-                EscrowStatus::Released => settled_count += 1,
-                EscrowStatus::Disputed => disputed_count += 1,
-                EscrowStatus::Refunded => settled_count += 1,
-            }
-        }
+        let latest = contract.get_recent_events(Some(2));
+        assert_eq!(latest[0].action, AuditAction::Disputed);
+        assert_eq!(latest[0].actor, "buyer.testnet".parse::<AccountId>().unwrap());
+        assert_eq!(latest[1].action, AuditAction::Created);
+        assert_eq!(latest[1].escrow_id, escrow_id);
 
-        EscrowStats {
-            total_escrows: self.escrow_count,
-            active_escrows: active_count,
-            total_value_locked: U128(active_value),
-            total_settled: settled_count,
-            total_disputed: disputed_count,
+        // 256 more creations push both entries above out of the buffer
+        for i in 2..=AUDIT_LOG_CAPACITY as u64 + 1 {
+            // A fresh context per call keeps under the mocked log limit
+            testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+            contract.create_escrow(
+                format!("INV-{:06}", i),
+                "seller.testnet".parse().unwrap(),
+                "buyer.testnet".parse().unwrap(),
+                U128(900_000_000),
+                U128(1_000_000_000),
+                env::block_timestamp_ms() + 1_000,
+                None,
+            );
         }
-    }
 
-    /// Get escrow count
-    pub fn get_escrow_count(&self) -> u64 {
-        self.escrow_count
+        let all = contract.get_recent_events(Some(1_000));
+        assert_eq!(all.len(), AUDIT_LOG_CAPACITY as usize);
+        assert_eq!(all[0].escrow_id, format!("ESC-{:06}", AUDIT_LOG_CAPACITY + 1));
+        assert_eq!(all.last().unwrap().escrow_id, "ESC-000002");
+        assert!(all.iter().all(|entry| entry.action == AuditAction::Created));
     }
 
-    /// Get admin address
-    pub fn get_admin(&self) -> AccountId {
-        self.admin.clone()
-    }
+    #[test]
+    fn test_settle_waits_for_dispute_window() {
+        let (mut contract, escrow_id) = funded_escrow();
+        let day = 24 * 60 * 60 * 1000;
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_dispute_window(3 * day);
+        assert_eq!(contract.get_settlement_eligible_at(escrow_id.clone()), None);
 
-    /// Get contract addresses
-    pub fn get_contract_addresses(&self) -> (AccountId, AccountId, AccountId) {
-        (
-            self.invoice_contract.clone(),
-            self.marketplace_contract.clone(),
-            self.usdc_contract.clone(),
-        )
+        pay_debtor(&mut contract, &escrow_id);
+        let paid_at = env::block_timestamp_ms();
+        assert_eq!(contract.get_settlement_eligible_at(escrow_id.clone()), Some(paid_at + 3 * day));
+
+        // Once the window has passed the seller can settle
+        let mut context = get_context("seller.testnet".parse().unwrap());
+        testing_env!(context.block_timestamp((paid_at + 3 * day) * 1_000_000).build());
+        let _ = contract.settle(escrow_id);
+        let settling = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .any(|receipt| receipt.receiver_id.as_str() == "invoice.testnet");
+        assert!(settling);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::testing_env;
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Dispute window is open until")]
+    fn test_settle_inside_dispute_window_fails() {
+        let (mut contract, escrow_id) = funded_escrow();
+        testing_env!(get_context("admin.testnet".parse().unwrap()).build());
+        contract.set_dispute_window(3 * 24 * 60 * 60 * 1000);
+        pay_debtor(&mut contract, &escrow_id);
 
-    fn get_context(predecessor: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder.predecessor_account_id(predecessor);
-        builder
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.settle(escrow_id);
     }
 
     #[test]
-    fn test_init() {
-        let invoice: AccountId = "invoice.testnet".parse().unwrap();
-        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
-        let usdc: AccountId = "usdc.testnet".parse().unwrap();
-        let admin: AccountId = "admin.testnet".parse().unwrap();
+    fn test_settle_reduces_value_locked_and_snapshots_record_it() {
+        let (mut contract, settled_id) = funded_escrow();
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        contract.create_escrow(
+            "INV-000002".to_string(),
+            "seller.testnet".parse().unwrap(),
+            "buyer.testnet".parse().unwrap(),
+            U128(900_000_000),
+            U128(1_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+        );
+        assert_eq!(contract.active_value_locked, 2_750_000_000);
+        contract.record_tvl_snapshot();
 
-        let context = get_context(admin.clone());
+        pay_debtor(&mut contract, &settled_id);
+        let mut context = get_context("admin.testnet".parse().unwrap());
+        context.block_timestamp(TVL_SNAPSHOT_INTERVAL_MS * 1_000_000);
         testing_env!(context.build());
+        let _ = contract.settle(settled_id.clone());
+        let _ = contract.on_settle_callback(settled_id, Err(PromiseError::Failed), Err(PromiseError::Failed));
 
-        let contract = EscrowContract::new(invoice, marketplace, usdc, admin.clone());
+        assert_eq!(contract.active_value_locked, 900_000_000);
+        assert_eq!(contract.active_value_locked, contract.get_stats().total_value_locked.0);
+        contract.record_tvl_snapshot();
 
-        assert_eq!(contract.get_escrow_count(), 0);
-        assert_eq!(contract.get_admin(), admin);
+        let history = contract.get_tvl_history(None);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].tvl.0, 900_000_000);
+        assert_eq!(history[0].timestamp, TVL_SNAPSHOT_INTERVAL_MS);
+        assert_eq!(history[1].tvl.0, 2_750_000_000);
     }
 
     #[test]
-    fn test_create_escrow() {
+    #[should_panic(expected = "Next TVL snapshot allowed at")]
+    fn test_tvl_snapshot_rate_limited() {
+        let (mut contract, _) = funded_escrow();
+        testing_env!(get_context("keeper.testnet".parse().unwrap()).build());
+        contract.record_tvl_snapshot();
+        contract.record_tvl_snapshot();
+    }
+
+    #[test]
+    fn test_payout_account_receives_seller_share() {
         let invoice: AccountId = "invoice.testnet".parse().unwrap();
         let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
         let usdc: AccountId = "usdc.testnet".parse().unwrap();
         let admin: AccountId = "admin.testnet".parse().unwrap();
         let seller: AccountId = "seller.testnet".parse().unwrap();
-        let buyer: AccountId = "buyer.testnet".parse().unwrap();
-
-        let context = get_context(marketplace.clone());
-        testing_env!(context.build());
+        let financier: AccountId = "financier.testnet".parse().unwrap();
 
-        let mut contract = EscrowContract::new(invoice, marketplace, usdc, admin);
+        testing_env!(get_context(marketplace.clone()).build());
+        let mut contract = EscrowContract::new(invoice, marketplace.clone(), usdc.clone(), admin);
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let mut create = || {
+            contract.create_escrow(
+                "INV-000001".to_string(),
+                seller.clone(),
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                due_date,
+                Some(financier.clone()),
+            )
+        };
+        let escrow_id = create();
+        // A retried purchase matches on the real seller and the same payout account
+        assert_eq!(create(), escrow_id);
+        let entry = contract.get_escrow(escrow_id.clone()).unwrap();
+        assert_eq!(entry.seller, seller);
+        assert_eq!(contract.get_escrows_by_seller(seller.clone(), None, None).items.len(), 1);
 
-        let escrow_id = contract.create_escrow(
-            "INV-000001".to_string(),
-            seller.clone(),
-            buyer.clone(),
-            U128(1_850_000_000), // $1,850
-            U128(2_000_000_000), // $2,000
-            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            marketplace,
+            U128(1_850_000_000),
+            "escrow_deposit:INV-000001".to_string(),
         );
+        pay_debtor(&mut contract, &escrow_id);
 
-        assert_eq!(escrow_id, "ESC-000001");
-
-        let escrow = contract.get_escrow(escrow_id).unwrap();
-        assert_eq!(escrow.seller, seller);
-        assert_eq!(escrow.buyer, buyer);
-        assert_eq!(escrow.status, EscrowStatus::Active);
-        assert!(!escrow.funds_deposited); // Funds not deposited yet
+        // The seller still acts on the escrow; only the money goes to the payout account
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.settle(escrow_id.clone());
+        let _ = contract.on_settle_callback(
+            escrow_id,
+            Err(PromiseError::Failed),
+            Err(PromiseError::Failed),
+        );
+        assert_eq!(contract.get_total_received(financier).0, 1_850_000_000);
+        assert_eq!(contract.get_total_received(seller).0, 0);
     }
 }
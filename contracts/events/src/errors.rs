@@ -0,0 +1,95 @@
+//! Stable error codes shared by the Adelante contracts
+//!
+//! Failures panic with `E_<CODE>: <message>` so frontends can branch on the code while the
+//! message stays human readable, e.g. `E_LISTING_NOT_FOUND: Listing not found`
+
+use near_sdk::env;
+
+/// Machine-parseable failure categories; codes never change once released
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractError {
+    Unauthorized,
+    Paused,
+    InvoiceNotFound,
+    ListingNotFound,
+    BidNotFound,
+    EscrowNotFound,
+    AccountNotRegistered,
+    ListingInactive,
+    BidInactive,
+    Expired,
+    AlreadyExists,
+    InvalidArgument,
+    InvalidAmount,
+    InvalidState,
+    InvalidDeposit,
+    InsufficientPayment,
+    InsufficientBalance,
+    InsufficientGas,
+    TokenNotAccepted,
+    TokenMismatch,
+    CallbackFailed,
+    PurchaseInFlight,
+}
+
+impl ContractError {
+    /// Code prefixed to the panic message
+    pub fn code(&self) -> &'static str {
+        match self {
+            ContractError::Unauthorized => "E_UNAUTHORIZED",
+            ContractError::Paused => "E_PAUSED",
+            ContractError::InvoiceNotFound => "E_INVOICE_NOT_FOUND",
+            ContractError::ListingNotFound => "E_LISTING_NOT_FOUND",
+            ContractError::BidNotFound => "E_BID_NOT_FOUND",
+            ContractError::EscrowNotFound => "E_ESCROW_NOT_FOUND",
+            ContractError::AccountNotRegistered => "E_ACCOUNT_NOT_REGISTERED",
+            ContractError::ListingInactive => "E_LISTING_INACTIVE",
+            ContractError::BidInactive => "E_BID_INACTIVE",
+            ContractError::Expired => "E_EXPIRED",
+            ContractError::AlreadyExists => "E_ALREADY_EXISTS",
+            ContractError::InvalidArgument => "E_INVALID_ARGUMENT",
+            ContractError::InvalidAmount => "E_INVALID_AMOUNT",
+            ContractError::InvalidState => "E_INVALID_STATE",
+            ContractError::InvalidDeposit => "E_INVALID_DEPOSIT",
+            ContractError::InsufficientPayment => "E_INSUFFICIENT_PAYMENT",
+            ContractError::InsufficientBalance => "E_INSUFFICIENT_BALANCE",
+            ContractError::InsufficientGas => "E_INSUFFICIENT_GAS",
+            ContractError::TokenNotAccepted => "E_TOKEN_NOT_ACCEPTED",
+            ContractError::TokenMismatch => "E_TOKEN_MISMATCH",
+            ContractError::CallbackFailed => "E_CALLBACK_FAILED",
+            ContractError::PurchaseInFlight => "E_PURCHASE_IN_FLIGHT",
+        }
+    }
+
+    /// Abort the call with `E_<CODE>: <message>`
+    pub fn panic(&self, message: &str) -> ! {
+        env::panic_str(&format!("{}: {}", self.code(), message))
+    }
+}
+
+/// Unwrap an Option or Result, panicking with a coded error when it is missing
+pub trait OrFail<T> {
+    fn or_fail(self, error: ContractError, message: &str) -> T;
+}
+
+impl<T> OrFail<T> for Option<T> {
+    fn or_fail(self, error: ContractError, message: &str) -> T {
+        self.unwrap_or_else(|| error.panic(message))
+    }
+}
+
+impl<T, E> OrFail<T> for Result<T, E> {
+    fn or_fail(self, error: ContractError, message: &str) -> T {
+        self.unwrap_or_else(|_| error.panic(message))
+    }
+}
+
+/// `assert!` with a coded error: `ensure!(cond, ContractError::Unauthorized, "Only admin ...")`
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $error:expr, $($message:tt)+) => {
+        if !$cond {
+            $crate::ContractError::panic(&$error, &format!($($message)+))
+        }
+    };
+}
@@ -0,0 +1,75 @@
+//! Keeper bounties shared by the Adelante contracts
+
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, AccountId, NearSchema, NearToken, Promise};
+
+use crate::{ensure, ContractError};
+
+/// Bounty paid to keepers for permissionless maintenance calls
+/// Bounties are drawn from a NEAR pool funded via `fund_keeper_pool` and capped in total
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct KeeperRewards {
+    pub bounty: NearToken,
+    pub pool: NearToken,
+    pub total_paid: NearToken,
+    pub max_total_paid: NearToken,
+}
+
+impl KeeperRewards {
+    pub fn new() -> Self {
+        Self {
+            bounty: NearToken::from_yoctonear(0),
+            pool: NearToken::from_yoctonear(0),
+            total_paid: NearToken::from_yoctonear(0),
+            max_total_paid: NearToken::from_yoctonear(0),
+        }
+    }
+
+    /// Add an attached deposit to the pool
+    pub fn fund(&mut self, deposit: NearToken) {
+        ensure!(
+            !deposit.is_zero(),
+            ContractError::InvalidDeposit,
+            "Must attach NEAR to fund the keeper pool"
+        );
+        self.pool = self.pool.saturating_add(deposit);
+    }
+
+    /// Set the bounty per call and the cap on total bounties paid
+    pub fn configure(&mut self, bounty: NearToken, max_total_paid: NearToken) {
+        self.bounty = bounty;
+        self.max_total_paid = max_total_paid;
+    }
+
+    /// Pay one bounty to `keeper` if enabled, funded and under the cap
+    pub fn reward(&mut self, keeper: AccountId) {
+        if let Some(bounty) = self.claim() {
+            env::log_str(&format!("Keeper bounty of {} paid to {}", bounty, keeper));
+            let _ = Promise::new(keeper).transfer(bounty);
+        }
+    }
+
+    /// Reserve one bounty if enabled, funded and under the cap
+    fn claim(&mut self) -> Option<NearToken> {
+        let bounty = self.bounty;
+        if bounty.is_zero()
+            || self.pool < bounty
+            || self.total_paid.saturating_add(bounty) > self.max_total_paid
+        {
+            return None;
+        }
+
+        self.pool = self.pool.saturating_sub(bounty);
+        self.total_paid = self.total_paid.saturating_add(bounty);
+        Some(bounty)
+    }
+}
+
+impl Default for KeeperRewards {
+    fn default() -> Self {
+        Self::new()
+    }
+}
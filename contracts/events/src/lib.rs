@@ -0,0 +1,264 @@
+//! NEP-297 events, error codes, keeper bounties and view pagination shared by the Adelante
+//! contracts
+//!
+//! Events are logged as `EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":...,"data":[...]}`
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId};
+
+mod errors;
+mod keeper;
+mod pagination;
+pub use errors::{ContractError, OrFail};
+pub use keeper::KeeperRewards;
+pub use pagination::{PaginatedResult, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT, MAX_VIEW_SCAN};
+
+pub const EVENT_STANDARD: &str = "adelante";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// NEP-297 envelope
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a AdelanteEvent,
+}
+
+/// Key state transitions across the invoice, marketplace and escrow contracts
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum AdelanteEvent {
+    InvoiceCreated(Vec<InvoiceCreated>),
+    InvoiceUpdated(Vec<InvoiceUpdated>),
+    InvoiceListed(Vec<InvoiceListed>),
+    InvoiceSold(Vec<InvoiceSold>),
+    InvoiceSettled(Vec<InvoiceSettled>),
+    ListingCreated(Vec<ListingCreated>),
+    ListingUpdated(Vec<ListingUpdated>),
+    ListingExtended(Vec<ListingExtended>),
+    ListingPurchased(Vec<ListingPurchased>),
+    ListingCancelled(Vec<ListingCancelled>),
+    BidPlaced(Vec<BidPlaced>),
+    EscrowCreated(Vec<EscrowCreated>),
+    DebtorPaymentReceived(Vec<DebtorPaymentReceived>),
+    EscrowSettled(Vec<EscrowSettled>),
+    DisputeOpened(Vec<DisputeOpened>),
+    DisputeResolved(Vec<DisputeResolved>),
+    TokensRecovered(Vec<TokensRecovered>),
+}
+
+impl AdelanteEvent {
+    /// Serialized log line, including the `EVENT_JSON:` prefix
+    pub fn to_log_string(&self) -> String {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self,
+        };
+        format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&log).expect("Failed to serialize event")
+        )
+    }
+
+    /// Write the event to the contract logs
+    pub fn emit(&self) {
+        env::log_str(&self.to_log_string());
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceCreated {
+    pub invoice_id: String,
+    pub creator: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceUpdated {
+    pub invoice_id: String,
+    pub owner: AccountId,
+    pub documents_hash: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceListed {
+    pub invoice_id: String,
+    pub owner: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceSold {
+    pub invoice_id: String,
+    pub old_owner: AccountId,
+    pub new_owner: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceSettled {
+    pub invoice_id: String,
+    pub owner: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingCreated {
+    pub listing_id: String,
+    pub invoice_id: String,
+    pub seller: AccountId,
+    pub asking_price: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingUpdated {
+    pub listing_id: String,
+    pub asking_price: U128,
+    pub min_price: Option<U128>,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingExtended {
+    pub listing_id: String,
+    pub invoice_id: String,
+    pub old_expires_at: Option<u64>,
+    pub new_expires_at: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingPurchased {
+    pub listing_id: String,
+    pub invoice_id: String,
+    pub seller: AccountId,
+    pub buyer: AccountId,
+    pub price: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingCancelled {
+    pub listing_id: String,
+    pub invoice_id: String,
+    pub cancelled_by: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BidPlaced {
+    pub bid_id: String,
+    pub listing_id: String,
+    pub bidder: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowCreated {
+    pub escrow_id: String,
+    pub invoice_id: String,
+    pub seller: AccountId,
+    pub buyer: AccountId,
+    pub sale_amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DebtorPaymentReceived {
+    pub escrow_id: String,
+    pub invoice_id: String,
+    pub payer: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EscrowSettled {
+    pub escrow_id: String,
+    pub invoice_id: String,
+    pub recipient: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeOpened {
+    pub escrow_id: String,
+    pub invoice_id: String,
+    pub opened_by: AccountId,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeResolved {
+    pub escrow_id: String,
+    pub invoice_id: String,
+    pub winner: AccountId,
+    pub resolution_ms: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokensRecovered {
+    pub token: AccountId,
+    pub recipient: AccountId,
+    pub amount: U128,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json_shape() {
+        let event = AdelanteEvent::InvoiceCreated(vec![InvoiceCreated {
+            invoice_id: "INV-000001".to_string(),
+            creator: "alice.testnet".parse().unwrap(),
+            amount: U128(2_000_000_000),
+        }]);
+
+        assert_eq!(
+            event.to_log_string(),
+            r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"invoice_created","data":[{"invoice_id":"INV-000001","creator":"alice.testnet","amount":"2000000000"}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E_LISTING_NOT_FOUND: Listing LST-000009 not found")]
+    fn test_error_panics_with_code_prefix() {
+        let listing: Option<u64> = None;
+        ensure!(true, ContractError::Unauthorized, "never raised");
+        listing.or_fail(
+            ContractError::ListingNotFound,
+            &format!("Listing {} not found", "LST-000009"),
+        );
+    }
+
+    #[test]
+    fn test_scan_stops_at_cap_and_returns_cursor() {
+        // Only the last of 2 * MAX_VIEW_SCAN items matches
+        let total = 2 * MAX_VIEW_SCAN;
+        let matches = |n: u64| (n == total - 1).then_some(n);
+
+        let first = PaginatedResult::scan(0..total, None, None, matches);
+        assert!(first.items.is_empty());
+        assert_eq!(first.next_index, Some(MAX_VIEW_SCAN));
+
+        let second = PaginatedResult::scan(0..total, first.next_index, None, matches);
+        assert_eq!(second.items, vec![total - 1]);
+        assert_eq!(second.next_index, None);
+    }
+}
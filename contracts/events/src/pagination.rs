@@ -0,0 +1,67 @@
+//! Paging for view methods shared by the Adelante contracts
+
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::NearSchema;
+
+/// Page size used by paginated views when no limit is given
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
+/// Largest page a paginated view will return
+pub const MAX_PAGE_LIMIT: u64 = 100;
+/// Most items a filtering view examines per call before handing back a cursor
+pub const MAX_VIEW_SCAN: u64 = 500;
+
+/// One page of a view result; `next_index` is None on the last page
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PaginatedResult<T> {
+    pub items: Vec<T>,
+    pub next_index: Option<u64>,
+}
+
+impl<T> PaginatedResult<T> {
+    /// Take one page from `items`, reading at most one element past the page
+    pub fn paginate(items: impl Iterator<Item = T>, from_index: Option<u64>, limit: Option<u64>) -> Self {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+
+        let mut items: Vec<T> = items
+            .skip(from_index as usize)
+            .take(limit as usize + 1)
+            .collect();
+        let next_index = if items.len() as u64 > limit {
+            items.truncate(limit as usize);
+            Some(from_index + limit)
+        } else {
+            None
+        };
+
+        Self { items, next_index }
+    }
+
+    /// Take one page of the items `select` keeps, examining at most MAX_VIEW_SCAN of them
+    /// Here `from_index` and `next_index` are scan positions, not match counts: a page may come
+    /// back short (or empty) with a `next_index` when the scan cap is hit before the page fills
+    pub fn scan<S>(
+        items: impl Iterator<Item = S>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        mut select: impl FnMut(S) -> Option<T>,
+    ) -> Self {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as usize;
+
+        let mut items = items.skip(from_index as usize).peekable();
+        let mut page = Vec::new();
+        let mut position = from_index;
+        while page.len() < limit && position < from_index + MAX_VIEW_SCAN {
+            let Some(item) = items.next() else {
+                break;
+            };
+            position += 1;
+            page.extend(select(item));
+        }
+        let next_index = items.peek().is_some().then_some(position);
+
+        Self { items: page, next_index }
+    }
+}
@@ -2,8 +2,50 @@ use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::store::{IterableMap, LookupMap};
-use near_sdk::{env, near, AccountId, NearToken, PanicOnDefault};
+use near_sdk::{
+    env, ext_contract, near, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue,
+};
 use near_sdk::NearSchema;
+use events::{
+    ensure, AdelanteEvent, ContractError, InvoiceCreated,
+    InvoiceListed, InvoiceSettled, InvoiceSold, InvoiceUpdated, OrFail, PaginatedResult,
+    DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+};
+
+const GAS_FOR_CROSS_CONTRACT: Gas = Gas::from_tgas(10);
+const GAS_FOR_CALLBACK: Gas = Gas::from_tgas(10);
+
+/// Number of ownership snapshots retained before the oldest is evicted
+const MAX_OWNER_SNAPSHOTS: u64 = 10;
+
+/// Term beyond which the risk score stops increasing within its tier
+const MAX_RISK_TERM_DAYS: u64 = 180;
+/// Largest risk reduction granted for a creator's settled invoices
+const MAX_RISK_HISTORY_DISCOUNT: u64 = 10;
+/// Risk reduction for an invoice its debtor has confirmed on-chain
+const DEBTOR_CONFIRMATION_DISCOUNT: u64 = 15;
+
+/// Most holders a split invoice may have, bounding settlement payouts
+const MAX_SHAREHOLDERS: usize = 10;
+
+/// NEP-177 metadata spec version
+const NFT_METADATA_SPEC: &str = "nft-1.0.0";
+
+/// Most invoices accepted by one create_invoices_batch call, bounding its gas
+const MAX_INVOICE_BATCH: usize = 20;
+
+/// Most entries kept in an invoice's ownership history; the creator's entry is never dropped
+const MAX_OWNERSHIP_HISTORY: usize = 20;
+
+/// Most tranches a structured invoice may be cut into, bounding settlement payouts
+const MAX_TRANCHES: usize = 4;
+
+/// Currency of invoices created without one; always supported at init
+const DEFAULT_CURRENCY: &str = "USDC";
+
+/// Smallest storage deposit accepted when registering an account (NEP-145)
+const STORAGE_MIN_BALANCE: NearToken = NearToken::from_millinear(10);
 
 /// Invoice status enum
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
@@ -16,6 +58,40 @@ pub enum InvoiceStatus {
     Settled,
     Disputed,
     Cancelled,
+    /// Sold invoice offered again on the secondary market; its escrow stays in place
+    Relisted,
+    /// Sold invoice whose due date passed before it was settled; otherwise treated as Sold
+    Overdue,
+}
+
+/// Accepted format for an invoice's documents_hash
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum HashFormat {
+    /// Any non-empty string
+    #[default]
+    Permissive,
+    /// IPFS CIDv0 (base58 "Qm...") or CIDv1 (base32 "b...")
+    Cid,
+    /// 64-character hex digest (e.g. SHA-256)
+    Sha256Hex,
+}
+
+impl HashFormat {
+    /// Whether a documents hash conforms to this format
+    pub fn matches(&self, hash: &str) -> bool {
+        match self {
+            HashFormat::Permissive => !hash.is_empty(),
+            HashFormat::Cid => {
+                let base58 = |c: char| c.is_ascii_alphanumeric() && !"0OIl".contains(c);
+                let base32 = |c: char| c.is_ascii_lowercase() || ('2'..='7').contains(&c);
+                (hash.len() == 46 && hash.starts_with("Qm") && hash.chars().all(base58))
+                    || (hash.len() >= 50 && hash.starts_with('b') && hash.chars().all(base32))
+            }
+            HashFormat::Sha256Hex => hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        }
+    }
 }
 
 /// Invoice data structure
@@ -36,18 +112,272 @@ pub struct Invoice {
     pub documents_hash: String,
     pub status: InvoiceStatus,
     pub risk_score: u8,
+    /// Terms version, incremented on every amendment
+    #[serde(default)]
+    pub version: u32,
+    /// Account that receives seller-side settlement proceeds (defaults to the owner)
+    #[serde(default)]
+    pub beneficiary: Option<AccountId>,
+    /// Number of shares the invoice is split into (0 if held whole)
+    #[serde(default)]
+    pub total_shares: u64,
+    /// Debtor's NEAR account, which may attest to the invoice via confirm_invoice
+    #[serde(default)]
+    pub debtor_account: Option<AccountId>,
+    /// Whether debtor_account has acknowledged the invoice
+    #[serde(default)]
+    pub debtor_confirmed: bool,
+    /// Senior-to-junior slices of the amount, paid in order at settlement (empty if unstructured)
+    #[serde(default)]
+    pub tranches: Vec<Tranche>,
+    /// Whether risk_score was set by an underwriter; automated rescoring then leaves it alone
+    #[serde(default)]
+    pub risk_reviewed: bool,
+    /// Each owner and when they acquired the invoice, creator first
+    /// Bounded by MAX_OWNERSHIP_HISTORY: past it the oldest post-creation entries are dropped
+    #[serde(default)]
+    pub ownership_history: Vec<(AccountId, u64)>,
+}
+
+/// Terms of a tranche requested at invoice creation
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TrancheInput {
+    pub amount: U128,
+    /// Target yield in basis points; junior tranches must offer more than senior ones
+    pub yield_bps: u16,
+}
+
+/// A slice of a structured invoice; earlier tranches are senior and are paid in full first
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct Tranche {
+    pub amount: U128,
+    pub yield_bps: u16,
+    pub holder: AccountId,
+}
+
+/// An account's stake in a fractionally owned invoice
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ShareHolding {
+    pub account_id: AccountId,
+    pub shares: u64,
+}
+
+/// NEP-177 contract metadata
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NFTContractMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub base_uri: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+}
+
+/// NEP-177 token metadata
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub media_hash: Option<String>,
+    pub copies: Option<u64>,
+    pub issued_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub starts_at: Option<u64>,
+    pub updated_at: Option<u64>,
+    pub extra: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+}
+
+/// NEP-171 token view of an invoice
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NFTToken {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub metadata: Option<TokenMetadata>,
+}
+
+impl From<&Invoice> for NFTToken {
+    fn from(invoice: &Invoice) -> Self {
+        Self {
+            token_id: invoice.id.clone(),
+            owner_id: invoice.owner.clone(),
+            metadata: Some(TokenMetadata {
+                title: Some(format!("Invoice {} ({})", invoice.id, invoice.debtor_name)),
+                description: Some(invoice.description.clone()),
+                media: None,
+                media_hash: None,
+                copies: Some(1),
+                issued_at: Some(invoice.created_at),
+                expires_at: Some(invoice.due_date),
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: Some(invoice.documents_hash.clone()),
+                reference_hash: None,
+            }),
+        }
+    }
+}
+
+/// Progress of an owner snapshot recorded one page at a time
+/// Resume an unfinished snapshot by passing its id and `next_index` back to snapshot_owners
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SnapshotProgress {
+    pub snapshot_id: u64,
+    /// Position of the next invoice to record, or None once the snapshot is complete
+    pub next_index: Option<u64>,
+}
+
+/// NEP-145 storage balance of an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// NEP-145 storage balance bounds
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+/// Storage deposit held for an account and the bytes its invoices occupy
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct StorageAccount {
+    pub deposited: u128,
+    pub used_bytes: u64,
+}
+
+impl StorageAccount {
+    fn used_cost(&self) -> u128 {
+        self.used_bytes as u128 * env::storage_byte_cost().as_yoctonear()
+    }
+
+    fn to_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: U128(self.deposited),
+            available: U128(self.deposited.saturating_sub(self.used_cost())),
+        }
+    }
+}
+
+/// Terms of one invoice in a create_invoices_batch call
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct InvoiceInput {
+    pub amount: U128,
+    pub debtor_name: String,
+    pub debtor_email: Option<String>,
+    pub description: String,
+    pub due_date: u64,
+    pub documents_hash: String,
+    pub beneficiary: Option<AccountId>,
+    /// Currency code from the supported list; defaults to USDC
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Optional senior-to-junior tranches whose amounts sum to the invoice amount
+    #[serde(default)]
+    pub tranches: Option<Vec<TrancheInput>>,
+}
+
+/// Archived invoice terms, recorded when an invoice is amended
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct InvoiceVersion {
+    pub version: u32,
+    pub amount: U128,
+    pub due_date: u64,
+    pub risk_score: u8,
+    pub archived_at: u64,
+}
+
+/// Cross-contract interface for Marketplace contract
+#[ext_contract(ext_marketplace)]
+pub trait MarketplaceContract {
+    fn refresh_listing_terms(&mut self, invoice_id: String, invoice_amount: U128, due_date: u64);
+    fn refresh_listing_risk(&mut self, invoice_id: String, risk_score: u8);
+}
+
+/// Invoice as stored by the original release, before versioning, shares and tranches
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct OldInvoice {
+    id: String,
+    creator: AccountId,
+    owner: AccountId,
+    amount: U128,
+    currency: String,
+    debtor_name: String,
+    debtor_email: Option<String>,
+    description: String,
+    due_date: u64,
+    created_at: u64,
+    documents_hash: String,
+    status: InvoiceStatus,
+    risk_score: u8,
+}
+
+impl From<OldInvoice> for Invoice {
+    fn from(old: OldInvoice) -> Self {
+        // When a resold invoice changed hands wasn't recorded; its owner is dated by creation
+        let mut ownership_history = vec![(old.creator.clone(), old.created_at)];
+        if old.owner != old.creator {
+            ownership_history.push((old.owner.clone(), old.created_at));
+        }
+        Invoice {
+            id: old.id,
+            creator: old.creator,
+            owner: old.owner,
+            amount: old.amount,
+            currency: old.currency,
+            debtor_name: old.debtor_name,
+            debtor_email: old.debtor_email,
+            description: old.description,
+            due_date: old.due_date,
+            created_at: old.created_at,
+            documents_hash: old.documents_hash,
+            status: old.status,
+            risk_score: old.risk_score,
+            version: 1,
+            beneficiary: None,
+            total_shares: 0,
+            debtor_account: None,
+            debtor_confirmed: false,
+            tranches: Vec::new(),
+            risk_reviewed: false,
+            ownership_history,
+        }
+    }
 }
 
-/// Old contract state (for migration from pre-admin version)
-#[derive(BorshDeserialize)]
+/// Old contract state (for migration from the original release)
+#[derive(BorshDeserialize, BorshSerialize)]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct OldInvoiceContract {
-    invoices: IterableMap<String, Invoice>,
+    invoices: IterableMap<String, OldInvoice>,
     invoices_by_creator: LookupMap<AccountId, Vec<String>>,
     invoices_by_owner: LookupMap<AccountId, Vec<String>>,
     invoice_count: u64,
     marketplace_contract: AccountId,
     escrow_contract: AccountId,
+    admin: AccountId,
 }
 
 /// Invoice NFT Contract
@@ -61,6 +391,21 @@ pub struct InvoiceContract {
     marketplace_contract: AccountId,
     escrow_contract: AccountId,
     admin: AccountId,
+    invoice_versions: LookupMap<String, Vec<InvoiceVersion>>,
+    /// Owner of each invoice keyed by (snapshot id, invoice id)
+    owner_snapshots: LookupMap<(u64, String), AccountId>,
+    snapshot_count: u64,
+    /// Position of the next invoice to record while the latest snapshot is unfinished
+    snapshot_cursor: Option<u64>,
+    hash_format: HashFormat,
+    share_holdings: LookupMap<String, Vec<ShareHolding>>,
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    require_debtor_confirmation: bool,
+    supported_currencies: IterableMap<String, bool>,
+    /// Accounts allowed routine maintenance (owner snapshots, counter reconciliation)
+    operators: LookupMap<AccountId, bool>,
+    /// Number of each creator's invoices that have settled, for risk scoring
+    settled_by_creator: LookupMap<AccountId, u64>,
 }
 
 #[near]
@@ -68,6 +413,8 @@ impl InvoiceContract {
     /// Initialize the contract
     #[init]
     pub fn new(marketplace_contract: AccountId, escrow_contract: AccountId, admin: AccountId) -> Self {
+        let mut supported_currencies = IterableMap::new(b"u");
+        supported_currencies.insert(DEFAULT_CURRENCY.to_string(), true);
         Self {
             invoices: IterableMap::new(b"i"),
             invoices_by_creator: LookupMap::new(b"c"),
@@ -76,27 +423,71 @@ impl InvoiceContract {
             marketplace_contract,
             escrow_contract,
             admin,
+            invoice_versions: LookupMap::new(b"v"),
+            owner_snapshots: LookupMap::new(b"s"),
+            snapshot_count: 0,
+            snapshot_cursor: None,
+            hash_format: HashFormat::Permissive,
+            share_holdings: LookupMap::new(b"h"),
+            storage_accounts: LookupMap::new(b"b"),
+            require_debtor_confirmation: false,
+            supported_currencies,
+            operators: LookupMap::new(b"p"),
+            settled_by_creator: LookupMap::new(b"t"),
         }
     }
 
-    /// Migrate from old state to new state (adds admin field)
+    /// Migrate from the original release's state (see OldInvoiceContract)
+    /// That layout held only the invoice map, the creator/owner indexes, invoice_count, the
+    /// contract accounts and admin; every invoice is rewritten in the current layout, so gas
+    /// grows with the number of invoices
     #[private]
     #[init(ignore_state)]
-    pub fn migrate(admin: AccountId) -> Self {
-        let old: OldInvoiceContract = env::state_read().expect("Failed to read old state");
+    pub fn migrate() -> Self {
+        let mut old: OldInvoiceContract = env::state_read()
+            .or_fail(ContractError::InvalidState, "Failed to read old state");
+        let old_invoices: Vec<(String, OldInvoice)> = old.invoices.drain().collect();
+        old.invoices.flush();
+
+        let mut invoices = IterableMap::new(b"i");
+        let mut settled_by_creator = LookupMap::new(b"t");
+        for (invoice_id, invoice) in old_invoices {
+            let invoice = Invoice::from(invoice);
+            if invoice.status == InvoiceStatus::Settled {
+                let settled = settled_by_creator.get(&invoice.creator).copied().unwrap_or(0);
+                settled_by_creator.insert(invoice.creator.clone(), settled + 1);
+            }
+            invoices.insert(invoice_id, invoice);
+        }
+
+        let mut supported_currencies = IterableMap::new(b"u");
+        supported_currencies.insert(DEFAULT_CURRENCY.to_string(), true);
         Self {
-            invoices: old.invoices,
+            invoices,
             invoices_by_creator: old.invoices_by_creator,
             invoices_by_owner: old.invoices_by_owner,
             invoice_count: old.invoice_count,
             marketplace_contract: old.marketplace_contract,
             escrow_contract: old.escrow_contract,
-            admin,
+            admin: old.admin,
+            invoice_versions: LookupMap::new(b"v"),
+            owner_snapshots: LookupMap::new(b"s"),
+            snapshot_count: 0,
+            snapshot_cursor: None,
+            hash_format: HashFormat::Permissive,
+            share_holdings: LookupMap::new(b"h"),
+            storage_accounts: LookupMap::new(b"b"),
+            require_debtor_confirmation: false,
+            supported_currencies,
+            operators: LookupMap::new(b"p"),
+            settled_by_creator,
         }
     }
 
-    /// Create a new invoice
+    /// Create a new invoice, paying for its storage from the caller's storage balance
+    /// Any attached deposit is added to that balance first
     #[payable]
+    #[allow(clippy::too_many_arguments)]
     pub fn create_invoice(
         &mut self,
         amount: U128,
@@ -105,371 +496,3647 @@ impl InvoiceContract {
         description: String,
         due_date: u64,
         documents_hash: String,
+        beneficiary: Option<AccountId>,
+        currency: Option<String>,
+        tranches: Option<Vec<TrancheInput>>,
     ) -> String {
-        // Require small deposit for storage
-        let deposit = env::attached_deposit();
-        assert!(
-            deposit >= NearToken::from_millinear(10),
-            "Requires 0.01 NEAR deposit for storage"
-        );
-
+        let initial_storage = env::storage_usage();
         let creator = env::predecessor_account_id();
-        self.invoice_count += 1;
-        let id = format!("INV-{:06}", self.invoice_count);
-
-        // Validate inputs
-        assert!(!debtor_name.is_empty(), "Debtor name required");
-        assert!(!description.is_empty(), "Description required");
-        assert!(!documents_hash.is_empty(), "Documents hash required");
-        assert!(amount.0 > 0, "Amount must be greater than 0");
-        assert!(
-            due_date > env::block_timestamp_ms(),
-            "Due date must be in the future"
-        );
-
-        // Calculate risk score based on amount and due date
-        let days_until_due =
-            (due_date.saturating_sub(env::block_timestamp_ms())) / (24 * 60 * 60 * 1000);
-        let amount_val = amount.0;
-
-        let risk_score = if amount_val < 1_000_000_000 && days_until_due < 30 {
-            // Small amount, short term = low risk
-            20 + (env::block_timestamp() % 10) as u8
-        } else if amount_val < 5_000_000_000 && days_until_due < 60 {
-            // Medium amount, medium term = medium risk
-            40 + (env::block_timestamp() % 15) as u8
-        } else if amount_val < 10_000_000_000 && days_until_due < 90 {
-            // Larger amount, longer term = higher risk
-            55 + (env::block_timestamp() % 15) as u8
-        } else {
-            // Large amount or long term = highest risk
-            70 + (env::block_timestamp() % 20) as u8
-        };
-
-        let invoice = Invoice {
-            id: id.clone(),
-            creator: creator.clone(),
-            owner: creator.clone(),
+        let input = InvoiceInput {
             amount,
-            currency: "USDC".to_string(),
             debtor_name,
             debtor_email,
             description,
             due_date,
-            created_at: env::block_timestamp_ms(),
             documents_hash,
-            status: InvoiceStatus::Draft,
-            risk_score: risk_score.min(99),
+            beneficiary,
+            currency,
+            tranches,
         };
+        self.assert_valid_input(&input);
+        let id = self.insert_invoice(&creator, input);
 
-        self.invoices.insert(id.clone(), invoice);
+        self.charge_created_storage(&creator, initial_storage);
+        id
+    }
 
-        // Update creator index
-        let mut creator_invoices = self
-            .invoices_by_creator
-            .get(&creator)
-            .cloned()
-            .unwrap_or_default();
-        creator_invoices.push(id.clone());
-        self.invoices_by_creator
-            .insert(creator.clone(), creator_invoices);
+    /// Create several invoices in one call; the batch fails as a whole if any invoice is invalid
+    /// Storage for all of them is paid from the caller's storage balance, topped up by any deposit
+    #[payable]
+    pub fn create_invoices_batch(&mut self, invoices: Vec<InvoiceInput>) -> Vec<String> {
+        ensure!(!invoices.is_empty(), ContractError::InvalidArgument, "Batch is empty");
+        ensure!(
+            invoices.len() <= MAX_INVOICE_BATCH,
+            ContractError::InvalidArgument,
+            "Too many invoices in batch (max {})",
+            MAX_INVOICE_BATCH
+        );
+        let initial_storage = env::storage_usage();
+        let creator = env::predecessor_account_id();
 
-        // Update owner index
-        let mut owner_invoices = self
-            .invoices_by_owner
-            .get(&creator)
+        // Validate everything before the first insert
+        for input in invoices.iter() {
+            self.assert_valid_input(input);
+        }
+        let ids: Vec<String> = invoices
+            .into_iter()
+            .map(|input| self.insert_invoice(&creator, input))
+            .collect();
+
+        self.charge_created_storage(&creator, initial_storage);
+        ids
+    }
+
+    /// Add a storage deposit for an account (NEP-145)
+    /// With `registration_only`, only the minimum balance is kept and the rest is refunded
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let predecessor = env::predecessor_account_id();
+        let account_id = account_id.unwrap_or_else(|| predecessor.clone());
+        let deposit = env::attached_deposit().as_yoctonear();
+        let mut account = self.storage_accounts.get(&account_id).cloned();
+
+        let mut credited = deposit;
+        if registration_only.unwrap_or(false) {
+            credited = if account.is_some() {
+                0
+            } else {
+                STORAGE_MIN_BALANCE.as_yoctonear()
+            };
+        }
+        if account.is_none() {
+            ensure!(
+                credited >= STORAGE_MIN_BALANCE.as_yoctonear(),
+                ContractError::InsufficientPayment,
+                "The attached deposit is less than the minimum storage balance"
+            );
+        }
+
+        let entry = account.get_or_insert_with(StorageAccount::default);
+        entry.deposited += credited;
+        let balance = entry.to_balance();
+        self.storage_accounts.insert(account_id, entry.clone());
+
+        let refund = deposit - credited;
+        if refund > 0 {
+            let _ = Promise::new(predecessor).transfer(NearToken::from_yoctonear(refund));
+        }
+        balance
+    }
+
+    /// Withdraw unused storage deposit (NEP-145); withdraws everything available when no amount is given
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        ensure!(
+            env::attached_deposit() == NearToken::from_yoctonear(1),
+            ContractError::InvalidDeposit,
+            "Requires attached deposit of exactly 1 yoctoNEAR"
+        );
+        let account_id = env::predecessor_account_id();
+        let mut account = self
+            .storage_accounts
+            .get(&account_id)
             .cloned()
-            .unwrap_or_default();
-        owner_invoices.push(id.clone());
-        self.invoices_by_owner.insert(creator, owner_invoices);
+            .or_fail(ContractError::AccountNotRegistered, "Account is not registered");
 
-        env::log_str(&format!("Invoice created: {}", id));
-        id
+        let available = account.to_balance().available.0;
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        ensure!(
+            amount <= available,
+            ContractError::InsufficientBalance,
+            "Withdrawal exceeds available storage balance"
+        );
+
+        account.deposited -= amount;
+        let balance = account.to_balance();
+        self.storage_accounts.insert(account_id.clone(), account);
+
+        if amount > 0 {
+            let _ = Promise::new(account_id).transfer(NearToken::from_yoctonear(amount));
+        }
+        balance
+    }
+
+    /// Get an account's storage balance (NEP-145)
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(&account_id)
+            .map(|account| account.to_balance())
+    }
+
+    /// Get the storage deposit bounds (NEP-145)
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(STORAGE_MIN_BALANCE.as_yoctonear()),
+            max: None,
+        }
     }
 
     /// Transfer invoice ownership (called by marketplace during sale)
     pub fn transfer_invoice(&mut self, invoice_id: String, new_owner: AccountId) {
         let caller = env::predecessor_account_id();
-        assert!(
+        ensure!(
             caller == self.marketplace_contract,
+            ContractError::Unauthorized,
             "Only marketplace can transfer invoices"
         );
 
         let mut invoice = self
             .invoices
             .get(&invoice_id)
-            .expect("Invoice not found")
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
             .clone();
-        assert!(
-            invoice.status == InvoiceStatus::Listed,
+        ensure!(
+            matches!(invoice.status, InvoiceStatus::Listed | InvoiceStatus::Relisted),
+            ContractError::InvalidState,
             "Invoice must be listed"
         );
 
-        let old_owner = invoice.owner.clone();
-
-        // Update owner
-        invoice.owner = new_owner.clone();
+        let old_owner = self.reassign_owner(&mut invoice, &new_owner);
         invoice.status = InvoiceStatus::Sold;
         self.invoices.insert(invoice_id.clone(), invoice);
 
-        // Update owner indexes
-        if let Some(mut old_owner_invoices) = self.invoices_by_owner.get(&old_owner).cloned() {
-            old_owner_invoices.retain(|id| id != &invoice_id);
-            self.invoices_by_owner.insert(old_owner, old_owner_invoices);
-        }
-
-        let mut new_owner_invoices = self
-            .invoices_by_owner
-            .get(&new_owner)
-            .cloned()
-            .unwrap_or_default();
-        new_owner_invoices.push(invoice_id.clone());
-        self.invoices_by_owner.insert(new_owner.clone(), new_owner_invoices);
-
         env::log_str(&format!(
             "Invoice {} transferred to {}",
             invoice_id, new_owner
         ));
+        AdelanteEvent::InvoiceSold(vec![InvoiceSold {
+            invoice_id,
+            old_owner,
+            new_owner,
+        }])
+        .emit();
     }
 
-    /// Mark invoice as settled
-    pub fn mark_settled(&mut self, invoice_id: String) {
+    /// Transfer an invoice directly to another account, e.g. a gift or OTC sale (owner only)
+    /// Listed invoices can only change hands through the marketplace
+    pub fn owner_transfer(&mut self, invoice_id: String, new_owner: AccountId) {
         let caller = env::predecessor_account_id();
-        assert!(
-            caller == self.escrow_contract || caller == self.marketplace_contract,
-            "Unauthorized"
-        );
-
         let mut invoice = self
             .invoices
             .get(&invoice_id)
-            .expect("Invoice not found")
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
             .clone();
-        assert!(
-            invoice.status == InvoiceStatus::Sold,
-            "Invoice must be sold to settle"
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can transfer invoice"
+        );
+        ensure!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Draft | InvoiceStatus::Sold | InvoiceStatus::Overdue
+            ),
+            ContractError::InvalidState,
+            "Only draft or sold invoices can be transferred by their owner"
+        );
+        ensure!(
+            new_owner != caller,
+            ContractError::InvalidArgument,
+            "New owner must differ from the current owner"
         );
 
-        invoice.status = InvoiceStatus::Settled;
+        self.reassign_owner(&mut invoice, &new_owner);
         self.invoices.insert(invoice_id.clone(), invoice);
 
-        env::log_str(&format!("Invoice {} settled", invoice_id));
+        env::log_str(&format!(
+            "Invoice {} transferred by {} to {}",
+            invoice_id, caller, new_owner
+        ));
     }
 
-    /// Update invoice status to Listed
-    pub fn set_listed(&mut self, invoice_id: String) {
+    /// Split an invoice into shares held by its owner, enabling fractional sales (owner only)
+    pub fn split_into_shares(&mut self, invoice_id: String, total_shares: u64) {
         let caller = env::predecessor_account_id();
         let mut invoice = self
             .invoices
             .get(&invoice_id)
-            .expect("Invoice not found")
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
             .clone();
 
-        // Allow marketplace or owner to set listed
-        assert!(
-            invoice.owner == caller || caller == self.marketplace_contract,
-            "Only owner or marketplace can list invoice"
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can split invoice"
         );
-        assert!(
-            invoice.status == InvoiceStatus::Draft,
-            "Invoice must be in Draft status"
+        ensure!(
+            invoice.status == InvoiceStatus::Draft || invoice.status == InvoiceStatus::Listed,
+            ContractError::InvalidState,
+            "Invoice must be draft or listed"
+        );
+        ensure!(
+            invoice.total_shares == 0,
+            ContractError::AlreadyExists,
+            "Invoice is already split"
+        );
+        ensure!(
+            invoice.tranches.is_empty(),
+            ContractError::InvalidState,
+            "Tranched invoices cannot be split into shares"
+        );
+        ensure!(
+            total_shares >= 2,
+            ContractError::InvalidArgument,
+            "Invoice must be split into at least 2 shares"
         );
 
-        invoice.status = InvoiceStatus::Listed;
+        invoice.total_shares = total_shares;
         self.invoices.insert(invoice_id.clone(), invoice);
+        self.share_holdings.insert(
+            invoice_id.clone(),
+            vec![ShareHolding {
+                account_id: caller,
+                shares: total_shares,
+            }],
+        );
 
-        env::log_str(&format!("Invoice {} listed", invoice_id));
+        env::log_str(&format!(
+            "Invoice {} split into {} shares",
+            invoice_id, total_shares
+        ));
     }
 
-    /// Cancel an invoice (only draft invoices)
-    pub fn cancel_invoice(&mut self, invoice_id: String) {
+    /// Transfer shares from the invoice owner (called by marketplace during a fractional sale)
+    pub fn transfer_shares(&mut self, invoice_id: String, new_owner: AccountId, shares: u64) {
         let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Only marketplace can transfer shares"
+        );
+
         let mut invoice = self
             .invoices
             .get(&invoice_id)
-            .expect("Invoice not found")
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
             .clone();
-
-        assert!(invoice.owner == caller, "Only owner can cancel invoice");
-        assert!(
-            invoice.status == InvoiceStatus::Draft,
-            "Can only cancel draft invoices"
+        ensure!(
+            invoice.total_shares > 0,
+            ContractError::InvalidState,
+            "Invoice is not split into shares"
+        );
+        ensure!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Listed | InvoiceStatus::Sold | InvoiceStatus::Overdue
+            ),
+            ContractError::InvalidState,
+            "Invoice must be listed or sold"
+        );
+        ensure!(shares > 0, ContractError::InvalidAmount, "Shares must be greater than 0");
+        ensure!(
+            new_owner != invoice.owner,
+            ContractError::InvalidArgument,
+            "New owner must differ from current owner"
         );
 
-        invoice.status = InvoiceStatus::Cancelled;
-        self.invoices.insert(invoice_id.clone(), invoice);
+        let old_owner = invoice.owner.clone();
+        let remaining = self.move_shares(&invoice_id, &old_owner, &new_owner, shares);
+        self.add_to_owner_index(&new_owner, &invoice_id);
 
-        env::log_str(&format!("Invoice {} cancelled", invoice_id));
+        env::log_str(&format!(
+            "Transferred {} of {} shares in invoice {} to {}",
+            shares, invoice.total_shares, invoice_id, new_owner
+        ));
+
+        // Selling the last share hands over the invoice itself
+        if remaining == 0 {
+            if let Some(mut old_owner_invoices) = self.invoices_by_owner.get(&old_owner).cloned() {
+                old_owner_invoices.retain(|id| id != &invoice_id);
+                self.invoices_by_owner.insert(old_owner.clone(), old_owner_invoices);
+            }
+            invoice.owner = new_owner.clone();
+            if invoice.status == InvoiceStatus::Listed {
+                invoice.status = InvoiceStatus::Sold;
+            }
+            Self::record_owner(&mut invoice);
+            self.invoices.insert(invoice_id.clone(), invoice);
+
+            AdelanteEvent::InvoiceSold(vec![InvoiceSold {
+                invoice_id,
+                old_owner,
+                new_owner,
+            }])
+            .emit();
+        }
     }
 
-    /// Unlist an invoice (revert to draft)
-    pub fn unlist_invoice(&mut self, invoice_id: String) {
+    /// Hand a tranche to a new holder, who then receives its share of settlement (holder only)
+    pub fn transfer_tranche(&mut self, invoice_id: String, index: u32, new_holder: AccountId) {
         let caller = env::predecessor_account_id();
         let mut invoice = self
             .invoices
             .get(&invoice_id)
-            .expect("Invoice not found")
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
             .clone();
+        ensure!(
+            invoice.status != InvoiceStatus::Settled,
+            ContractError::InvalidState,
+            "Invoice is already settled"
+        );
 
-        assert!(
-            invoice.owner == caller || caller == self.marketplace_contract,
-            "Unauthorized"
+        let tranche = invoice
+            .tranches
+            .get_mut(index as usize)
+            .or_fail(ContractError::InvalidArgument, "Tranche not found");
+        ensure!(
+            tranche.holder == caller,
+            ContractError::Unauthorized,
+            "Only the tranche holder can transfer it"
         );
-        assert!(
-            invoice.status == InvoiceStatus::Listed,
-            "Invoice must be listed"
+        ensure!(
+            new_holder != caller,
+            ContractError::InvalidArgument,
+            "New holder must differ from current holder"
         );
-
-        invoice.status = InvoiceStatus::Draft;
+        tranche.holder = new_holder.clone();
         self.invoices.insert(invoice_id.clone(), invoice);
 
-        env::log_str(&format!("Invoice {} unlisted", invoice_id));
-    }
-
-    /// Update marketplace contract (admin only)
-    pub fn set_marketplace_contract(&mut self, marketplace_contract: AccountId) {
-        let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can update marketplace contract");
-        self.marketplace_contract = marketplace_contract;
+        env::log_str(&format!(
+            "Tranche {} of invoice {} transferred by {} to {}",
+            index, invoice_id, caller, new_holder
+        ));
     }
 
-    /// Update escrow contract (admin only)
-    pub fn set_escrow_contract(&mut self, escrow_contract: AccountId) {
-        let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can update escrow contract");
-        self.escrow_contract = escrow_contract;
+    /// NEP-171 transfer; the marketplace moves listed invoices under transfer_invoice rules, and
+    /// owners move their own invoices under owner_transfer rules, which exclude listed ones
+    #[payable]
+    pub fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        ensure!(
+            env::attached_deposit() == NearToken::from_yoctonear(1),
+            ContractError::InvalidDeposit,
+            "Requires attached deposit of exactly 1 yoctoNEAR"
+        );
+        ensure!(
+            approval_id.is_none(),
+            ContractError::InvalidArgument,
+            "Approvals are not supported"
+        );
+        if let Some(memo) = memo {
+            env::log_str(&format!("Memo: {}", memo));
+        }
+        if env::predecessor_account_id() == self.marketplace_contract {
+            self.transfer_invoice(token_id, receiver_id);
+        } else {
+            self.owner_transfer(token_id, receiver_id);
+        }
     }
 
-    /// Update admin (current admin only)
-    pub fn set_admin(&mut self, new_admin: AccountId) {
+    /// Flag a sold invoice whose due date has passed as Overdue (callable by anyone)
+    /// Overdue is accepted wherever Sold is; listed invoices stay listed so they can be unlisted
+    pub fn mark_invoice_overdue(&mut self, invoice_id: String) {
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+        ensure!(
+            invoice.status == InvoiceStatus::Sold && env::block_timestamp_ms() > invoice.due_date,
+            ContractError::InvalidState,
+            "Invoice is not overdue"
+        );
+
+        invoice.status = InvoiceStatus::Overdue;
+        self.invoices.insert(invoice_id.clone(), invoice);
+        env::log_str(&format!("Invoice {} marked overdue", invoice_id));
+    }
+
+    /// Mark invoice as settled
+    pub fn mark_settled(&mut self, invoice_id: String) {
         let caller = env::predecessor_account_id();
-        assert!(caller == self.admin, "Only admin can change admin");
-        self.admin = new_admin;
+        ensure!(
+            caller == self.escrow_contract || caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Unauthorized"
+        );
+
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+        // A resale listing does not hold up settlement of the underlying escrow, nor does lateness
+        ensure!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::Sold | InvoiceStatus::Relisted | InvoiceStatus::Overdue
+            ),
+            ContractError::InvalidState,
+            "Invoice must be sold to settle"
+        );
+
+        invoice.status = InvoiceStatus::Settled;
+        self.invoices.insert(invoice_id.clone(), invoice.clone());
+        let settled = self.settled_by_creator.get(&invoice.creator).copied().unwrap_or(0);
+        self.settled_by_creator.insert(invoice.creator.clone(), settled + 1);
+
+        env::log_str(&format!("Invoice {} settled", invoice_id));
+        AdelanteEvent::InvoiceSettled(vec![InvoiceSettled {
+            invoice_id,
+            owner: invoice.owner,
+        }])
+        .emit();
+    }
+
+    /// Update invoice status to Listed for `seller`, returning the listed invoice
+    /// The marketplace lists on behalf of the seller, who must own the invoice
+    pub fn set_listed(&mut self, invoice_id: String, seller: AccountId) -> Invoice {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        // Allow marketplace or owner to set listed
+        ensure!(
+            caller == seller || caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Only owner or marketplace can list invoice"
+        );
+        ensure!(
+            invoice.owner == seller,
+            ContractError::Unauthorized,
+            "Seller {} does not own invoice {}",
+            seller,
+            invoice_id
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Draft,
+            ContractError::InvalidState,
+            "Invoice must be in Draft status"
+        );
+        ensure!(
+            !self.require_debtor_confirmation || invoice.debtor_confirmed,
+            ContractError::InvalidState,
+            "Debtor has not confirmed invoice"
+        );
+
+        invoice.status = InvoiceStatus::Listed;
+        self.invoices.insert(invoice_id.clone(), invoice.clone());
+
+        env::log_str(&format!("Invoice {} listed", invoice_id));
+        AdelanteEvent::InvoiceListed(vec![InvoiceListed {
+            invoice_id,
+            owner: invoice.owner.clone(),
+        }])
+        .emit();
+        invoice
+    }
+
+    /// Offer a purchased invoice for resale by `seller`, returning it (owner or marketplace)
+    /// The escrow from the first sale is untouched; a resale moves its position to the new buyer
+    pub fn relist_invoice(&mut self, invoice_id: String, seller: AccountId) -> Invoice {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            caller == seller || caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Only owner or marketplace can list invoice"
+        );
+        ensure!(
+            invoice.owner == seller,
+            ContractError::Unauthorized,
+            "Seller {} does not own invoice {}",
+            seller,
+            invoice_id
+        );
+        ensure!(
+            matches!(invoice.status, InvoiceStatus::Sold | InvoiceStatus::Overdue),
+            ContractError::InvalidState,
+            "Only sold invoices can be relisted"
+        );
+
+        invoice.status = InvoiceStatus::Relisted;
+        self.invoices.insert(invoice_id.clone(), invoice.clone());
+
+        env::log_str(&format!("Invoice {} relisted", invoice_id));
+        AdelanteEvent::InvoiceListed(vec![InvoiceListed {
+            invoice_id,
+            owner: invoice.owner.clone(),
+        }])
+        .emit();
+        invoice
+    }
+
+    /// Name the debtor account allowed to confirm a draft invoice (owner only)
+    pub fn set_debtor_account(&mut self, invoice_id: String, debtor_account: Option<AccountId>) {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can set debtor account"
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Draft,
+            ContractError::InvalidState,
+            "Can only update draft invoices"
+        );
+        ensure!(
+            !invoice.debtor_confirmed,
+            ContractError::AlreadyExists,
+            "Debtor has already confirmed invoice"
+        );
+
+        invoice.debtor_account = debtor_account;
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        env::log_str(&format!("Invoice {} debtor account updated", invoice_id));
+    }
+
+    /// Acknowledge an invoice as its debtor (named debtor account only), lowering its risk score
+    pub fn confirm_invoice(&mut self, invoice_id: String) {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.debtor_account.as_ref() == Some(&caller),
+            ContractError::Unauthorized,
+            "Only the debtor account can confirm invoice"
+        );
+        ensure!(
+            matches!(invoice.status, InvoiceStatus::Draft | InvoiceStatus::Listed),
+            ContractError::InvalidState,
+            "Invoice must be draft or listed"
+        );
+        ensure!(
+            !invoice.debtor_confirmed,
+            ContractError::AlreadyExists,
+            "Debtor has already confirmed invoice"
+        );
+
+        invoice.debtor_confirmed = true;
+        if !invoice.risk_reviewed {
+            invoice.risk_score = self.calculate_risk_score(
+                &invoice.creator,
+                invoice.amount,
+                invoice.due_date,
+                true,
+            );
+        }
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        env::log_str(&format!("Invoice {} confirmed by debtor {}", invoice_id, caller));
+    }
+
+    /// Set or clear the settlement beneficiary of a draft invoice (owner only)
+    pub fn set_beneficiary(&mut self, invoice_id: String, beneficiary: Option<AccountId>) {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can update beneficiary"
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Draft,
+            ContractError::InvalidState,
+            "Can only update draft invoices"
+        );
+
+        invoice.beneficiary = beneficiary;
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        env::log_str(&format!("Invoice {} beneficiary updated", invoice_id));
+    }
+
+    /// Correct the debtor details, description or documents of a draft invoice (owner only)
+    /// Amount and due date stay fixed; any storage growth is paid from the owner's storage balance
+    #[payable]
+    pub fn update_invoice(
+        &mut self,
+        invoice_id: String,
+        debtor_name: String,
+        debtor_email: Option<String>,
+        description: String,
+        documents_hash: String,
+    ) {
+        let initial_storage = env::storage_usage();
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can update invoice"
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Draft,
+            ContractError::InvalidState,
+            "Can only update draft invoices"
+        );
+        self.assert_valid_metadata(&debtor_name, &description, &documents_hash);
+
+        invoice.debtor_name = debtor_name;
+        invoice.debtor_email = debtor_email;
+        invoice.description = description;
+        invoice.documents_hash = documents_hash.clone();
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        self.invoices.flush();
+        let used_bytes = env::storage_usage().saturating_sub(initial_storage);
+        self.charge_storage(&caller, env::attached_deposit().as_yoctonear(), used_bytes);
+
+        env::log_str(&format!("Invoice {} updated", invoice_id));
+        AdelanteEvent::InvoiceUpdated(vec![InvoiceUpdated {
+            invoice_id,
+            owner: caller,
+            documents_hash,
+        }])
+        .emit();
+    }
+
+    /// Cancel a draft invoice, deleting it and crediting its storage to the creator's balance
+    pub fn cancel_invoice(&mut self, invoice_id: String) {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can cancel invoice"
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Draft,
+            ContractError::InvalidState,
+            "Can only cancel draft invoices"
+        );
+
+        invoice.status = InvoiceStatus::Cancelled;
+        env::log_str(&format!("Invoice {} cancelled", invoice_id));
+        self.delete_invoice(invoice);
+    }
+
+    /// Delete an invoice cancelled before cancellation removed invoices, crediting its storage
+    pub fn delete_cancelled_invoice(&mut self, invoice_id: String) {
+        let caller = env::predecessor_account_id();
+        let invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can delete invoice"
+        );
+        ensure!(
+            invoice.status == InvoiceStatus::Cancelled,
+            ContractError::InvalidState,
+            "Can only delete cancelled invoices"
+        );
+
+        self.delete_invoice(invoice);
+    }
+
+    /// Unlist an invoice (revert to draft)
+    pub fn unlist_invoice(&mut self, invoice_id: String) {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller || caller == self.marketplace_contract,
+            ContractError::Unauthorized,
+            "Unauthorized"
+        );
+        invoice.status = match invoice.status {
+            InvoiceStatus::Listed => InvoiceStatus::Draft,
+            InvoiceStatus::Relisted => InvoiceStatus::Sold,
+            _ => ContractError::InvalidState.panic("Invoice must be listed"),
+        };
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        env::log_str(&format!("Invoice {} unlisted", invoice_id));
+    }
+
+    /// Amend the terms of a draft or listed invoice, archiving the prior version
+    /// A listed invoice's new terms must be accepted by the marketplace, which refuses them once a
+    /// sale is in flight or bids are locked; otherwise the amendment is rolled back
+    pub fn amend_invoice(
+        &mut self,
+        invoice_id: String,
+        new_amount: U128,
+        new_due_date: u64,
+    ) -> PromiseOrValue<()> {
+        let caller = env::predecessor_account_id();
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+
+        ensure!(
+            invoice.owner == caller,
+            ContractError::Unauthorized,
+            "Only owner can amend invoice"
+        );
+        // Once sold the terms are fixed by the escrow
+        ensure!(
+            matches!(invoice.status, InvoiceStatus::Draft | InvoiceStatus::Listed),
+            ContractError::InvalidState,
+            "Invoice must be a draft or listed"
+        );
+        ensure!(
+            invoice.tranches.is_empty(),
+            ContractError::InvalidState,
+            "Tranched invoices cannot be amended"
+        );
+        ensure!(new_amount.0 > 0, ContractError::InvalidAmount, "Amount must be greater than 0");
+        ensure!(
+            new_due_date > env::block_timestamp_ms(),
+            ContractError::InvalidArgument,
+            "Due date must be in the future"
+        );
+
+        let previous = InvoiceVersion {
+            version: invoice.version,
+            amount: invoice.amount,
+            due_date: invoice.due_date,
+            risk_score: invoice.risk_score,
+            archived_at: env::block_timestamp_ms(),
+        };
+
+        let mut versions = self
+            .invoice_versions
+            .get(&invoice_id)
+            .cloned()
+            .unwrap_or_default();
+        versions.push(previous);
+        self.invoice_versions.insert(invoice_id.clone(), versions);
+
+        invoice.amount = new_amount;
+        invoice.due_date = new_due_date;
+        if !invoice.risk_reviewed {
+            invoice.risk_score = self.calculate_risk_score(
+                &invoice.creator,
+                new_amount,
+                new_due_date,
+                invoice.debtor_confirmed,
+            );
+        }
+        invoice.version += 1;
+        self.invoices.insert(invoice_id.clone(), invoice.clone());
+
+        env::log_str(&format!(
+            "Invoice {} amended to version {}",
+            invoice_id, invoice.version
+        ));
+
+        if invoice.status == InvoiceStatus::Draft {
+            return PromiseOrValue::Value(());
+        }
+
+        // Marketplace refreshes the listing's cached amount and due date
+        ext_marketplace::ext(self.marketplace_contract.clone())
+            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
+            .refresh_listing_terms(invoice_id.clone(), new_amount, new_due_date)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .on_amend_callback(invoice_id),
+            )
+            .into()
+    }
+
+    #[private]
+    pub fn on_amend_callback(
+        &mut self,
+        invoice_id: String,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) {
+        if result.is_ok() {
+            return;
+        }
+
+        // Rollback: restore the archived terms if the marketplace rejected them
+        let mut versions = self
+            .invoice_versions
+            .get(&invoice_id)
+            .cloned()
+            .unwrap_or_default();
+        if let (Some(previous), Some(mut invoice)) =
+            (versions.pop(), self.invoices.get(&invoice_id).cloned())
+        {
+            invoice.amount = previous.amount;
+            invoice.due_date = previous.due_date;
+            invoice.risk_score = previous.risk_score;
+            invoice.version = previous.version;
+            self.invoices.insert(invoice_id.clone(), invoice);
+            self.invoice_versions.insert(invoice_id.clone(), versions);
+        }
+
+        env::log_str(&format!(
+            "Amendment of invoice {} rolled back",
+            invoice_id
+        ));
+    }
+
+    /// Record the current owners of a page of invoices into an owner snapshot (admin or operator)
+    /// Without `snapshot_id` a new snapshot starts at position 0; pass the id of the unfinished
+    /// snapshot to resume it, from the stored position unless `from_index` rewinds it
+    /// Only the latest MAX_OWNER_SNAPSHOTS snapshots are retained; the oldest is evicted page by
+    /// page as the new one is recorded
+    pub fn snapshot_owners(
+        &mut self,
+        snapshot_id: Option<u64>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> SnapshotProgress {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can snapshot owners"
+        );
+
+        let (snapshot_id, start) = match snapshot_id {
+            Some(snapshot_id) => {
+                let cursor = self.snapshot_cursor.filter(|_| snapshot_id == self.snapshot_count);
+                let cursor = cursor.or_fail(
+                    ContractError::InvalidState,
+                    "Only the unfinished latest snapshot can be resumed",
+                );
+                let start = from_index.unwrap_or(cursor);
+                ensure!(
+                    start <= cursor,
+                    ContractError::InvalidArgument,
+                    "Snapshot {} has only been recorded up to position {}",
+                    snapshot_id,
+                    cursor
+                );
+                (snapshot_id, start)
+            }
+            None => {
+                ensure!(
+                    self.snapshot_cursor.is_none(),
+                    ContractError::InvalidState,
+                    "Snapshot {} is unfinished",
+                    self.snapshot_count
+                );
+                self.snapshot_count += 1;
+                (self.snapshot_count, from_index.unwrap_or(0))
+            }
+        };
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+
+        let owners: Vec<(String, AccountId)> = self
+            .invoices
+            .iter()
+            .skip(start as usize)
+            .take(limit as usize)
+            .map(|(id, invoice)| (id.clone(), invoice.owner.clone()))
+            .collect();
+        let evicted = snapshot_id.checked_sub(MAX_OWNER_SNAPSHOTS).filter(|id| *id > 0);
+        for (invoice_id, owner) in owners {
+            if let Some(evicted) = evicted {
+                self.owner_snapshots.remove(&(evicted, invoice_id.clone()));
+            }
+            self.owner_snapshots.insert((snapshot_id, invoice_id), owner);
+        }
+
+        let end = start + limit;
+        let next_index = (end < self.invoices.len() as u64).then_some(end);
+        self.snapshot_cursor = next_index;
+        match next_index {
+            Some(next_index) => env::log_str(&format!(
+                "Owner snapshot {} recorded up to position {}",
+                snapshot_id, next_index
+            )),
+            None => env::log_str(&format!("Owner snapshot {} recorded", snapshot_id)),
+        }
+        SnapshotProgress {
+            snapshot_id,
+            next_index,
+        }
+    }
+
+    /// Update marketplace contract (admin only)
+    pub fn set_marketplace_contract(&mut self, marketplace_contract: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update marketplace contract"
+        );
+        self.marketplace_contract = marketplace_contract;
+    }
+
+    /// Update escrow contract (admin only)
+    pub fn set_escrow_contract(&mut self, escrow_contract: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update escrow contract"
+        );
+        self.escrow_contract = escrow_contract;
+    }
+
+    /// Require debtor confirmation before an invoice can be listed (admin only)
+    pub fn set_require_debtor_confirmation(&mut self, required: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update listing policy"
+        );
+        self.require_debtor_confirmation = required;
     }
 
-    /// Get admin address
-    pub fn get_admin(&self) -> AccountId {
-        self.admin.clone()
+    /// Override an invoice's risk score after manual underwriting review (admin only)
+    /// A listed invoice's cached score on the marketplace is refreshed to match
+    pub fn set_risk_score(&mut self, invoice_id: String, score: u8) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can review risk scores"
+        );
+        ensure!(score <= 99, ContractError::InvalidArgument, "Risk score must be between 0 and 99");
+        let mut invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found")
+            .clone();
+        ensure!(
+            invoice.status != InvoiceStatus::Settled,
+            ContractError::InvalidState,
+            "Invoice is already settled"
+        );
+
+        let previous = invoice.risk_score;
+        invoice.risk_score = score;
+        invoice.risk_reviewed = true;
+        let listed = invoice.status == InvoiceStatus::Listed;
+        self.invoices.insert(invoice_id.clone(), invoice);
+
+        env::log_str(&format!(
+            "Risk score of invoice {} reviewed by {}: {} -> {}",
+            invoice_id, caller, previous, score
+        ));
+        if listed {
+            let _ = ext_marketplace::ext(self.marketplace_contract.clone())
+                .with_static_gas(GAS_FOR_CROSS_CONTRACT)
+                .refresh_listing_risk(invoice_id, score);
+        }
+    }
+
+    /// Allow invoices to be issued in a currency, e.g. "EUR" (admin only)
+    pub fn add_supported_currency(&mut self, currency: String) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage currencies"
+        );
+        ensure!(
+            (3..=5).contains(&currency.len())
+                && currency.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()),
+            ContractError::InvalidArgument,
+            "Currency code must be 3-5 uppercase letters or digits"
+        );
+        self.supported_currencies.insert(currency, true);
+    }
+
+    /// Stop accepting a currency for new invoices; existing invoices keep theirs (admin only)
+    pub fn remove_supported_currency(&mut self, currency: String) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage currencies"
+        );
+        self.supported_currencies.remove(&currency);
+    }
+
+    /// Set the required documents_hash format (admin only)
+    pub fn set_hash_format(&mut self, hash_format: HashFormat) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update hash format"
+        );
+        self.hash_format = hash_format;
+    }
+
+    /// Raise invoice_count past every stored invoice id so new ids never collide
+    /// (admin or operator)
+    pub fn recompute_counters(&mut self) -> u64 {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can recompute counters"
+        );
+
+        let max_sequence = self.invoices.keys().map(|id| id_sequence(id)).max().unwrap_or(0);
+        let previous = self.invoice_count;
+        self.invoice_count = previous.max(max_sequence).max(self.invoices.len() as u64);
+
+        env::log_str(&format!(
+            "invoice_count recomputed: {} -> {}",
+            previous, self.invoice_count
+        ));
+        self.invoice_count
+    }
+
+    /// Update admin (current admin only)
+    pub fn set_admin(&mut self, new_admin: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can change admin");
+        self.admin = new_admin;
+    }
+
+    /// Add an operator for routine maintenance (admin only)
+    pub fn add_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.insert(operator, true);
+    }
+
+    /// Remove an operator (admin only)
+    pub fn remove_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.remove(&operator);
+    }
+
+    /// Get admin address
+    pub fn get_admin(&self) -> AccountId {
+        self.admin.clone()
+    }
+
+    /// Check whether an account is an operator
+    pub fn is_operator(&self, account_id: AccountId) -> bool {
+        self.operators.get(&account_id).copied().unwrap_or(false)
+    }
+
+    // ============ VIEW METHODS ============
+
+    /// Get the currencies invoices may be issued in
+    pub fn get_supported_currencies(&self) -> Vec<String> {
+        self.supported_currencies.keys().cloned().collect()
+    }
+
+    /// Get single invoice by ID
+    pub fn get_invoice(&self, invoice_id: String) -> Option<Invoice> {
+        self.invoices.get(&invoice_id).cloned()
+    }
+
+    /// NEP-171 token for an invoice
+    pub fn nft_token(&self, token_id: String) -> Option<NFTToken> {
+        self.invoices.get(&token_id).map(NFTToken::from)
+    }
+
+    /// NEP-181 tokens owned by an account, in the same order as get_invoices_by_owner
+    pub fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<NFTToken> {
+        let ids = self.invoices_by_owner.get(&account_id).into_iter().flatten();
+        PaginatedResult::paginate(
+            ids.filter_map(|id| self.invoices.get(id)).map(NFTToken::from),
+            from_index.map(|index| index.0 as u64),
+            limit,
+        )
+        .items
+    }
+
+    /// NEP-177 contract metadata
+    pub fn nft_metadata(&self) -> NFTContractMetadata {
+        NFTContractMetadata {
+            spec: NFT_METADATA_SPEC.to_string(),
+            name: "Adelante Invoices".to_string(),
+            symbol: "INVOICE".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    /// Get an invoice's chain of custody: each owner with the time (ms) they acquired it
+    pub fn get_ownership_history(&self, invoice_id: String) -> Vec<(AccountId, u64)> {
+        self.invoices
+            .get(&invoice_id)
+            .map(|invoice| invoice.ownership_history.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get an invoice's tranches from most senior to most junior (empty if it has none)
+    pub fn get_tranches(&self, invoice_id: String) -> Vec<Tranche> {
+        self.invoices
+            .get(&invoice_id)
+            .map(|invoice| invoice.tranches.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get each holder's shares in an invoice (a whole invoice reports its owner with one share)
+    pub fn get_shareholders(&self, invoice_id: String) -> Vec<ShareHolding> {
+        let Some(invoice) = self.invoices.get(&invoice_id) else {
+            return vec![];
+        };
+        if invoice.total_shares == 0 {
+            return vec![ShareHolding {
+                account_id: invoice.owner.clone(),
+                shares: 1,
+            }];
+        }
+        self.share_holdings.get(&invoice_id).cloned().unwrap_or_default()
+    }
+
+    /// Get invoices created by an account (paginated), optionally only those in `status`
+    pub fn get_invoices_by_creator(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<InvoiceStatus>,
+    ) -> PaginatedResult<Invoice> {
+        let ids = self
+            .invoices_by_creator
+            .get(&account_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        self.page_of_ids(ids, from_index, limit, status)
+    }
+
+    /// Get invoices owned by an account (paginated), optionally only those in `status`
+    pub fn get_invoices_by_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<InvoiceStatus>,
+    ) -> PaginatedResult<Invoice> {
+        let ids = self
+            .invoices_by_owner
+            .get(&account_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        self.page_of_ids(ids, from_index, limit, status)
+    }
+
+    /// Get an owner's invoices in `status` (paginated over the matches); empty for unknown owners
+    pub fn get_invoices_by_owner_and_status(
+        &self,
+        owner: AccountId,
+        status: InvoiceStatus,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Invoice> {
+        self.get_invoices_by_owner(owner, from_index, limit, Some(status))
+    }
+
+    /// Get all invoices (paginated)
+    pub fn get_all_invoices(&self, from_index: u64, limit: u64) -> Vec<Invoice> {
+        self.invoices
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(_, invoice)| invoice.clone())
+            .collect()
+    }
+
+    /// Get invoices with ids after `last_id`, in ascending invoice id (creation) order
+    /// Reads only the ids after the cursor, unlike get_all_invoices which skips from the start
+    pub fn get_invoices_after(&self, last_id: Option<String>, limit: Option<u64>) -> Vec<Invoice> {
+        let start = last_id.map_or(0, |id| id_sequence(&id)) + 1;
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+
+        (start..=self.invoice_count)
+            .filter_map(|sequence| self.invoices.get(&format!("INV-{:06}", sequence)))
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    /// Get invoices by status, examining at most MAX_VIEW_SCAN invoices per call
+    /// `from_index` and `next_index` are positions in the invoice map, so keep following
+    /// `next_index` until it is None even when a page comes back short
+    pub fn get_invoices_by_status(
+        &self,
+        status: InvoiceStatus,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Invoice> {
+        PaginatedResult::scan(self.invoices.values(), from_index, limit, |invoice| {
+            (invoice.status == status).then(|| invoice.clone())
+        })
+    }
+
+    /// Get overdue invoices, including past-due ones not yet flagged as Overdue
+    /// Scans like get_invoices_by_status: follow `next_index` until it is None
+    pub fn get_overdue_invoices(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Invoice> {
+        let now = env::block_timestamp_ms();
+        PaginatedResult::scan(self.invoices.values(), from_index, limit, |invoice| {
+            (invoice.status == InvoiceStatus::Overdue || Self::is_past_due(invoice, now))
+                .then(|| invoice.clone())
+        })
+    }
+
+    /// Get invoices not yet confirmed by their debtor, optionally only those naming `debtor` (paginated)
+    pub fn get_unconfirmed_invoices(
+        &self,
+        debtor: Option<AccountId>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Invoice> {
+        PaginatedResult::paginate(
+            self.invoices
+                .values()
+                .filter(|invoice| !invoice.debtor_confirmed)
+                .filter(|invoice| debtor.is_none() || invoice.debtor_account == debtor)
+                .cloned(),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Search invoices by case-insensitive debtor name substring and due date window (paginated)
+    /// Due bounds are exclusive. Scans the whole invoice map, so view gas grows with the invoice
+    /// count; pages are capped at MAX_PAGE_LIMIT
+    pub fn search_invoices(
+        &self,
+        debtor_substring: Option<String>,
+        due_before: Option<u64>,
+        due_after: Option<u64>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Invoice> {
+        let needle = debtor_substring.map(|name| name.to_lowercase());
+        PaginatedResult::paginate(
+            self.invoices
+                .values()
+                .filter(|invoice| due_before.is_none_or(|before| invoice.due_date < before))
+                .filter(|invoice| due_after.is_none_or(|after| invoice.due_date > after))
+                .filter(|invoice| {
+                    needle
+                        .as_ref()
+                        .is_none_or(|needle| invoice.debtor_name.to_lowercase().contains(needle))
+                })
+                .cloned(),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get archived prior versions of an invoice's terms
+    pub fn get_invoice_history(&self, invoice_id: String) -> Vec<InvoiceVersion> {
+        self.invoice_versions
+            .get(&invoice_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the owner of an invoice as recorded in a snapshot
+    pub fn get_snapshot_owner(&self, snapshot_id: u64, invoice_id: String) -> Option<AccountId> {
+        // An evicted snapshot may keep entries until its replacement reaches them
+        if snapshot_id + MAX_OWNER_SNAPSHOTS <= self.snapshot_count {
+            return None;
+        }
+        self.owner_snapshots.get(&(snapshot_id, invoice_id)).cloned()
+    }
+
+    /// Get the id of the latest owner snapshot (0 if none)
+    pub fn get_snapshot_count(&self) -> u64 {
+        self.snapshot_count
+    }
+
+    /// Get total invoice count
+    pub fn get_invoice_count(&self) -> u64 {
+        self.invoice_count
+    }
+
+    /// Get marketplace contract address
+    pub fn get_marketplace_contract(&self) -> AccountId {
+        self.marketplace_contract.clone()
+    }
+
+    /// Get escrow contract address
+    pub fn get_escrow_contract(&self) -> AccountId {
+        self.escrow_contract.clone()
+    }
+
+    /// Get the required documents_hash format
+    pub fn get_hash_format(&self) -> HashFormat {
+        self.hash_format.clone()
+    }
+
+    /// Check a document's hash against the one recorded on the invoice
+    /// Hex digests compare case-insensitively; CIDs must match exactly
+    pub fn verify_document(&self, invoice_id: String, provided_hash: String) -> bool {
+        let invoice = self
+            .invoices
+            .get(&invoice_id)
+            .or_fail(ContractError::InvoiceNotFound, "Invoice not found");
+        if HashFormat::Sha256Hex.matches(&invoice.documents_hash) {
+            invoice.documents_hash.eq_ignore_ascii_case(&provided_hash)
+        } else {
+            invoice.documents_hash == provided_hash
+        }
+    }
+}
+
+impl InvoiceContract {
+    /// One page of the invoices behind an index; without a status filter the ids are paged
+    /// before any lookup, with one the page is taken over the matching invoices
+    fn page_of_ids(
+        &self,
+        ids: &[String],
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<InvoiceStatus>,
+    ) -> PaginatedResult<Invoice> {
+        match status {
+            None => {
+                let page = PaginatedResult::paginate(ids.iter(), from_index, limit);
+                PaginatedResult {
+                    items: page
+                        .items
+                        .into_iter()
+                        .filter_map(|id| self.invoices.get(id).cloned())
+                        .collect(),
+                    next_index: page.next_index,
+                }
+            }
+            Some(status) => PaginatedResult::paginate(
+                ids.iter()
+                    .filter_map(|id| self.invoices.get(id))
+                    .filter(|invoice| invoice.status == status)
+                    .cloned(),
+                from_index,
+                limit,
+            ),
+        }
+    }
+
+    /// Checks an invoice's terms before it is created
+    fn assert_valid_input(&self, input: &InvoiceInput) {
+        self.assert_valid_metadata(&input.debtor_name, &input.description, &input.documents_hash);
+        ensure!(input.amount.0 > 0, ContractError::InvalidAmount, "Amount must be greater than 0");
+        ensure!(
+            input.due_date > env::block_timestamp_ms(),
+            ContractError::InvalidArgument,
+            "Due date must be in the future"
+        );
+        if let Some(currency) = &input.currency {
+            ensure!(
+                self.supported_currencies.contains_key(currency),
+                ContractError::InvalidArgument,
+                "Unsupported currency {}",
+                currency
+            );
+        }
+        if let Some(tranches) = &input.tranches {
+            Self::assert_valid_tranches(tranches, input.amount);
+        }
+    }
+
+    /// Tranches must cover the amount exactly, with yields rising from senior to junior
+    fn assert_valid_tranches(tranches: &[TrancheInput], amount: U128) {
+        ensure!(
+            (2..=MAX_TRANCHES).contains(&tranches.len()),
+            ContractError::InvalidArgument,
+            "An invoice takes between 2 and {} tranches",
+            MAX_TRANCHES
+        );
+        ensure!(
+            tranches.iter().all(|tranche| tranche.amount.0 > 0),
+            ContractError::InvalidAmount,
+            "Tranche amounts must be greater than 0"
+        );
+        let total = tranches
+            .iter()
+            .try_fold(0u128, |total, tranche| total.checked_add(tranche.amount.0));
+        ensure!(
+            total == Some(amount.0),
+            ContractError::InvalidAmount,
+            "Tranche amounts must sum to the invoice amount of {}",
+            amount.0
+        );
+        ensure!(
+            tranches.windows(2).all(|pair| pair[0].yield_bps < pair[1].yield_bps),
+            ContractError::InvalidArgument,
+            "Each junior tranche must yield more than the tranche senior to it"
+        );
+    }
+
+    /// Store a validated invoice under the next id and index it for its creator
+    fn insert_invoice(&mut self, creator: &AccountId, input: InvoiceInput) -> String {
+        self.invoice_count += 1;
+        let id = format!("INV-{:06}", self.invoice_count);
+
+        let risk_score = self.calculate_risk_score(creator, input.amount, input.due_date, false);
+
+        let invoice = Invoice {
+            id: id.clone(),
+            creator: creator.clone(),
+            owner: creator.clone(),
+            amount: input.amount,
+            currency: input.currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+            debtor_name: input.debtor_name,
+            debtor_email: input.debtor_email,
+            description: input.description,
+            due_date: input.due_date,
+            created_at: env::block_timestamp_ms(),
+            documents_hash: input.documents_hash,
+            status: InvoiceStatus::Draft,
+            risk_score,
+            version: 1,
+            beneficiary: input.beneficiary,
+            total_shares: 0,
+            debtor_account: None,
+            debtor_confirmed: false,
+            risk_reviewed: false,
+            ownership_history: vec![(creator.clone(), env::block_timestamp_ms())],
+            tranches: input
+                .tranches
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tranche| Tranche {
+                    amount: tranche.amount,
+                    yield_bps: tranche.yield_bps,
+                    holder: creator.clone(),
+                })
+                .collect(),
+        };
+
+        self.invoices.insert(id.clone(), invoice);
+
+        // Update creator index
+        let mut creator_invoices = self
+            .invoices_by_creator
+            .get(creator)
+            .cloned()
+            .unwrap_or_default();
+        creator_invoices.push(id.clone());
+        self.invoices_by_creator
+            .insert(creator.clone(), creator_invoices);
+
+        // Update owner index
+        let mut owner_invoices = self
+            .invoices_by_owner
+            .get(creator)
+            .cloned()
+            .unwrap_or_default();
+        owner_invoices.push(id.clone());
+        self.invoices_by_owner.insert(creator.clone(), owner_invoices);
+
+        env::log_str(&format!("Invoice created: {}", id));
+        AdelanteEvent::InvoiceCreated(vec![InvoiceCreated {
+            invoice_id: id.clone(),
+            creator: creator.clone(),
+            amount: input.amount,
+        }])
+        .emit();
+        id
+    }
+
+    /// Charge the creator for the bytes written since `initial_storage`, adding any attached deposit
+    fn charge_created_storage(&mut self, creator: &AccountId, initial_storage: u64) {
+        // Collections buffer writes, so flush before measuring the bytes added
+        self.invoices.flush();
+        self.invoices_by_creator.flush();
+        self.invoices_by_owner.flush();
+        let used_bytes = env::storage_usage().saturating_sub(initial_storage);
+        self.charge_storage(creator, env::attached_deposit().as_yoctonear(), used_bytes);
+    }
+
+    /// Checks shared by create_invoice and update_invoice
+    fn assert_valid_metadata(&self, debtor_name: &str, description: &str, documents_hash: &str) {
+        ensure!(!debtor_name.is_empty(), ContractError::InvalidArgument, "Debtor name required");
+        ensure!(!description.is_empty(), ContractError::InvalidArgument, "Description required");
+        ensure!(
+            !documents_hash.is_empty(),
+            ContractError::InvalidArgument,
+            "Documents hash required"
+        );
+        ensure!(
+            self.hash_format.matches(documents_hash),
+            ContractError::InvalidArgument,
+            "Documents hash does not match required format {:?}",
+            self.hash_format
+        );
+    }
+
+    /// Credit a deposit to an account's storage balance and charge it for newly used bytes
+    fn charge_storage(&mut self, account_id: &AccountId, deposit: u128, used_bytes: u64) {
+        let mut account = self
+            .storage_accounts
+            .get(account_id)
+            .cloned()
+            .unwrap_or_default();
+        account.deposited += deposit;
+        account.used_bytes += used_bytes;
+
+        let required = account.used_cost();
+        ensure!(
+            account.deposited >= required,
+            ContractError::InsufficientPayment,
+            "Insufficient storage balance: requires {} yoctoNEAR, deposited {}",
+            required,
+            account.deposited
+        );
+        self.storage_accounts.insert(account_id.clone(), account);
+    }
+
+    /// Shares of an invoice held by an account
+    fn shares_of(&self, invoice_id: &String, account_id: &AccountId) -> u64 {
+        self.share_holdings
+            .get(invoice_id)
+            .and_then(|holdings| holdings.iter().find(|h| &h.account_id == account_id))
+            .map(|holding| holding.shares)
+            .unwrap_or(0)
+    }
+
+    /// Move shares between holders, returning what the sender has left
+    fn move_shares(&mut self, invoice_id: &String, from: &AccountId, to: &AccountId, shares: u64) -> u64 {
+        let mut holdings = self
+            .share_holdings
+            .get(invoice_id)
+            .cloned()
+            .unwrap_or_default();
+
+        let sender = holdings
+            .iter_mut()
+            .find(|h| &h.account_id == from)
+            .or_fail(ContractError::InsufficientBalance, "Sender holds no shares");
+        ensure!(sender.shares >= shares, ContractError::InsufficientBalance, "Insufficient shares");
+        sender.shares -= shares;
+        let remaining = sender.shares;
+
+        match holdings.iter_mut().find(|h| &h.account_id == to) {
+            Some(receiver) => receiver.shares += shares,
+            None => holdings.push(ShareHolding {
+                account_id: to.clone(),
+                shares,
+            }),
+        }
+        holdings.retain(|h| h.shares > 0);
+        ensure!(
+            holdings.len() <= MAX_SHAREHOLDERS,
+            ContractError::InvalidArgument,
+            "Too many shareholders"
+        );
+        self.share_holdings.insert(invoice_id.clone(), holdings);
+
+        remaining
+    }
+
+    /// Remove an invoice and every key that mentions it, crediting the freed storage to the
+    /// creator who paid for it; the credit is withdrawn with storage_withdraw
+    fn delete_invoice(&mut self, invoice: Invoice) {
+        let initial_storage = env::storage_usage();
+        self.invoices.remove(&invoice.id);
+        self.invoice_versions.remove(&invoice.id);
+        self.share_holdings.remove(&invoice.id);
+        // Includes the evicted snapshot, whose entries linger until its replacement finishes
+        let oldest_snapshot = self.snapshot_count.saturating_sub(MAX_OWNER_SNAPSHOTS).max(1);
+        for snapshot_id in oldest_snapshot..=self.snapshot_count {
+            self.owner_snapshots.remove(&(snapshot_id, invoice.id.clone()));
+        }
+        for (index, account) in [
+            (&mut self.invoices_by_creator, &invoice.creator),
+            (&mut self.invoices_by_owner, &invoice.owner),
+        ] {
+            if let Some(mut ids) = index.get(account).cloned() {
+                ids.retain(|id| id != &invoice.id);
+                index.insert(account.clone(), ids);
+            }
+        }
+
+        // Collections buffer writes, so flush before measuring the bytes freed
+        self.invoices.flush();
+        self.invoice_versions.flush();
+        self.invoices_by_creator.flush();
+        self.invoices_by_owner.flush();
+        self.share_holdings.flush();
+        self.owner_snapshots.flush();
+        let freed_bytes = initial_storage.saturating_sub(env::storage_usage());
+
+        let Some(mut account) = self.storage_accounts.get(&invoice.creator).cloned() else {
+            return;
+        };
+        let freed_bytes = freed_bytes.min(account.used_bytes);
+        account.used_bytes -= freed_bytes;
+        self.storage_accounts.insert(invoice.creator.clone(), account);
+
+        env::log_str(&format!(
+            "Invoice {} deleted, {} yoctoNEAR storage freed for {} to withdraw",
+            invoice.id,
+            freed_bytes as u128 * env::storage_byte_cost().as_yoctonear(),
+            invoice.creator
+        ));
+    }
+
+    /// Hand the invoice and the owner's shares and tranches to `new_owner`, moving it between
+    /// owner indexes. Returns the previous owner; the caller persists the invoice
+    fn reassign_owner(&mut self, invoice: &mut Invoice, new_owner: &AccountId) -> AccountId {
+        let old_owner = invoice.owner.clone();
+
+        // The owner's remaining shares go with the invoice
+        if invoice.total_shares > 0 {
+            let held = self.shares_of(&invoice.id, &old_owner);
+            self.move_shares(&invoice.id, &old_owner, new_owner, held);
+        }
+        for tranche in invoice.tranches.iter_mut().filter(|tranche| tranche.holder == old_owner) {
+            tranche.holder = new_owner.clone();
+        }
+        invoice.owner = new_owner.clone();
+        Self::record_owner(invoice);
+
+        if let Some(mut old_owner_invoices) = self.invoices_by_owner.get(&old_owner).cloned() {
+            old_owner_invoices.retain(|id| id != &invoice.id);
+            self.invoices_by_owner.insert(old_owner.clone(), old_owner_invoices);
+        }
+        self.add_to_owner_index(new_owner, &invoice.id);
+
+        old_owner
+    }
+
+    /// Append the invoice's current owner to its ownership history, trimming it to the cap
+    fn record_owner(invoice: &mut Invoice) {
+        invoice
+            .ownership_history
+            .push((invoice.owner.clone(), env::block_timestamp_ms()));
+        if invoice.ownership_history.len() > MAX_OWNERSHIP_HISTORY {
+            invoice.ownership_history.remove(1);
+        }
+    }
+
+    /// Whether a listed or sold invoice is past its due date; settled invoices never are
+    fn is_past_due(invoice: &Invoice, now: u64) -> bool {
+        matches!(
+            invoice.status,
+            InvoiceStatus::Listed | InvoiceStatus::Sold | InvoiceStatus::Relisted
+        ) && now > invoice.due_date
+    }
+
+    /// Add an invoice to an owner's index if not already present
+    fn add_to_owner_index(&mut self, owner: &AccountId, invoice_id: &String) {
+        let mut owner_invoices = self
+            .invoices_by_owner
+            .get(owner)
+            .cloned()
+            .unwrap_or_default();
+        if !owner_invoices.contains(invoice_id) {
+            owner_invoices.push(invoice_id.clone());
+            self.invoices_by_owner.insert(owner.clone(), owner_invoices);
+        }
+    }
+
+    /// Calculate risk score from amount tier, term and the creator's settlement history
+    fn calculate_risk_score(
+        &self,
+        creator: &AccountId,
+        amount: U128,
+        due_date: u64,
+        debtor_confirmed: bool,
+    ) -> u8 {
+        let days_until_due =
+            (due_date.saturating_sub(env::block_timestamp_ms())) / (24 * 60 * 60 * 1000);
+        let amount_val = amount.0;
+
+        // (base, spread) per tier; the spread is scaled by term within the tier
+        let (base, spread) = if amount_val < 1_000_000_000 && days_until_due < 30 {
+            // Small amount, short term = low risk
+            (20, 10)
+        } else if amount_val < 5_000_000_000 && days_until_due < 60 {
+            // Medium amount, medium term = medium risk
+            (40, 15)
+        } else if amount_val < 10_000_000_000 && days_until_due < 90 {
+            // Larger amount, longer term = higher risk
+            (55, 15)
+        } else {
+            // Large amount or long term = highest risk
+            (70, 20)
+        };
+        let term_component = days_until_due.min(MAX_RISK_TERM_DAYS) * spread / MAX_RISK_TERM_DAYS;
+
+        // Each previously settled invoice by the same creator lowers risk by a point
+        let settled_history = self.settled_by_creator.get(creator).copied().unwrap_or(0);
+
+        let confirmation_discount = if debtor_confirmed { DEBTOR_CONFIRMATION_DISCOUNT } else { 0 };
+
+        let risk_score = (base + term_component)
+            .saturating_sub(settled_history.min(MAX_RISK_HISTORY_DISCOUNT))
+            .saturating_sub(confirmation_discount);
+        risk_score.min(99) as u8
+    }
+}
+
+/// Numeric sequence of a generated id such as "INV-000042" (0 if unparseable)
+fn id_sequence(id: &str) -> u64 {
+    id.rsplit('-')
+        .next()
+        .and_then(|suffix| suffix.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn get_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(predecessor)
+            .attached_deposit(NearToken::from_millinear(100));
+        builder
+    }
+
+    #[test]
+    fn test_create_invoice() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        let context = get_context(alice.clone());
+        testing_env!(context.build());
+
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000), // $2000 USDC
+            "Acme Corp".to_string(),
+            Some("billing@acme.com".to_string()),
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000, // 30 days
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(invoice_id, "INV-000001");
+
+        let invoice = contract.get_invoice(invoice_id).unwrap();
+        assert_eq!(invoice.creator, alice);
+        assert_eq!(invoice.owner, alice);
+        assert_eq!(invoice.amount.0, 2_000_000_000);
+        assert_eq!(invoice.status, InvoiceStatus::Draft);
+    }
+
+    #[test]
+    fn test_list_invoice() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        let context = get_context(alice.clone());
+        testing_env!(context.build());
+
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        let invoice = contract.get_invoice(invoice_id).unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Listed);
+    }
+
+    #[test]
+    fn test_amend_listed_invoice() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        let context = get_context(alice.clone());
+        testing_env!(context.build());
+
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        let new_due_date = env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000;
+        let _ = contract.amend_invoice(invoice_id.clone(), U128(1_200_000_000), new_due_date);
+
+        let invoice = contract.get_invoice(invoice_id.clone()).unwrap();
+        assert_eq!(invoice.version, 2);
+        assert_eq!(invoice.amount.0, 1_200_000_000);
+        assert_eq!(invoice.due_date, new_due_date);
+
+        let history = contract.get_invoice_history(invoice_id);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].version, 1);
+        assert_eq!(history[0].amount.0, 1_000_000_000);
+    }
+
+    #[test]
+    fn test_snapshot_retains_prior_owner() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        let snapshot_id = contract.snapshot_owners(None, None, None).snapshot_id;
+
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(invoice_id.clone(), bob.clone());
+
+        assert_eq!(contract.get_invoice(invoice_id.clone()).unwrap().owner, bob);
+        assert_eq!(
+            contract.get_snapshot_owner(snapshot_id, invoice_id.clone()),
+            Some(alice)
+        );
+        assert_eq!(contract.get_snapshot_owner(snapshot_id + 1, invoice_id), None);
+    }
+
+    #[test]
+    fn test_beneficiary_returned_on_listing() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let financier: AccountId = "financier.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_beneficiary(invoice_id.clone(), Some(financier.clone()));
+
+        let listed = contract.set_listed(invoice_id.clone(), alice.clone());
+        assert_eq!(listed.beneficiary, Some(financier.clone()));
+        assert_eq!(contract.get_invoice(invoice_id).unwrap().beneficiary, Some(financier));
+    }
+
+    #[test]
+    fn test_recompute_counters_prevents_id_collision() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        // Simulate a migration that carried over invoices without their counter
+        let mut seeded = contract.get_invoice(invoice_id).unwrap();
+        seeded.id = "INV-000007".to_string();
+        contract.invoices.insert(seeded.id.clone(), seeded);
+        contract.invoice_count = 0;
+
+        assert_eq!(contract.recompute_counters(), 7);
+
+        let next_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Globex".to_string(),
+            None,
+            "Consulting".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmABC456".to_string(),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(next_id, "INV-000008");
+        assert_eq!(contract.get_invoice_count(), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can update escrow contract")]
+    fn test_operator_runs_maintenance_but_not_governance() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let operator: AccountId = "operator.testnet".parse().unwrap();
+
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow.clone(), admin);
+        contract.add_operator(operator.clone());
+        assert!(contract.is_operator(operator.clone()));
+
+        testing_env!(get_context(operator).build());
+        assert_eq!(
+            contract.snapshot_owners(None, None, None),
+            SnapshotProgress {
+                snapshot_id: 1,
+                next_index: None
+            }
+        );
+        assert_eq!(contract.recompute_counters(), 0);
+
+        contract.set_escrow_contract(escrow);
+    }
+
+    #[test]
+    #[should_panic(expected = "Documents hash does not match required format Cid")]
+    fn test_strict_hash_format() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+        contract.set_hash_format(HashFormat::Cid);
+
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG".to_string(),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(invoice_id, "INV-000001");
+
+        // Truncated CID is rejected under strict mode
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_invoice_created_event() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(
+            near_sdk::test_utils::get_logs(),
+            vec![
+                "Invoice created: INV-000001".to_string(),
+                r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"invoice_created","data":[{"invoice_id":"INV-000001","creator":"alice.testnet","amount":"2000000000"}]}"#.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_owner_view_paginates() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+
+        for i in 0..(MAX_PAGE_LIMIT + 5) {
+            testing_env!(get_context(alice.clone()).build());
+            contract.create_invoice(
+                U128(1_000_000_000),
+                "Acme Corp".to_string(),
+                None,
+                format!("Batch {}", i),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                format!("QmBatch{}", i),
+                None,
+                None,
+                None,
+            );
+        }
+
+        let first = contract.get_invoices_by_owner(alice.clone(), None, Some(2), None);
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.next_index, Some(2));
+
+        let last = contract.get_invoices_by_owner(alice.clone(), Some(MAX_PAGE_LIMIT + 3), Some(2), None);
+        assert_eq!(last.items.len(), 2);
+        assert_eq!(last.next_index, None);
+
+        // Defaults and oversized limits are capped
+        let default_page = contract.get_invoices_by_owner(alice.clone(), None, None, None);
+        assert_eq!(default_page.items.len() as u64, DEFAULT_PAGE_LIMIT);
+        let capped = contract.get_invoices_by_owner(alice.clone(), None, Some(10_000), None);
+        assert_eq!(capped.items.len() as u64, MAX_PAGE_LIMIT);
+        assert_eq!(capped.next_index, Some(MAX_PAGE_LIMIT));
+    }
+
+    #[test]
+    fn test_risk_score_is_deterministic() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+        let due_date = env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000;
+
+        let first = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            due_date,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+        let second = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            due_date,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let first = contract.get_invoice(first).unwrap();
+        let second = contract.get_invoice(second).unwrap();
+        assert_eq!(first.risk_score, second.risk_score);
+        // Medium tier, 45 of 180 days into the term
+        assert_eq!(first.risk_score, 43);
+    }
+
+    #[test]
+    fn test_settled_invoice_lowers_creator_risk() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow.clone(), alice.clone());
+        let due_date = env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000;
+        let create = |contract: &mut InvoiceContract| {
+            contract.create_invoice(
+                U128(2_000_000_000),
+                "Acme Corp".to_string(),
+                None,
+                "500 widgets".to_string(),
+                due_date,
+                "QmXYZ123".to_string(),
+                None,
+                None,
+                None,
+            )
+        };
+
+        let first = create(&mut contract);
+        contract.set_listed(first.clone(), alice.clone());
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(first.clone(), "bob.testnet".parse().unwrap());
+        testing_env!(get_context(escrow).build());
+        contract.mark_settled(first.clone());
+
+        testing_env!(get_context(alice).build());
+        let second = create(&mut contract);
+        assert_eq!(contract.get_invoice(first).unwrap().risk_score, 43);
+        assert_eq!(contract.get_invoice(second).unwrap().risk_score, 42);
+    }
+
+    #[test]
+    fn test_transfer_shares_tracks_holders() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow, alice.clone());
+
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.split_into_shares(invoice_id.clone(), 4);
+
+        testing_env!(get_context(marketplace.clone()).build());
+        contract.set_listed(invoice_id.clone(), alice.clone());
+        contract.transfer_shares(invoice_id.clone(), bob.clone(), 3);
+
+        let holders = contract.get_shareholders(invoice_id.clone());
+        assert_eq!(holders.len(), 2);
+        assert_eq!(holders[0], ShareHolding { account_id: alice.clone(), shares: 1 });
+        assert_eq!(holders[1], ShareHolding { account_id: bob.clone(), shares: 3 });
+        assert_eq!(contract.get_invoices_by_owner(bob.clone(), None, None, None).items.len(), 1);
+
+        // Selling the last share transfers the invoice itself
+        contract.transfer_shares(invoice_id.clone(), bob.clone(), 1);
+        let invoice = contract.get_invoice(invoice_id.clone()).unwrap();
+        assert_eq!(invoice.owner, bob);
+        assert_eq!(invoice.status, InvoiceStatus::Sold);
+        assert!(contract.get_invoices_by_owner(alice, None, None, None).items.is_empty());
+        assert_eq!(contract.get_shareholders(invoice_id).len(), 1);
+    }
+
+    #[test]
+    fn test_storage_deposit_charges_actual_usage_and_refunds_excess() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone())
+            .attached_deposit(NearToken::from_near(1))
+            .build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+        contract.storage_deposit(None, None);
+
+        // No deposit attached: the invoice is paid for from the registered balance
+        testing_env!(get_context(alice.clone())
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let balance = contract.storage_balance_of(alice.clone()).unwrap();
+        let used = NearToken::from_near(1).as_yoctonear() - balance.available.0;
+        assert!(used > 0 && used < STORAGE_MIN_BALANCE.as_yoctonear());
+
+        testing_env!(get_context(alice.clone())
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        let after = contract.storage_withdraw(None);
+        assert_eq!(after.available.0, 0);
+        assert_eq!(after.total.0, used);
+
+        let refunded: u128 = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::Transfer { deposit, .. } => Some(deposit.as_yoctonear()),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(refunded, balance.available.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage balance")]
+    fn test_create_invoice_requires_storage_balance() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone())
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_owner_nft_transfers_unlisted_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "Consulting".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context(alice)
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        contract.nft_transfer(bob.clone(), invoice_id.clone(), None, None);
+        assert_eq!(contract.get_invoice(invoice_id).unwrap().owner, bob);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Only draft or sold invoices can be transferred by their owner")]
+    fn test_owner_cannot_nft_transfer_listed_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            marketplace.clone(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "Consulting".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmXYZ123".to_string(),
+            None,
+            None,
+            None,
+        );
+        testing_env!(get_context(marketplace).build());
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        testing_env!(get_context(alice)
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        contract.nft_transfer("bob.testnet".parse().unwrap(), invoice_id, None, None);
+    }
+
+    #[test]
+    fn test_nft_tokens_for_owner_matches_owner_index() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow, alice.clone());
+        for debtor in ["Acme Corp", "Globex", "Initech"] {
+            contract.create_invoice(
+                U128(1_000_000_000),
+                debtor.to_string(),
+                None,
+                "Consulting".to_string(),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                "QmXYZ123".to_string(),
+                None,
+                None,
+                None,
+            );
+        }
+
+        // Move one to bob through the marketplace
+        testing_env!(get_context(marketplace.clone()).build());
+        contract.set_listed("INV-000002".to_string(), alice.clone());
+        testing_env!(get_context(marketplace)
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        contract.nft_transfer(bob.clone(), "INV-000002".to_string(), None, None);
+
+        for owner in [alice, bob] {
+            let invoices = contract.get_invoices_by_owner(owner.clone(), None, None, None).items;
+            let tokens = contract.nft_tokens_for_owner(owner.clone(), None, None);
+            assert_eq!(tokens.len(), invoices.len());
+            for (token, invoice) in tokens.iter().zip(&invoices) {
+                assert_eq!(token.token_id, invoice.id);
+                assert_eq!(token.owner_id, owner);
+                assert_eq!(
+                    token.metadata.as_ref().unwrap().reference.as_deref(),
+                    Some(invoice.documents_hash.as_str())
+                );
+            }
+        }
+        assert_eq!(contract.nft_metadata().spec, NFT_METADATA_SPEC);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: Only owner can cancel invoice")]
+    fn test_error_code_for_unauthorized_cancel() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context("mallory.testnet".parse().unwrap()).build());
+        contract.cancel_invoice(invoice_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVOICE_NOT_FOUND: Invoice not found")]
+    fn test_error_code_for_missing_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.cancel_invoice("INV-999999".to_string());
+    }
+
+    #[test]
+    fn test_update_draft_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Tset Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        contract.update_invoice(
+            invoice_id.clone(),
+            "Test Corp".to_string(),
+            Some("ap@test.com".to_string()),
+            "Test invoice, 40 crates".to_string(),
+            "QmTest2".to_string(),
+        );
+
+        let invoice = contract.get_invoice(invoice_id).unwrap();
+        assert_eq!(invoice.debtor_name, "Test Corp");
+        assert_eq!(invoice.debtor_email, Some("ap@test.com".to_string()));
+        assert_eq!(invoice.description, "Test invoice, 40 crates");
+        assert_eq!(invoice.documents_hash, "QmTest2");
+        assert_eq!(invoice.amount.0, 1_000_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only update draft invoices")]
+    fn test_update_listed_invoice_rejected() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        contract.update_invoice(
+            invoice_id,
+            "Other Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            "QmTest".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_owner_invoices_filtered_by_status_in_pages() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            testing_env!(get_context(alice.clone()).build());
+            ids.push(contract.create_invoice(
+                U128(1_000_000_000),
+                "Acme Corp".to_string(),
+                None,
+                format!("Batch {}", i),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                format!("QmBatch{}", i),
+                None,
+                None,
+                None,
+            ));
+        }
+        // INV-000001, 3 and 5 are listed; 2 and 4 stay in draft
+        for id in [&ids[0], &ids[2], &ids[4]] {
+            contract.set_listed(id.clone(), alice.clone());
+        }
+
+        let listed = Some(InvoiceStatus::Listed);
+        let first = contract.get_invoices_by_owner(alice.clone(), None, Some(2), listed.clone());
+        let first_ids: Vec<String> = first.items.into_iter().map(|invoice| invoice.id).collect();
+        assert_eq!(first_ids, vec![ids[0].clone(), ids[2].clone()]);
+        assert_eq!(first.next_index, Some(2));
+
+        let second = contract.get_invoices_by_owner(alice.clone(), first.next_index, Some(2), listed);
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].id, ids[4]);
+        assert_eq!(second.next_index, None);
+
+        let draft = Some(InvoiceStatus::Draft);
+        let drafts = contract.get_invoices_by_creator(alice.clone(), None, None, draft);
+        assert_eq!(drafts.items.len(), 2);
+
+        // Unfiltered pages walk the whole index
+        let page = contract.get_invoices_by_creator(alice.clone(), Some(3), Some(3), None);
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.next_index, None);
+        let exact = contract.get_invoices_by_creator(alice, Some(2), Some(3), None);
+        assert_eq!(exact.items.len(), 3);
+        assert_eq!(exact.next_index, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can update marketplace contract")]
+    fn test_non_admin_cannot_set_marketplace_contract() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            admin,
+        );
+
+        testing_env!(get_context("mallory.testnet".parse().unwrap()).build());
+        contract.set_marketplace_contract("mallory.testnet".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can update escrow contract")]
+    fn test_non_admin_cannot_set_escrow_contract() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        testing_env!(get_context(admin.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            admin.clone(),
+        );
+
+        // A transferred admin role is honoured and the old admin loses it
+        contract.set_admin("ops.testnet".parse().unwrap());
+        testing_env!(get_context("ops.testnet".parse().unwrap()).build());
+        contract.set_escrow_contract("escrow-v2.testnet".parse().unwrap());
+        assert_eq!(contract.get_escrow_contract().as_str(), "escrow-v2.testnet");
+
+        testing_env!(get_context(admin).build());
+        contract.set_escrow_contract("mallory.testnet".parse().unwrap());
+    }
+
+    fn batch_input(debtor_name: &str, i: u64) -> InvoiceInput {
+        InvoiceInput {
+            amount: U128(1_000_000_000 * i as u128),
+            debtor_name: debtor_name.to_string(),
+            debtor_email: None,
+            description: format!("Batch {}", i),
+            due_date: env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            documents_hash: format!("QmBatch{}", i),
+            beneficiary: None,
+            currency: None,
+            tranches: None,
+        }
+    }
+
+    #[test]
+    fn test_create_invoices_batch() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+
+        let batch = (1..=3).map(|i| batch_input("Acme Corp", i)).collect();
+        let ids = contract.create_invoices_batch(batch);
+
+        assert_eq!(ids, vec!["INV-000001", "INV-000002", "INV-000003"]);
+        let third = contract.get_invoice("INV-000003".to_string()).unwrap();
+        assert_eq!(third.amount.0, 3_000_000_000);
+        let owned = contract.get_invoices_by_owner(alice.clone(), None, None, None);
+        assert_eq!(owned.items.len(), 3);
+        let balance = contract.storage_balance_of(alice).unwrap();
+        assert!(balance.available.0 < balance.total.0);
+    }
+
+    #[test]
+    fn test_create_invoices_batch_is_atomic() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+
+        let batch = vec![batch_input("Acme Corp", 1), batch_input("", 2)];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.create_invoices_batch(batch)
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(contract.get_invoice_count(), 0);
+        assert!(contract.get_invoice("INV-000001".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many invoices in batch (max 20)")]
+    fn test_create_invoices_batch_is_capped() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.create_invoices_batch((1..=21).map(|i| batch_input("Acme Corp", i)).collect());
+    }
+
+    #[test]
+    fn test_debtor_confirms_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let debtor: AccountId = "acme.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        contract.set_require_debtor_confirmation(true);
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_debtor_account(invoice_id.clone(), Some(debtor.clone()));
+        let unconfirmed = contract.get_unconfirmed_invoices(Some(debtor.clone()), None, None);
+        assert_eq!(unconfirmed.items.len(), 1);
+        let risk_before = contract.get_invoice(invoice_id.clone()).unwrap().risk_score;
+
+        testing_env!(get_context(debtor.clone()).build());
+        contract.confirm_invoice(invoice_id.clone());
+
+        let invoice = contract.get_invoice(invoice_id.clone()).unwrap();
+        assert!(invoice.debtor_confirmed);
+        assert_eq!(invoice.risk_score as u64, risk_before as u64 - DEBTOR_CONFIRMATION_DISCOUNT);
+        assert!(contract.get_unconfirmed_invoices(Some(debtor), None, None).items.is_empty());
+
+        // Confirmed invoices may be listed under the confirmation policy
+        testing_env!(get_context(alice.clone()).build());
+        contract.set_listed(invoice_id.clone(), alice);
+        assert_eq!(contract.get_invoice(invoice_id).unwrap().status, InvoiceStatus::Listed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the debtor account can confirm invoice")]
+    fn test_confirm_invoice_rejects_other_accounts() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_debtor_account(invoice_id.clone(), Some("acme.testnet".parse().unwrap()));
+
+        // The creator cannot vouch for their own invoice
+        contract.confirm_invoice(invoice_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Debtor has not confirmed invoice")]
+    fn test_listing_requires_confirmation_when_enabled() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        contract.set_require_debtor_confirmation(true);
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id, alice.clone());
+    }
+
+    #[test]
+    fn test_relist_sold_invoice() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            marketplace.clone(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice.clone());
+
+        testing_env!(get_context(marketplace.clone()).build());
+        contract.transfer_invoice(invoice_id.clone(), bob.clone());
+
+        // Bob resells to Carol; unlisting first returns the invoice to Sold
+        testing_env!(get_context(bob.clone()).build());
+        contract.relist_invoice(invoice_id.clone(), bob.clone());
+        contract.unlist_invoice(invoice_id.clone());
+        assert_eq!(contract.get_invoice(invoice_id.clone()).unwrap().status, InvoiceStatus::Sold);
+        let relisted = contract.relist_invoice(invoice_id.clone(), bob.clone());
+        assert_eq!(relisted.status, InvoiceStatus::Relisted);
+
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(invoice_id.clone(), carol.clone());
+
+        let invoice = contract.get_invoice(invoice_id).unwrap();
+        assert_eq!(invoice.owner, carol);
+        assert_eq!(invoice.status, InvoiceStatus::Sold);
+        assert!(contract.get_invoices_by_owner(bob, None, None, None).items.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Seller mallory.testnet does not own invoice INV-000001")]
+    fn test_marketplace_cannot_relist_for_non_owner() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            marketplace.clone(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice);
+
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(invoice_id.clone(), bob);
+        contract.relist_invoice(invoice_id, "mallory.testnet".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only sold invoices can be relisted")]
+    fn test_relist_requires_sold_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.relist_invoice(invoice_id, alice.clone());
+    }
+
+    #[test]
+    fn test_verify_document() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let digest = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
+        contract.set_hash_format(HashFormat::Sha256Hex);
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            digest.to_string(),
+            None,
+            None,
+            None,
+        );
+
+        assert!(contract.verify_document(invoice_id.clone(), digest.to_string()));
+        assert!(contract.verify_document(invoice_id.clone(), digest.to_uppercase()));
+        assert!(!contract.verify_document(invoice_id, digest.replace('9', "8")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Documents hash does not match required format Sha256Hex")]
+    fn test_sha256_hash_format_rejects_malformed_hash() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
+        contract.set_hash_format(HashFormat::Sha256Hex);
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "not-a-digest".to_string(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_cancel_invoice_deletes_it_and_credits_storage() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let mut create = |description: &str| {
+            contract.create_invoice(
+                U128(1_000_000_000),
+                "Test Corp".to_string(),
+                None,
+                description.to_string(),
+                due_date,
+                "QmTest".to_string(),
+                None,
+                None,
+                None,
+            )
+        };
+        let kept = create("Kept invoice");
+        let cancelled = create("Cancelled invoice");
+        contract.split_into_shares(cancelled.clone(), 100);
+        let snapshot_id = contract.snapshot_owners(None, None, None).snapshot_id;
+        let used_before = contract.storage_accounts.get(&alice).unwrap().used_bytes;
+        let available_before = contract.storage_balance_of(alice.clone()).unwrap().available.0;
+
+        testing_env!(get_context(alice.clone()).build());
+        contract.cancel_invoice(cancelled.clone());
+
+        assert!(contract.get_invoice(cancelled.clone()).is_none());
+        let owned = contract.get_invoices_by_owner(alice.clone(), None, None, None);
+        assert_eq!(owned.items.len(), 1);
+        assert_eq!(owned.items[0].id, kept);
+        assert_eq!(contract.get_invoices_by_creator(alice.clone(), None, None, None).items.len(), 1);
+
+        // No key mentioning the invoice survives
+        assert!(contract.share_holdings.get(&cancelled).is_none());
+        assert_eq!(contract.get_snapshot_owner(snapshot_id, cancelled), None);
+        assert_eq!(contract.get_snapshot_owner(snapshot_id, kept), Some(alice.clone()));
+
+        // The freed storage is credited to the balance rather than pushed out
+        let account = contract.storage_accounts.get(&alice).unwrap();
+        assert!(account.used_bytes < used_before);
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+        let available = contract.storage_balance_of(alice.clone()).unwrap().available.0;
+        assert!(available > available_before);
+
+        let mut context = get_context(alice.clone());
+        context.attached_deposit(NearToken::from_yoctonear(1));
+        testing_env!(context.build());
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.available.0, 0);
+    }
+
+    #[test]
+    fn test_delete_cancelled_invoice_removes_legacy_cancellations() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        // Invoices cancelled before cancellation deleted them are still stored
+        let mut invoice = contract.get_invoice(invoice_id.clone()).unwrap();
+        invoice.status = InvoiceStatus::Cancelled;
+        contract.invoices.insert(invoice_id.clone(), invoice);
+
+        contract.delete_cancelled_invoice(invoice_id.clone());
+        assert!(contract.get_invoice(invoice_id).is_none());
+        assert!(contract.get_invoices_by_owner(alice, None, None, None).items.is_empty());
     }
 
-    // ============ VIEW METHODS ============
+    #[test]
+    fn test_search_invoices_by_debtor_and_due_window() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        let day = 24 * 60 * 60 * 1000;
+        let now = env::block_timestamp_ms();
+        let batch = [("Acme Corp", 10), ("Globex Inc", 20), ("ACME Logistics", 40)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (debtor, days))| InvoiceInput {
+                due_date: now + days * day,
+                ..batch_input(debtor, i as u64 + 1)
+            })
+            .collect();
+        contract.create_invoices_batch(batch);
 
-    /// Get single invoice by ID
-    pub fn get_invoice(&self, invoice_id: String) -> Option<Invoice> {
-        self.invoices.get(&invoice_id).cloned()
+        let ids = |result: PaginatedResult<Invoice>| -> Vec<String> {
+            result.items.into_iter().map(|invoice| invoice.id).collect()
+        };
+        let acme = contract.search_invoices(Some("acme".to_string()), None, None, None, None);
+        assert_eq!(ids(acme), vec!["INV-000001", "INV-000003"]);
+        let partial = contract.search_invoices(Some("BEX".to_string()), None, None, None, None);
+        assert_eq!(ids(partial), vec!["INV-000002"]);
+
+        let (before, after) = (Some(now + 30 * day), Some(now + 15 * day));
+        let window = contract.search_invoices(None, before, after, None, None);
+        assert_eq!(ids(window), vec!["INV-000002"]);
+        let acme_soon = contract.search_invoices(Some("acme".to_string()), before, None, None, None);
+        assert_eq!(ids(acme_soon), vec!["INV-000001"]);
+
+        let page = contract.search_invoices(Some("c".to_string()), None, None, Some(0), Some(2));
+        assert_eq!(page.next_index, Some(2));
+        assert_eq!(page.items.len(), 2);
+        let none = contract.search_invoices(Some("initech".to_string()), None, None, None, None);
+        assert!(none.items.is_empty());
     }
 
-    /// Get all invoices created by an account
-    pub fn get_invoices_by_creator(&self, account_id: AccountId) -> Vec<Invoice> {
-        self.invoices_by_creator
-            .get(&account_id)
-            .cloned()
-            .unwrap_or_default()
-            .iter()
-            .filter_map(|id| self.invoices.get(id).cloned())
-            .collect()
+    #[test]
+    fn test_owner_transfer_draft_and_sold_invoices() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract =
+            InvoiceContract::new(marketplace.clone(), "escrow.testnet".parse().unwrap(), alice.clone());
+        let batch = (1..=2).map(|i| batch_input("Acme Corp", i)).collect();
+        contract.create_invoices_batch(batch);
+
+        // A draft can be gifted directly
+        contract.owner_transfer("INV-000001".to_string(), bob.clone());
+        assert_eq!(contract.get_invoice("INV-000001".to_string()).unwrap().owner, bob);
+        let owned = |contract: &InvoiceContract, account: &AccountId| -> Vec<String> {
+            contract
+                .get_invoices_by_owner(account.clone(), None, None, None)
+                .items
+                .into_iter()
+                .map(|invoice| invoice.id)
+                .collect()
+        };
+        assert_eq!(owned(&contract, &alice), vec!["INV-000002"]);
+        assert_eq!(owned(&contract, &bob), vec!["INV-000001"]);
+
+        // A sold invoice can be passed on by its buyer
+        contract.set_listed("INV-000002".to_string(), alice.clone());
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice("INV-000002".to_string(), bob.clone());
+        testing_env!(get_context(bob.clone()).build());
+        contract.owner_transfer("INV-000002".to_string(), carol.clone());
+
+        let invoice = contract.get_invoice("INV-000002".to_string()).unwrap();
+        assert_eq!(invoice.owner, carol);
+        assert_eq!(invoice.status, InvoiceStatus::Sold);
+        assert_eq!(owned(&contract, &bob), vec!["INV-000001"]);
+        assert_eq!(owned(&contract, &carol), vec!["INV-000002"]);
     }
 
-    /// Get all invoices owned by an account
-    pub fn get_invoices_by_owner(&self, account_id: AccountId) -> Vec<Invoice> {
-        self.invoices_by_owner
-            .get(&account_id)
-            .cloned()
-            .unwrap_or_default()
-            .iter()
-            .filter_map(|id| self.invoices.get(id).cloned())
-            .collect()
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Only draft or sold invoices can be transferred by their owner")]
+    fn test_owner_transfer_rejects_listed_invoice() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        contract.create_invoices_batch(vec![batch_input("Acme Corp", 1)]);
+        contract.set_listed("INV-000001".to_string(), alice.clone());
+
+        contract.owner_transfer("INV-000001".to_string(), "bob.testnet".parse().unwrap());
     }
 
-    /// Get all invoices (paginated)
-    pub fn get_all_invoices(&self, from_index: u64, limit: u64) -> Vec<Invoice> {
-        self.invoices
-            .iter()
-            .skip(from_index as usize)
-            .take(limit as usize)
-            .map(|(_, invoice)| invoice.clone())
-            .collect()
+    #[test]
+    fn test_invoice_overdue_after_due_date() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        contract.create_invoices_batch((1..=2).map(|i| batch_input("Acme Corp", i)).collect());
+        contract.set_listed("INV-000001".to_string(), alice.clone());
+        testing_env!(get_context("marketplace.testnet".parse().unwrap()).build());
+        contract.transfer_invoice("INV-000001".to_string(), "bob.testnet".parse().unwrap());
+        let due_date = contract.get_invoice("INV-000001".to_string()).unwrap().due_date;
+
+        // Due today is not yet overdue
+        let mut context = get_context(alice.clone());
+        testing_env!(context.block_timestamp(due_date * 1_000_000).build());
+        assert!(contract.get_overdue_invoices(None, None).items.is_empty());
+
+        testing_env!(context.block_timestamp((due_date + 1) * 1_000_000).build());
+        let overdue = contract.get_overdue_invoices(None, None);
+        assert_eq!(overdue.items.len(), 1);
+        assert_eq!(overdue.items[0].id, "INV-000001");
+
+        contract.mark_invoice_overdue("INV-000001".to_string());
+        let invoice = contract.get_invoice("INV-000001".to_string()).unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Overdue);
+        assert_eq!(contract.get_overdue_invoices(None, None).items.len(), 1);
+
+        // The overdue owner can still resell it
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        testing_env!(context.predecessor_account_id(bob.clone()).build());
+        let relisted = contract.relist_invoice("INV-000001".to_string(), bob);
+        assert_eq!(relisted.status, InvoiceStatus::Relisted);
     }
 
-    /// Get invoices by status
-    pub fn get_invoices_by_status(&self, status: InvoiceStatus) -> Vec<Invoice> {
-        self.invoices
-            .iter()
-            .filter(|(_, invoice)| invoice.status == status)
-            .map(|(_, invoice)| invoice.clone())
-            .collect()
+    #[test]
+    fn test_overdue_listing_can_be_cancelled() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            marketplace.clone(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        contract.create_invoices_batch(vec![batch_input("Acme Corp", 1)]);
+        contract.set_listed("INV-000001".to_string(), alice);
+        let due_date = contract.get_invoice("INV-000001".to_string()).unwrap().due_date;
+
+        // A listing past its due date is reported overdue but keeps its Listed status
+        let mut context = get_context(marketplace);
+        testing_env!(context.block_timestamp((due_date + 1) * 1_000_000).build());
+        assert_eq!(contract.get_overdue_invoices(None, None).items.len(), 1);
+        let flagged = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.mark_invoice_overdue("INV-000001".to_string())
+        }));
+        assert!(flagged.is_err());
+
+        // Cancelling the listing returns the invoice to Draft
+        testing_env!(context.build());
+        contract.unlist_invoice("INV-000001".to_string());
+        let invoice = contract.get_invoice("INV-000001".to_string()).unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Draft);
+        assert!(contract.get_overdue_invoices(None, None).items.is_empty());
     }
 
-    /// Get total invoice count
-    pub fn get_invoice_count(&self) -> u64 {
-        self.invoice_count
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Invoice is not overdue")]
+    fn test_settled_invoice_is_not_marked_overdue() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow.clone(), alice.clone());
+        contract.create_invoices_batch(vec![batch_input("Acme Corp", 1)]);
+        contract.set_listed("INV-000001".to_string(), alice.clone());
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice("INV-000001".to_string(), "bob.testnet".parse().unwrap());
+        testing_env!(get_context(escrow.clone()).build());
+        contract.mark_settled("INV-000001".to_string());
+
+        let due_date = contract.get_invoice("INV-000001".to_string()).unwrap().due_date;
+        testing_env!(get_context(escrow).block_timestamp((due_date + 1) * 1_000_000).build());
+        assert!(contract.get_overdue_invoices(None, None).items.is_empty());
+        contract.mark_invoice_overdue("INV-000001".to_string());
     }
 
-    /// Get marketplace contract address
-    pub fn get_marketplace_contract(&self) -> AccountId {
-        self.marketplace_contract.clone()
+    #[test]
+    fn test_invoice_cursor_matches_offset_paging() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.create_invoices_batch((1..=5).map(|i| batch_input("Acme Corp", i)).collect());
+
+        let first = contract.get_invoices_after(None, Some(3));
+        let cursor = first.last().map(|invoice| invoice.id.clone());
+        let second = contract.get_invoices_after(cursor, Some(3));
+        assert_eq!(second.len(), 2);
+        let paged: Vec<String> =
+            first.into_iter().chain(second).map(|invoice| invoice.id).collect();
+
+        let offset: Vec<String> =
+            contract.get_all_invoices(0, 10).into_iter().map(|invoice| invoice.id).collect();
+        assert_eq!(paged, offset);
     }
 
-    /// Get escrow contract address
-    pub fn get_escrow_contract(&self) -> AccountId {
-        self.escrow_contract.clone()
+    #[test]
+    fn test_create_invoice_in_supported_currency() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.add_supported_currency("EUR".to_string());
+        assert_eq!(contract.get_supported_currencies(), vec!["USDC", "EUR"]);
+
+        let eur = InvoiceInput {
+            currency: Some("EUR".to_string()),
+            ..batch_input("Acme GmbH", 1)
+        };
+        let ids = contract.create_invoices_batch(vec![eur, batch_input("Acme Corp", 2)]);
+
+        assert_eq!(contract.get_invoice(ids[0].clone()).unwrap().currency, "EUR");
+        assert_eq!(contract.get_invoice(ids[1].clone()).unwrap().currency, "USDC");
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::testing_env;
+    #[test]
+    #[should_panic(expected = "E_INVALID_ARGUMENT: Unsupported currency GBP")]
+    fn test_create_invoice_rejects_unknown_currency() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.create_invoice(
+            U128(1_000_000_000),
+            "Acme Ltd".to_string(),
+            None,
+            "Consulting".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            Some("GBP".to_string()),
+            None,
+        );
+    }
 
-    fn get_context(predecessor: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .predecessor_account_id(predecessor)
-            .attached_deposit(NearToken::from_millinear(100));
-        builder
+    fn senior_junior(senior: u128, junior: u128) -> Option<Vec<TrancheInput>> {
+        Some(vec![
+            TrancheInput { amount: U128(senior), yield_bps: 600 },
+            TrancheInput { amount: U128(junior), yield_bps: 1_400 },
+        ])
     }
 
     #[test]
-    fn test_create_invoice() {
+    fn test_tranched_invoice_follows_owner_and_transfers() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let senior: AccountId = "senior.testnet".parse().unwrap();
         let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
-        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            marketplace.clone(),
+            "escrow.testnet".parse().unwrap(),
+            alice.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "Structured".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTranche".to_string(),
+            None,
+            None,
+            senior_junior(1_500_000_000, 500_000_000),
+        );
+        let tranches = contract.get_tranches(invoice_id.clone());
+        assert_eq!(tranches.len(), 2);
+        assert!(tranches.iter().all(|tranche| tranche.holder == alice));
+
+        // The senior tranche is sold off; the junior one goes with the invoice
+        contract.transfer_tranche(invoice_id.clone(), 0, senior.clone());
+        contract.set_listed(invoice_id.clone(), alice.clone());
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(invoice_id.clone(), bob.clone());
+
+        let holders: Vec<AccountId> = contract
+            .get_tranches(invoice_id)
+            .into_iter()
+            .map(|tranche| tranche.holder)
+            .collect();
+        assert_eq!(holders, vec![senior, bob]);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_AMOUNT: Tranche amounts must sum to the invoice amount of 2000000000")]
+    fn test_create_invoice_rejects_tranches_not_covering_amount() {
         let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            alice,
+        );
+        contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "Structured".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTranche".to_string(),
+            None,
+            None,
+            senior_junior(1_500_000_000, 400_000_000),
+        );
+    }
 
-        let context = get_context(alice.clone());
-        testing_env!(context.build());
+    #[test]
+    fn test_reviewed_risk_score_survives_debtor_confirmation() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let debtor: AccountId = "acme.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            admin.clone(),
+        );
+        let invoice_id = contract.create_invoice(
+            U128(2_000_000_000),
+            "Acme Corp".to_string(),
+            None,
+            "500 widgets".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_debtor_account(invoice_id.clone(), Some(debtor.clone()));
 
-        let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+        testing_env!(get_context(admin).build());
+        contract.set_risk_score(invoice_id.clone(), 72);
+        let invoice = contract.get_invoice(invoice_id.clone()).unwrap();
+        assert_eq!(invoice.risk_score, 72);
+        assert!(invoice.risk_reviewed);
+
+        // Automated rescoring no longer applies once an underwriter has set the score
+        testing_env!(get_context(debtor).build());
+        contract.confirm_invoice(invoice_id.clone());
+        assert_eq!(contract.get_invoice(invoice_id).unwrap().risk_score, 72);
+    }
 
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: Only admin can review risk scores")]
+    fn test_set_risk_score_requires_admin() {
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(
+            "marketplace.testnet".parse().unwrap(),
+            "escrow.testnet".parse().unwrap(),
+            "admin.testnet".parse().unwrap(),
+        );
         let invoice_id = contract.create_invoice(
-            U128(2_000_000_000), // $2000 USDC
+            U128(2_000_000_000),
             "Acme Corp".to_string(),
-            Some("billing@acme.com".to_string()),
+            None,
             "500 widgets".to_string(),
-            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000, // 30 days
-            "QmXYZ123".to_string(),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
         );
+        contract.set_risk_score(invoice_id, 10);
+    }
 
-        assert_eq!(invoice_id, "INV-000001");
+    #[test]
+    fn test_ownership_history_tracks_each_transfer() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let mut context = get_context(alice.clone());
+        testing_env!(context.block_timestamp(1_000 * 1_000_000).build());
+        let mut contract =
+            InvoiceContract::new(marketplace.clone(), "escrow.testnet".parse().unwrap(), alice.clone());
+        contract.create_invoices_batch(vec![batch_input("Acme Corp", 1)]);
+        let invoice_id = "INV-000001".to_string();
 
-        let invoice = contract.get_invoice(invoice_id).unwrap();
-        assert_eq!(invoice.creator, alice);
-        assert_eq!(invoice.owner, alice);
-        assert_eq!(invoice.amount.0, 2_000_000_000);
-        assert_eq!(invoice.status, InvoiceStatus::Draft);
+        // Sold to bob through the marketplace, then passed on to carol
+        contract.set_listed(invoice_id.clone(), alice.clone());
+        testing_env!(get_context(marketplace).block_timestamp(2_000 * 1_000_000).build());
+        contract.transfer_invoice(invoice_id.clone(), bob.clone());
+        testing_env!(get_context(bob.clone()).block_timestamp(3_000 * 1_000_000).build());
+        contract.owner_transfer(invoice_id.clone(), carol.clone());
+
+        assert_eq!(
+            contract.get_ownership_history(invoice_id),
+            vec![(alice, 1_000), (bob, 2_000), (carol, 3_000)]
+        );
     }
 
     #[test]
-    fn test_list_invoice() {
+    fn test_get_invoices_by_owner_and_status() {
         let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
-        let escrow: AccountId = "escrow.testnet".parse().unwrap();
         let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
 
-        let context = get_context(alice.clone());
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract =
+            InvoiceContract::new(marketplace.clone(), "escrow.testnet".parse().unwrap(), alice.clone());
+        let create = |contract: &mut InvoiceContract, creator: &AccountId| {
+            testing_env!(get_context(creator.clone()).build());
+            contract.create_invoice(
+                U128(2_000_000_000),
+                "Acme Corp".to_string(),
+                None,
+                "500 widgets".to_string(),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                "QmXYZ123".to_string(),
+                None,
+                None,
+                None,
+            )
+        };
+        let bought = create(&mut contract, &alice);
+        let listed = create(&mut contract, &bob);
+        create(&mut contract, &bob);
+
+        testing_env!(get_context(marketplace).build());
+        contract.set_listed(bought.clone(), alice.clone());
+        contract.set_listed(listed, bob.clone());
+        contract.transfer_invoice(bought.clone(), bob.clone());
+
+        let sold = contract.get_invoices_by_owner_and_status(bob.clone(), InvoiceStatus::Sold, None, None);
+        assert_eq!(sold.items.len(), 1);
+        assert_eq!(sold.items[0].id, bought);
+        assert!(sold.next_index.is_none());
+        assert_eq!(contract.get_invoices_by_owner(bob, None, None, None).items.len(), 3);
+        assert!(contract
+            .get_invoices_by_owner_and_status(alice, InvoiceStatus::Sold, None, None)
+            .items
+            .is_empty());
+    }
+
+    #[test]
+    fn test_migrate_from_original_layout() {
+        let mut context = VMContextBuilder::new();
+        context.predecessor_account_id("invoice.testnet".parse().unwrap());
+        context.current_account_id("invoice.testnet".parse().unwrap());
         testing_env!(context.build());
 
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut old = OldInvoiceContract {
+            invoices: IterableMap::new(b"i"),
+            invoices_by_creator: LookupMap::new(b"c"),
+            invoices_by_owner: LookupMap::new(b"o"),
+            invoice_count: 1,
+            marketplace_contract: "marketplace.testnet".parse().unwrap(),
+            escrow_contract: "escrow.testnet".parse().unwrap(),
+            admin: "admin.testnet".parse().unwrap(),
+        };
+        old.invoices.insert(
+            "INV-000001".to_string(),
+            OldInvoice {
+                id: "INV-000001".to_string(),
+                creator: alice.clone(),
+                owner: bob.clone(),
+                amount: U128(2_000_000_000),
+                currency: "USDC".to_string(),
+                debtor_name: "Acme Corp".to_string(),
+                debtor_email: None,
+                description: "500 widgets".to_string(),
+                due_date: 1_000,
+                created_at: 10,
+                documents_hash: "QmXYZ123".to_string(),
+                status: InvoiceStatus::Sold,
+                risk_score: 40,
+            },
+        );
+        old.invoices_by_creator.insert(alice.clone(), vec!["INV-000001".to_string()]);
+        old.invoices_by_owner.insert(bob.clone(), vec!["INV-000001".to_string()]);
+        old.invoices.flush();
+        old.invoices_by_creator.flush();
+        old.invoices_by_owner.flush();
+        env::state_write(&old);
+
+        let contract = InvoiceContract::migrate();
+        let invoice = contract.get_invoice("INV-000001".to_string()).unwrap();
+        assert_eq!(invoice.owner, bob);
+        assert_eq!(invoice.status, InvoiceStatus::Sold);
+        assert_eq!(invoice.risk_score, 40);
+        assert_eq!(invoice.version, 1);
+        assert!(invoice.tranches.is_empty());
+        assert_eq!(
+            contract.get_ownership_history("INV-000001".to_string()),
+            vec![(alice.clone(), 10), (bob.clone(), 10)]
+        );
+        assert_eq!(contract.get_invoices_by_owner(bob, None, None, None).items.len(), 1);
+        assert_eq!(contract.get_invoices_by_creator(alice, None, None, None).items.len(), 1);
+        assert_eq!(contract.get_admin(), "admin.testnet".parse::<AccountId>().unwrap());
+        assert_eq!(contract.get_supported_currencies(), vec!["USDC".to_string()]);
+    }
+
+    #[test]
+    fn test_snapshot_owners_resumes_by_page() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
         let mut contract = InvoiceContract::new(marketplace, escrow, alice.clone());
+        let invoice_ids: Vec<String> = (0..3)
+            .map(|_| {
+                contract.create_invoice(
+                    U128(1_000_000_000),
+                    "Test Corp".to_string(),
+                    None,
+                    "Test invoice".to_string(),
+                    env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                    "QmTest".to_string(),
+                    None,
+                    None,
+                    None,
+                )
+            })
+            .collect();
+
+        let progress = contract.snapshot_owners(None, None, Some(2));
+        assert_eq!(progress.next_index, Some(2));
+        assert_eq!(contract.get_snapshot_owner(1, invoice_ids[2].clone()), None);
+
+        let progress = contract.snapshot_owners(Some(progress.snapshot_id), None, Some(2));
+        assert_eq!(progress.next_index, None);
+        for invoice_id in &invoice_ids {
+            assert_eq!(contract.get_snapshot_owner(1, invoice_id.clone()), Some(alice.clone()));
+        }
+
+        // Each new snapshot evicts the oldest once MAX_OWNER_SNAPSHOTS are held
+        for _ in 0..MAX_OWNER_SNAPSHOTS {
+            contract.snapshot_owners(None, None, None);
+        }
+        assert_eq!(contract.get_snapshot_owner(1, invoice_ids[0].clone()), None);
+        assert!(contract.owner_snapshots.get(&(1, invoice_ids[0].clone())).is_none());
+        assert_eq!(contract.get_snapshot_owner(2, invoice_ids[0].clone()), Some(alice));
+    }
+
+    #[test]
+    #[should_panic(expected = "Snapshot 1 is unfinished")]
+    fn test_snapshot_owners_rejects_new_snapshot_while_unfinished() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
 
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
+        for _ in 0..2 {
+            contract.create_invoice(
+                U128(1_000_000_000),
+                "Test Corp".to_string(),
+                None,
+                "Test invoice".to_string(),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                "QmTest".to_string(),
+                None,
+                None,
+                None,
+            );
+        }
+
+        contract.snapshot_owners(None, None, Some(1));
+        contract.snapshot_owners(None, None, None);
+    }
+
+    #[test]
+    fn test_amend_draft_invoice_without_marketplace() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace, escrow, alice);
         let invoice_id = contract.create_invoice(
             U128(1_000_000_000),
             "Test Corp".to_string(),
@@ -477,11 +4144,48 @@ mod tests {
             "Test invoice".to_string(),
             env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
             "QmTest".to_string(),
+            None,
+            None,
+            None,
         );
 
-        contract.set_listed(invoice_id.clone());
+        let new_due_date = env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000;
+        let result = contract.amend_invoice(invoice_id.clone(), U128(1_200_000_000), new_due_date);
 
+        assert!(matches!(result, PromiseOrValue::Value(())));
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
         let invoice = contract.get_invoice(invoice_id).unwrap();
-        assert_eq!(invoice.status, InvoiceStatus::Listed);
+        assert_eq!(invoice.version, 2);
+        assert_eq!(invoice.amount.0, 1_200_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invoice must be a draft or listed")]
+    fn test_amend_sold_invoice_rejected() {
+        let marketplace: AccountId = "marketplace.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+
+        testing_env!(get_context(alice.clone()).build());
+        let mut contract = InvoiceContract::new(marketplace.clone(), escrow, alice.clone());
+        let invoice_id = contract.create_invoice(
+            U128(1_000_000_000),
+            "Test Corp".to_string(),
+            None,
+            "Test invoice".to_string(),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            "QmTest".to_string(),
+            None,
+            None,
+            None,
+        );
+        contract.set_listed(invoice_id.clone(), alice.clone());
+        testing_env!(get_context(marketplace).build());
+        contract.transfer_invoice(invoice_id.clone(), bob.clone());
+
+        testing_env!(get_context(bob).build());
+        let new_due_date = env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000;
+        let _ = contract.amend_invoice(invoice_id, U128(1_200_000_000), new_due_date);
     }
 }
@@ -3,10 +3,41 @@ use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::store::{IterableMap, LookupMap};
 use near_sdk::{env, ext_contract, near, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseError, PromiseOrValue, NearSchema};
+use events::{
+    ensure, AdelanteEvent, BidPlaced, ContractError, KeeperRewards,
+    ListingCancelled, ListingCreated, ListingExtended, ListingPurchased, ListingUpdated, OrFail,
+    PaginatedResult, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+};
 
+/// Default gas for calls to the sibling contracts, until the admin tunes it
 const GAS_FOR_CROSS_CONTRACT: Gas = Gas::from_tgas(10);
+/// Default gas reserved for our own callbacks
 const GAS_FOR_CALLBACK: Gas = Gas::from_tgas(10);
+/// Default gas for each ft_transfer
 const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(15);
+/// Lowest value set_gas_config accepts for any of the three amounts
+const MIN_GAS_CONFIG: Gas = Gas::from_tgas(5);
+/// Covers create_escrow, scheduled once the invoice transfer succeeds
+const GAS_FOR_PURCHASE_STEP: Gas = Gas::from_tgas(25);
+/// Covers the fee and escrow deposit transfers, or the buyer's refund
+const GAS_FOR_PURCHASE_CALLBACK: Gas = Gas::from_tgas(45);
+
+/// Currency assumed for listings that don't specify one
+const DEFAULT_CURRENCY: &str = "USDC";
+/// Maximum number of listings accepted by batch bid queries
+const MAX_LISTINGS_PER_BID_QUERY: usize = 20;
+
+/// Cap on the annualized yield shown for listings moments from their due date, in percent
+const MAX_ANNUALIZED_YIELD: f64 = 10_000.0;
+
+/// Most active listings get_popular_listings ranks; listings past the cap are not considered
+const MAX_POPULAR_CANDIDATES: usize = 500;
+
+/// Default minimum raise over the highest bid on other listings, as a share of min_price (1%)
+const DEFAULT_MIN_BID_INCREMENT_BPS: u16 = 100;
+
+/// Default minimum raise over the highest English auction bid (1%)
+const DEFAULT_AUCTION_MIN_INCREMENT_BPS: u16 = 100;
 
 /// Marketplace listing
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
@@ -23,6 +54,227 @@ pub struct Listing {
     pub created_at: u64,
     pub expires_at: Option<u64>,
     pub active: bool,
+    /// Currency code the invoice is denominated in
+    #[serde(default)]
+    pub currency: String,
+    /// Invoice risk score, cached when the invoice contract confirms the listing
+    #[serde(default)]
+    pub risk_score: Option<u8>,
+    /// Seller-side settlement recipient, cached from the invoice's beneficiary
+    #[serde(default)]
+    pub payout_account: Option<AccountId>,
+    /// Highest annualized yield (bps) the seller will give a buyer; lower-priced sales are rejected
+    #[serde(default)]
+    pub min_yield_bps: Option<u16>,
+    /// Hash of the seller's off-chain negotiation log (e.g. IPFS CID)
+    #[serde(default)]
+    pub thread_hash: Option<String>,
+    /// Id of the highest active bid, maintained by place_bid/cancel_bid
+    #[serde(default)]
+    pub highest_bid_id: Option<String>,
+    /// Listing is hidden and unbuyable before this time (ms)
+    #[serde(default)]
+    pub start_at: Option<u64>,
+    /// Pricing mode
+    #[serde(default)]
+    pub listing_type: ListingType,
+    /// Token the buyer paid in, recorded when the listing sells via ft_transfer_call
+    #[serde(default)]
+    pub payment_token: Option<AccountId>,
+    /// Lifecycle stage; `active` mirrors `status == Active` for older clients
+    #[serde(default)]
+    pub status: ListingStatus,
+    /// Resale of a purchased invoice: the buyer takes over its existing escrow and the
+    /// proceeds go straight to the seller
+    #[serde(default)]
+    pub secondary: bool,
+    /// Smallest raise over the highest active bid; None uses the default for the listing type
+    #[serde(default)]
+    pub min_bid_increment: Option<U128>,
+    /// Views recorded by frontends through record_view
+    #[serde(default)]
+    pub views: u64,
+    /// Account credited with referring buyers, unless the buyer names one when purchasing
+    #[serde(default)]
+    pub referrer: Option<AccountId>,
+    /// Share of the marketplace fee paid to the referrer, in basis points of the fee
+    #[serde(default)]
+    pub referral_bps: u16,
+    /// Only account that may buy or bid (a private sale); None lists publicly
+    #[serde(default)]
+    pub allowed_buyer: Option<AccountId>,
+}
+
+impl Listing {
+    /// Move the listing to `status`, keeping the legacy `active` flag in step
+    fn set_status(&mut self, status: ListingStatus) {
+        self.active = status == ListingStatus::Active;
+        self.status = status;
+    }
+
+    /// Status as seen at `now`: an active listing past its expiry reads as Expired
+    pub fn status_at(&self, now: u64) -> ListingStatus {
+        match self.expires_at {
+            Some(expires_at) if self.status == ListingStatus::Active && now >= expires_at => {
+                ListingStatus::Expired
+            }
+            _ => self.status.clone(),
+        }
+    }
+}
+
+/// Where a listing is in its lifecycle
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum ListingStatus {
+    /// Open for purchase or bids
+    #[default]
+    Active,
+    /// Sold to a buyer or bidder
+    Sold,
+    /// Withdrawn by the seller
+    Cancelled,
+    /// Closed unsold after expires_at
+    Expired,
+    /// Awaiting the invoice contract's confirmation; not yet open for purchase or bids
+    Pending,
+}
+
+/// How a listing's price is determined
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum ListingType {
+    /// Sold at asking_price
+    #[default]
+    FixedPrice,
+    /// Price decays linearly from asking_price to min_price until expires_at
+    DutchAuction,
+    /// Ascending bids; the highest bid wins once expires_at passes (see finalize_auction)
+    EnglishAuction,
+}
+
+/// Bid on a listing
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct Bid {
+    pub id: String,
+    pub listing_id: String,
+    pub bidder: AccountId,
+    pub amount: U128,
+    pub created_at: u64,
+    pub active: bool,
+    /// Hash of the bidder's off-chain negotiation log (e.g. IPFS CID)
+    #[serde(default)]
+    pub thread_hash: Option<String>,
+    /// Whether the bid's USDC is still held by the contract
+    #[serde(default)]
+    pub funds_locked: bool,
+    /// Whether the seller accepted this bid
+    #[serde(default)]
+    pub accepted: bool,
+    /// Whether a refund transfer is in flight
+    #[serde(default)]
+    pub refund_pending: bool,
+    /// Token the bid was paid in (None for bids placed before multi-token support, i.e. USDC)
+    #[serde(default)]
+    pub token: Option<AccountId>,
+    /// Bid can no longer be accepted after this time (ms)
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Price the seller countered with, see counter_state
+    #[serde(default)]
+    pub counter_amount: Option<U128>,
+    #[serde(default)]
+    pub counter_state: CounterState,
+    /// Locked funds above an accepted counter whose refund failed, owed via retry_counter_refund
+    #[serde(default)]
+    pub excess_owed: U128,
+}
+
+/// Progress of a seller's counter-offer on a bid
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub enum CounterState {
+    /// The seller has not countered
+    #[default]
+    None,
+    /// counter_amount is open for the bidder to accept
+    Offered,
+    /// The seller withdrew the counter; it can no longer be accepted
+    Withdrawn,
+    /// The bidder accepted and the listing sold at counter_amount
+    Accepted,
+}
+
+/// A sale awaiting confirmation by on_purchase_callback
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingPurchase {
+    pub listing_id: String,
+    pub buyer: AccountId,
+    /// Token the buyer paid in; None for the legacy NEAR purchase
+    pub token: Option<AccountId>,
+    /// Amount held for the buyer, refunded if the sale fails
+    pub payment: U128,
+    /// Sale price credited to the seller's volume
+    pub price: U128,
+    /// Marketplace fee withheld from the escrow deposit
+    pub fee: U128,
+    /// Referrer paid `referral_fee` out of `fee`
+    #[serde(default)]
+    pub referrer: Option<AccountId>,
+    #[serde(default)]
+    pub referral_fee: U128,
+}
+
+/// Gas attached to cross-contract calls, callbacks and token transfers
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct GasConfig {
+    pub cross_contract: Gas,
+    pub callback: Gas,
+    pub ft_transfer: Gas,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            cross_contract: GAS_FOR_CROSS_CONTRACT,
+            callback: GAS_FOR_CALLBACK,
+            ft_transfer: GAS_FOR_FT_TRANSFER,
+        }
+    }
+}
+
+/// Marketplace-wide listing and sale totals
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketplaceStats {
+    pub total_listings: u64,
+    /// Listings still open, including lapsed ones not yet swept
+    pub active_listings: u64,
+    pub total_sales: u64,
+    pub total_volume: U128,
+    /// Mean discount off the invoice amount across completed sales, in percent
+    pub average_discount_percentage: f64,
+}
+
+/// A seller's track record on the marketplace, for buyers to weigh before purchasing
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SellerStats {
+    /// Listings confirmed by the invoice contract
+    pub total_listings: u64,
+    pub completed_sales: u64,
+    pub cancelled_listings: u64,
+    /// Disputes raised against the seller's sold invoices
+    pub disputes: u64,
 }
 
 /// Combined listing with calculated fields for frontend
@@ -35,10 +287,88 @@ pub struct ListingView {
     pub annualized_yield: f64,
 }
 
+/// Listing detail page data: the listing's view, its live bids and the seller's record
+#[derive(Serialize, Deserialize, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingDetail {
+    pub listing: ListingView,
+    pub active_bids: Vec<Bid>,
+    pub highest_bid: Option<Bid>,
+    pub seller_stats: SellerStats,
+}
+
+impl ListingView {
+    /// Build a view with derived yield fields; degenerate inputs yield 0.0, never NaN or inf
+    pub fn from_listing(listing: &Listing, now: u64) -> Self {
+        let (discount_percentage, annualized_yield) = discount_and_yield(
+            listing.asking_price.0,
+            listing.invoice_amount.0,
+            listing.due_date,
+            now,
+        );
+
+        ListingView {
+            listing: listing.clone(),
+            discount_percentage,
+            days_until_due: ((listing.due_date as i64) - (now as i64)) / (24 * 60 * 60 * 1000),
+            annualized_yield,
+        }
+    }
+}
+
+/// Discount off the invoice amount and its annualized yield, both in percent
+/// Zero invoice amounts and past due dates yield 0.0, never NaN or inf
+fn discount_and_yield(
+    asking_price: u128,
+    invoice_amount: u128,
+    due_date: u64,
+    now: u64,
+) -> (f64, f64) {
+    let discount = if invoice_amount > 0 {
+        (invoice_amount.saturating_sub(asking_price) as f64 / invoice_amount as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    // Fractional days, so an invoice due later today still has a yield
+    let remaining_ms = (due_date as i64) - (now as i64);
+    let annualized_yield = if remaining_ms > 0 {
+        let days_until_due = remaining_ms as f64 / (24 * 60 * 60 * 1000) as f64;
+        ((discount / days_until_due) * 365.0).min(MAX_ANNUALIZED_YIELD)
+    } else {
+        0.0
+    };
+
+    (finite_or_zero(discount), finite_or_zero(annualized_yield))
+}
+
+/// Replace NaN and infinities with 0.0 so views always serialize to valid JSON
+fn finite_or_zero(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
+
+/// Invoice fields returned by the invoice contract when a listing is confirmed
+#[derive(Serialize, Deserialize, Clone, NearSchema)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListedInvoice {
+    pub risk_score: u8,
+    #[serde(default)]
+    pub beneficiary: Option<AccountId>,
+    #[serde(default)]
+    pub owner: Option<AccountId>,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
 /// Cross-contract interface for Invoice contract
 #[ext_contract(ext_invoice)]
 pub trait InvoiceContract {
-    fn set_listed(&mut self, invoice_id: String);
+    fn set_listed(&mut self, invoice_id: String, seller: AccountId) -> ListedInvoice;
+    fn relist_invoice(&mut self, invoice_id: String, seller: AccountId) -> ListedInvoice;
     fn transfer_invoice(&mut self, invoice_id: String, new_owner: AccountId);
     fn unlist_invoice(&mut self, invoice_id: String);
 }
@@ -46,6 +376,7 @@ pub trait InvoiceContract {
 /// Cross-contract interface for Escrow contract
 #[ext_contract(ext_escrow)]
 pub trait EscrowContract {
+    #[allow(clippy::too_many_arguments)]
     fn create_escrow(
         &mut self,
         invoice_id: String,
@@ -54,7 +385,9 @@ pub trait EscrowContract {
         sale_amount: U128,
         invoice_amount: U128,
         due_date: u64,
+        payout_account: Option<AccountId>,
     ) -> String;
+    fn resell_position(&mut self, invoice_id: String, new_buyer: AccountId) -> String;
 }
 
 /// Cross-contract interface for USDC (NEP-141 Fungible Token)
@@ -70,27 +403,65 @@ pub trait FungibleToken {
     ) -> U128;
 }
 
-/// Old Bid struct (for migration deserialization only)
+/// Listing as stored by the original release, before currencies, bids and auctions
 #[derive(BorshDeserialize, BorshSerialize)]
 #[borsh(crate = "near_sdk::borsh")]
-pub struct OldBid {
-    pub id: String,
-    pub listing_id: String,
-    pub bidder: AccountId,
-    pub amount: U128,
-    pub created_at: u64,
-    pub active: bool,
+pub struct OldListing {
+    id: String,
+    invoice_id: String,
+    seller: AccountId,
+    asking_price: U128,
+    min_price: Option<U128>,
+    invoice_amount: U128,
+    due_date: u64,
+    created_at: u64,
+    expires_at: Option<u64>,
+    active: bool,
+}
+
+impl From<OldListing> for Listing {
+    fn from(old: OldListing) -> Self {
+        // The original release only cleared `active`, so a sale can't be told apart from a
+        // withdrawal; closed listings come across as Cancelled
+        let status = if old.active { ListingStatus::Active } else { ListingStatus::Cancelled };
+        Listing {
+            id: old.id,
+            invoice_id: old.invoice_id,
+            seller: old.seller,
+            asking_price: old.asking_price,
+            min_price: old.min_price,
+            invoice_amount: old.invoice_amount,
+            due_date: old.due_date,
+            created_at: old.created_at,
+            expires_at: old.expires_at,
+            active: old.active,
+            currency: DEFAULT_CURRENCY.to_string(),
+            risk_score: None,
+            payout_account: None,
+            min_yield_bps: None,
+            thread_hash: None,
+            highest_bid_id: None,
+            start_at: None,
+            listing_type: ListingType::FixedPrice,
+            payment_token: None,
+            status,
+            secondary: false,
+            min_bid_increment: None,
+            views: 0,
+            referrer: None,
+            referral_bps: 0,
+            allowed_buyer: None,
+        }
+    }
 }
 
-/// Old contract state (for migration from bidding version)
-#[derive(BorshDeserialize)]
+/// Old contract state (for migration from the original release)
+#[derive(BorshDeserialize, BorshSerialize)]
 #[borsh(crate = "near_sdk::borsh")]
 pub struct OldMarketplaceContract {
-    listings: IterableMap<String, Listing>,
+    listings: IterableMap<String, OldListing>,
     listings_by_invoice: LookupMap<String, String>,
-    bids: IterableMap<String, Vec<OldBid>>,
     listing_count: u64,
-    bid_count: u64,
     invoice_contract: AccountId,
     escrow_contract: AccountId,
     usdc_contract: AccountId,
@@ -112,6 +483,52 @@ pub struct MarketplaceContract {
 
     fee_basis_points: u16,
     fee_recipient: AccountId,
+
+    admin: AccountId,
+    operators: LookupMap<AccountId, bool>,
+    paused: bool,
+    fee_by_currency: LookupMap<String, u16>,
+
+    bids: IterableMap<String, Vec<Bid>>,
+    bid_count: u64,
+
+    /// Required discount per invoice risk point, in basis points (0 = disabled)
+    min_discount_bps_per_risk_point: u16,
+    keeper_rewards: KeeperRewards,
+
+    /// Volume-based fee tiers as (volume_threshold, fee_bps), sorted by threshold descending
+    fee_tiers: Vec<(U128, u16)>,
+    /// Cumulative sale volume per seller
+    seller_volume: LookupMap<AccountId, u128>,
+    /// Lifetime marketplace fees sent to fee_recipient
+    total_fees_collected: u128,
+    /// Minimum raise over the highest bid on English auctions, in basis points
+    auction_min_increment_bps: u16,
+    /// Fungible tokens accepted for purchases and bids
+    accepted_tokens: IterableMap<AccountId, bool>,
+    /// Listings with a purchase awaiting on_purchase_callback; further purchases are refused
+    processing: LookupMap<String, bool>,
+    /// Gas amounts attached to outgoing calls
+    gas_config: GasConfig,
+    /// Listing, sale, cancellation and dispute counts per seller
+    seller_stats: LookupMap<AccountId, SellerStats>,
+    /// Accounts cleared to buy and bid when require_kyc is set
+    kyc_verified: IterableMap<AccountId, bool>,
+    require_kyc: bool,
+    /// Running marketplace totals, so stats never scan the listings
+    active_listing_count: u64,
+    total_sales: u64,
+    total_sale_volume: u128,
+    /// Sum of each sale's discount off the invoice amount, in basis points
+    total_discount_bps: u128,
+    /// Token required for listings in a currency; unmapped currencies take any accepted token
+    currency_tokens: LookupMap<String, AccountId>,
+    /// Block height of each account's last recorded listing view
+    last_view_block: LookupMap<AccountId, u64>,
+    /// Listing ids per seller, oldest first
+    listings_by_seller: LookupMap<AccountId, Vec<String>>,
+    /// Ids of the listings each account has bid on, in order of their first bid
+    bids_by_bidder: LookupMap<AccountId, Vec<String>>,
 }
 
 #[near]
@@ -123,7 +540,11 @@ impl MarketplaceContract {
         escrow_contract: AccountId,
         usdc_contract: AccountId,
         fee_recipient: AccountId,
+        admin: AccountId,
     ) -> Self {
+        let mut accepted_tokens = IterableMap::new(b"t");
+        accepted_tokens.insert(usdc_contract.clone(), true);
+
         Self {
             listings: IterableMap::new(b"l"),
             listings_by_invoice: LookupMap::new(b"i"),
@@ -133,16 +554,66 @@ impl MarketplaceContract {
             usdc_contract,
             fee_basis_points: 100, // 1% fee
             fee_recipient,
+            admin,
+            operators: LookupMap::new(b"p"),
+            paused: false,
+            fee_by_currency: LookupMap::new(b"f"),
+            bids: IterableMap::new(b"d"),
+            bid_count: 0,
+            min_discount_bps_per_risk_point: 0,
+            keeper_rewards: KeeperRewards::new(),
+            fee_tiers: Vec::new(),
+            seller_volume: LookupMap::new(b"v"),
+            total_fees_collected: 0,
+            auction_min_increment_bps: DEFAULT_AUCTION_MIN_INCREMENT_BPS,
+            accepted_tokens,
+            processing: LookupMap::new(b"g"),
+            gas_config: GasConfig::default(),
+            seller_stats: LookupMap::new(b"r"),
+            kyc_verified: IterableMap::new(b"y"),
+            require_kyc: false,
+            active_listing_count: 0,
+            total_sales: 0,
+            total_sale_volume: 0,
+            total_discount_bps: 0,
+            currency_tokens: LookupMap::new(b"k"),
+            last_view_block: LookupMap::new(b"w"),
+            listings_by_seller: LookupMap::new(b"s"),
+            bids_by_bidder: LookupMap::new(b"b"),
         }
     }
 
-    /// Migrate from old state to new state (removes bidding system)
+    /// Migrate from the original release's state (see OldMarketplaceContract), adding `admin`
+    /// That layout held only the listing map and invoice index, listing_count, the contract
+    /// accounts and the fee settings; every listing is rewritten in the current layout, so gas
+    /// grows with the number of listings
     #[private]
     #[init(ignore_state)]
-    pub fn migrate() -> Self {
-        let old: OldMarketplaceContract = env::state_read().expect("Failed to read old state");
+    pub fn migrate(admin: AccountId) -> Self {
+        let mut old: OldMarketplaceContract = env::state_read()
+            .or_fail(ContractError::InvalidState, "Failed to read old state");
+        let old_listings: Vec<(String, OldListing)> = old.listings.drain().collect();
+        old.listings.flush();
+
+        let mut listings = IterableMap::new(b"l");
+        let mut listings_by_seller: LookupMap<AccountId, Vec<String>> = LookupMap::new(b"s");
+        let mut active_listing_count = 0;
+        for (listing_id, listing) in old_listings {
+            if listing.active {
+                active_listing_count += 1;
+            }
+            listings_by_seller
+                .entry(listing.seller.clone())
+                .or_default()
+                .push(listing_id.clone());
+            listings.insert(listing_id, Listing::from(listing));
+        }
+
+        let mut accepted_tokens = IterableMap::new(b"t");
+        accepted_tokens.insert(old.usdc_contract.clone(), true);
+
         Self {
-            listings: old.listings,
+            listings,
             listings_by_invoice: old.listings_by_invoice,
             listing_count: old.listing_count,
             invoice_contract: old.invoice_contract,
@@ -150,11 +621,40 @@ impl MarketplaceContract {
             usdc_contract: old.usdc_contract,
             fee_basis_points: old.fee_basis_points,
             fee_recipient: old.fee_recipient,
+            admin,
+            operators: LookupMap::new(b"p"),
+            paused: false,
+            fee_by_currency: LookupMap::new(b"f"),
+            bids: IterableMap::new(b"d"),
+            bid_count: 0,
+            min_discount_bps_per_risk_point: 0,
+            keeper_rewards: KeeperRewards::new(),
+            fee_tiers: Vec::new(),
+            seller_volume: LookupMap::new(b"v"),
+            total_fees_collected: 0,
+            auction_min_increment_bps: DEFAULT_AUCTION_MIN_INCREMENT_BPS,
+            accepted_tokens,
+            processing: LookupMap::new(b"g"),
+            gas_config: GasConfig::default(),
+            seller_stats: LookupMap::new(b"r"),
+            kyc_verified: IterableMap::new(b"y"),
+            require_kyc: false,
+            active_listing_count,
+            total_sales: 0,
+            total_sale_volume: 0,
+            total_discount_bps: 0,
+            currency_tokens: LookupMap::new(b"k"),
+            last_view_block: LookupMap::new(b"w"),
+            listings_by_seller,
+            bids_by_bidder: LookupMap::new(b"b"),
         }
     }
 
     /// List an invoice for sale
+    /// Listing is free: the marketplace pays for listing storage and returns any attached deposit
+    /// (payable only so wallets that attach 1 yoctoNEAR by default are not rejected)
     #[payable]
+    #[allow(clippy::too_many_arguments)]
     pub fn list_invoice(
         &mut self,
         invoice_id: String,
@@ -163,19 +663,98 @@ impl MarketplaceContract {
         due_date: u64,
         min_price: Option<U128>,
         expires_at: Option<u64>,
+        currency: Option<String>,
+        start_at: Option<u64>,
+        listing_type: Option<ListingType>,
+        allowed_buyer: Option<AccountId>,
+    ) -> Promise {
+        self.open_listing(
+            invoice_id,
+            asking_price,
+            invoice_amount,
+            due_date,
+            min_price,
+            expires_at,
+            currency,
+            start_at,
+            listing_type,
+            allowed_buyer,
+            false,
+        )
+    }
+
+    /// Resell a purchased invoice (current owner only); its escrow passes to the next buyer
+    /// Free like list_invoice; any attached deposit is returned
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn relist_invoice(
+        &mut self,
+        invoice_id: String,
+        asking_price: U128,
+        invoice_amount: U128,
+        due_date: u64,
+        min_price: Option<U128>,
+        expires_at: Option<u64>,
+        currency: Option<String>,
+        start_at: Option<u64>,
+        listing_type: Option<ListingType>,
+        allowed_buyer: Option<AccountId>,
+    ) -> Promise {
+        self.open_listing(
+            invoice_id,
+            asking_price,
+            invoice_amount,
+            due_date,
+            min_price,
+            expires_at,
+            currency,
+            start_at,
+            listing_type,
+            allowed_buyer,
+            true,
+        )
+    }
+
+    /// Record a pending listing and ask the invoice contract to mark the invoice listed (or
+    /// relisted) for the seller; on_list_callback opens it once the invoice contract agrees
+    #[allow(clippy::too_many_arguments)]
+    fn open_listing(
+        &mut self,
+        invoice_id: String,
+        asking_price: U128,
+        invoice_amount: U128,
+        due_date: u64,
+        min_price: Option<U128>,
+        expires_at: Option<u64>,
+        currency: Option<String>,
+        start_at: Option<u64>,
+        listing_type: Option<ListingType>,
+        allowed_buyer: Option<AccountId>,
+        secondary: bool,
     ) -> Promise {
+        self.assert_not_paused();
         let seller = env::predecessor_account_id();
+        let listing_type = listing_type.unwrap_or_default();
+        ensure!(
+            allowed_buyer.as_ref() != Some(&seller),
+            ContractError::InvalidArgument,
+            "Cannot restrict a listing to its seller"
+        );
 
         // Validate
-        assert!(asking_price.0 > 0, "Asking price must be greater than 0");
-        assert!(
-            asking_price.0 <= invoice_amount.0,
-            "Asking price cannot exceed invoice amount"
+        Self::assert_valid_terms(
+            asking_price,
+            invoice_amount,
+            min_price,
+            expires_at,
+            start_at,
+            &listing_type,
         );
 
         // Check if invoice is already listed
-        assert!(
+        ensure!(
             self.listings_by_invoice.get(&invoice_id).is_none(),
+            ContractError::AlreadyExists,
             "Invoice already listed"
         );
 
@@ -192,21 +771,57 @@ impl MarketplaceContract {
             due_date,
             created_at: env::block_timestamp_ms(),
             expires_at,
-            active: true,
+            active: false,
+            currency: currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+            risk_score: None,
+            payout_account: None,
+            min_yield_bps: None,
+            thread_hash: None,
+            highest_bid_id: None,
+            start_at,
+            listing_type,
+            payment_token: None,
+            status: ListingStatus::Pending,
+            secondary,
+            min_bid_increment: None,
+            views: 0,
+            referrer: None,
+            referral_bps: 0,
+            allowed_buyer,
         };
 
         self.listings.insert(id.clone(), listing);
         self.listings_by_invoice.insert(invoice_id.clone(), id.clone());
+        self.listings_by_seller
+            .entry(seller.clone())
+            .or_default()
+            .push(id.clone());
 
         env::log_str(&format!("Listing {} created for invoice {}", id, invoice_id));
+        AdelanteEvent::ListingCreated(vec![ListingCreated {
+            listing_id: id.clone(),
+            invoice_id: invoice_id.clone(),
+            seller: seller.clone(),
+            asking_price,
+        }])
+        .emit();
 
-        // Call invoice contract to mark as listed
-        ext_invoice::ext(self.invoice_contract.clone())
-            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-            .set_listed(invoice_id)
-            .then(
+        let deposit = env::attached_deposit();
+        if !deposit.is_zero() {
+            let _ = Promise::new(seller.clone()).transfer(deposit);
+        }
+
+        // Call invoice contract to mark as listed; it checks the seller owns the invoice
+        let invoice = ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract);
+        let mark_listed = if secondary {
+            invoice.relist_invoice(invoice_id, seller)
+        } else {
+            invoice.set_listed(invoice_id, seller)
+        };
+        mark_listed.then(
                 Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_CALLBACK)
+                    .with_static_gas(self.gas_config.callback)
                     .on_list_callback(id),
             )
     }
@@ -215,131 +830,222 @@ impl MarketplaceContract {
     pub fn on_list_callback(
         &mut self,
         listing_id: String,
-        #[callback_result] result: Result<(), PromiseError>,
-    ) -> String {
+        #[callback_result] result: Result<ListedInvoice, PromiseError>,
+    ) -> Option<String> {
         match result {
-            Ok(_) => {
+            Ok(invoice) => {
+                let mut listing = self.listings.get(&listing_id).cloned()?;
+                let risk_score = invoice.risk_score;
+
+                if !self.meets_discount_gate(&listing, risk_score) {
+                    // Rollback: discount too low for the invoice's risk, release the invoice again
+                    self.discard_listing(&listing);
+                    let _ = ext_invoice::ext(self.invoice_contract.clone())
+                        .with_static_gas(self.gas_config.cross_contract)
+                        .unlist_invoice(listing.invoice_id);
+                    env::log_str(&format!(
+                        "Listing {} rejected: discount too low for risk score {}",
+                        listing_id, risk_score
+                    ));
+                    return None;
+                }
+
+                listing.risk_score = Some(risk_score);
+                // The invoice's own denomination wins over the currency given at listing
+                if let Some(currency) = invoice.currency {
+                    listing.currency = currency;
+                }
+                if !listing.secondary {
+                    listing.payout_account = invoice.beneficiary;
+                }
+                self.set_listing_status(&mut listing, ListingStatus::Active);
+                self.update_seller_stats(&listing.seller, |stats| stats.total_listings += 1);
+                self.listings.insert(listing_id.clone(), listing);
+
                 env::log_str(&format!("Listing {} confirmed", listing_id));
-                listing_id
+                Some(listing_id)
             }
             Err(_) => {
                 // Rollback: remove listing if invoice contract call failed
                 if let Some(listing) = self.listings.get(&listing_id).cloned() {
-                    self.listings.remove(&listing_id);
-                    self.listings_by_invoice.remove(&listing.invoice_id);
+                    self.discard_listing(&listing);
                 }
-                env::panic_str("Failed to mark invoice as listed");
+                env::log_str(&format!(
+                    "Listing {} rolled back: failed to mark invoice as listed",
+                    listing_id
+                ));
+                None
             }
         }
     }
 
-    /// NEP-141 callback: Receive USDC tokens for purchasing invoices
-    /// Message format: "buy_listing:LST-000001"
+    /// NEP-141 callback: Receive accepted tokens for purchasing invoices or placing bids
+    /// Message format: "buy_listing:LST-000001[:referrer.near]",
+    /// "place_bid:LST-000001[:expires_at_ms]" or
+    /// "accept_counter:LST-000001:BID-000001" (tops up a bid to the seller's counter)
     pub fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
         amount: U128,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        // Verify the caller is the USDC contract
+        self.assert_not_paused();
+
+        // Verify the caller is an accepted token contract
         let token_contract = env::predecessor_account_id();
-        assert!(
-            token_contract == self.usdc_contract,
-            "Only USDC token transfers accepted"
+        ensure!(
+            self.is_accepted_token(&token_contract),
+            ContractError::TokenNotAccepted,
+            "Token {} is not accepted",
+            token_contract
         );
 
         // Parse the message
         let parts: Vec<&str> = msg.split(':').collect();
-        assert!(parts.len() >= 2, "Invalid message format. Use 'buy_listing:LST-000001'");
+        ensure!(
+            parts.len() >= 2,
+            ContractError::InvalidArgument,
+            "Invalid message format. Use 'buy_listing:LST-000001'"
+        );
 
         let action = parts[0];
         let listing_id = parts[1].to_string();
 
         match action {
-            "buy_listing" => self.process_usdc_purchase(sender_id, amount, listing_id),
+            "buy_listing" => {
+                let referrer = parts.get(2).map(|raw| {
+                    raw.parse::<AccountId>()
+                        .or_fail(ContractError::InvalidArgument, "Invalid referrer account")
+                });
+                self.process_usdc_purchase(token_contract, sender_id, amount, listing_id, referrer)
+            }
+            "place_bid" => {
+                let expires_at = parts
+                    .get(2)
+                    .map(|raw| {
+                        raw.parse::<u64>()
+                            .or_fail(ContractError::InvalidArgument, "Invalid bid expiry")
+                    });
+                self.process_usdc_bid(token_contract, sender_id, amount, listing_id, expires_at)
+            }
+            "accept_counter" => {
+                let bid_id = parts
+                    .get(2)
+                    .or_fail(ContractError::InvalidArgument, "Missing bid id")
+                    .to_string();
+                self.process_counter_top_up(token_contract, sender_id, amount, listing_id, bid_id)
+            }
             _ => {
-                env::panic_str("Unknown action. Use 'buy_listing:LST-000001'");
+                ContractError::InvalidArgument.panic(
+                    "Unknown action. Use 'buy_listing:LST-000001', 'place_bid:LST-000001' or 'accept_counter:LST-000001:BID-000001'",
+                );
             }
         }
     }
 
     /// Process a USDC purchase of an invoice listing
+    /// A referrer named by the buyer takes precedence over the listing's own
     fn process_usdc_purchase(
         &mut self,
+        token: AccountId,
         buyer: AccountId,
         payment: U128,
         listing_id: String,
+        referrer: Option<AccountId>,
     ) -> PromiseOrValue<U128> {
+        self.assert_kyc_verified(&buyer);
+        self.lock_purchase(&listing_id);
         let listing = self
             .listings
             .get(&listing_id)
-            .expect("Listing not found")
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
             .clone();
 
-        assert!(listing.active, "Listing is not active");
-        assert!(listing.seller != buyer, "Cannot buy your own listing");
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.seller != buyer,
+            ContractError::InvalidArgument,
+            "Cannot buy your own listing"
+        );
+        ensure!(
+            listing.listing_type != ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Auction listings can only be bid on"
+        );
+        Self::assert_allowed_buyer(&listing, &buyer);
+        Self::assert_started(&listing);
+        self.assert_currency_token(&listing, &token);
 
         if let Some(expires_at) = listing.expires_at {
-            assert!(
+            ensure!(
                 env::block_timestamp_ms() < expires_at,
+                ContractError::Expired,
                 "Listing has expired"
             );
         }
 
+        let price = Self::current_price(&listing, env::block_timestamp_ms());
+        ensure!(
+            self.within_yield_floor(&listing, price.0),
+            ContractError::InvalidAmount,
+            "Purchase exceeds seller's yield floor"
+        );
+
         // Verify payment amount
-        assert!(
-            payment.0 >= listing.asking_price.0,
+        ensure!(
+            payment.0 >= price.0,
+            ContractError::InsufficientPayment,
             "Insufficient payment. Required: {}, Received: {}",
-            listing.asking_price.0,
+            price.0,
             payment.0
         );
 
         // Calculate excess payment to refund
-        let excess = payment.0 - listing.asking_price.0;
+        let excess = payment.0 - price.0;
 
         // Deactivate listing
         let mut updated_listing = listing.clone();
-        updated_listing.active = false;
+        self.set_listing_status(&mut updated_listing, ListingStatus::Sold);
+        updated_listing.payment_token = Some(token.clone());
         self.listings.insert(listing_id.clone(), updated_listing);
         self.listings_by_invoice.remove(&listing.invoice_id);
 
+        // Fee is priced on volume before this sale and collected once the sale is confirmed
+        let fee = self.calculate_fee(&listing.currency, &listing.seller, price.0);
+        let referrer = referrer.or_else(|| listing.referrer.clone());
+        ensure!(
+            referrer.as_ref() != Some(&buyer),
+            ContractError::InvalidArgument,
+            "Buyers cannot refer themselves"
+        );
+        // referral_bps is capped at 100% of the fee, so the referrer never takes more than it
+        let referral_fee = match &referrer {
+            Some(_) => fee * listing.referral_bps as u128 / 10_000,
+            None => 0,
+        };
+
         env::log_str(&format!(
-            "Invoice {} purchased by {} for {} USDC via ft_transfer_call",
+            "Invoice {} purchased by {} for {} {} via ft_transfer_call",
             listing.invoice_id,
             buyer,
-            listing.asking_price.0
+            price.0,
+            token
         ));
 
-        // Transfer invoice ownership and create escrow
-        // The USDC is already in this contract, we need to forward it to escrow
-        let transfer_promise = ext_ft::ext(self.usdc_contract.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(
-                self.escrow_contract.clone(),
-                listing.asking_price,
-                Some(format!("escrow_deposit:{}", listing.invoice_id)),
-            );
-
-        let invoice_transfer = ext_invoice::ext(self.invoice_contract.clone())
-            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-            .transfer_invoice(listing.invoice_id.clone(), buyer.clone());
-
-        let escrow_creation = ext_escrow::ext(self.escrow_contract.clone())
-            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-            .create_escrow(
-                listing.invoice_id,
-                listing.seller,
+        // The tokens stay here until the invoice is transferred and the escrow exists
+        let _ = self.complete_purchase(
+            &listing,
+            PendingPurchase {
+                listing_id,
                 buyer,
-                listing.asking_price,
-                listing.invoice_amount,
-                listing.due_date,
-            );
-
-        // Chain the promises: transfer USDC to escrow, then transfer invoice, then create escrow record
-        // Note: We detach the promise chain and return excess immediately
-        // The cross-contract calls will happen asynchronously
-        let _ = transfer_promise.then(invoice_transfer).then(escrow_creation);
+                token: Some(token),
+                payment: price,
+                price,
+                fee: U128(fee),
+                referrer,
+                referral_fee: U128(referral_fee),
+            },
+        );
 
         // Return excess payment (will be refunded to sender)
         PromiseOrValue::Value(U128(excess))
@@ -349,34 +1055,64 @@ impl MarketplaceContract {
     /// Kept for backwards compatibility during transition
     #[payable]
     pub fn buy_invoice(&mut self, listing_id: String) -> Promise {
+        self.assert_not_paused();
         let buyer = env::predecessor_account_id();
+        self.assert_kyc_verified(&buyer);
+        self.lock_purchase(&listing_id);
         let listing = self
             .listings
             .get(&listing_id)
-            .expect("Listing not found")
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
             .clone();
 
-        assert!(listing.active, "Listing is not active");
-        assert!(listing.seller != buyer, "Cannot buy your own listing");
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.seller != buyer,
+            ContractError::InvalidArgument,
+            "Cannot buy your own listing"
+        );
+        ensure!(
+            listing.listing_type != ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Auction listings can only be bid on"
+        );
+        ensure!(
+            !listing.secondary,
+            ContractError::InvalidState,
+            "Resale listings are bought with tokens"
+        );
+        Self::assert_allowed_buyer(&listing, &buyer);
+        Self::assert_started(&listing);
 
         if let Some(expires_at) = listing.expires_at {
-            assert!(
+            ensure!(
                 env::block_timestamp_ms() < expires_at,
+                ContractError::Expired,
                 "Listing has expired"
             );
         }
 
-        // For demo: accept any attached NEAR as "payment"
+        let price = Self::current_price(&listing, env::block_timestamp_ms());
+        ensure!(
+            self.within_yield_floor(&listing, price.0),
+            ContractError::InvalidAmount,
+            "Purchase exceeds seller's yield floor"
+        );
+
+        // For demo: the price is read as yoctoNEAR and paid with the attached deposit
         // In production: integrate with USDC ft_transfer_call
-        let payment = env::attached_deposit();
-        assert!(
-            payment >= NearToken::from_millinear(1),
-            "Must attach payment"
+        let payment = env::attached_deposit().as_yoctonear();
+        ensure!(
+            payment >= price.0,
+            ContractError::InsufficientPayment,
+            "Attached deposit below asking price. Required: {}, Received: {}",
+            price.0,
+            payment
         );
 
         // Deactivate listing
         let mut updated_listing = listing.clone();
-        updated_listing.active = false;
+        self.set_listing_status(&mut updated_listing, ListingStatus::Sold);
         self.listings.insert(listing_id.clone(), updated_listing);
         self.listings_by_invoice.remove(&listing.invoice_id);
 
@@ -384,173 +1120,5252 @@ impl MarketplaceContract {
             "Invoice {} purchased by {} for {}",
             listing.invoice_id,
             buyer,
-            listing.asking_price.0
+            price.0
         ));
 
-        // Transfer invoice ownership and create escrow
-        ext_invoice::ext(self.invoice_contract.clone())
-            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-            .transfer_invoice(listing.invoice_id.clone(), buyer.clone())
-            .then(
-                ext_escrow::ext(self.escrow_contract.clone())
-                    .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-                    .create_escrow(
-                        listing.invoice_id,
-                        listing.seller,
-                        buyer,
-                        listing.asking_price,
-                        listing.invoice_amount,
-                        listing.due_date,
-                    ),
-            )
+        // Return anything attached beyond the price
+        let excess = payment - price.0;
+        if excess > 0 {
+            env::log_str(&format!("Refunding {} yoctoNEAR overpayment to {}", excess, buyer));
+            let _ = Promise::new(buyer.clone()).transfer(NearToken::from_yoctonear(excess));
+        }
+
+        // Transfer invoice ownership and create escrow, refunding the NEAR if either fails
+        self.complete_purchase(
+            &listing,
+            PendingPurchase {
+                listing_id,
+                buyer,
+                token: None,
+                payment: price,
+                price,
+                fee: U128(0),
+                referrer: None,
+                referral_fee: U128(0),
+            },
+        )
     }
 
-    /// Cancel a listing
-    pub fn cancel_listing(&mut self, listing_id: String) -> Promise {
-        let caller = env::predecessor_account_id();
+    /// Create the escrow once the invoice has moved to the buyer; fails if the transfer failed
+    #[private]
+    pub fn on_invoice_transferred(
+        &mut self,
+        purchase: PendingPurchase,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> Promise {
+        ensure!(result.is_ok(), ContractError::CallbackFailed, "Invoice transfer failed");
         let listing = self
             .listings
-            .get(&listing_id)
-            .expect("Listing not found")
+            .get(&purchase.listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
             .clone();
 
-        assert!(listing.seller == caller, "Only seller can cancel listing");
-        assert!(listing.active, "Listing is not active");
-
-        // Deactivate listing
-        let mut updated_listing = listing.clone();
-        updated_listing.active = false;
-        self.listings.insert(listing_id.clone(), updated_listing);
-        self.listings_by_invoice.remove(&listing.invoice_id);
-
-        env::log_str(&format!("Listing {} cancelled", listing_id));
-
-        // Call invoice contract to unlist
-        ext_invoice::ext(self.invoice_contract.clone())
-            .with_static_gas(GAS_FOR_CROSS_CONTRACT)
-            .unlist_invoice(listing.invoice_id)
-    }
-
-    /// Update fee (admin only)
-    pub fn set_fee_basis_points(&mut self, fee_basis_points: u16) {
-        assert!(fee_basis_points <= 1000, "Fee cannot exceed 10%");
-        self.fee_basis_points = fee_basis_points;
+        let escrow = ext_escrow::ext(self.escrow_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract);
+        if listing.secondary {
+            return escrow.resell_position(listing.invoice_id, purchase.buyer);
+        }
+        escrow.create_escrow(
+            listing.invoice_id.clone(),
+            listing.seller.clone(),
+            purchase.buyer,
+            U128(purchase.price.0 - purchase.fee.0),
+            listing.invoice_amount,
+            listing.due_date,
+            listing.payout_account.clone(),
+        )
     }
 
-    // ============ VIEW METHODS ============
-
-    /// Get all active listings with calculated fields
-    pub fn get_active_listings(&self, from_index: u64, limit: u64) -> Vec<ListingView> {
-        let now = env::block_timestamp_ms();
+    /// Confirm a purchase: fund the escrow and collect the fee, or restore the listing and refund
+    /// the buyer if the invoice transfer or escrow creation failed
+    #[private]
+    pub fn on_purchase_callback(
+        &mut self,
+        purchase: PendingPurchase,
+        #[callback_result] escrow_id: Result<String, PromiseError>,
+    ) -> bool {
+        self.processing.remove(&purchase.listing_id);
+        let mut listing = self
+            .listings
+            .get(&purchase.listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
 
-        self.listings
-            .iter()
-            .filter(|(_, listing)| listing.active)
-            .skip(from_index as usize)
-            .take(limit as usize)
-            .map(|(_, listing)| {
-                let discount = if listing.invoice_amount.0 > 0 {
-                    ((listing.invoice_amount.0 - listing.asking_price.0) as f64
-                        / listing.invoice_amount.0 as f64)
-                        * 100.0
-                } else {
-                    0.0
-                };
+        match escrow_id {
+            Ok(escrow_id) => {
+                if let Some(token) = &purchase.token {
+                    self.collect_fee(&listing.id, token, purchase.fee.0 - purchase.referral_fee.0);
+                    if let Some(referrer) = &purchase.referrer {
+                        self.pay_referral(&listing.id, token, referrer, purchase.referral_fee.0);
+                    }
+                    let _ = self.pay_sale_proceeds(
+                        &listing,
+                        token,
+                        U128(purchase.price.0 - purchase.fee.0),
+                    );
+                }
+                self.record_sale(&listing, purchase.price.0);
 
-                let days_until_due = ((listing.due_date as i64) - (now as i64)) / (24 * 60 * 60 * 1000);
+                env::log_str(&format!(
+                    "Purchase of listing {} confirmed: escrow {}",
+                    listing.id, escrow_id
+                ));
+                Self::emit_purchase(&listing, &purchase.buyer, purchase.price);
+                true
+            }
+            Err(_) => {
+                self.set_listing_status(&mut listing, ListingStatus::Active);
+                listing.payment_token = None;
+                self.listings.insert(listing.id.clone(), listing.clone());
+                self.listings_by_invoice
+                    .insert(listing.invoice_id.clone(), listing.id.clone());
 
-                let annualized_yield = if days_until_due > 0 {
-                    (discount / days_until_due as f64) * 365.0
-                } else {
-                    0.0
+                let _ = match &purchase.token {
+                    Some(token) => ext_ft::ext(token.clone())
+                        .with_static_gas(self.gas_config.ft_transfer)
+                        .with_attached_deposit(NearToken::from_yoctonear(1))
+                        .ft_transfer(
+                            purchase.buyer.clone(),
+                            purchase.payment,
+                            Some(format!("purchase_refund:{}", listing.id)),
+                        ),
+                    None => Promise::new(purchase.buyer.clone())
+                        .transfer(NearToken::from_yoctonear(purchase.payment.0)),
                 };
 
-                ListingView {
-                    listing: listing.clone(),
-                    discount_percentage: discount,
-                    days_until_due,
-                    annualized_yield,
-                }
-            })
-            .collect()
+                env::log_str(&format!(
+                    "Purchase of listing {} rolled back: refunding {} to {}",
+                    listing.id, purchase.payment.0, purchase.buyer
+                ));
+                false
+            }
+        }
     }
 
-    /// Get listing by ID
-    pub fn get_listing(&self, listing_id: String) -> Option<Listing> {
-        self.listings.get(&listing_id).cloned()
-    }
+    /// Lock a USDC bid on an active listing; the bid amount is the transferred amount
+    /// Replaces (and refunds) the bidder's existing active bid on the same listing
+    fn process_usdc_bid(
+        &mut self,
+        token: AccountId,
+        bidder: AccountId,
+        amount: U128,
+        listing_id: String,
+        expires_at: Option<u64>,
+    ) -> PromiseOrValue<U128> {
+        self.assert_kyc_verified(&bidder);
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
 
-    /// Get listing by invoice ID
-    pub fn get_listing_by_invoice(&self, invoice_id: String) -> Option<Listing> {
-        self.listings_by_invoice
-            .get(&invoice_id)
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.seller != bidder,
+            ContractError::InvalidArgument,
+            "Cannot bid on your own listing"
+        );
+        Self::assert_allowed_buyer(&listing, &bidder);
+        Self::assert_started(&listing);
+        self.assert_currency_token(&listing, &token);
+        if let Some(expires_at) = listing.expires_at {
+            ensure!(
+                env::block_timestamp_ms() < expires_at,
+                ContractError::Expired,
+                "Listing has expired"
+            );
+        }
+        if let Some(expires_at) = expires_at {
+            ensure!(
+                expires_at > env::block_timestamp_ms(),
+                ContractError::InvalidArgument,
+                "Bid expiry must be in the future"
+            );
+            ensure!(
+                listing.listing_type != ListingType::EnglishAuction,
+                ContractError::InvalidArgument,
+                "Auction bids cannot expire"
+            );
+        }
+        ensure!(amount.0 > 0, ContractError::InvalidAmount, "Bid amount must be greater than 0");
+        ensure!(
+            amount.0 <= listing.invoice_amount.0,
+            ContractError::InvalidAmount,
+            "Bid cannot exceed invoice amount"
+        );
+        if let Some(min_price) = listing.min_price {
+            ensure!(
+                amount.0 >= min_price.0,
+                ContractError::InvalidAmount,
+                "Bid is below minimum price"
+            );
+        }
+        ensure!(
+            self.within_yield_floor(&listing, amount.0),
+            ContractError::InvalidAmount,
+            "Bid exceeds seller's yield floor"
+        );
+
+        let mut listing_bids = self.bids.get(&listing_id).cloned().unwrap_or_default();
+
+        // The first bid only has to clear min_price
+        if let Some(highest) = Self::highest_active_bid(&listing_bids) {
+            let increment = self.min_bid_increment(&listing, highest.amount.0);
+            ensure!(
+                amount.0 >= highest.amount.0 + increment,
+                ContractError::InvalidAmount,
+                "Bid must exceed current high by at least {}",
+                increment
+            );
+        }
+
+        // Release the bidder's previous bid before recording the new one
+        if let Some(previous) = listing_bids
+            .iter_mut()
+            .find(|bid| bid.bidder == bidder && bid.active)
+        {
+            previous.active = false;
+            previous.refund_pending = true;
+            env::log_str(&format!(
+                "Bid {} replaced, refunding {} {} to {}",
+                previous.id,
+                previous.amount.0,
+                self.bid_token(previous),
+                bidder
+            ));
+            let _ = self.refund_bid(previous);
+        }
+
+        self.bid_count += 1;
+        let id = format!("BID-{:06}", self.bid_count);
+        let bid_listings = self.bids_by_bidder.entry(bidder.clone()).or_default();
+        if !bid_listings.contains(&listing_id) {
+            bid_listings.push(listing_id.clone());
+        }
+
+        listing_bids.push(Bid {
+            id: id.clone(),
+            listing_id: listing_id.clone(),
+            bidder: bidder.clone(),
+            amount,
+            created_at: env::block_timestamp_ms(),
+            active: true,
+            thread_hash: None,
+            funds_locked: true,
+            accepted: false,
+            refund_pending: false,
+            token: Some(token.clone()),
+            expires_at,
+            counter_amount: None,
+            counter_state: CounterState::None,
+            excess_owed: U128(0),
+        });
+
+        let highest_bid_id = Self::highest_active_bid(&listing_bids).map(|bid| bid.id.clone());
+        self.bids.insert(listing_id.clone(), listing_bids);
+        if listing.highest_bid_id != highest_bid_id {
+            listing.highest_bid_id = highest_bid_id;
+            self.listings.insert(listing_id.clone(), listing);
+        }
+
+        env::log_str(&format!(
+            "Bid {} placed on listing {} by {} for {} {}",
+            id, listing_id, bidder, amount.0, token
+        ));
+        AdelanteEvent::BidPlaced(vec![BidPlaced {
+            bid_id: id,
+            listing_id,
+            bidder,
+            amount,
+        }])
+        .emit();
+
+        // Keep the full transfer locked in the contract
+        PromiseOrValue::Value(U128(0))
+    }
+
+    /// Accept a bid (seller only): forwards the winning USDC to escrow and refunds every other bidder
+    /// An expired bid is refunded instead of accepted
+    pub fn accept_bid(&mut self, listing_id: String, bid_id: String) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can accept bids"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.listing_type != ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Auction listings are settled by finalize_auction"
+        );
+
+        let winner = self
+            .bids
+            .get(&listing_id)
+            .and_then(|bids| bids.iter().find(|bid| bid.id == bid_id))
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(
+            winner.active && winner.funds_locked,
+            ContractError::BidInactive,
+            "Bid is not active"
+        );
+        if Self::is_bid_expired(winner, env::block_timestamp_ms()) {
+            return self.refund_expired_bid(listing, &bid_id);
+        }
+        ensure!(
+            self.within_yield_floor(&listing, winner.amount.0),
+            ContractError::InvalidAmount,
+            "Bid exceeds seller's yield floor"
+        );
+
+        self.sell_to_bid(listing, &bid_id)
+    }
+
+    /// Counter a bid with a different price (seller only); replaces any earlier counter
+    pub fn counter_bid(&mut self, listing_id: String, bid_id: String, counter_amount: U128) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can counter bids"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.listing_type != ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Auction listings are settled by finalize_auction"
+        );
+        ensure!(
+            counter_amount.0 > 0,
+            ContractError::InvalidAmount,
+            "Counter amount must be greater than 0"
+        );
+        ensure!(
+            counter_amount.0 <= listing.invoice_amount.0,
+            ContractError::InvalidAmount,
+            "Counter cannot exceed invoice amount"
+        );
+
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(bid.active && bid.funds_locked, ContractError::BidInactive, "Bid is not active");
+        ensure!(
+            !Self::is_bid_expired(bid, env::block_timestamp_ms()),
+            ContractError::Expired,
+            "Bid has expired"
+        );
+
+        bid.counter_amount = Some(counter_amount);
+        bid.counter_state = CounterState::Offered;
+        self.bids.insert(listing_id.clone(), listing_bids);
+
+        env::log_str(&format!(
+            "Seller countered bid {} on listing {} at {} USDC",
+            bid_id, listing_id, counter_amount.0
+        ));
+    }
+
+    /// Withdraw an open counter-offer (seller only)
+    pub fn withdraw_counter(&mut self, listing_id: String, bid_id: String) {
+        let caller = env::predecessor_account_id();
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found");
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can withdraw counters"
+        );
+
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(
+            bid.counter_state == CounterState::Offered,
+            ContractError::InvalidState,
+            "No open counter offer"
+        );
+
+        bid.counter_state = CounterState::Withdrawn;
+        self.bids.insert(listing_id.clone(), listing_bids);
+
+        env::log_str(&format!("Counter on bid {} withdrawn", bid_id));
+    }
+
+    /// Accept the seller's counter (bidder only) when the locked bid covers it; the difference is
+    /// refunded. A higher counter is accepted by topping up via ft_transfer_call "accept_counter:..."
+    pub fn accept_counter(&mut self, listing_id: String, bid_id: String) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let bid = self.counter_bid_of(&listing_id, &bid_id, &caller);
+        let counter_amount = bid
+            .counter_amount
+            .or_fail(ContractError::InvalidState, "No open counter offer");
+        ensure!(
+            counter_amount.0 <= bid.amount.0,
+            ContractError::InsufficientPayment,
+            "Counter exceeds locked bid; top up {} via ft_transfer_call",
+            counter_amount.0 - bid.amount.0
+        );
+
+        let sale = self.sell_at_counter(listing_id, bid_id);
+        let excess = bid.amount.0 - counter_amount.0;
+        if excess > 0 {
+            let _ = self.refund_counter_excess(&bid, U128(excess));
+        }
+        sale
+    }
+
+    /// Record the outcome of a counter excess refund; a failed amount stays owed on the bid
+    #[private]
+    pub fn on_counter_refund_callback(
+        &mut self,
+        listing_id: String,
+        bid_id: String,
+        amount: U128,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        if result.is_ok() {
+            return true;
+        }
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        bid.excess_owed = U128(bid.excess_owed.0 + amount.0);
+        self.bids.insert(listing_id, listing_bids);
+
+        env::log_str(&format!(
+            "Counter refund of {} for bid {} failed; queued for retry_counter_refund",
+            amount.0, bid_id
+        ));
+        false
+    }
+
+    /// Re-send a failed counter excess refund (permissionless; funds only go to the bidder)
+    pub fn retry_counter_refund(&mut self, listing_id: String, bid_id: String) -> Promise {
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(bid.excess_owed.0 > 0, ContractError::InvalidState, "Bid has no refund owed");
+
+        // Cleared while in flight; on_counter_refund_callback restores it if the transfer fails
+        let amount = bid.excess_owed;
+        bid.excess_owed = U128(0);
+        let bid = bid.clone();
+        self.bids.insert(listing_id, listing_bids);
+
+        self.refund_counter_excess(&bid, amount)
+    }
+
+    /// Accept a counter above the locked bid with the bidder's top-up, returning any excess
+    fn process_counter_top_up(
+        &mut self,
+        token: AccountId,
+        bidder: AccountId,
+        amount: U128,
+        listing_id: String,
+        bid_id: String,
+    ) -> PromiseOrValue<U128> {
+        let bid = self.counter_bid_of(&listing_id, &bid_id, &bidder);
+        ensure!(
+            token == self.bid_token(&bid),
+            ContractError::TokenMismatch,
+            "Top-up must use the bid's token"
+        );
+        let counter_amount = bid
+            .counter_amount
+            .or_fail(ContractError::InvalidState, "No open counter offer");
+        let available = bid.amount.0 + amount.0;
+        ensure!(
+            available >= counter_amount.0,
+            ContractError::InsufficientPayment,
+            "Insufficient top-up. Required: {}, Received: {}",
+            counter_amount.0.saturating_sub(bid.amount.0),
+            amount.0
+        );
+
+        let _ = self.sell_at_counter(listing_id, bid_id);
+        PromiseOrValue::Value(U128((available - counter_amount.0).min(amount.0)))
+    }
+
+    /// Close an English auction after expiry (anyone): the highest bid wins, ties going to the earliest
+    /// An auction without bids is simply delisted
+    pub fn finalize_auction(&mut self, listing_id: String) -> Promise {
+        self.assert_not_paused();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.listing_type == ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Listing is not an English auction"
+        );
+        let expires_at = listing
+            .expires_at
+            .or_fail(ContractError::InvalidState, "Listing has no expiry");
+        ensure!(
+            env::block_timestamp_ms() >= expires_at,
+            ContractError::InvalidState,
+            "Auction has not ended"
+        );
+
+        let winner_id = self
+            .bids
+            .get(&listing_id)
+            .and_then(|bids| Self::highest_active_bid(bids))
+            .map(|bid| bid.id.clone());
+        if let Some(winner_id) = winner_id {
+            return self.sell_to_bid(listing, &winner_id);
+        }
+
+        self.set_listing_status(&mut listing, ListingStatus::Expired);
+        let invoice_id = listing.invoice_id.clone();
+        self.listings.insert(listing_id.clone(), listing);
+        self.listings_by_invoice.remove(&invoice_id);
+
+        env::log_str(&format!("Auction {} closed without bids", listing_id));
+
+        ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .unlist_invoice(invoice_id)
+    }
+
+    /// Mark a bid refund as completed, or leave it queued for retry if the transfer failed
+    #[private]
+    pub fn on_bid_refund_callback(
+        &mut self,
+        listing_id: String,
+        bid_id: String,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+
+        bid.refund_pending = false;
+        let refunded = result.is_ok();
+        if refunded {
+            bid.funds_locked = false;
+        } else {
+            env::log_str(&format!(
+                "Refund of bid {} failed; queued for retry_bid_refund",
+                bid_id
+            ));
+        }
+        self.bids.insert(listing_id, listing_bids);
+        refunded
+    }
+
+    /// Re-send a failed bid refund (permissionless; funds only go to the bidder)
+    pub fn retry_bid_refund(&mut self, listing_id: String, bid_id: String) -> Promise {
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+
+        ensure!(
+            !bid.active && bid.funds_locked && !bid.accepted,
+            ContractError::InvalidState,
+            "Bid has no refund owed"
+        );
+        ensure!(!bid.refund_pending, ContractError::InvalidState, "Refund already in flight");
+
+        bid.refund_pending = true;
+        let bid = bid.clone();
+        self.bids.insert(listing_id, listing_bids);
+
+        self.refund_bid(&bid)
+    }
+
+    /// Pull a bid's locked tokens back once its listing has closed (bidder only)
+    /// Fallback for push refunds that failed (also retryable by anyone via retry_bid_refund);
+    /// the winning bid's funds went to the sale and cannot be claimed
+    pub fn claim_bid_refund(&mut self, listing_id: String, bid_id: String) -> Promise {
+        let caller = env::predecessor_account_id();
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found");
+        ensure!(
+            listing.status != ListingStatus::Active,
+            ContractError::InvalidState,
+            "Listing is still open; cancel the bid instead"
+        );
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+
+        ensure!(bid.bidder == caller, ContractError::Unauthorized, "Only bidder can claim a refund");
+        ensure!(!bid.accepted, ContractError::InvalidState, "Winning bid is not refundable");
+        ensure!(bid.funds_locked, ContractError::InvalidState, "Bid has no refund owed");
+        ensure!(!bid.refund_pending, ContractError::InvalidState, "Refund already in flight");
+
+        bid.active = false;
+        bid.refund_pending = true;
+        let bid = bid.clone();
+        self.bids.insert(listing_id, listing_bids);
+
+        env::log_str(&format!("Refund of bid {} claimed by {}", bid_id, caller));
+        self.refund_bid(&bid)
+    }
+
+    /// Cancel a bid (bidder only)
+    pub fn cancel_bid(&mut self, listing_id: String, bid_id: String) -> Promise {
+        let caller = env::predecessor_account_id();
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(bid.bidder == caller, ContractError::Unauthorized, "Only bidder can cancel bid");
+        ensure!(bid.active, ContractError::BidInactive, "Bid is not active");
+        ensure!(
+            self.listings
+                .get(&listing_id)
+                .is_none_or(|listing| listing.listing_type != ListingType::EnglishAuction),
+            ContractError::InvalidState,
+            "Auction bids cannot be cancelled"
+        );
+
+        let refund = self.refund_bid(bid);
+        bid.active = false;
+        bid.refund_pending = true;
+
+        if let Some(mut listing) = self.listings.get(&listing_id).cloned() {
+            if listing.highest_bid_id.as_ref() == Some(&bid_id) {
+                listing.highest_bid_id =
+                    Self::highest_active_bid(&listing_bids).map(|bid| bid.id.clone());
+                self.listings.insert(listing_id.clone(), listing);
+            }
+        }
+        self.bids.insert(listing_id, listing_bids);
+
+        env::log_str(&format!("Bid {} cancelled", bid_id));
+        refund
+    }
+
+    /// Set the referrer of a listing and its share of the marketplace fee, in bps of the fee
+    /// (seller only); buyers may still name their own referrer when purchasing
+    pub fn set_referral(
+        &mut self,
+        listing_id: String,
+        referrer: Option<AccountId>,
+        referral_bps: u16,
+    ) {
+        let caller = env::predecessor_account_id();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can set referral"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            referral_bps <= 10_000,
+            ContractError::InvalidArgument,
+            "Referral cut cannot exceed the marketplace fee"
+        );
+
+        listing.referrer = referrer;
+        listing.referral_bps = referral_bps;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Referral updated for listing {}", listing_id));
+    }
+
+    /// Set or clear the yield floor on a listing (seller only)
+    pub fn set_min_yield_bps(&mut self, listing_id: String, min_yield_bps: Option<u16>) {
+        let caller = env::predecessor_account_id();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can set yield floor"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+
+        listing.min_yield_bps = min_yield_bps;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Yield floor updated for listing {}", listing_id));
+    }
+
+    /// Set the smallest raise a new bid needs over the highest bid (seller only)
+    /// None restores the default: a share of the highest bid on auctions, of min_price otherwise
+    pub fn set_min_bid_increment(&mut self, listing_id: String, min_bid_increment: Option<U128>) {
+        let caller = env::predecessor_account_id();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can set bid increment"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        if let Some(increment) = min_bid_increment {
+            ensure!(
+                increment.0 > 0,
+                ContractError::InvalidAmount,
+                "Bid increment must be greater than 0"
+            );
+        }
+
+        listing.min_bid_increment = min_bid_increment;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Bid increment updated for listing {}", listing_id));
+    }
+
+    /// Count a view of an active listing; each account may record one view per block
+    pub fn record_view(&mut self, listing_id: String) -> u64 {
+        let caller = env::predecessor_account_id();
+        let now = env::block_timestamp_ms();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.status_at(now) == ListingStatus::Active,
+            ContractError::ListingInactive,
+            "Listing is not active"
+        );
+        ensure!(
+            listing.seller != caller,
+            ContractError::Unauthorized,
+            "Sellers cannot record views of their own listings"
+        );
+        let height = env::block_height();
+        ensure!(
+            self.last_view_block.get(&caller) != Some(&height),
+            ContractError::InvalidState,
+            "Only one view per account per block"
+        );
+        self.last_view_block.insert(caller, height);
+
+        listing.views += 1;
+        let views = listing.views;
+        self.listings.insert(listing_id, listing);
+        views
+    }
+
+    /// Reprice an active listing (seller only); open bids must still clear the new min_price
+    pub fn update_listing(
+        &mut self,
+        listing_id: String,
+        asking_price: U128,
+        min_price: Option<U128>,
+        expires_at: Option<u64>,
+    ) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can update listing"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        if let Some(expires_at) = expires_at {
+            ensure!(
+                expires_at > env::block_timestamp_ms(),
+                ContractError::InvalidArgument,
+                "Expiry must be in the future"
+            );
+        }
+        Self::assert_valid_terms(
+            asking_price,
+            listing.invoice_amount,
+            min_price,
+            expires_at,
+            listing.start_at,
+            &listing.listing_type,
+        );
+
+        if let Some(min_price) = min_price {
+            let lowest_bid = self
+                .bids
+                .get(&listing_id)
+                .and_then(|bids| bids.iter().filter(|bid| bid.active).map(|bid| bid.amount.0).min());
+            if let Some(lowest_bid) = lowest_bid {
+                ensure!(
+                    min_price.0 <= lowest_bid,
+                    ContractError::InvalidAmount,
+                    "Min price cannot exceed existing bid of {}",
+                    lowest_bid
+                );
+            }
+        }
+
+        listing.asking_price = asking_price;
+        listing.min_price = min_price;
+        listing.expires_at = expires_at;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Listing {} updated", listing_id));
+        AdelanteEvent::ListingUpdated(vec![ListingUpdated {
+            listing_id,
+            asking_price,
+            min_price,
+            expires_at,
+        }])
+        .emit();
+    }
+
+    /// Push back a live listing's expiry (seller only); expired listings must be relisted instead
+    pub fn extend_listing(&mut self, listing_id: String, new_expires_at: u64) {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let now = env::block_timestamp_ms();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can extend listing"
+        );
+        match listing.status_at(now) {
+            ListingStatus::Active => {}
+            ListingStatus::Expired => {
+                ContractError::Expired.panic("Listing has expired, relist it instead")
+            }
+            _ => ContractError::ListingInactive.panic("Listing is not active"),
+        }
+        ensure!(
+            new_expires_at > now,
+            ContractError::InvalidArgument,
+            "Expiry must be in the future"
+        );
+        let old_expires_at = listing.expires_at;
+        if let Some(current) = old_expires_at {
+            ensure!(
+                new_expires_at > current,
+                ContractError::InvalidArgument,
+                "New expiry must be after the current expiry of {}",
+                current
+            );
+        }
+
+        listing.expires_at = Some(new_expires_at);
+        let invoice_id = listing.invoice_id.clone();
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Listing {} extended to {}", listing_id, new_expires_at));
+        AdelanteEvent::ListingExtended(vec![ListingExtended {
+            listing_id,
+            invoice_id,
+            old_expires_at,
+            new_expires_at,
+        }])
+        .emit();
+    }
+
+    /// Anchor an off-chain negotiation log to a listing (seller only)
+    pub fn set_listing_thread_hash(&mut self, listing_id: String, thread_hash: Option<String>) {
+        let caller = env::predecessor_account_id();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can set thread hash"
+        );
+
+        listing.thread_hash = thread_hash;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!("Thread hash updated for listing {}", listing_id));
+    }
+
+    /// Anchor an off-chain negotiation log to a bid (bidder only)
+    pub fn set_bid_thread_hash(&mut self, listing_id: String, bid_id: String, thread_hash: Option<String>) {
+        let caller = env::predecessor_account_id();
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(
+            bid.bidder == caller,
+            ContractError::Unauthorized,
+            "Only bidder can set thread hash"
+        );
+
+        bid.thread_hash = thread_hash;
+        self.bids.insert(listing_id, listing_bids);
+
+        env::log_str(&format!("Thread hash updated for bid {}", bid_id));
+    }
+
+    /// Cancel a listing
+    pub fn cancel_listing(&mut self, listing_id: String) -> Promise {
+        let caller = env::predecessor_account_id();
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(
+            listing.seller == caller,
+            ContractError::Unauthorized,
+            "Only seller can cancel listing"
+        );
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+
+        self.update_seller_stats(&listing.seller, |stats| stats.cancelled_listings += 1);
+        env::log_str(&format!("Listing {} cancelled", listing_id));
+        Self::emit_listing_cancelled(&listing, caller);
+        self.close_listing(listing, ListingStatus::Cancelled)
+    }
+
+    /// Cancel an active listing whose seller cannot, refunding its bids (admin only)
+    /// Escape hatch for listings stranded by a lost seller key or a stuck invoice call
+    pub fn admin_force_cancel_listing(&mut self, listing_id: String) -> Promise {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can force-cancel listings"
+        );
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            !self.processing.contains_key(&listing_id),
+            ContractError::PurchaseInFlight,
+            "Purchase of listing {} already in flight",
+            listing_id
+        );
+
+        env::log_str(&format!("Listing {} force-cancelled by admin", listing_id));
+        Self::emit_listing_cancelled(&listing, caller);
+        self.close_listing(listing, ListingStatus::Cancelled)
+    }
+
+    /// Refresh a listing's cached invoice terms after an amendment (invoice contract only)
+    /// Refused while bids are locked against the current terms
+    pub fn refresh_listing_terms(&mut self, invoice_id: String, invoice_amount: U128, due_date: u64) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.invoice_contract,
+            ContractError::Unauthorized,
+            "Only invoice contract can refresh listing terms"
+        );
+
+        // A sale unindexes its listing as soon as it starts, so terms cannot change mid-purchase
+        let listing_id = self
+            .listings_by_invoice
+            .get(&invoice_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.asking_price.0 <= invoice_amount.0,
+            ContractError::InvalidAmount,
+            "Asking price cannot exceed invoice amount"
+        );
+        // Bidders priced their locked bids against the current terms
+        ensure!(
+            !self
+                .bids
+                .get(&listing_id)
+                .is_some_and(|bids| bids.iter().any(|bid| bid.active)),
+            ContractError::InvalidState,
+            "Listing {} has active bids",
+            listing_id
+        );
+
+        listing.invoice_amount = invoice_amount;
+        listing.due_date = due_date;
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!(
+            "Listing {} refreshed for amended invoice {}",
+            listing_id, invoice_id
+        ));
+    }
+
+    /// Refresh a listing's cached risk score after an underwriter review (invoice contract only)
+    pub fn refresh_listing_risk(&mut self, invoice_id: String, risk_score: u8) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.invoice_contract,
+            ContractError::Unauthorized,
+            "Only invoice contract can refresh listing risk"
+        );
+
+        let listing_id = self
+            .listings_by_invoice
+            .get(&invoice_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+        let mut listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+
+        listing.risk_score = Some(risk_score);
+        self.listings.insert(listing_id.clone(), listing);
+
+        env::log_str(&format!(
+            "Listing {} risk score refreshed to {} after review",
+            listing_id, risk_score
+        ));
+    }
+
+    /// Deactivate a listing past its expiry (permissionless, pays the keeper bounty)
+    pub fn expire_listing(&mut self, listing_id: String) -> Promise {
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        ensure!(
+            listing.listing_type != ListingType::EnglishAuction,
+            ContractError::InvalidState,
+            "Auction listings are closed by finalize_auction"
+        );
+        let expires_at = listing
+            .expires_at
+            .or_fail(ContractError::InvalidState, "Listing has no expiry");
+        ensure!(
+            env::block_timestamp_ms() >= expires_at,
+            ContractError::InvalidState,
+            "Listing has not expired"
+        );
+
+        self.reward_keeper();
+        self.close_expired_listing(listing)
+    }
+
+    /// Close the lapsed listings in a page of listings and refund their bids (permissionless)
+    /// English auctions are left to finalize_auction; returns how many listings were closed
+    pub fn sweep_expired_listings(&mut self, from_index: u64, limit: u64) -> u64 {
+        let now = env::block_timestamp_ms();
+        let expired: Vec<Listing> = self
+            .listings
+            .values()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_PAGE_LIMIT) as usize)
+            .filter(|listing| {
+                listing.active
+                    && listing.status_at(now) == ListingStatus::Expired
+                    && listing.listing_type != ListingType::EnglishAuction
+                    && !self.processing.contains_key(&listing.id)
+            })
+            .cloned()
+            .collect();
+
+        let swept = expired.len() as u64;
+        for listing in expired {
+            let _ = self.close_expired_listing(listing);
+        }
+        if swept > 0 {
+            self.reward_keeper();
+        }
+        swept
+    }
+
+    /// Add NEAR to the keeper bounty pool
+    #[payable]
+    pub fn fund_keeper_pool(&mut self) {
+        self.keeper_rewards.fund(env::attached_deposit());
+    }
+
+    /// Configure the keeper bounty and the cap on total bounties paid (admin only)
+    pub fn set_keeper_bounty(&mut self, bounty: NearToken, max_total_paid: NearToken) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can configure keeper bounties"
+        );
+        self.keeper_rewards.configure(bounty, max_total_paid);
+    }
+
+    /// Set the gas attached to cross-contract calls, callbacks and ft_transfers (admin only)
+    pub fn set_gas_config(&mut self, cross_contract: Gas, callback: Gas, ft_transfer: Gas) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update gas config"
+        );
+        for (name, gas) in [
+            ("cross_contract", cross_contract),
+            ("callback", callback),
+            ("ft_transfer", ft_transfer),
+        ] {
+            ensure!(
+                gas >= MIN_GAS_CONFIG,
+                ContractError::InvalidArgument,
+                "Gas for {} below minimum of {} Tgas",
+                name,
+                MIN_GAS_CONFIG.as_tgas()
+            );
+        }
+
+        self.gas_config = GasConfig { cross_contract, callback, ft_transfer };
+        env::log_str(&format!(
+            "Gas config set: cross_contract {} Tgas, callback {} Tgas, ft_transfer {} Tgas",
+            cross_contract.as_tgas(),
+            callback.as_tgas(),
+            ft_transfer.as_tgas()
+        ));
+    }
+
+    /// Count a dispute raised on one of a seller's sales (escrow contract or admin)
+    pub fn record_seller_dispute(&mut self, seller: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.escrow_contract || caller == self.admin,
+            ContractError::Unauthorized,
+            "Only escrow contract or admin can record disputes"
+        );
+        self.update_seller_stats(&seller, |stats| stats.disputes += 1);
+        env::log_str(&format!("Dispute recorded against seller {}", seller));
+    }
+
+    /// Update fee (admin only)
+    pub fn set_fee_basis_points(&mut self, fee_basis_points: u16) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can update fee");
+        ensure!(fee_basis_points <= 1000, ContractError::InvalidAmount, "Fee cannot exceed 10%");
+        self.fee_basis_points = fee_basis_points;
+    }
+
+    /// Replace the volume-based fee tiers; an empty list restores the flat fee (admin only)
+    pub fn set_fee_tiers(&mut self, tiers: Vec<(U128, u16)>) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can update fee");
+        ensure!(
+            tiers.iter().all(|(_, fee_bps)| *fee_bps <= 1000),
+            ContractError::InvalidAmount,
+            "Fee cannot exceed 10%"
+        );
+        let mut tiers = tiers;
+        tiers.sort_by_key(|(threshold, _)| std::cmp::Reverse(threshold.0));
+        self.fee_tiers = tiers;
+    }
+
+    /// Set the minimum discount required per risk point, in basis points (admin only)
+    /// A value of 0 disables the check
+    pub fn set_min_discount_bps_per_risk_point(&mut self, bps: u16) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update listing policy"
+        );
+        self.min_discount_bps_per_risk_point = bps;
+    }
+
+    /// Set the minimum raise over the highest English auction bid, in basis points (admin only)
+    pub fn set_auction_min_increment_bps(&mut self, bps: u16) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can update listing policy"
+        );
+        self.auction_min_increment_bps = bps;
+    }
+
+    /// Set a fee override for a currency (admin only)
+    pub fn set_currency_fee(&mut self, currency: String, fee_basis_points: u16) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can update fee");
+        ensure!(fee_basis_points <= 1000, ContractError::InvalidAmount, "Fee cannot exceed 10%");
+        self.fee_by_currency.insert(currency, fee_basis_points);
+    }
+
+    /// Clear a currency fee override, falling back to the default fee (admin only)
+    pub fn clear_currency_fee(&mut self, currency: String) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can update fee");
+        self.fee_by_currency.remove(&currency);
+    }
+
+    /// Require listings in a currency to be paid with `token`, or lift the requirement with None
+    /// (admin only)
+    pub fn set_currency_token(&mut self, currency: String, token: Option<AccountId>) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage accepted tokens"
+        );
+        match token {
+            Some(token) => {
+                ensure!(
+                    self.is_accepted_token(&token),
+                    ContractError::TokenNotAccepted,
+                    "Token {} is not accepted",
+                    token
+                );
+                self.currency_tokens.insert(currency, token);
+            }
+            None => {
+                self.currency_tokens.remove(&currency);
+            }
+        }
+    }
+
+    /// Accept a fungible token for purchases and bids (admin only)
+    pub fn add_accepted_token(&mut self, token: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage accepted tokens"
+        );
+        self.accepted_tokens.insert(token.clone(), true);
+        env::log_str(&format!("Token {} accepted", token));
+    }
+
+    /// Stop accepting a fungible token; open bids in it are still refunded in it (admin only)
+    pub fn remove_accepted_token(&mut self, token: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage accepted tokens"
+        );
+        self.accepted_tokens.remove(&token);
+        env::log_str(&format!("Token {} no longer accepted", token));
+    }
+
+    /// Mark an account as KYC verified (admin only)
+    pub fn add_kyc_verified(&mut self, account: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can manage KYC");
+        self.kyc_verified.insert(account.clone(), true);
+        env::log_str(&format!("Account {} KYC verified", account));
+    }
+
+    /// Revoke an account's KYC verification (admin only)
+    pub fn remove_kyc_verified(&mut self, account: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can manage KYC");
+        self.kyc_verified.remove(&account);
+        env::log_str(&format!("Account {} KYC revoked", account));
+    }
+
+    /// Require buyers and bidders to be KYC verified (admin only)
+    pub fn set_require_kyc(&mut self, required: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can manage KYC");
+        self.require_kyc = required;
+        env::log_str(&format!("KYC required: {}", required));
+    }
+
+    /// Pause or unpause trading (admin or operator)
+    pub fn set_paused(&mut self, paused: bool) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can pause the contract"
+        );
+        self.paused = paused;
+        env::log_str(&format!("Marketplace paused: {}", paused));
+    }
+
+    /// Add an operator for routine maintenance (admin only)
+    pub fn add_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.insert(operator, true);
+    }
+
+    /// Remove an operator (admin only)
+    pub fn remove_operator(&mut self, operator: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin,
+            ContractError::Unauthorized,
+            "Only admin can manage operators"
+        );
+        self.operators.remove(&operator);
+    }
+
+    /// Raise listing_count and bid_count past every stored id so new ids never collide
+    /// (admin or operator)
+    pub fn recompute_counters(&mut self) -> (u64, u64) {
+        let caller = env::predecessor_account_id();
+        ensure!(
+            caller == self.admin || self.is_operator(caller),
+            ContractError::Unauthorized,
+            "Only admin or operator can recompute counters"
+        );
+
+        let max_listing = self.listings.keys().map(|id| id_sequence(id)).max().unwrap_or(0);
+        let previous_listings = self.listing_count;
+        self.listing_count = previous_listings
+            .max(max_listing)
+            .max(self.listings.len() as u64);
+
+        let (max_bid, bid_total) = self
+            .bids
+            .values()
+            .flatten()
+            .fold((0, 0u64), |(max, total), bid| (max.max(id_sequence(&bid.id)), total + 1));
+        let previous_bids = self.bid_count;
+        self.bid_count = previous_bids.max(max_bid).max(bid_total);
+
+        env::log_str(&format!(
+            "Counters recomputed: listing_count {} -> {}, bid_count {} -> {}",
+            previous_listings, self.listing_count, previous_bids, self.bid_count
+        ));
+        (self.listing_count, self.bid_count)
+    }
+
+    /// Update admin (current admin only)
+    pub fn set_admin(&mut self, new_admin: AccountId) {
+        let caller = env::predecessor_account_id();
+        ensure!(caller == self.admin, ContractError::Unauthorized, "Only admin can change admin");
+        self.admin = new_admin;
+    }
+
+    // ============ VIEW METHODS ============
+
+    /// Get all active listings with calculated fields
+    pub fn get_active_listings(&self, from_index: u64, limit: u64) -> Vec<ListingView> {
+        let now = env::block_timestamp_ms();
+
+        self.listings
+            .iter()
+            .filter(|(_, listing)| {
+                listing.status_at(now) == ListingStatus::Active && Self::has_started(listing, now)
+            })
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(_, listing)| ListingView::from_listing(listing, now))
+            .collect()
+    }
+
+    /// Get active listings with ids after `last_id`, in ascending listing id (creation) order
+    /// Seeks straight to the ids following the cursor instead of skipping earlier entries, so a
+    /// deep page costs no more than the first; pass the last returned id to fetch the next page
+    pub fn get_active_listings_after(
+        &self,
+        last_id: Option<String>,
+        limit: Option<u64>,
+    ) -> Vec<ListingView> {
+        let now = env::block_timestamp_ms();
+        let start = last_id.map_or(0, |id| id_sequence(&id)) + 1;
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+
+        (start..=self.listing_count)
+            .filter_map(|sequence| self.listings.get(&format!("LST-{:06}", sequence)))
+            .filter(|listing| {
+                listing.status_at(now) == ListingStatus::Active && Self::has_started(listing, now)
+            })
+            .take(limit as usize)
+            .map(|listing| ListingView::from_listing(listing, now))
+            .collect()
+    }
+
+    /// Get active listings ordered by view count, most viewed first (ties by listing id)
+    /// Only the first MAX_POPULAR_CANDIDATES active listings are ranked, bounding the sort
+    pub fn get_popular_listings(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<ListingView> {
+        let now = env::block_timestamp_ms();
+        let mut candidates: Vec<&Listing> = self
+            .listings
+            .values()
+            .filter(|listing| {
+                listing.status_at(now) == ListingStatus::Active && Self::has_started(listing, now)
+            })
+            .take(MAX_POPULAR_CANDIDATES)
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.views
+                .cmp(&a.views)
+                .then_with(|| id_sequence(&a.id).cmp(&id_sequence(&b.id)))
+        });
+
+        PaginatedResult::paginate(
+            candidates
+                .into_iter()
+                .map(|listing| ListingView::from_listing(listing, now)),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get every listing, sold and cancelled ones included unless `active_only` (paginated)
+    pub fn get_all_listings(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        active_only: Option<bool>,
+    ) -> PaginatedResult<ListingView> {
+        let now = env::block_timestamp_ms();
+        let active_only = active_only.unwrap_or(false);
+        PaginatedResult::paginate(
+            self.listings
+                .values()
+                .filter(|listing| !active_only || listing.active)
+                .map(|listing| ListingView::from_listing(listing, now)),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get listings in a lifecycle status (paginated); unswept listings past expiry count as Expired
+    pub fn get_listings_by_status(
+        &self,
+        status: ListingStatus,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<ListingView> {
+        let now = env::block_timestamp_ms();
+        PaginatedResult::paginate(
+            self.listings
+                .values()
+                .filter(|listing| listing.status_at(now) == status)
+                .map(|listing| ListingView::from_listing(listing, now)),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Whether a purchase of the listing is waiting on its cross-contract calls
+    pub fn is_purchase_in_flight(&self, listing_id: String) -> bool {
+        self.processing.contains_key(&listing_id)
+    }
+
+    /// Get listing by ID
+    pub fn get_listing(&self, listing_id: String) -> Option<Listing> {
+        self.listings.get(&listing_id).cloned()
+    }
+
+    /// Get listing by invoice ID
+    pub fn get_listing_by_invoice(&self, invoice_id: String) -> Option<Listing> {
+        self.listings_by_invoice
+            .get(&invoice_id)
             .and_then(|id| self.listings.get(id).cloned())
     }
 
-    /// Get listings by seller
-    pub fn get_listings_by_seller(&self, seller: AccountId) -> Vec<Listing> {
-        self.listings
-            .iter()
-            .filter(|(_, listing)| listing.seller == seller)
-            .map(|(_, listing)| listing.clone())
-            .collect()
+    /// Get listings by seller (paginated over the seller's listing index)
+    pub fn get_listings_by_seller(
+        &self,
+        seller: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Listing> {
+        let ids = self
+            .listings_by_seller
+            .get(&seller)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        PaginatedResult::paginate(
+            ids.iter().filter_map(|id| self.listings.get(id).cloned()),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get all bids for a listing, including accepted, refunded and pending-refund bids
+    pub fn get_bids(&self, listing_id: String) -> Vec<Bid> {
+        let now = env::block_timestamp_ms();
+        self.bids
+            .get(&listing_id)
+            .map(|bids| {
+                bids.iter()
+                    .filter(|bid| !Self::is_bid_expired(bid, now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get a listing with its yield fields, unexpired active bids, highest bid and seller stats
+    pub fn get_listing_detail(&self, listing_id: String) -> Option<ListingDetail> {
+        let now = env::block_timestamp_ms();
+        let listing = self.listings.get(&listing_id)?;
+        let active_bids = self
+            .bids
+            .get(&listing_id)
+            .map(|bids| {
+                bids.iter()
+                    .filter(|bid| bid.active && !Self::is_bid_expired(bid, now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(ListingDetail {
+            listing: ListingView::from_listing(listing, now),
+            active_bids,
+            highest_bid: self.get_highest_bid(listing_id),
+            seller_stats: self.get_seller_stats(listing.seller.clone()),
+        })
+    }
+
+    /// Get active bids on a listing that have passed their expiry and still hold funds
+    pub fn get_expired_bids(&self, listing_id: String) -> Vec<Bid> {
+        let now = env::block_timestamp_ms();
+        self.bids
+            .get(&listing_id)
+            .map(|bids| {
+                bids.iter()
+                    .filter(|bid| bid.active && Self::is_bid_expired(bid, now))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the highest active bid for a listing
+    pub fn get_highest_bid(&self, listing_id: String) -> Option<Bid> {
+        let listing = self.listings.get(&listing_id)?;
+        let top_id = listing.highest_bid_id.as_ref()?;
+        self.bids
+            .get(&listing_id)?
+            .iter()
+            .find(|bid| &bid.id == top_id && bid.active)
+            .cloned()
+    }
+
+    /// Get active bids placed by an account (paginated)
+    /// Only the listings the account has bid on are read
+    pub fn get_bids_by_bidder(
+        &self,
+        bidder: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> PaginatedResult<Bid> {
+        PaginatedResult::paginate(
+            self.bids_of(&bidder).filter(|bid| bid.active).cloned(),
+            from_index,
+            limit,
+        )
+    }
+
+    /// Get the USDC a bidder currently has locked in bids, including refunds still in flight
+    /// and counter excess still owed
+    pub fn get_locked_balance(&self, bidder: AccountId) -> U128 {
+        U128(
+            self.bids_of(&bidder)
+                .map(|bid| if bid.funds_locked { bid.amount.0 } else { 0 } + bid.excess_owed.0)
+                .sum(),
+        )
+    }
+
+    /// Get active bids for several listings, grouped by listing id
+    pub fn get_bids_for_listings(&self, listing_ids: Vec<String>) -> Vec<(String, Vec<Bid>)> {
+        ensure!(
+            listing_ids.len() <= MAX_LISTINGS_PER_BID_QUERY,
+            ContractError::InvalidArgument,
+            "Too many listings requested (max {})",
+            MAX_LISTINGS_PER_BID_QUERY
+        );
+
+        listing_ids
+            .into_iter()
+            .map(|listing_id| {
+                let bids = self
+                    .get_bids(listing_id.clone())
+                    .into_iter()
+                    .filter(|bid| bid.active)
+                    .collect();
+                (listing_id, bids)
+            })
+            .collect()
+    }
+
+    /// Get bid count
+    pub fn get_bid_count(&self) -> u64 {
+        self.bid_count
+    }
+
+    /// Get listing count
+    pub fn get_listing_count(&self) -> u64 {
+        self.listing_count
+    }
+
+    /// Get active listing count
+    pub fn get_active_listing_count(&self) -> u64 {
+        let now = env::block_timestamp_ms();
+        self.listings
+            .iter()
+            .filter(|(_, listing)| listing.active && Self::has_started(listing, now))
+            .count() as u64
+    }
+
+    /// Check whether an account is an operator
+    pub fn is_operator(&self, account_id: AccountId) -> bool {
+        self.operators.get(&account_id).copied().unwrap_or(false)
+    }
+
+    /// Check whether trading is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Get admin address
+    pub fn get_admin(&self) -> AccountId {
+        self.admin.clone()
+    }
+
+    /// Get the tokens accepted for purchases and bids
+    pub fn get_accepted_tokens(&self) -> Vec<AccountId> {
+        self.accepted_tokens.keys().cloned().collect()
+    }
+
+    /// Get the token designated for paying listings in a currency, if any
+    pub fn get_currency_token(&self, currency: String) -> Option<AccountId> {
+        self.currency_tokens.get(&currency).cloned()
+    }
+
+    /// Get the fee applied to sales in a currency
+    pub fn get_fee_for_currency(&self, currency: String) -> u16 {
+        self.fee_by_currency
+            .get(&currency)
+            .copied()
+            .unwrap_or(self.fee_basis_points)
+    }
+
+    /// Get the fee rate a seller currently pays on USDC sales, after any volume tier
+    pub fn get_fee_for_seller(&self, seller: AccountId) -> u16 {
+        self.fee_bps_for(DEFAULT_CURRENCY, &seller)
+    }
+
+    /// Get the fee charged on a sale of a listing at its asking price
+    pub fn get_sale_fee(&self, listing_id: String) -> U128 {
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found");
+        let price = Self::current_price(listing, env::block_timestamp_ms());
+        U128(self.calculate_fee(&listing.currency, &listing.seller, price.0))
+    }
+
+    /// Quote the marketplace fee and the seller's net proceeds on a sale at `asking_price`
+    /// Uses the currency's fee and the seller's volume tier, as a sale would
+    pub fn quote_listing(
+        &self,
+        asking_price: U128,
+        currency: String,
+        seller: AccountId,
+    ) -> (U128, U128) {
+        let fee = self.calculate_fee(&currency, &seller, asking_price.0);
+        (U128(fee), U128(asking_price.0 - fee))
+    }
+
+    /// Quote the discount percentage and annualized yield shown for a listing with these terms
+    pub fn quote_yield(
+        &self,
+        asking_price: U128,
+        invoice_amount: U128,
+        due_date: u64,
+    ) -> (f64, f64) {
+        discount_and_yield(asking_price.0, invoice_amount.0, due_date, env::block_timestamp_ms())
+    }
+
+    /// Get the price a listing can be bought at right now
+    pub fn get_current_price(&self, listing_id: String) -> U128 {
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found");
+        Self::current_price(listing, env::block_timestamp_ms())
+    }
+
+    /// Get the minimum discount required per risk point, in basis points
+    pub fn get_min_discount_bps_per_risk_point(&self) -> u16 {
+        self.min_discount_bps_per_risk_point
+    }
+
+    /// Get the minimum raise over the highest English auction bid, in basis points
+    pub fn get_auction_min_increment_bps(&self) -> u16 {
+        self.auction_min_increment_bps
+    }
+
+    /// Get keeper bounty configuration and totals
+    pub fn get_keeper_rewards(&self) -> KeeperRewards {
+        self.keeper_rewards.clone()
+    }
+
+    /// Get the gas attached to outgoing calls
+    pub fn get_gas_config(&self) -> GasConfig {
+        self.gas_config.clone()
+    }
+
+    /// Get the volume-based fee tiers, highest threshold first
+    pub fn get_fee_tiers(&self) -> Vec<(U128, u16)> {
+        self.fee_tiers.clone()
+    }
+
+    /// Get marketplace-wide listing and sale totals
+    pub fn get_marketplace_stats(&self) -> MarketplaceStats {
+        let average_discount_percentage = if self.total_sales > 0 {
+            self.total_discount_bps as f64 / self.total_sales as f64 / 100.0
+        } else {
+            0.0
+        };
+        MarketplaceStats {
+            total_listings: self.listing_count,
+            active_listings: self.active_listing_count,
+            total_sales: self.total_sales,
+            total_volume: U128(self.total_sale_volume),
+            average_discount_percentage,
+        }
+    }
+
+    /// Get a seller's cumulative sale volume
+    pub fn get_seller_volume(&self, seller: AccountId) -> U128 {
+        U128(self.seller_volume.get(&seller).copied().unwrap_or(0))
+    }
+
+    /// Whether an account is KYC verified
+    pub fn is_kyc_verified(&self, account: AccountId) -> bool {
+        self.kyc_verified.contains_key(&account)
+    }
+
+    /// Whether buyers and bidders must be KYC verified
+    pub fn get_require_kyc(&self) -> bool {
+        self.require_kyc
+    }
+
+    /// Get a seller's listing, sale, cancellation and dispute counts
+    pub fn get_seller_stats(&self, seller: AccountId) -> SellerStats {
+        self.seller_stats.get(&seller).cloned().unwrap_or_default()
+    }
+
+    /// Get lifetime marketplace fees collected
+    pub fn get_total_fees_collected(&self) -> U128 {
+        U128(self.total_fees_collected)
+    }
+
+    /// Get fee basis points
+    pub fn get_fee_basis_points(&self) -> u16 {
+        self.fee_basis_points
+    }
+
+    /// Get contract addresses
+    pub fn get_contract_addresses(&self) -> (AccountId, AccountId, AccountId) {
+        (
+            self.invoice_contract.clone(),
+            self.escrow_contract.clone(),
+            self.usdc_contract.clone(),
+        )
+    }
+}
+
+impl MarketplaceContract {
+    fn assert_not_paused(&self) {
+        ensure!(!self.paused, ContractError::Paused, "Contract is paused");
+    }
+
+    fn is_accepted_token(&self, token: &AccountId) -> bool {
+        self.accepted_tokens.get(token).copied().unwrap_or(false)
+    }
+
+    /// Require payment in the token designated for the listing's currency, when one is set
+    fn assert_currency_token(&self, listing: &Listing, token: &AccountId) {
+        if let Some(designated) = self.currency_tokens.get(&listing.currency) {
+            ensure!(
+                designated == token,
+                ContractError::TokenMismatch,
+                "Listings in {} must be paid with {}",
+                listing.currency,
+                designated
+            );
+        }
+    }
+
+    /// Token a bid's funds are held in
+    fn bid_token(&self, bid: &Bid) -> AccountId {
+        bid.token.clone().unwrap_or_else(|| self.usdc_contract.clone())
+    }
+
+    /// Pay the keeper bounty to the caller of a maintenance action, if available
+    fn reward_keeper(&mut self) {
+        self.keeper_rewards.reward(env::predecessor_account_id());
+    }
+
+    /// Whether a listing's discount is large enough for the invoice's risk score
+    fn meets_discount_gate(&self, listing: &Listing, risk_score: u8) -> bool {
+        if self.min_discount_bps_per_risk_point == 0 || listing.invoice_amount.0 == 0 {
+            return true;
+        }
+
+        let discount_bps = (listing.invoice_amount.0 - listing.asking_price.0) * 10_000
+            / listing.invoice_amount.0;
+        let required_bps = risk_score as u128 * self.min_discount_bps_per_risk_point as u128;
+        discount_bps >= required_bps
+    }
+
+    /// Emit a listing_purchased event for a completed sale
+    fn emit_purchase(listing: &Listing, buyer: &AccountId, price: U128) {
+        AdelanteEvent::ListingPurchased(vec![ListingPurchased {
+            listing_id: listing.id.clone(),
+            invoice_id: listing.invoice_id.clone(),
+            seller: listing.seller.clone(),
+            buyer: buyer.clone(),
+            price,
+        }])
+        .emit();
+    }
+
+    /// Send a bid's locked USDC back to the bidder and record the outcome
+    fn refund_bid(&self, bid: &Bid) -> Promise {
+        self.bid_refund_transfer(bid).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(self.gas_config.callback)
+                .on_bid_refund_callback(bid.listing_id.clone(), bid.id.clone()),
+        )
+    }
+
+    /// Mark a lapsed listing expired, refund its open bids and unlist the invoice
+    fn close_expired_listing(&mut self, listing: Listing) -> Promise {
+        env::log_str(&format!("Listing {} expired", listing.id));
+        self.close_listing(listing, ListingStatus::Expired)
+    }
+
+    /// Move a listing to a closed status, refund its open bids and unlist the invoice
+    fn close_listing(&mut self, mut listing: Listing, status: ListingStatus) -> Promise {
+        let listing_id = listing.id.clone();
+        let invoice_id = listing.invoice_id.clone();
+        self.set_listing_status(&mut listing, status);
+        listing.highest_bid_id = None;
+        self.listings.insert(listing_id.clone(), listing);
+        self.listings_by_invoice.remove(&invoice_id);
+
+        if let Some(mut listing_bids) = self.bids.get(&listing_id).cloned() {
+            for bid in listing_bids.iter_mut().filter(|bid| bid.active && bid.funds_locked) {
+                let _ = self.refund_bid(bid);
+                bid.active = false;
+                bid.refund_pending = true;
+            }
+            self.bids.insert(listing_id, listing_bids);
+        }
+
+        ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .unlist_invoice(invoice_id)
+    }
+
+    fn emit_listing_cancelled(listing: &Listing, cancelled_by: AccountId) {
+        AdelanteEvent::ListingCancelled(vec![ListingCancelled {
+            listing_id: listing.id.clone(),
+            invoice_id: listing.invoice_id.clone(),
+            cancelled_by,
+        }])
+        .emit();
+    }
+
+    /// Transfer a bid's locked tokens back to the bidder
+    fn bid_refund_transfer(&self, bid: &Bid) -> Promise {
+        ext_ft::ext(self.bid_token(bid))
+            .with_static_gas(self.gas_config.ft_transfer)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(
+                bid.bidder.clone(),
+                bid.amount,
+                Some(format!("bid_refund:{}", bid.id)),
+            )
+    }
+
+    /// Return the part of a bid above its accepted counter, tracked by on_counter_refund_callback
+    fn refund_counter_excess(&self, bid: &Bid, amount: U128) -> Promise {
+        ext_ft::ext(self.bid_token(bid))
+            .with_static_gas(self.gas_config.ft_transfer)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(bid.bidder.clone(), amount, Some(format!("counter_refund:{}", bid.id)))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(self.gas_config.callback)
+                    .on_counter_refund_callback(bid.listing_id.clone(), bid.id.clone(), amount),
+            )
+    }
+
+    /// An active bid with an open counter, checked for acceptance by its bidder
+    fn counter_bid_of(&self, listing_id: &String, bid_id: &String, bidder: &AccountId) -> Bid {
+        let listing = self
+            .listings
+            .get(listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found");
+        ensure!(listing.active, ContractError::ListingInactive, "Listing is not active");
+        let bid = self
+            .bids
+            .get(listing_id)
+            .and_then(|bids| bids.iter().find(|bid| &bid.id == bid_id))
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        ensure!(
+            &bid.bidder == bidder,
+            ContractError::Unauthorized,
+            "Only bidder can accept counter"
+        );
+        ensure!(bid.active && bid.funds_locked, ContractError::BidInactive, "Bid is not active");
+        ensure!(
+            bid.counter_state == CounterState::Offered,
+            ContractError::InvalidState,
+            "No open counter offer"
+        );
+        bid
+    }
+
+    /// Re-price an accepted counter's bid to the counter amount and sell the listing to it
+    fn sell_at_counter(&mut self, listing_id: String, bid_id: String) -> Promise {
+        let listing = self
+            .listings
+            .get(&listing_id)
+            .or_fail(ContractError::ListingNotFound, "Listing not found")
+            .clone();
+        let mut listing_bids = self
+            .bids
+            .get(&listing_id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+        let counter_amount = bid
+            .counter_amount
+            .or_fail(ContractError::InvalidState, "No open counter offer");
+        ensure!(
+            self.within_yield_floor(&listing, counter_amount.0),
+            ContractError::InvalidAmount,
+            "Counter exceeds seller's yield floor"
+        );
+
+        bid.amount = counter_amount;
+        bid.counter_state = CounterState::Accepted;
+        self.bids.insert(listing_id, listing_bids);
+
+        env::log_str(&format!("Counter on bid {} accepted", bid_id));
+        self.sell_to_bid(listing, &bid_id)
+    }
+
+    /// Whether a bid has passed its expiry
+    fn is_bid_expired(bid: &Bid, now: u64) -> bool {
+        bid.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+    /// Deactivate an expired bid and return its funds to the bidder
+    fn refund_expired_bid(&mut self, mut listing: Listing, bid_id: &String) -> Promise {
+        let mut listing_bids = self
+            .bids
+            .get(&listing.id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let bid = listing_bids
+            .iter_mut()
+            .find(|bid| &bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+
+        bid.active = false;
+        bid.refund_pending = true;
+        let bid = bid.clone();
+
+        if listing.highest_bid_id.as_ref() == Some(bid_id) {
+            listing.highest_bid_id = Self::highest_active_bid(&listing_bids).map(|bid| bid.id.clone());
+            self.listings.insert(listing.id.clone(), listing.clone());
+        }
+        self.bids.insert(listing.id.clone(), listing_bids);
+
+        env::log_str(&format!(
+            "Bid has expired: bid {} not accepted, refunding {} {} to {}",
+            bid.id,
+            bid.amount.0,
+            self.bid_token(&bid),
+            bid.bidder
+        ));
+        self.refund_bid(&bid)
+    }
+
+    /// Whether a listing's scheduled start has passed
+    fn has_started(listing: &Listing, now: u64) -> bool {
+        listing.start_at.is_none_or(|start_at| now >= start_at)
+    }
+
+    /// Pricing and timing rules shared by list_invoice and update_listing
+    fn assert_valid_terms(
+        asking_price: U128,
+        invoice_amount: U128,
+        min_price: Option<U128>,
+        expires_at: Option<u64>,
+        start_at: Option<u64>,
+        listing_type: &ListingType,
+    ) {
+        ensure!(
+            asking_price.0 > 0,
+            ContractError::InvalidAmount,
+            "Asking price must be greater than 0"
+        );
+        ensure!(
+            asking_price.0 <= invoice_amount.0,
+            ContractError::InvalidAmount,
+            "Asking price cannot exceed invoice amount"
+        );
+        if let (Some(start_at), Some(expires_at)) = (start_at, expires_at) {
+            ensure!(
+                start_at < expires_at,
+                ContractError::InvalidArgument,
+                "Start time must be before expiry"
+            );
+        }
+        if *listing_type == ListingType::EnglishAuction {
+            ensure!(
+                expires_at.is_some(),
+                ContractError::InvalidArgument,
+                "English auction requires expires_at"
+            );
+        }
+        if *listing_type == ListingType::DutchAuction {
+            let floor = min_price
+                .or_fail(ContractError::InvalidArgument, "Dutch auction requires min_price");
+            ensure!(
+                expires_at.is_some(),
+                ContractError::InvalidArgument,
+                "Dutch auction requires expires_at"
+            );
+            ensure!(
+                floor.0 < asking_price.0,
+                ContractError::InvalidAmount,
+                "Min price must be below asking price"
+            );
+        }
+    }
+
+    /// Reject purchases and bids on a private listing from anyone but its allowed buyer
+    fn assert_allowed_buyer(listing: &Listing, buyer: &AccountId) {
+        if let Some(allowed_buyer) = &listing.allowed_buyer {
+            ensure!(
+                allowed_buyer == buyer,
+                ContractError::Unauthorized,
+                "Listing is reserved for {}",
+                allowed_buyer
+            );
+        }
+    }
+
+    /// Reject purchases and bids on a listing before its scheduled start
+    fn assert_started(listing: &Listing) {
+        ensure!(
+            Self::has_started(listing, env::block_timestamp_ms()),
+            ContractError::ListingInactive,
+            "Listing is not yet active"
+        );
+    }
+
+    /// Highest active bid, earliest first on ties
+    fn highest_active_bid(bids: &[Bid]) -> Option<&Bid> {
+        // Ties go to the earliest bid; max_by keeps the last maximum, so scan newest-first
+        bids.iter()
+            .rev()
+            .filter(|bid| bid.active)
+            .max_by(|a, b| {
+                a.amount.0
+                    .cmp(&b.amount.0)
+                    .then(b.created_at.cmp(&a.created_at))
+            })
+    }
+
+    /// Whether selling at `price` keeps the buyer's annualized yield within the seller's floor
+    fn within_yield_floor(&self, listing: &Listing, price: u128) -> bool {
+        let Some(max_yield_bps) = listing.min_yield_bps else {
+            return true;
+        };
+        if price >= listing.invoice_amount.0 {
+            return true;
+        }
+
+        let ms_per_day = 24 * 60 * 60 * 1000;
+        let days = listing
+            .due_date
+            .saturating_sub(env::block_timestamp_ms())
+            .div_ceil(ms_per_day)
+            .max(1) as u128;
+
+        // (invoice - price) / price * 365 / days <= max_yield_bps / 10_000
+        let earned = (listing.invoice_amount.0 - price)
+            .saturating_mul(10_000)
+            .saturating_mul(365);
+        let allowed = price
+            .saturating_mul(max_yield_bps as u128)
+            .saturating_mul(days);
+        earned <= allowed
+    }
+
+    /// Purchase price at `now`; Dutch auctions decay from the start (or creation) time to expiry
+    fn current_price(listing: &Listing, now: u64) -> U128 {
+        let (ListingType::DutchAuction, Some(min_price), Some(end)) =
+            (&listing.listing_type, listing.min_price, listing.expires_at)
+        else {
+            return listing.asking_price;
+        };
+        let start = listing.start_at.unwrap_or(listing.created_at);
+        if now <= start || end <= start {
+            return listing.asking_price;
+        }
+        if now >= end {
+            return min_price;
+        }
+
+        let drop = listing.asking_price.0.saturating_sub(min_price.0);
+        let elapsed = (now - start) as u128;
+        let window = (end - start) as u128;
+        U128(listing.asking_price.0 - drop * elapsed / window)
+    }
+
+    /// Fee owed on a sale amount, using the currency's override when set
+    fn calculate_fee(&self, currency: &str, seller: &AccountId, amount: u128) -> u128 {
+        amount * self.fee_bps_for(currency, seller) as u128 / 10_000
+    }
+
+    /// Fee rate for a seller's sale in a currency
+    /// A seller's volume tier lowers the fee but never raises it above the currency fee
+    fn fee_bps_for(&self, currency: &str, seller: &AccountId) -> u16 {
+        let fee_basis_points = self
+            .fee_by_currency
+            .get(currency)
+            .copied()
+            .unwrap_or(self.fee_basis_points);
+
+        let volume = self.seller_volume.get(seller).copied().unwrap_or(0);
+        match self.fee_tiers.iter().find(|(threshold, _)| volume >= threshold.0) {
+            Some((_, tier_bps)) => fee_basis_points.min(*tier_bps),
+            None => fee_basis_points,
+        }
+    }
+
+    /// Sell a listing to one of its locked bids, forwarding the USDC less the marketplace fee to
+    /// escrow and refunding the rest
+    fn sell_to_bid(&mut self, mut listing: Listing, bid_id: &String) -> Promise {
+        let mut listing_bids = self
+            .bids
+            .get(&listing.id)
+            .cloned()
+            .or_fail(ContractError::BidNotFound, "No bids for listing");
+        let winner = listing_bids
+            .iter_mut()
+            .find(|bid| &bid.id == bid_id)
+            .or_fail(ContractError::BidNotFound, "Bid not found");
+
+        // The winning USDC moves to escrow, so it is no longer locked here
+        winner.active = false;
+        winner.funds_locked = false;
+        winner.accepted = true;
+        let winner = winner.clone();
+
+        let mut refunds = Vec::new();
+        for bid in listing_bids.iter_mut().filter(|bid| bid.active && bid.funds_locked) {
+            bid.active = false;
+            bid.refund_pending = true;
+            refunds.push(bid.clone());
+        }
+        self.bids.insert(listing.id.clone(), listing_bids);
+
+        self.set_listing_status(&mut listing, ListingStatus::Sold);
+        listing.highest_bid_id = None;
+        self.listings.insert(listing.id.clone(), listing.clone());
+        self.listings_by_invoice.remove(&listing.invoice_id);
+
+        // Fee is priced on volume before this sale, as for a purchase at the asking price
+        let token = self.bid_token(&winner);
+        let fee = self.calculate_fee(&listing.currency, &listing.seller, winner.amount.0);
+        let referral_fee = match &listing.referrer {
+            Some(_) => fee * listing.referral_bps as u128 / 10_000,
+            None => 0,
+        };
+        self.collect_fee(&listing.id, &token, fee - referral_fee);
+        if let Some(referrer) = &listing.referrer {
+            self.pay_referral(&listing.id, &token, referrer, referral_fee);
+        }
+        let net_amount = U128(winner.amount.0 - fee);
+        self.record_sale(&listing, winner.amount.0);
+
+        for bid in refunds {
+            env::log_str(&format!(
+                "Refunding bid {} ({} {}) to {}",
+                bid.id,
+                bid.amount.0,
+                self.bid_token(&bid),
+                bid.bidder
+            ));
+            let _ = self.refund_bid(&bid);
+        }
+
+        env::log_str(&format!(
+            "Bid {} accepted: invoice {} sold to {} for {} {}",
+            winner.id, listing.invoice_id, winner.bidder, winner.amount.0, token
+        ));
+        Self::emit_purchase(&listing, &winner.bidder, winner.amount);
+
+        let transfer = self
+            .pay_sale_proceeds(&listing, &token, net_amount)
+            .then(
+                ext_invoice::ext(self.invoice_contract.clone())
+                    .with_static_gas(self.gas_config.cross_contract)
+                    .transfer_invoice(listing.invoice_id.clone(), winner.bidder.clone()),
+            );
+        if listing.secondary {
+            return transfer.then(
+                ext_escrow::ext(self.escrow_contract.clone())
+                    .with_static_gas(self.gas_config.cross_contract)
+                    .resell_position(listing.invoice_id, winner.bidder),
+            );
+        }
+        transfer.then(
+            ext_escrow::ext(self.escrow_contract.clone())
+                .with_static_gas(self.gas_config.cross_contract)
+                .create_escrow(
+                    listing.invoice_id.clone(),
+                    listing.seller.clone(),
+                    winner.bidder,
+                    net_amount,
+                    listing.invoice_amount,
+                    listing.due_date,
+                    listing.payout_account.clone(),
+                ),
+        )
+    }
+
+    /// Smallest raise a new bid needs over the highest active bid
+    fn min_bid_increment(&self, listing: &Listing, highest: u128) -> u128 {
+        if let Some(increment) = listing.min_bid_increment {
+            return increment.0;
+        }
+        let increment = if listing.listing_type == ListingType::EnglishAuction {
+            highest * self.auction_min_increment_bps as u128 / 10_000
+        } else {
+            let base = listing.min_price.unwrap_or(listing.asking_price).0;
+            base * DEFAULT_MIN_BID_INCREMENT_BPS as u128 / 10_000
+        };
+        increment.max(1)
+    }
+
+    /// Refuse unverified buyers and bidders while KYC is required
+    fn assert_kyc_verified(&self, account: &AccountId) {
+        ensure!(
+            !self.require_kyc || self.kyc_verified.contains_key(account),
+            ContractError::Unauthorized,
+            "KYC required for {}",
+            account
+        );
+    }
+
+    /// Claim a listing for one purchase until on_purchase_callback releases it
+    fn lock_purchase(&mut self, listing_id: &String) {
+        ensure!(
+            !self.processing.contains_key(listing_id),
+            ContractError::PurchaseInFlight,
+            "Purchase of listing {} already in flight",
+            listing_id
+        );
+        self.processing.insert(listing_id.clone(), true);
+    }
+
+    /// Transfer a purchase's invoice, then create its escrow, then confirm or roll back the sale
+    fn complete_purchase(&self, listing: &Listing, purchase: PendingPurchase) -> Promise {
+        ext_invoice::ext(self.invoice_contract.clone())
+            .with_static_gas(self.gas_config.cross_contract)
+            .transfer_invoice(listing.invoice_id.clone(), purchase.buyer.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_PURCHASE_STEP)
+                    .on_invoice_transferred(purchase.clone()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_PURCHASE_CALLBACK)
+                    .on_purchase_callback(purchase),
+            )
+    }
+
+    /// Fund the escrow with a sale's net amount, or pay a resale's seller directly since its
+    /// escrow is already funded
+    fn pay_sale_proceeds(&self, listing: &Listing, token: &AccountId, amount: U128) -> Promise {
+        let (receiver, memo) = if listing.secondary {
+            (listing.seller.clone(), format!("resale_proceeds:{}", listing.id))
+        } else {
+            (self.escrow_contract.clone(), format!("escrow_deposit:{}", listing.invoice_id))
+        };
+        ext_ft::ext(token.clone())
+            .with_static_gas(self.gas_config.ft_transfer)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(receiver, amount, Some(memo))
+    }
+
+    /// Send the marketplace fee on a sale to fee_recipient
+    fn collect_fee(&mut self, listing_id: &String, token: &AccountId, fee: u128) {
+        if fee > 0 {
+            self.total_fees_collected = self.total_fees_collected.saturating_add(fee);
+            env::log_str(&format!(
+                "Marketplace fee of {} {} collected on listing {}",
+                fee, token, listing_id
+            ));
+            let _ = ext_ft::ext(token.clone())
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(
+                    self.fee_recipient.clone(),
+                    U128(fee),
+                    Some(format!("marketplace_fee:{}", listing_id)),
+                );
+        }
+    }
+
+    /// Send the referrer's cut of the marketplace fee on a sale
+    fn pay_referral(
+        &self,
+        listing_id: &String,
+        token: &AccountId,
+        referrer: &AccountId,
+        amount: u128,
+    ) {
+        if amount > 0 {
+            env::log_str(&format!(
+                "Referral fee of {} {} paid to {} on listing {}",
+                amount, token, referrer, listing_id
+            ));
+            let _ = ext_ft::ext(token.clone())
+                .with_static_gas(self.gas_config.ft_transfer)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .ft_transfer(
+                    referrer.clone(),
+                    U128(amount),
+                    Some(format!("referral_fee:{}", listing_id)),
+                );
+        }
+    }
+
+    /// Add a completed sale to the seller's and the marketplace's volume and sale counts
+    fn record_sale(&mut self, listing: &Listing, amount: u128) {
+        let seller = &listing.seller;
+        let volume = self.seller_volume.get(seller).copied().unwrap_or(0);
+        self.seller_volume
+            .insert(seller.clone(), volume.saturating_add(amount));
+        self.update_seller_stats(seller, |stats| stats.completed_sales += 1);
+
+        self.total_sales += 1;
+        self.total_sale_volume = self.total_sale_volume.saturating_add(amount);
+        if let Some(discount_bps) = (listing.invoice_amount.0.saturating_sub(amount) * 10_000)
+            .checked_div(listing.invoice_amount.0)
+        {
+            self.total_discount_bps = self.total_discount_bps.saturating_add(discount_bps);
+        }
+    }
+
+    /// Move a listing to `status`, keeping the active listing count in step
+    fn set_listing_status(&mut self, listing: &mut Listing, status: ListingStatus) {
+        let was_active = listing.active;
+        listing.set_status(status);
+        match (was_active, listing.active) {
+            (true, false) => {
+                self.active_listing_count = self.active_listing_count.saturating_sub(1)
+            }
+            (false, true) => self.active_listing_count += 1,
+            _ => {}
+        }
+    }
+
+    /// Every bid `bidder` has placed, read through the bidder's listing index
+    fn bids_of<'a>(&'a self, bidder: &'a AccountId) -> impl Iterator<Item = &'a Bid> + 'a {
+        self.bids_by_bidder
+            .get(bidder)
+            .into_iter()
+            .flatten()
+            .filter_map(|listing_id| self.bids.get(listing_id))
+            .flatten()
+            .filter(move |bid| &bid.bidder == bidder)
+    }
+
+    /// Drop a listing that never went live, e.g. when the invoice contract rejects it
+    fn discard_listing(&mut self, listing: &Listing) {
+        self.listings.remove(&listing.id);
+        self.listings_by_invoice.remove(&listing.invoice_id);
+        if let Some(ids) = self.listings_by_seller.get_mut(&listing.seller) {
+            ids.retain(|id| id != &listing.id);
+        }
+        if listing.active {
+            self.active_listing_count = self.active_listing_count.saturating_sub(1);
+        }
+    }
+
+    fn update_seller_stats(&mut self, seller: &AccountId, update: impl FnOnce(&mut SellerStats)) {
+        let mut stats = self.seller_stats.get(seller).cloned().unwrap_or_default();
+        update(&mut stats);
+        self.seller_stats.insert(seller.clone(), stats);
+    }
+}
+
+/// Numeric sequence of a generated id such as "INV-000042" (0 if unparseable)
+fn id_sequence(id: &str) -> u64 {
+    id.rsplit('-')
+        .next()
+        .and_then(|suffix| suffix.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    fn get_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .predecessor_account_id(predecessor)
+            .attached_deposit(NearToken::from_millinear(100));
+        builder
+    }
+
+    #[test]
+    fn test_init() {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let fee_recipient: AccountId = "fees.testnet".parse().unwrap();
+
+        let context = get_context(fee_recipient.clone());
+        testing_env!(context.build());
+
+        let contract = MarketplaceContract::new(invoice, escrow, usdc, fee_recipient.clone(), fee_recipient);
+
+        assert_eq!(contract.get_listing_count(), 0);
+        assert_eq!(contract.get_fee_basis_points(), 100);
+    }
+
+    fn listed_invoice(risk_score: u8, beneficiary: Option<AccountId>) -> ListedInvoice {
+        ListedInvoice {
+            risk_score,
+            beneficiary,
+            owner: Some("seller.testnet".parse().unwrap()),
+            currency: None,
+        }
+    }
+
+    /// Confirm the most recent listing as the invoice contract would
+    fn confirm_last_listing(contract: &mut MarketplaceContract) {
+        let listing_id = format!("LST-{:06}", contract.listing_count);
+        assert!(contract.on_list_callback(listing_id, Ok(listed_invoice(30, None))).is_some());
+    }
+
+    /// Function calls scheduled so far, as (method name, JSON args)
+    fn scheduled_calls() -> Vec<(String, String)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::FunctionCallWeight {
+                    method_name, args, ..
+                } => Some((
+                    String::from_utf8(method_name).unwrap(),
+                    String::from_utf8(args).unwrap(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A USDC purchase by buyer.testnet at the given price and fee
+    fn usdc_purchase(listing_id: &str, price: u128, fee: u128) -> PendingPurchase {
+        PendingPurchase {
+            listing_id: listing_id.to_string(),
+            buyer: "buyer.testnet".parse().unwrap(),
+            token: Some("usdc.testnet".parse().unwrap()),
+            payment: U128(price),
+            price: U128(price),
+            fee: U128(fee),
+            referrer: None,
+            referral_fee: U128(0),
+        }
+    }
+
+    /// Drive a purchase through a successful invoice transfer and escrow creation
+    fn confirm_purchase(contract: &mut MarketplaceContract, purchase: PendingPurchase) {
+        let _ = contract.on_invoice_transferred(purchase.clone(), Ok(()));
+        assert!(contract.on_purchase_callback(purchase, Ok("ESC-000001".to_string())));
+    }
+
+    /// Place a bid through the USDC contract's ft_transfer_call, returning the new bid id
+    fn place_usdc_bid(
+        contract: &mut MarketplaceContract,
+        bidder: &AccountId,
+        listing_id: &str,
+        amount: u128,
+    ) -> String {
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            bidder.clone(),
+            U128(amount),
+            format!("place_bid:{}", listing_id),
+        );
+        format!("BID-{:06}", contract.bid_count)
+    }
+
+    fn setup_contract(admin: &AccountId) -> MarketplaceContract {
+        let invoice: AccountId = "invoice.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let fee_recipient: AccountId = "fees.testnet".parse().unwrap();
+
+        testing_env!(get_context(admin.clone()).build());
+        MarketplaceContract::new(invoice, escrow, usdc, fee_recipient, admin.clone())
+    }
+
+    #[test]
+    fn test_operator_can_pause() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let operator: AccountId = "operator.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        contract.add_operator(operator.clone());
+        assert!(contract.is_operator(operator.clone()));
+
+        testing_env!(get_context(operator).build());
+        contract.set_paused(true);
+        assert!(contract.is_paused());
+        contract.set_paused(false);
+        assert!(!contract.is_paused());
+        assert_eq!(contract.recompute_counters(), (0, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only admin can update fee")]
+    fn test_operator_cannot_set_fee() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let operator: AccountId = "operator.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        contract.add_operator(operator.clone());
+
+        testing_env!(get_context(operator).build());
+        contract.set_fee_basis_points(50);
+    }
+
+    #[test]
+    fn test_admin_can_pause_and_set_fee() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        contract.set_paused(true);
+        contract.set_fee_basis_points(50);
+
+        assert!(contract.is_paused());
+        assert_eq!(contract.get_fee_basis_points(), 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_paused_rejects_usdc_purchase() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        list_scheduled(&mut contract, env::block_timestamp_ms());
+
+        testing_env!(get_context(admin).build());
+        contract.set_paused(true);
+
+        // Views stay available while trading is halted
+        assert_eq!(contract.get_active_listings(0, 10).len(), 1);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_paused_rejects_legacy_purchase() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        list_scheduled(&mut contract, env::block_timestamp_ms());
+
+        testing_env!(get_context(admin).build());
+        contract.set_paused(true);
+
+        testing_env!(get_context("buyer.testnet".parse().unwrap())
+            .attached_deposit(NearToken::from_near(1))
+            .build());
+        let _ = contract.buy_invoice("LST-000001".to_string());
+    }
+
+    #[test]
+    fn test_currency_fee_override() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        contract.set_currency_fee("USDT".to_string(), 50);
+
+        testing_env!(get_context(seller).build());
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+            None,
+            Some("USDT".to_string()),
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let _ = contract.list_invoice(
+            "INV-000002".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            due_date,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+
+        // 0.5% override for USDT, 1% default for USDC
+        assert_eq!(contract.get_sale_fee("LST-000001".to_string()).0, 9_250_000);
+        assert_eq!(contract.get_sale_fee("LST-000002".to_string()).0, 18_500_000);
+        assert_eq!(contract.get_fee_for_currency("USDT".to_string()), 50);
+        assert_eq!(contract.get_fee_for_currency("USDC".to_string()), 100);
+    }
+
+    #[test]
+    fn test_get_bids_for_listings() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller).build());
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        for invoice_id in ["INV-000001", "INV-000002"] {
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                due_date,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+
+        place_usdc_bid(&mut contract, &alice, "LST-000001", 1_700_000_000);
+        place_usdc_bid(&mut contract, &alice, "LST-000002", 1_750_000_000);
+        place_usdc_bid(&mut contract, &bob, "LST-000001", 1_800_000_000);
+
+        let grouped = contract.get_bids_for_listings(vec![
+            "LST-000001".to_string(),
+            "LST-000002".to_string(),
+        ]);
+
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].0, "LST-000001");
+        assert_eq!(grouped[0].1.len(), 2);
+        assert_eq!(grouped[1].0, "LST-000002");
+        assert_eq!(grouped[1].1.len(), 1);
+        assert_eq!(grouped[1].1[0].amount.0, 1_750_000_000);
+    }
+
+    #[test]
+    fn test_discount_gate_rejects_high_risk_listing() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        // Risk score 80 requires an 8% discount
+        contract.set_min_discount_bps_per_risk_point(10);
+
+        testing_env!(get_context(seller).build());
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_900_000_000), // 5% discount
+            U128(2_000_000_000),
+            due_date,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let _ = contract.list_invoice(
+            "INV-000002".to_string(),
+            U128(1_800_000_000), // 10% discount
+            U128(2_000_000_000),
+            due_date,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(contract.on_list_callback("LST-000001".to_string(), Ok(listed_invoice(80, None))), None);
+        assert!(contract.get_listing("LST-000001".to_string()).is_none());
+        assert!(contract.get_listing_by_invoice("INV-000001".to_string()).is_none());
+
+        assert_eq!(
+            contract.on_list_callback("LST-000002".to_string(), Ok(listed_invoice(80, None))),
+            Some("LST-000002".to_string())
+        );
+        assert_eq!(
+            contract.get_listing("LST-000002".to_string()).unwrap().risk_score,
+            Some(80)
+        );
+    }
+
+    fn test_listing(invoice_amount: u128, asking_price: u128, due_date: u64) -> Listing {
+        Listing {
+            id: "LST-000001".to_string(),
+            invoice_id: "INV-000001".to_string(),
+            seller: "seller.testnet".parse().unwrap(),
+            asking_price: U128(asking_price),
+            min_price: None,
+            invoice_amount: U128(invoice_amount),
+            due_date,
+            created_at: 0,
+            expires_at: None,
+            active: true,
+            currency: DEFAULT_CURRENCY.to_string(),
+            risk_score: None,
+            payout_account: None,
+            min_yield_bps: None,
+            thread_hash: None,
+            highest_bid_id: None,
+            start_at: None,
+            listing_type: ListingType::FixedPrice,
+            payment_token: None,
+            status: ListingStatus::Active,
+            secondary: false,
+            min_bid_increment: None,
+            views: 0,
+            referrer: None,
+            referral_bps: 0,
+            allowed_buyer: None,
+        }
+    }
+
+    #[test]
+    fn test_listing_view_zero_invoice_amount() {
+        let now = 1_000_000;
+        let view = ListingView::from_listing(&test_listing(0, 0, now + 30 * 24 * 60 * 60 * 1000), now);
+
+        assert!(view.discount_percentage.is_finite());
+        assert!(view.annualized_yield.is_finite());
+        assert_eq!(view.discount_percentage, 0.0);
+        assert_eq!(view.annualized_yield, 0.0);
+    }
+
+    #[test]
+    fn test_listing_view_zero_days_until_due() {
+        let now = 1_000_000;
+        let view = ListingView::from_listing(&test_listing(2_000_000_000, 1_850_000_000, now), now);
+
+        assert_eq!(view.days_until_due, 0);
+        assert!(view.discount_percentage.is_finite());
+        assert!(view.annualized_yield.is_finite());
+        assert_eq!(view.annualized_yield, 0.0);
+
+        // Asking above invoice amount must not underflow
+        let view = ListingView::from_listing(&test_listing(1_000, 2_000, now), now);
+        assert_eq!(view.discount_percentage, 0.0);
+    }
+
+    #[test]
+    fn test_listing_view_yield_for_same_day_due_date() {
+        let hour = 60 * 60 * 1000;
+        let now = 100 * hour;
+        let view_due_at =
+            |due_date| ListingView::from_listing(&test_listing(2_000_000_000, 1_850_000_000, due_date), now);
+
+        // Due in 12 hours: whole days round to 0 but the yield uses half a day
+        let view = view_due_at(now + 12 * hour);
+        assert_eq!(view.days_until_due, 0);
+        let expected = view.discount_percentage / 0.5 * 365.0;
+        assert!((view.annualized_yield - expected).abs() < 1e-6);
+
+        // A minute before due the yield is clamped rather than exploding
+        assert_eq!(view_due_at(now + 60_000).annualized_yield, MAX_ANNUALIZED_YIELD);
+
+        // Past due: no yield
+        let view = view_due_at(now - hour);
+        assert!(view.days_until_due <= 0);
+        assert_eq!(view.annualized_yield, 0.0);
+    }
+
+    #[test]
+    fn test_quote_listing_and_yield_match_listing_view() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        let (fee, net) =
+            contract.quote_listing(U128(1_850_000_000), "USDC".to_string(), seller.clone());
+        assert_eq!(fee.0, 18_500_000);
+        assert_eq!(net.0, 1_831_500_000);
+
+        // Currency overrides and volume tiers apply as they do at sale time
+        contract.set_currency_fee("USDT".to_string(), 50);
+        let (fee, _) =
+            contract.quote_listing(U128(1_850_000_000), "USDT".to_string(), seller.clone());
+        assert_eq!(fee.0, 9_250_000);
+        contract.set_fee_tiers(vec![(U128(1_000_000_000), 25)]);
+        contract.seller_volume.insert(seller.clone(), 1_000_000_000);
+        let (fee, net) = contract.quote_listing(U128(1_850_000_000), "USDC".to_string(), seller);
+        assert_eq!(fee.0, 4_625_000);
+        assert_eq!(net.0, 1_845_375_000);
+
+        let now = env::block_timestamp_ms();
+        let due_date = now + 30 * 24 * 60 * 60 * 1000;
+        let view = ListingView::from_listing(&test_listing(2_000_000_000, 1_850_000_000, due_date), now);
+        let (discount, annualized) =
+            contract.quote_yield(U128(1_850_000_000), U128(2_000_000_000), due_date);
+        assert_eq!(discount, view.discount_percentage);
+        assert_eq!(annualized, view.annualized_yield);
+        assert!(discount > 7.4 && discount < 7.6);
+
+        // Zero invoice amounts and past due dates quote zeros
+        assert_eq!(contract.quote_yield(U128(0), U128(0), due_date), (0.0, 0.0));
+        let (discount, annualized) =
+            contract.quote_yield(U128(1_850_000_000), U128(2_000_000_000), now);
+        assert!(discount > 0.0);
+        assert_eq!(annualized, 0.0);
+    }
+
+    #[test]
+    fn test_sale_proceeds_route_to_beneficiary() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let financier: AccountId = "financier.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        contract.on_list_callback(
+            "LST-000001".to_string(),
+            Ok(listed_invoice(30, Some(financier.clone()))),
+        );
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            buyer,
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        confirm_purchase(&mut contract, usdc_purchase("LST-000001", 1_850_000_000, 18_500_000));
+
+        let (_, args) = scheduled_calls()
+            .into_iter()
+            .find(|(method, _)| method == "create_escrow")
+            .expect("create_escrow not scheduled");
+        assert!(args.contains(&format!("\"seller\":\"{}\"", seller)));
+        assert!(args.contains(&format!("\"payout_account\":\"{}\"", financier)));
+    }
+
+    #[test]
+    fn test_purchase_collects_marketplace_fee() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let fee_recipient: AccountId = "fees.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        list_scheduled(&mut contract, env::block_timestamp_ms());
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        confirm_purchase(&mut contract, usdc_purchase("LST-000001", 1_850_000_000, 18_500_000));
+
+        // 1% of $1,850 goes to the fee recipient, the rest to escrow
+        let calls = scheduled_calls();
+        let sent_to = |receiver: &AccountId, amount: &str| {
+            calls.iter().any(|(method, args)| {
+                method == "ft_transfer"
+                    && args.contains(&format!("\"receiver_id\":\"{}\"", receiver))
+                    && args.contains(&format!("\"amount\":\"{}\"", amount))
+            })
+        };
+        assert!(sent_to(&fee_recipient, "18500000"));
+        assert!(sent_to(&escrow, "1831500000"));
+
+        let (_, args) = calls
+            .iter()
+            .find(|(method, _)| method == "create_escrow")
+            .expect("create_escrow not scheduled");
+        assert!(args.contains("\"sale_amount\":\"1831500000\""));
+        assert_eq!(contract.get_total_fees_collected().0, 18_500_000);
+    }
+
+    #[test]
+    fn test_expire_listing_pays_keeper_bounty() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let keeper: AccountId = "keeper.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        let bounty = NearToken::from_millinear(10);
+        contract.set_keeper_bounty(bounty, NearToken::from_near(1));
+        contract.fund_keeper_pool();
+
+        testing_env!(get_context(seller).build());
+        let expires_at = env::block_timestamp_ms() + 60 * 60 * 1000;
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            Some(expires_at),
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+
+        let mut context = get_context(keeper.clone());
+        context.block_timestamp(expires_at * 1_000_000);
+        testing_env!(context.build());
+        let _ = contract.expire_listing("LST-000001".to_string());
+
+        let listing = contract.get_listing("LST-000001".to_string()).unwrap();
+        assert!(!listing.active);
+        assert_eq!(listing.status, ListingStatus::Expired);
+        let rewards = contract.get_keeper_rewards();
+        assert_eq!(rewards.total_paid, bounty);
+        assert_eq!(rewards.pool, NearToken::from_millinear(90));
+
+        let paid = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == keeper)
+            .flat_map(|receipt| receipt.actions)
+            .any(|action| {
+                matches!(action, near_sdk::mock::MockAction::Transfer { deposit, .. } if deposit == bounty)
+            });
+        assert!(paid);
+    }
+
+    #[test]
+    #[should_panic(expected = "Purchase exceeds seller's yield floor")]
+    fn test_yield_floor_rejects_lowball_purchase() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        contract.on_list_callback("LST-000001".to_string(), Ok(listed_invoice(30, None)));
+
+        // A 7.5% discount over 30 days is roughly a 98% annualized yield
+        contract.set_min_yield_bps("LST-000001".to_string(), Some(5_000));
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            buyer,
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_bid_thread_hash() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+
+        let bid_id = place_usdc_bid(&mut contract, &alice, "LST-000001", 1_800_000_000);
+        testing_env!(get_context(alice).build());
+        contract.set_bid_thread_hash(
+            "LST-000001".to_string(),
+            bid_id.clone(),
+            Some("QmThread123".to_string()),
+        );
+
+        let bids = contract.get_bids("LST-000001".to_string());
+        assert_eq!(bids[0].id, bid_id);
+        assert_eq!(bids[0].thread_hash, Some("QmThread123".to_string()));
+    }
+
+    #[test]
+    fn test_highest_bid_tracks_cancellation() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let listing_id = "LST-000001".to_string();
+        assert!(contract.get_highest_bid(listing_id.clone()).is_none());
+
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+        let second_bid = place_usdc_bid(&mut contract, &carol, &listing_id, 1_750_000_000);
+        let top_bid = place_usdc_bid(&mut contract, &bob, &listing_id, 1_800_000_000);
+
+        let highest = contract.get_highest_bid(listing_id.clone()).unwrap();
+        assert_eq!(highest.id, top_bid);
+        assert_eq!(highest.amount.0, 1_800_000_000);
+
+        testing_env!(get_context(bob).build());
+        let _ = contract.cancel_bid(listing_id.clone(), top_bid);
+
+        let highest = contract.get_highest_bid(listing_id).unwrap();
+        assert_eq!(highest.id, second_bid);
+        assert_eq!(highest.amount.0, 1_750_000_000);
+    }
+
+    fn list_scheduled(contract: &mut MarketplaceContract, start_at: u64) {
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            start_at + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            Some(start_at),
+            None,
+            None,
+        );
+        contract.on_list_callback("LST-000001".to_string(), Ok(listed_invoice(30, None)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Listing is not yet active")]
+    fn test_scheduled_listing_rejects_early_purchase() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let start_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        list_scheduled(&mut contract, start_at);
+
+        assert!(contract.get_active_listings(0, 10).is_empty());
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_scheduled_listing_buyable_after_start() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let start_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        list_scheduled(&mut contract, start_at);
+
+        let mut context = get_context("usdc.testnet".parse().unwrap());
+        context.block_timestamp(start_at * 1_000_000);
+        testing_env!(context.build());
+        assert_eq!(contract.get_active_listings(0, 10).len(), 1);
+
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        assert!(!contract.get_listing("LST-000001".to_string()).unwrap().active);
+    }
+
+    #[test]
+    fn test_usdc_bid_replaces_and_refunds_previous() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+
+        let first_bid = place_usdc_bid(&mut contract, &alice, "LST-000001", 1_700_000_000);
+        let second_bid = place_usdc_bid(&mut contract, &alice, "LST-000001", 1_750_000_000);
+
+        let (_, args) = scheduled_calls()
+            .into_iter()
+            .find(|(method, _)| method == "ft_transfer")
+            .expect("refund not scheduled");
+        assert!(args.contains("\"amount\":\"1700000000\""));
+        assert!(args.contains(&format!("bid_refund:{}", first_bid)));
+
+        let bids = contract.get_bids("LST-000001".to_string());
+        assert_eq!(bids.len(), 2);
+        assert!(!bids[0].active && bids[0].refund_pending);
+        assert_eq!(bids[1].id, second_bid);
+        assert_eq!(bids[1].amount.0, 1_750_000_000);
+        assert!(bids[1].active && bids[1].funds_locked);
+        assert_eq!(
+            contract.get_highest_bid("LST-000001".to_string()).unwrap().id,
+            second_bid
+        );
+    }
+
+    #[test]
+    fn test_accept_bid_refunds_losing_bidders() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let listing_id = "LST-000001".to_string();
+
+        let alice_bid = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+        let carol_bid = place_usdc_bid(&mut contract, &carol, &listing_id, 1_750_000_000);
+        let bob_bid = place_usdc_bid(&mut contract, &bob, &listing_id, 1_800_000_000);
+
+        testing_env!(get_context(seller).build());
+        let _ = contract.accept_bid(listing_id.clone(), bob_bid.clone());
+
+        let transfers: Vec<String> = scheduled_calls()
+            .into_iter()
+            .filter(|(method, _)| method == "ft_transfer")
+            .map(|(_, args)| args)
+            .collect();
+        let sent_to = |receiver: &AccountId, amount: &str| {
+            transfers.iter().any(|args| {
+                args.contains(&format!("\"receiver_id\":\"{}\"", receiver))
+                    && args.contains(&format!("\"amount\":\"{}\"", amount))
+            })
+        };
+        // 1% fee of 1,800M: 18M to fees.testnet and the rest to escrow
+        let fee_recipient: AccountId = "fees.testnet".parse().unwrap();
+        assert_eq!(transfers.len(), 4);
+        assert!(sent_to(&fee_recipient, "18000000"));
+        assert!(sent_to(&escrow, "1782000000"));
+        assert!(sent_to(&alice, "1700000000"));
+        assert!(sent_to(&carol, "1750000000"));
+        assert!(!contract.get_listing(listing_id.clone()).unwrap().active);
+
+        // Alice's refund lands; Carol's fails and stays queued
+        assert!(contract.on_bid_refund_callback(listing_id.clone(), alice_bid.clone(), Ok(())));
+        assert!(!contract.on_bid_refund_callback(
+            listing_id.clone(),
+            carol_bid.clone(),
+            Err(PromiseError::Failed)
+        ));
+
+        let bids = contract.get_bids(listing_id.clone());
+        let bid = |id: &str| bids.iter().find(|bid| bid.id == id).unwrap();
+        assert!(bid(&bob_bid).accepted && !bid(&bob_bid).funds_locked);
+        assert!(!bid(&alice_bid).funds_locked && !bid(&alice_bid).refund_pending);
+        assert!(bid(&carol_bid).funds_locked && !bid(&carol_bid).refund_pending);
+
+        let _ = contract.retry_bid_refund(listing_id.clone(), carol_bid.clone());
+        let bids = contract.get_bids(listing_id);
+        assert!(bids.iter().find(|bid| bid.id == carol_bid).unwrap().refund_pending);
+    }
+
+    #[test]
+    fn test_fee_tier_applies_past_volume_threshold() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        contract.set_fee_tiers(vec![(U128(1_000_000_000), 50), (U128(10_000_000_000), 25)]);
+        assert_eq!(contract.get_fee_tiers()[0].1, 25);
+
+        testing_env!(get_context(seller.clone()).build());
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        for invoice_id in ["INV-000001", "INV-000002"] {
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                due_date,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+
+        // No volume yet: flat 1% fee
+        assert_eq!(contract.get_sale_fee("LST-000002".to_string()).0, 18_500_000);
+        assert_eq!(contract.get_fee_for_seller(seller.clone()), 100);
+
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        confirm_purchase(&mut contract, usdc_purchase("LST-000001", 1_850_000_000, 18_500_000));
+        assert_eq!(contract.get_seller_volume(seller.clone()).0, 1_850_000_000);
+
+        // Past the first threshold: 0.5% tier
+        assert_eq!(contract.get_sale_fee("LST-000002".to_string()).0, 9_250_000);
+        assert_eq!(contract.get_fee_for_seller(seller), 50);
+        assert_eq!(contract.get_fee_for_seller("buyer.testnet".parse().unwrap()), 100);
+    }
+
+    #[test]
+    fn test_fee_for_seller_matches_fee_charged_at_tier_boundaries() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        contract.set_fee_tiers(vec![(U128(1_000_000_000), 50), (U128(2_000_000_000), 25)]);
+
+        testing_env!(get_context(seller.clone()).build());
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        for invoice_id in ["INV-000001", "INV-000002", "INV-000003", "INV-000004"] {
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                due_date,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+
+        // Each tier starts exactly at its threshold
+        let cases = [
+            ("LST-000001", 999_999_999, 100),
+            ("LST-000002", 1_000_000_000, 50),
+            ("LST-000003", 1_999_999_999, 50),
+            ("LST-000004", 2_000_000_000, 25),
+        ];
+        for (listing_id, volume, fee_bps) in cases {
+            contract.seller_volume.insert(seller.clone(), volume);
+            assert_eq!(contract.get_fee_for_seller(seller.clone()), fee_bps);
+
+            testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+            let _ = contract.ft_on_transfer(
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                format!("buy_listing:{}", listing_id),
+            );
+            let charged = format!("\"fee\":\"{}\"", 1_850_000_000 * fee_bps as u128 / 10_000);
+            assert!(scheduled_calls().iter().any(|(method, args)| {
+                method == "on_invoice_transferred" && args.contains(&charged)
+            }));
+        }
+    }
+
+    #[test]
+    fn test_cancel_refunds_locked_bids() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let listing_id = "LST-000001".to_string();
+
+        let alice_bid = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+        let bob_bid = place_usdc_bid(&mut contract, &bob, &listing_id, 1_800_000_000);
+        assert_eq!(contract.get_locked_balance(alice.clone()).0, 1_700_000_000);
+
+        // Alice cancels, but the refund transfer fails: the bid stays cancelled with funds locked
+        testing_env!(get_context(alice.clone()).build());
+        let _ = contract.cancel_bid(listing_id.clone(), alice_bid.clone());
+        assert!(scheduled_calls().iter().any(|(method, _)| method == "ft_transfer"));
+        assert!(!contract.on_bid_refund_callback(
+            listing_id.clone(),
+            alice_bid.clone(),
+            Err(PromiseError::Failed)
+        ));
+        assert!(contract.get_bids_by_bidder(alice.clone(), None, None).items.is_empty());
+        assert_eq!(contract.get_locked_balance(alice.clone()).0, 1_700_000_000);
+        assert_eq!(contract.get_highest_bid(listing_id.clone()).unwrap().id, bob_bid);
+
+        // Seller cancels the listing: only Bob's open bid is refunded
+        testing_env!(get_context(seller).build());
+        let _ = contract.cancel_listing(listing_id.clone());
+        let refunds = scheduled_calls()
+            .into_iter()
+            .filter(|(method, args)| method == "ft_transfer" && args.contains("bid_refund"))
+            .count();
+        assert_eq!(refunds, 1);
+        assert!(contract.on_bid_refund_callback(listing_id.clone(), bob_bid, Ok(())));
+
+        // Alice's refund is retried from the queue
+        let _ = contract.retry_bid_refund(listing_id.clone(), alice_bid.clone());
+        assert!(contract.on_bid_refund_callback(listing_id, alice_bid, Ok(())));
+        assert_eq!(contract.get_locked_balance(alice).0, 0);
+        assert_eq!(contract.get_locked_balance(bob).0, 0);
+    }
+
+    #[test]
+    fn test_listing_and_bid_events() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(seller).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        assert!(near_sdk::test_utils::get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"listing_created","data":[{"listing_id":"LST-000001","invoice_id":"INV-000001","seller":"seller.testnet","asking_price":"1850000000"}]}"#.to_string()
+        ));
+
+        place_usdc_bid(&mut contract, &alice, "LST-000001", 1_800_000_000);
+        assert!(near_sdk::test_utils::get_logs().contains(
+            &r#"EVENT_JSON:{"standard":"adelante","version":"1.0.0","event":"bid_placed","data":[{"bid_id":"BID-000001","listing_id":"LST-000001","bidder":"alice.testnet","amount":"1800000000"}]}"#.to_string()
+        ));
+    }
+
+    #[test]
+    fn test_seller_view_paginates() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        for i in 0..(MAX_PAGE_LIMIT + 5) {
+            let mut listing = test_listing(2_000_000_000, 1_850_000_000, 1_000_000);
+            listing.id = format!("LST-{:06}", i + 1);
+            contract
+                .listings_by_seller
+                .entry(seller.clone())
+                .or_default()
+                .push(listing.id.clone());
+            contract.listings.insert(listing.id.clone(), listing);
+        }
+
+        let first = contract.get_listings_by_seller(seller.clone(), None, Some(2));
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.next_index, Some(2));
+
+        let last = contract.get_listings_by_seller(seller.clone(), Some(MAX_PAGE_LIMIT + 4), Some(2));
+        assert_eq!(last.items.len(), 1);
+        assert_eq!(last.next_index, None);
+
+        let capped = contract.get_listings_by_seller(seller, None, Some(10_000));
+        assert_eq!(capped.items.len() as u64, MAX_PAGE_LIMIT);
+        assert_eq!(capped.next_index, Some(MAX_PAGE_LIMIT));
+    }
+
+    #[test]
+    fn test_dutch_auction_price_decays_linearly() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let start = env::block_timestamp_ms();
+        let window = 10 * 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_900_000_000),
+            U128(2_000_000_000),
+            start + 60 * 24 * 60 * 60 * 1000,
+            Some(U128(1_700_000_000)),
+            Some(start + window),
+            None,
+            None,
+            Some(ListingType::DutchAuction),
+            None,
+        );
+        confirm_last_listing(&mut contract);
+        let listing_id = "LST-000001".to_string();
+        let at = |timestamp_ms: u64| {
+            let mut context = get_context("usdc.testnet".parse().unwrap());
+            context.block_timestamp(timestamp_ms * 1_000_000);
+            testing_env!(context.build());
+        };
+
+        at(start);
+        assert_eq!(contract.get_current_price(listing_id.clone()).0, 1_900_000_000);
+        at(start + window);
+        assert_eq!(contract.get_current_price(listing_id.clone()).0, 1_700_000_000);
+
+        // Midway the buyer pays the decayed price and the rest is refunded
+        at(start + window / 2);
+        assert_eq!(contract.get_current_price(listing_id.clone()).0, 1_800_000_000);
+        let excess = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_900_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(matches!(excess, PromiseOrValue::Value(U128(100_000_000))));
+        assert!(!contract.get_listing(listing_id).unwrap().active);
+    }
+
+    fn list_english_auction(contract: &mut MarketplaceContract, expires_at: u64) -> String {
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            expires_at + 30 * 24 * 60 * 60 * 1000,
+            Some(U128(1_600_000_000)),
+            Some(expires_at),
+            None,
+            None,
+            Some(ListingType::EnglishAuction),
+            None,
+        );
+        confirm_last_listing(contract);
+        "LST-000001".to_string()
+    }
+
+    #[test]
+    fn test_english_auction_awards_highest_bid() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let expires_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        let listing_id = list_english_auction(&mut contract, expires_at);
+
+        let alice_bid = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+        // 1% above 1,700 is the smallest accepted raise
+        let bob_bid = place_usdc_bid(&mut contract, &bob, &listing_id, 1_717_000_000);
+
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        context.block_timestamp(expires_at * 1_000_000);
+        testing_env!(context.build());
+        let _ = contract.finalize_auction(listing_id.clone());
+
+        let bids = contract.get_bids(listing_id.clone());
+        let bid = |id: &str| bids.iter().find(|bid| bid.id == id).unwrap();
+        assert!(bid(&bob_bid).accepted);
+        assert!(bid(&alice_bid).refund_pending && !bid(&alice_bid).active);
+        assert!(!contract.get_listing(listing_id).unwrap().active);
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "ft_transfer" && args.contains(&format!("bid_refund:{}", alice_bid))
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid must exceed current high by at least 17000000")]
+    fn test_english_auction_rejects_small_raise() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id =
+            list_english_auction(&mut contract, env::block_timestamp_ms() + 24 * 60 * 60 * 1000);
+
+        place_usdc_bid(&mut contract, &"alice.testnet".parse().unwrap(), &listing_id, 1_700_000_000);
+        place_usdc_bid(&mut contract, &"bob.testnet".parse().unwrap(), &listing_id, 1_716_999_999);
+    }
+
+    #[test]
+    fn test_english_auction_without_bids_deactivates() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let expires_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        let listing_id = list_english_auction(&mut contract, expires_at);
+
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        context.block_timestamp(expires_at * 1_000_000);
+        testing_env!(context.build());
+        let _ = contract.finalize_auction(listing_id.clone());
+
+        assert!(!contract.get_listing(listing_id).unwrap().active);
+        assert!(contract.get_listing_by_invoice("INV-000001".to_string()).is_none());
+        assert!(scheduled_calls().iter().any(|(method, _)| method == "unlist_invoice"));
+    }
+
+    #[test]
+    fn test_english_auction_tie_goes_to_earliest_bid() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let expires_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        let listing_id = list_english_auction(&mut contract, expires_at);
+
+        // Equal bids recorded out of order, e.g. after a migration
+        let tied_bid = |id: &str, bidder: &str, created_at: u64| Bid {
+            id: id.to_string(),
+            listing_id: listing_id.clone(),
+            bidder: bidder.parse().unwrap(),
+            amount: U128(1_700_000_000),
+            created_at,
+            active: true,
+            thread_hash: None,
+            funds_locked: true,
+            accepted: false,
+            refund_pending: false,
+            token: None,
+            expires_at: None,
+            counter_amount: None,
+            counter_state: CounterState::None,
+            excess_owed: U128(0),
+        };
+        contract.bids.insert(
+            listing_id.clone(),
+            vec![
+                tied_bid("BID-000001", "alice.testnet", 2_000),
+                tied_bid("BID-000002", "bob.testnet", 1_000),
+            ],
+        );
+
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        context.block_timestamp(expires_at * 1_000_000);
+        testing_env!(context.build());
+        let _ = contract.finalize_auction(listing_id.clone());
+
+        let bids = contract.get_bids(listing_id);
+        assert!(bids.iter().any(|bid| bid.id == "BID-000002" && bid.accepted));
+        assert!(bids.iter().any(|bid| bid.id == "BID-000001" && bid.refund_pending));
+    }
+
+    fn list_fixed_price(contract: &mut MarketplaceContract) -> String {
+        let listing_id = list_pending_fixed_price(contract);
+        confirm_last_listing(contract);
+        listing_id
+    }
+
+    /// List INV-000001 at a fixed price without the invoice contract's confirmation
+    fn list_pending_fixed_price(contract: &mut MarketplaceContract) -> String {
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            Some(U128(1_600_000_000)),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        "LST-000001".to_string()
+    }
+
+    #[test]
+    fn test_update_listing_reprices() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let expires_at = env::block_timestamp_ms() + 24 * 60 * 60 * 1000;
+        contract.update_listing(
+            listing_id.clone(),
+            U128(1_800_000_000),
+            Some(U128(1_700_000_000)),
+            Some(expires_at),
+        );
+
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert_eq!(listing.asking_price.0, 1_800_000_000);
+        assert_eq!(listing.min_price, Some(U128(1_700_000_000)));
+        assert_eq!(listing.expires_at, Some(expires_at));
+        // Bid history survives the update
+        assert_eq!(contract.get_bids(listing_id).len(), 1);
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"listing_updated\"")));
+    }
+
+    #[test]
+    fn test_extend_listing_pushes_back_expiry() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let day = 24 * 60 * 60 * 1000;
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let first = env::block_timestamp_ms() + day;
+        contract.extend_listing(listing_id.clone(), first);
+        contract.extend_listing(listing_id.clone(), first + day);
+
+        let listing = contract.get_listing(listing_id).unwrap();
+        assert_eq!(listing.expires_at, Some(first + day));
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"listing_extended\"")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expiry must be in the future")]
+    fn test_extend_listing_rejects_past_timestamp() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        let mut context = get_context("seller.testnet".parse().unwrap());
+        context.block_timestamp(10_000 * 1_000_000);
+        testing_env!(context.build());
+        contract.extend_listing(listing_id, 5_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Asking price cannot exceed invoice amount")]
+    fn test_update_listing_rejects_price_above_invoice_amount() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.update_listing(listing_id, U128(2_100_000_000), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Min price cannot exceed existing bid of 1700000000")]
+    fn test_update_listing_rejects_min_price_above_bids() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.update_listing(listing_id, U128(1_850_000_000), Some(U128(1_750_000_000)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only seller can update listing")]
+    fn test_update_listing_requires_seller() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("alice.testnet".parse().unwrap()).build());
+        contract.update_listing(listing_id, U128(1_800_000_000), None, None);
+    }
+
+    #[test]
+    fn test_purchases_accept_multiple_tokens() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let usdc: AccountId = "usdc.testnet".parse().unwrap();
+        let usdt: AccountId = "usdt.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        contract.add_accepted_token(usdt.clone());
+        assert_eq!(contract.get_accepted_tokens(), vec![usdc.clone(), usdt.clone()]);
+
+        for invoice_id in ["INV-000001", "INV-000002"] {
+            testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+
+        for (token, listing_id) in [(&usdc, "LST-000001"), (&usdt, "LST-000002")] {
+            testing_env!(get_context(token.clone()).build());
+            let _ = contract.ft_on_transfer(
+                "buyer.testnet".parse().unwrap(),
+                U128(1_850_000_000),
+                format!("buy_listing:{}", listing_id),
+            );
+            let mut purchase = usdc_purchase(listing_id, 1_850_000_000, 18_500_000);
+            purchase.token = Some(token.clone());
+            confirm_purchase(&mut contract, purchase);
+
+            // Fee and escrow deposit both move in the token the buyer paid with
+            let token_receipts: Vec<AccountId> = near_sdk::test_utils::get_created_receipts()
+                .into_iter()
+                .filter(|receipt| receipt.receiver_id == usdc || receipt.receiver_id == usdt)
+                .map(|receipt| receipt.receiver_id)
+                .collect();
+            assert_eq!(token_receipts, vec![token.clone(), token.clone()]);
+
+            let listing = contract.get_listing(listing_id.to_string()).unwrap();
+            assert_eq!(listing.payment_token, Some(token.clone()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Token dai.testnet is not accepted")]
+    fn test_rejects_unlisted_token() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        list_fixed_price(&mut contract);
+
+        testing_env!(get_context("dai.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_expired_bid_is_refunded_not_accepted() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        let bid_expires_at = env::block_timestamp_ms() + 60 * 60 * 1000;
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            alice.clone(),
+            U128(1_700_000_000),
+            format!("place_bid:{}:{}", listing_id, bid_expires_at),
+        );
+        let bid_id = format!("BID-{:06}", contract.bid_count);
+        assert_eq!(contract.get_bids(listing_id.clone()).len(), 1);
+
+        let mut context = get_context("seller.testnet".parse().unwrap());
+        context.block_timestamp(bid_expires_at * 1_000_000);
+        testing_env!(context.build());
+        assert!(contract.get_bids(listing_id.clone()).is_empty());
+        assert_eq!(contract.get_expired_bids(listing_id.clone()).len(), 1);
+
+        let _ = contract.accept_bid(listing_id.clone(), bid_id);
+
+        // The listing stays open and the bidder gets their funds back
+        assert!(contract.get_listing(listing_id.clone()).unwrap().active);
+        assert!(contract.get_expired_bids(listing_id).is_empty());
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "ft_transfer"
+                && args.contains(&format!("\"receiver_id\":\"{}\"", alice))
+                && args.contains("\"amount\":\"1700000000\"")
+        }));
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.starts_with("Bid has expired")));
+    }
+
+    #[test]
+    fn test_failed_invoice_transfer_restores_listing() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            buyer.clone(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(!contract.get_listing(listing_id.clone()).unwrap().active);
+
+        // transfer_invoice failed, so on_invoice_transferred never created the escrow
+        let purchase = usdc_purchase(&listing_id, 1_850_000_000, 18_500_000);
+        assert!(!contract.on_purchase_callback(purchase, Err(PromiseError::Failed)));
+
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert!(listing.active);
+        assert_eq!(listing.payment_token, None);
+        assert_eq!(
+            contract.get_listing_by_invoice("INV-000001".to_string()).map(|listing| listing.id),
+            Some(listing_id)
+        );
+
+        // The buyer gets the full price back and no fee is taken
+        let transfers: Vec<String> = scheduled_calls()
+            .into_iter()
+            .filter(|(method, _)| method == "ft_transfer")
+            .map(|(_, args)| args)
+            .collect();
+        assert_eq!(transfers.len(), 1);
+        assert!(transfers[0].contains(&format!("\"receiver_id\":\"{}\"", buyer)));
+        assert!(transfers[0].contains("\"amount\":\"1850000000\""));
+        assert_eq!(contract.get_total_fees_collected().0, 0);
+    }
+
+    #[test]
+    fn test_counter_offer_accepted_with_top_up() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let bid_id = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.counter_bid(listing_id.clone(), bid_id.clone(), U128(1_800_000_000));
+
+        // Alice adds the missing $100 (plus $5 too much) through the USDC contract
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let excess = contract.ft_on_transfer(
+            alice.clone(),
+            U128(105_000_000),
+            format!("accept_counter:{}:{}", listing_id, bid_id),
+        );
+        assert!(matches!(excess, PromiseOrValue::Value(U128(5_000_000))));
+
+        let bid = contract
+            .get_bids(listing_id.clone())
+            .into_iter()
+            .find(|bid| bid.id == bid_id)
+            .unwrap();
+        assert_eq!(bid.counter_state, CounterState::Accepted);
+        assert_eq!(bid.amount.0, 1_800_000_000);
+        assert!(!contract.get_listing(listing_id).unwrap().active);
+
+        let (_, args) = scheduled_calls()
+            .into_iter()
+            .find(|(method, _)| method == "create_escrow")
+            .expect("create_escrow not scheduled");
+        // 1% of the $1,800 goes to fees, the rest funds the escrow
+        assert!(args.contains("\"sale_amount\":\"1782000000\""));
+    }
+
+    #[test]
+    fn test_failed_counter_refund_stays_owed() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let bid_id = place_usdc_bid(&mut contract, &alice, &listing_id, 1_800_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.counter_bid(listing_id.clone(), bid_id.clone(), U128(1_700_000_000));
+
+        // The $100 above the counter goes back through a tracked refund
+        testing_env!(get_context(alice.clone()).build());
+        let _ = contract.accept_counter(listing_id.clone(), bid_id.clone());
+        let calls = scheduled_calls();
+        assert!(calls.iter().any(|(method, args)| {
+            method == "ft_transfer" && args.contains("\"amount\":\"100000000\"")
+        }));
+        assert!(calls.iter().any(|(method, _)| method == "on_counter_refund_callback"));
+
+        let excess_owed = |contract: &MarketplaceContract| {
+            contract
+                .get_bids(listing_id.clone())
+                .into_iter()
+                .find(|bid| bid.id == bid_id)
+                .unwrap()
+                .excess_owed
+                .0
+        };
+        assert!(!contract.on_counter_refund_callback(
+            listing_id.clone(),
+            bid_id.clone(),
+            U128(100_000_000),
+            Err(PromiseError::Failed)
+        ));
+        assert_eq!(excess_owed(&contract), 100_000_000);
+        assert_eq!(contract.get_locked_balance(alice.clone()).0, 100_000_000);
+
+        // Anyone can re-send it; the amount is cleared while the transfer is in flight
+        testing_env!(get_context("keeper.testnet".parse().unwrap()).build());
+        let _ = contract.retry_counter_refund(listing_id.clone(), bid_id.clone());
+        assert_eq!(excess_owed(&contract), 0);
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "ft_transfer" && args.contains(&format!("\"receiver_id\":\"{}\"", alice))
+        }));
+        assert!(contract.on_counter_refund_callback(
+            listing_id,
+            bid_id,
+            U128(100_000_000),
+            Ok(())
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "No open counter offer")]
+    fn test_withdrawn_counter_cannot_be_accepted() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let bid_id = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.counter_bid(listing_id.clone(), bid_id.clone(), U128(1_650_000_000));
+        contract.withdraw_counter(listing_id.clone(), bid_id.clone());
+
+        testing_env!(get_context(alice).build());
+        let _ = contract.accept_counter(listing_id, bid_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid is not active")]
+    fn test_counter_rejected_on_inactive_bid() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let bid_id = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context(alice).build());
+        let _ = contract.cancel_bid(listing_id.clone(), bid_id.clone());
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.counter_bid(listing_id, bid_id, U128(1_800_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_LISTING_NOT_FOUND: Listing not found")]
+    fn test_error_code_for_missing_listing() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.update_listing("LST-999999".to_string(), U128(1_700_000_000), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_LISTING_INACTIVE: Listing is not active")]
+    fn test_error_code_for_inactive_listing() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.cancel_listing(listing_id.clone());
+        contract.update_listing(listing_id, U128(1_700_000_000), None, None);
+    }
+
+    #[test]
+    fn test_get_all_listings_includes_inactive() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let now = env::block_timestamp_ms();
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        for i in 1..=3 {
+            let _ = contract.list_invoice(
+                format!("INV-00000{}", i),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                now + 30 * 24 * 60 * 60 * 1000,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+        let _ = contract.cancel_listing("LST-000002".to_string());
+
+        let all = contract.get_all_listings(None, None, None);
+        assert_eq!(all.items.len(), 3);
+        assert!(!all.items[1].listing.active);
+        assert!(all.items[1].discount_percentage > 7.4);
+
+        let active = contract.get_all_listings(None, None, Some(true));
+        let ids: Vec<String> = active.items.into_iter().map(|view| view.listing.id).collect();
+        assert_eq!(ids, vec!["LST-000001".to_string(), "LST-000003".to_string()]);
+
+        let page = contract.get_all_listings(Some(1), Some(1), None);
+        assert_eq!(page.items[0].listing.id, "LST-000002");
+        assert_eq!(page.next_index, Some(2));
+    }
+
+    #[test]
+    fn test_listing_status_transitions() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let now = env::block_timestamp_ms();
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        for i in 1..=4 {
+            let _ = contract.list_invoice(
+                format!("INV-00000{}", i),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                now + 30 * 24 * 60 * 60 * 1000,
+                None,
+                Some(now + 60 * 60 * 1000),
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+        let status = |contract: &MarketplaceContract, id: &str| {
+            contract.get_listing(id.to_string()).unwrap().status
+        };
+        assert_eq!(status(&contract, "LST-000001"), ListingStatus::Active);
+
+        // Bought outright
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        assert_eq!(status(&contract, "LST-000001"), ListingStatus::Sold);
+
+        // Sold to an accepted bid
+        let bid_id = place_usdc_bid(&mut contract, &alice, "LST-000002", 1_800_000_000);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.accept_bid("LST-000002".to_string(), bid_id);
+        assert_eq!(status(&contract, "LST-000002"), ListingStatus::Sold);
+
+        // Withdrawn by the seller
+        let _ = contract.cancel_listing("LST-000003".to_string());
+        let cancelled = contract.get_listing("LST-000003".to_string()).unwrap();
+        assert_eq!(cancelled.status, ListingStatus::Cancelled);
+        assert!(!cancelled.active);
+
+        // Past expiry it reads as Expired before and after the sweep
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        context.block_timestamp((now + 60 * 60 * 1000) * 1_000_000);
+        testing_env!(context.build());
+        let expired = contract.get_listings_by_status(ListingStatus::Expired, None, None);
+        assert_eq!(expired.items.len(), 1);
+        assert_eq!(expired.items[0].listing.id, "LST-000004");
+        let _ = contract.expire_listing("LST-000004".to_string());
+        assert_eq!(status(&contract, "LST-000004"), ListingStatus::Expired);
+
+        let sold = contract.get_listings_by_status(ListingStatus::Sold, None, None);
+        assert_eq!(sold.items.len(), 2);
+        assert!(contract
+            .get_listings_by_status(ListingStatus::Active, None, None)
+            .items
+            .is_empty());
+    }
+
+    #[test]
+    fn test_failed_purchase_reopens_listing_status() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert_eq!(listing.status, ListingStatus::Sold);
+
+        let purchase = usdc_purchase(&listing_id, 1_850_000_000, 0);
+        assert!(!contract.on_purchase_callback(purchase, Err(PromiseError::Failed)));
+        let listing = contract.get_listing(listing_id).unwrap();
+        assert_eq!(listing.status, ListingStatus::Active);
+        assert!(listing.active);
+    }
+
+    /// Relist INV-000001 as a resale by seller.testnet and confirm it with the invoice contract
+    fn relist_as_resale(contract: &mut MarketplaceContract) -> String {
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.relist_invoice(
+            "INV-000001".to_string(),
+            U128(1_900_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 20 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(contract);
+        "LST-000001".to_string()
+    }
+
+    #[test]
+    fn test_resale_moves_escrow_position_and_pays_seller() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = relist_as_resale(&mut contract);
+        assert!(scheduled_calls().iter().any(|(method, _)| method == "relist_invoice"));
+        assert!(contract.get_listing(listing_id.clone()).unwrap().secondary);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_900_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let fee = contract.calculate_fee(DEFAULT_CURRENCY, &seller, 1_900_000_000);
+        let purchase = usdc_purchase(&listing_id, 1_900_000_000, fee);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.on_invoice_transferred(purchase.clone(), Ok(()));
+        let calls = scheduled_calls();
+        assert!(calls.iter().any(|(method, args)| {
+            method == "resell_position" && args.contains("\"new_buyer\":\"buyer.testnet\"")
+        }));
+        assert!(!calls.iter().any(|(method, _)| method == "create_escrow"));
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        assert!(contract.on_purchase_callback(purchase, Ok("ESC-000001".to_string())));
+        let proceeds = format!("\"amount\":\"{}\"", 1_900_000_000 - fee);
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "ft_transfer"
+                && args.contains("\"receiver_id\":\"seller.testnet\"")
+                && args.contains(&proceeds)
+                && args.contains("resale_proceeds:LST-000001")
+        }));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_LISTING_INACTIVE: Listing is not active")]
+    fn test_unconfirmed_listing_cannot_be_bought() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_pending_fixed_price(&mut contract);
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert_eq!(listing.status, ListingStatus::Pending);
+        assert_eq!(contract.get_active_listing_count(), 0);
+        // The seller is named to the invoice contract, which checks ownership
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "set_listed" && args.contains("\"seller\":\"seller.testnet\"")
+        }));
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
     }
 
-    /// Get listing count
-    pub fn get_listing_count(&self) -> u64 {
-        self.listing_count
+    #[test]
+    #[should_panic(expected = "E_PURCHASE_IN_FLIGHT: Purchase of listing LST-000001 already in flight")]
+    fn test_second_purchase_while_in_flight_is_rejected() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(contract.is_purchase_in_flight(listing_id.clone()));
+
+        // The legacy NEAR path shares the lock
+        testing_env!(get_context("alice.testnet".parse().unwrap()).build());
+        let _ = contract.buy_invoice(listing_id);
     }
 
-    /// Get active listing count
-    pub fn get_active_listing_count(&self) -> u64 {
-        self.listings
+    #[test]
+    #[should_panic(expected = "E_LISTING_NOT_FOUND: Listing not found")]
+    fn test_refresh_terms_rejected_while_purchase_in_flight() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+
+        testing_env!(get_context("invoice.testnet".parse().unwrap()).build());
+        contract.refresh_listing_terms(
+            "INV-000001".to_string(),
+            U128(2_200_000_000),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Listing LST-000001 has active bids")]
+    fn test_refresh_terms_rejected_with_active_bids() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context("invoice.testnet".parse().unwrap()).build());
+        contract.refresh_listing_terms(
+            "INV-000001".to_string(),
+            U128(2_200_000_000),
+            env::block_timestamp_ms() + 45 * 24 * 60 * 60 * 1000,
+        );
+    }
+
+    #[test]
+    fn test_purchase_lock_released_by_callback() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+
+        // A failed purchase frees the listing for the next buyer
+        let purchase = usdc_purchase(&listing_id, 1_850_000_000, 0);
+        assert!(!contract.on_purchase_callback(purchase, Err(PromiseError::Failed)));
+        assert!(!contract.is_purchase_in_flight(listing_id.clone()));
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "alice.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(contract.is_purchase_in_flight(listing_id));
+    }
+
+    #[test]
+    fn test_set_gas_config_updates_attached_gas() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        assert_eq!(contract.get_gas_config().ft_transfer, GAS_FOR_FT_TRANSFER);
+
+        testing_env!(get_context(admin).build());
+        contract.set_gas_config(Gas::from_tgas(12), Gas::from_tgas(8), Gas::from_tgas(20));
+
+        let config = contract.get_gas_config();
+        assert_eq!(config.cross_contract, Gas::from_tgas(12));
+        assert_eq!(config.callback, Gas::from_tgas(8));
+        assert_eq!(config.ft_transfer, Gas::from_tgas(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_ARGUMENT: Gas for callback below minimum of 5 Tgas")]
+    fn test_set_gas_config_rejects_values_below_floor() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context(admin).build());
+        contract.set_gas_config(Gas::from_tgas(10), Gas::from_tgas(1), Gas::from_tgas(15));
+    }
+
+    #[test]
+    fn test_sweep_expired_listings_closes_only_lapsed_listings() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let expires_at = env::block_timestamp_ms() + 60 * 60 * 1000;
+        for (invoice_id, expiry) in [("INV-000001", Some(expires_at)), ("INV-000002", None)] {
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                Some(U128(1_600_000_000)),
+                expiry,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+        let bid_id = place_usdc_bid(&mut contract, &alice, "LST-000001", 1_700_000_000);
+
+        let mut context = get_context("keeper.testnet".parse().unwrap());
+        context.block_timestamp(expires_at * 1_000_000);
+        testing_env!(context.build());
+
+        // The lapsed listing drops out of the active view before anyone sweeps it
+        let active = contract.get_active_listings(0, 10);
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].listing.id, "LST-000002");
+
+        assert_eq!(contract.sweep_expired_listings(0, 10), 1);
+        let expired = contract.get_listing("LST-000001".to_string()).unwrap();
+        assert_eq!(expired.status, ListingStatus::Expired);
+        assert!(contract.get_listing_by_invoice("INV-000001".to_string()).is_none());
+        assert!(contract.get_listing("LST-000002".to_string()).unwrap().active);
+
+        let bid = contract
+            .get_bids("LST-000001".to_string())
+            .into_iter()
+            .find(|bid| bid.id == bid_id)
+            .unwrap();
+        assert!(!bid.active);
+        assert!(bid.refund_pending);
+        let calls = scheduled_calls();
+        assert!(calls.iter().any(|(method, args)| {
+            method == "ft_transfer" && args.contains("\"receiver_id\":\"alice.testnet\"")
+        }));
+        assert!(calls.iter().any(|(method, _)| method == "unlist_invoice"));
+
+        // Nothing is left to sweep on a second pass
+        assert_eq!(contract.sweep_expired_listings(0, 10), 0);
+    }
+
+    #[test]
+    fn test_seller_stats_count_completed_sales_for_seller() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_pending_fixed_price(&mut contract);
+
+        testing_env!(get_context(env::current_account_id()).build());
+        contract.on_list_callback(listing_id.clone(), Ok(listed_invoice(30, None)));
+        assert_eq!(contract.get_seller_stats(seller.clone()).total_listings, 1);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        // Only the confirmed purchase counts as a sale
+        assert_eq!(contract.get_seller_stats(seller.clone()).completed_sales, 0);
+        confirm_purchase(&mut contract, usdc_purchase(&listing_id, 1_850_000_000, 18_500_000));
+
+        let stats = contract.get_seller_stats(seller);
+        assert_eq!(stats.completed_sales, 1);
+        assert_eq!(stats.cancelled_listings, 0);
+        assert_eq!(contract.get_seller_stats("buyer.testnet".parse().unwrap()).completed_sales, 0);
+    }
+
+    #[test]
+    fn test_seller_stats_count_cancellations_and_disputes() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context(seller.clone()).build());
+        let _ = contract.cancel_listing(listing_id);
+
+        testing_env!(get_context("escrow.testnet".parse().unwrap()).build());
+        contract.record_seller_dispute(seller.clone());
+
+        let stats = contract.get_seller_stats(seller);
+        assert_eq!(stats.cancelled_listings, 1);
+        assert_eq!(stats.disputes, 1);
+        assert_eq!(stats.completed_sales, 0);
+    }
+
+    #[test]
+    fn test_kyc_not_enforced_until_required() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        assert!(!contract.get_require_kyc());
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(contract.is_purchase_in_flight(listing_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: KYC required for buyer.testnet")]
+    fn test_kyc_required_rejects_unverified_buyer() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        contract.set_require_kyc(true);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+    }
+
+    #[test]
+    fn test_kyc_required_admits_verified_accounts() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        contract.set_require_kyc(true);
+        contract.add_kyc_verified(alice.clone());
+        contract.add_kyc_verified(buyer.clone());
+        contract.remove_kyc_verified(alice.clone());
+        assert!(contract.is_kyc_verified(buyer.clone()));
+        assert!(!contract.is_kyc_verified(alice.clone()));
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            buyer.clone(),
+            U128(1_700_000_000),
+            format!("place_bid:{}", listing_id),
+        );
+        assert_eq!(contract.get_bids(listing_id.clone()).len(), 1);
+
+        let unverified_bid = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.ft_on_transfer(alice, U128(1_700_000_000), format!("place_bid:{}", listing_id))
+        }));
+        assert!(unverified_bid.is_err());
+
+        let _ = contract.ft_on_transfer(
+            buyer,
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        assert!(contract.is_purchase_in_flight(listing_id));
+    }
+
+    #[test]
+    fn test_bid_increment_applies_after_first_bid() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        // The first bid only has to meet min_price
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_600_000_000);
+        assert_eq!(contract.get_highest_bid(listing_id.clone()).unwrap().bidder, alice);
+
+        // Default increment is 1% of the 1,600 USDC min_price
+        let top = place_usdc_bid(&mut contract, &bob, &listing_id, 1_616_000_000);
+        assert_eq!(contract.get_highest_bid(listing_id.clone()).unwrap().id, top);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        contract.set_min_bid_increment(listing_id.clone(), Some(U128(50_000_000)));
+        let top = place_usdc_bid(&mut contract, &alice, &listing_id, 1_666_000_000);
+        assert_eq!(contract.get_highest_bid(listing_id).unwrap().id, top);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_AMOUNT: Bid must exceed current high by at least 16000000")]
+    fn test_bid_below_increment_rejected() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        place_usdc_bid(&mut contract, &"alice.testnet".parse().unwrap(), &listing_id, 1_600_000_000);
+        place_usdc_bid(&mut contract, &"bob.testnet".parse().unwrap(), &listing_id, 1_600_000_001);
+    }
+
+    #[test]
+    fn test_marketplace_stats_after_two_sales() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        for invoice_id in ["INV-000001", "INV-000002", "INV-000003"] {
+            let _ = contract.list_invoice(
+                invoice_id.to_string(),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                Some(U128(1_600_000_000)),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+        assert_eq!(contract.get_marketplace_stats().active_listings, 3);
+
+        // One sale through a token purchase, one through an accepted bid
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            "buy_listing:LST-000001".to_string(),
+        );
+        confirm_purchase(&mut contract, usdc_purchase("LST-000001", 1_850_000_000, 18_500_000));
+        let bid_id = place_usdc_bid(&mut contract, &alice, "LST-000002", 1_600_000_000);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.accept_bid("LST-000002".to_string(), bid_id);
+
+        let stats = contract.get_marketplace_stats();
+        assert_eq!(stats.total_listings, 3);
+        assert_eq!(stats.active_listings, 1);
+        assert_eq!(stats.total_sales, 2);
+        assert_eq!(stats.total_volume.0, 3_450_000_000);
+        // 7.5% and 20% off the invoice amount
+        assert!((stats.average_discount_percentage - 13.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_failed_legacy_purchase_restores_listing_and_refunds_near() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        let payment = NearToken::from_yoctonear(1_850_000_000);
+        testing_env!(get_context(buyer.clone()).attached_deposit(payment).build());
+        let _ = contract.buy_invoice(listing_id.clone());
+        assert!(!contract.get_listing(listing_id.clone()).unwrap().active);
+        let calls: Vec<String> = scheduled_calls().into_iter().map(|(method, _)| method).collect();
+        assert!(calls.contains(&"transfer_invoice".to_string()));
+        assert!(calls.contains(&"on_purchase_callback".to_string()));
+
+        // The invoice transfer failed, so escrow creation never ran
+        let purchase = PendingPurchase {
+            listing_id: listing_id.clone(),
+            buyer: buyer.clone(),
+            token: None,
+            payment: U128(payment.as_yoctonear()),
+            price: U128(1_850_000_000),
+            fee: U128(0),
+            referrer: None,
+            referral_fee: U128(0),
+        };
+        assert!(!contract.on_purchase_callback(purchase, Err(PromiseError::Failed)));
+
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert!(listing.active);
+        assert_eq!(listing.status, ListingStatus::Active);
+        assert!(!contract.is_purchase_in_flight(listing_id));
+        let refunded = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == buyer)
+            .flat_map(|receipt| receipt.actions)
+            .any(|action| {
+                matches!(action, near_sdk::mock::MockAction::Transfer { deposit, .. } if deposit == payment)
+            });
+        assert!(refunded);
+    }
+
+    #[test]
+    fn test_cursor_paging_matches_offset_paging() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        for i in 1..=5 {
+            let _ = contract.list_invoice(
+                format!("INV-{:06}", i),
+                U128(1_850_000_000),
+                U128(2_000_000_000),
+                env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            confirm_last_listing(&mut contract);
+        }
+        let _ = contract.cancel_listing("LST-000002".to_string());
+
+        let mut cursor = None;
+        let mut paged = Vec::new();
+        loop {
+            let page = contract.get_active_listings_after(cursor, Some(2));
+            if page.is_empty() {
+                break;
+            }
+            cursor = page.last().map(|view| view.listing.id.clone());
+            paged.extend(page.into_iter().map(|view| view.listing.id));
+        }
+
+        let offset: Vec<String> = contract
+            .get_active_listings(0, 10)
+            .into_iter()
+            .map(|view| view.listing.id)
+            .collect();
+        assert_eq!(paged, vec!["LST-000001", "LST-000003", "LST-000004", "LST-000005"]);
+        assert_eq!(paged, offset);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_TOKEN_MISMATCH: Listings in EUR must be paid with eurc.testnet")]
+    fn test_listing_currency_requires_designated_token() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let eurc: AccountId = "eurc.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        contract.add_accepted_token(eurc.clone());
+        contract.set_currency_token("EUR".to_string(), Some(eurc.clone()));
+        assert_eq!(contract.get_currency_token("EUR".to_string()), Some(eurc));
+
+        let listing_id = list_pending_fixed_price(&mut contract);
+        let invoice = ListedInvoice {
+            currency: Some("EUR".to_string()),
+            ..listed_invoice(20, None)
+        };
+        assert!(contract.on_list_callback(listing_id.clone(), Ok(invoice)).is_some());
+        assert_eq!(contract.get_listing(listing_id.clone()).unwrap().currency, "EUR");
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+    }
+
+    #[test]
+    fn test_admin_force_cancels_listing_and_refunds_bids() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        let bid_id = place_usdc_bid(&mut contract, &alice, &listing_id, 1_700_000_000);
+
+        testing_env!(get_context(admin).build());
+        let _ = contract.admin_force_cancel_listing(listing_id.clone());
+
+        let listing = contract.get_listing(listing_id.clone()).unwrap();
+        assert!(!listing.active);
+        assert_eq!(listing.status, ListingStatus::Cancelled);
+        assert!(contract.get_listing_by_invoice("INV-000001".to_string()).is_none());
+        let bids = contract.get_bids(listing_id);
+        assert!(bids.iter().find(|bid| bid.id == bid_id).unwrap().refund_pending);
+
+        let calls: Vec<String> = scheduled_calls().into_iter().map(|(method, _)| method).collect();
+        assert!(calls.contains(&"unlist_invoice".to_string()));
+        assert!(calls.contains(&"ft_transfer".to_string()));
+        assert!(near_sdk::test_utils::get_logs()
             .iter()
-            .filter(|(_, listing)| listing.active)
-            .count() as u64
+            .any(|log| log.contains("\"event\":\"listing_cancelled\"")));
     }
 
-    /// Get fee basis points
-    pub fn get_fee_basis_points(&self) -> u16 {
-        self.fee_basis_points
+    #[test]
+    #[should_panic(expected = "E_UNAUTHORIZED: Only admin can force-cancel listings")]
+    fn test_non_admin_cannot_force_cancel_listing() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.admin_force_cancel_listing(listing_id);
     }
 
-    /// Get contract addresses
-    pub fn get_contract_addresses(&self) -> (AccountId, AccountId, AccountId) {
-        (
-            self.invoice_contract.clone(),
-            self.escrow_contract.clone(),
-            self.usdc_contract.clone(),
-        )
+    #[test]
+    fn test_legacy_purchase_refunds_near_overpayment() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let buyer: AccountId = "buyer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context(buyer.clone())
+            .attached_deposit(NearToken::from_yoctonear(2_000_000_000))
+            .build());
+        let _ = contract.buy_invoice(listing_id);
+
+        let refunds: Vec<NearToken> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == buyer)
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(refunds, vec![NearToken::from_yoctonear(150_000_000)]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::testing_env;
+    #[test]
+    #[should_panic(expected = "E_INSUFFICIENT_PAYMENT: Attached deposit below asking price")]
+    fn test_legacy_purchase_rejects_underpayment() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
 
-    fn get_context(predecessor: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .predecessor_account_id(predecessor)
-            .attached_deposit(NearToken::from_millinear(100));
-        builder
+        testing_env!(get_context("buyer.testnet".parse().unwrap())
+            .attached_deposit(NearToken::from_yoctonear(1_800_000_000))
+            .build());
+        let _ = contract.buy_invoice(listing_id);
     }
 
     #[test]
-    fn test_init() {
-        let invoice: AccountId = "invoice.testnet".parse().unwrap();
-        let escrow: AccountId = "escrow.testnet".parse().unwrap();
+    fn test_listing_detail_combines_listing_bids_and_seller() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let alice: AccountId = "alice.testnet".parse().unwrap();
+        let bob: AccountId = "bob.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_pending_fixed_price(&mut contract);
+        assert!(contract.on_list_callback(listing_id.clone(), Ok(listed_invoice(20, None))).is_some());
+        place_usdc_bid(&mut contract, &alice, &listing_id, 1_650_000_000);
+        let top_bid = place_usdc_bid(&mut contract, &bob, &listing_id, 1_700_000_000);
+
+        let detail = contract.get_listing_detail(listing_id.clone()).unwrap();
+        assert_eq!(detail.listing.listing.id, listing_id);
+        assert!(detail.listing.discount_percentage > 0.0);
+        assert_eq!(detail.active_bids.len(), 2);
+        assert_eq!(detail.highest_bid.unwrap().id, top_bid);
+        assert_eq!(detail.seller_stats.total_listings, 1);
+        assert!(contract.get_listing_detail("LST-999999".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_record_view_counts_once_per_block() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let viewer: AccountId = "viewer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context(viewer.clone()).block_height(10).build());
+        assert_eq!(contract.record_view(listing_id.clone()), 1);
+        testing_env!(get_context(viewer).block_height(11).build());
+        assert_eq!(contract.record_view(listing_id.clone()), 2);
+        assert_eq!(contract.get_listing(listing_id).unwrap().views, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Only one view per account per block")]
+    fn test_record_view_rejects_repeat_in_same_block() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let viewer: AccountId = "viewer.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context(viewer).block_height(10).build());
+        contract.record_view(listing_id.clone());
+        contract.record_view(listing_id);
+    }
+
+    #[test]
+    fn test_popular_listings_order_by_views() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let due_date = env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000;
+        for i in 1..=3u64 {
+            let listing = Listing {
+                id: format!("LST-{:06}", i),
+                invoice_id: format!("INV-{:06}", i),
+                ..test_listing(2_000_000_000, 1_850_000_000, due_date)
+            };
+            contract.listings.insert(listing.id.clone(), listing);
+        }
+        // LST-000002 gets two views and LST-000003 one; LST-000001 none
+        for (height, listing_id) in [(1, "LST-000002"), (2, "LST-000003"), (3, "LST-000002")] {
+            testing_env!(get_context("viewer.testnet".parse().unwrap()).block_height(height).build());
+            contract.record_view(listing_id.to_string());
+        }
+
+        let ranked: Vec<String> = contract
+            .get_popular_listings(None, None)
+            .items
+            .into_iter()
+            .map(|view| view.listing.id)
+            .collect();
+        assert_eq!(ranked, vec!["LST-000002", "LST-000003", "LST-000001"]);
+    }
+
+    #[test]
+    fn test_refresh_listing_risk_updates_cached_score() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+
+        testing_env!(get_context("invoice.testnet".parse().unwrap()).build());
+        contract.refresh_listing_risk("INV-000001".to_string(), 35);
+        assert_eq!(contract.get_listing(listing_id).unwrap().risk_score, Some(35));
+    }
+
+    #[test]
+    fn test_list_invoice_without_deposit() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap())
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        confirm_last_listing(&mut contract);
+
+        assert!(contract.get_listing("LST-000001".to_string()).unwrap().active);
+        assert_eq!(scheduled_calls()[0].0, "set_listed");
+    }
+
+    #[test]
+    fn test_list_invoice_returns_attached_deposit() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let seller: AccountId = "seller.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        // get_context attaches 100 milliNEAR, none of which is kept
+        list_fixed_price(&mut contract);
+
+        let refunds: Vec<NearToken> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == seller)
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(refunds, vec![NearToken::from_millinear(100)]);
+    }
+
+    /// Lists LST-000001 with bids from alice and carol, sells it to bob's bid and fails
+    /// carol's push refund; returns (carol's bid, bob's bid)
+    fn sold_with_failed_refund(contract: &mut MarketplaceContract) -> (String, String) {
+        let listing_id = list_fixed_price(contract);
+        let alice_bid =
+            place_usdc_bid(contract, &"alice.testnet".parse().unwrap(), &listing_id, 1_700_000_000);
+        let carol_bid =
+            place_usdc_bid(contract, &"carol.testnet".parse().unwrap(), &listing_id, 1_750_000_000);
+        let bob_bid =
+            place_usdc_bid(contract, &"bob.testnet".parse().unwrap(), &listing_id, 1_800_000_000);
+
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.accept_bid(listing_id.clone(), bob_bid.clone());
+        contract.on_bid_refund_callback(listing_id.clone(), alice_bid, Ok(()));
+        contract.on_bid_refund_callback(listing_id, carol_bid.clone(), Err(PromiseError::Failed));
+        (carol_bid, bob_bid)
+    }
+
+    #[test]
+    fn test_claim_bid_refund_after_failed_push() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let (carol_bid, _) = sold_with_failed_refund(&mut contract);
+        let listing_id = "LST-000001".to_string();
+
+        testing_env!(get_context(carol.clone()).build());
+        let _ = contract.claim_bid_refund(listing_id.clone(), carol_bid.clone());
+        assert!(scheduled_calls().iter().any(|(method, args)| {
+            method == "ft_transfer"
+                && args.contains(&format!("\"receiver_id\":\"{}\"", carol))
+                && args.contains(&format!("bid_refund:{}", carol_bid))
+        }));
+
+        assert!(contract.on_bid_refund_callback(listing_id.clone(), carol_bid.clone(), Ok(())));
+        let bids = contract.get_bids(listing_id);
+        let bid = bids.iter().find(|bid| bid.id == carol_bid).unwrap();
+        assert!(!bid.funds_locked && !bid.refund_pending);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Bid has no refund owed")]
+    fn test_claim_bid_refund_twice_fails() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let carol: AccountId = "carol.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let (carol_bid, _) = sold_with_failed_refund(&mut contract);
+        let listing_id = "LST-000001".to_string();
+
+        testing_env!(get_context(carol).build());
+        let _ = contract.claim_bid_refund(listing_id.clone(), carol_bid.clone());
+        contract.on_bid_refund_callback(listing_id.clone(), carol_bid.clone(), Ok(()));
+        let _ = contract.claim_bid_refund(listing_id, carol_bid);
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_STATE: Winning bid is not refundable")]
+    fn test_claim_bid_refund_rejects_winning_bid() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let (_, bob_bid) = sold_with_failed_refund(&mut contract);
+
+        testing_env!(get_context("bob.testnet".parse().unwrap()).build());
+        let _ = contract.claim_bid_refund("LST-000001".to_string(), bob_bid);
+    }
+
+    #[test]
+    fn test_referral_splits_fee_three_ways() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
         let usdc: AccountId = "usdc.testnet".parse().unwrap();
-        let fee_recipient: AccountId = "fees.testnet".parse().unwrap();
+        let referrer: AccountId = "ref.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        contract.set_referral(listing_id.clone(), None, 4_000);
 
-        let context = get_context(fee_recipient.clone());
+        // The buyer names the referrer in the purchase message
+        testing_env!(get_context(usdc).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}:{}", listing_id, referrer),
+        );
+        let (_, args) = scheduled_calls()
+            .into_iter()
+            .find(|(method, _)| method == "on_invoice_transferred")
+            .unwrap();
+        assert!(args.contains("\"referrer\":\"ref.testnet\""));
+        assert!(args.contains("\"referral_fee\":\"7400000\""));
+
+        // 1% fee of 18.5M: 7.4M to the referrer, 11.1M to fees.testnet and the seller's
+        // 1831.5M into escrow
+        let purchase = PendingPurchase {
+            referrer: Some(referrer),
+            referral_fee: U128(7_400_000),
+            ..usdc_purchase(&listing_id, 1_850_000_000, 18_500_000)
+        };
+        confirm_purchase(&mut contract, purchase);
+        let transfers: Vec<String> = scheduled_calls()
+            .into_iter()
+            .filter(|(method, _)| method == "ft_transfer")
+            .map(|(_, args)| args)
+            .collect();
+        let sent = |receiver: &str, amount: &str| {
+            transfers.iter().any(|args| {
+                args.contains(&format!("\"receiver_id\":\"{}\"", receiver))
+                    && args.contains(&format!("\"amount\":\"{}\"", amount))
+            })
+        };
+        assert!(sent("ref.testnet", "7400000"));
+        assert!(sent("fees.testnet", "11100000"));
+        assert!(sent("escrow.testnet", "1831500000"));
+    }
+
+    #[test]
+    #[should_panic(expected = "E_INVALID_ARGUMENT: Referral cut cannot exceed the marketplace fee")]
+    fn test_referral_cut_capped_at_fee() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_fixed_price(&mut contract);
+        contract.set_referral(listing_id, Some("ref.testnet".parse().unwrap()), 10_001);
+    }
+
+    /// List INV-000001 as a private sale reserved for buyer.testnet
+    fn list_private(contract: &mut MarketplaceContract) -> String {
+        testing_env!(get_context("seller.testnet".parse().unwrap()).build());
+        let _ = contract.list_invoice(
+            "INV-000001".to_string(),
+            U128(1_850_000_000),
+            U128(2_000_000_000),
+            env::block_timestamp_ms() + 30 * 24 * 60 * 60 * 1000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("buyer.testnet".parse().unwrap()),
+        );
+        confirm_last_listing(contract);
+        "LST-000001".to_string()
+    }
+
+    #[test]
+    #[should_panic(expected = "Listing is reserved for buyer.testnet")]
+    fn test_private_listing_rejects_other_buyer() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_private(&mut contract);
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "alice.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+    }
+
+    #[test]
+    fn test_private_listing_sells_to_allowed_buyer() {
+        let admin: AccountId = "admin.testnet".parse().unwrap();
+        let mut contract = setup_contract(&admin);
+        let listing_id = list_private(&mut contract);
+        assert_eq!(
+            contract.get_listing(listing_id.clone()).unwrap().allowed_buyer,
+            Some("buyer.testnet".parse().unwrap())
+        );
+
+        testing_env!(get_context("usdc.testnet".parse().unwrap()).build());
+        let _ = contract.ft_on_transfer(
+            "buyer.testnet".parse().unwrap(),
+            U128(1_850_000_000),
+            format!("buy_listing:{}", listing_id),
+        );
+        confirm_purchase(&mut contract, usdc_purchase(&listing_id, 1_850_000_000, 18_500_000));
+
+        assert_eq!(contract.get_listing(listing_id).unwrap().status, ListingStatus::Sold);
+    }
+
+    #[test]
+    fn test_migrate_from_original_layout() {
+        let mut context = VMContextBuilder::new();
+        context.predecessor_account_id("marketplace.testnet".parse().unwrap());
+        context.current_account_id("marketplace.testnet".parse().unwrap());
         testing_env!(context.build());
 
-        let contract = MarketplaceContract::new(invoice, escrow, usdc, fee_recipient);
+        let old_listing = |id: &str, invoice_id: &str, active: bool| OldListing {
+            id: id.to_string(),
+            invoice_id: invoice_id.to_string(),
+            seller: "seller.testnet".parse().unwrap(),
+            asking_price: U128(1_850_000_000),
+            min_price: None,
+            invoice_amount: U128(2_000_000_000),
+            due_date: 1_000,
+            created_at: 0,
+            expires_at: None,
+            active,
+        };
+        let mut old = OldMarketplaceContract {
+            listings: IterableMap::new(b"l"),
+            listings_by_invoice: LookupMap::new(b"i"),
+            listing_count: 2,
+            invoice_contract: "invoice.testnet".parse().unwrap(),
+            escrow_contract: "escrow.testnet".parse().unwrap(),
+            usdc_contract: "usdc.testnet".parse().unwrap(),
+            fee_basis_points: 250,
+            fee_recipient: "fees.testnet".parse().unwrap(),
+        };
+        old.listings.insert("LST-000001".to_string(), old_listing("LST-000001", "INV-000001", false));
+        old.listings.insert("LST-000002".to_string(), old_listing("LST-000002", "INV-000002", true));
+        old.listings_by_invoice.insert("INV-000002".to_string(), "LST-000002".to_string());
+        old.listings.flush();
+        old.listings_by_invoice.flush();
+        env::state_write(&old);
 
-        assert_eq!(contract.get_listing_count(), 0);
-        assert_eq!(contract.get_fee_basis_points(), 100);
+        let contract = MarketplaceContract::migrate("admin.testnet".parse().unwrap());
+        let open = contract.get_listing_by_invoice("INV-000002".to_string()).unwrap();
+        assert_eq!(open.id, "LST-000002");
+        assert_eq!(open.status, ListingStatus::Active);
+        assert_eq!(open.currency, "USDC");
+        let closed = contract.get_listing("LST-000001".to_string()).unwrap();
+        assert_eq!(closed.status, ListingStatus::Cancelled);
+        assert!(!closed.active);
+        assert_eq!(contract.get_active_listing_count(), 1);
+        assert_eq!(contract.get_listing_count(), 2);
+        assert_eq!(contract.get_fee_basis_points(), 250);
+        assert_eq!(contract.get_admin(), "admin.testnet".parse::<AccountId>().unwrap());
     }
 }
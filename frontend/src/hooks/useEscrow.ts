@@ -40,7 +40,7 @@ export function useEscrow() {
       const data = await response.json();
       if (data.result?.result) {
         const resultString = String.fromCharCode(...data.result.result);
-        const escrowList: EscrowEntry[] = JSON.parse(resultString);
+        const escrowList: EscrowEntry[] = JSON.parse(resultString).items;
         setEscrows(escrowList);
       }
     } catch (err) {
@@ -78,7 +78,7 @@ export function useEscrow() {
       const data = await response.json();
       if (data.result?.result) {
         const resultString = String.fromCharCode(...data.result.result);
-        const escrowList: EscrowEntry[] = JSON.parse(resultString);
+        const escrowList: EscrowEntry[] = JSON.parse(resultString).items;
         setEscrows(escrowList);
       }
     } catch (err) {
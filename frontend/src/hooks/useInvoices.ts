@@ -44,7 +44,7 @@ export function useInvoices() {
       const data = await response.json();
       if (data.result?.result) {
         const resultString = String.fromCharCode(...data.result.result);
-        const invoiceList: Invoice[] = JSON.parse(resultString);
+        const invoiceList: Invoice[] = JSON.parse(resultString).items;
         setInvoices(invoiceList);
       }
     } catch (error) {
@@ -85,7 +85,7 @@ export function useInvoices() {
       const data = await response.json();
       if (data.result?.result) {
         const resultString = String.fromCharCode(...data.result.result);
-        const invoiceList: Invoice[] = JSON.parse(resultString);
+        const invoiceList: Invoice[] = JSON.parse(resultString).items;
         setInvoices(invoiceList);
       }
     } catch (error) {
@@ -158,7 +158,7 @@ export function useInvoices() {
           actions: [
             actionCreators.functionCall(
               "set_listed",
-              { invoice_id: invoiceId },
+              { invoice_id: invoiceId, seller: accountId },
               THIRTY_TGAS,
               BigInt(0),
             ),
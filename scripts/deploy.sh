@@ -39,7 +39,7 @@ echo ""
 echo "Deploying Marketplace Contract to $MARKETPLACE_CONTRACT..."
 near deploy $MARKETPLACE_CONTRACT out/marketplace.wasm \
     --init-function new \
-    --init-args '{"invoice_contract": "'$INVOICE_CONTRACT'", "escrow_contract": "'$ESCROW_CONTRACT'", "usdc_contract": "'$USDC_CONTRACT'", "fee_recipient": "'$MARKETPLACE_CONTRACT'"}' \
+    --init-args '{"invoice_contract": "'$INVOICE_CONTRACT'", "escrow_contract": "'$ESCROW_CONTRACT'", "usdc_contract": "'$USDC_CONTRACT'", "fee_recipient": "'$MARKETPLACE_CONTRACT'", "admin": "'$MASTER_ACCOUNT'"}' \
     --network-id $NETWORK
 
 # Deploy + Initialize Escrow Contract
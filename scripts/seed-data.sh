@@ -84,13 +84,13 @@ echo "---------------------------------------------------"
 
 # Set invoices to Listed status so they can be listed on marketplace
 echo "Setting Invoice 1 to Listed..."
-near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000001"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 1 status update skipped"
+near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000001", "seller": "'$CREATOR'"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 1 status update skipped"
 
 echo "Setting Invoice 2 to Listed..."
-near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000002"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 2 status update skipped"
+near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000002", "seller": "'$CREATOR'"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 2 status update skipped"
 
 echo "Setting Invoice 3 to Listed..."
-near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000003"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 3 status update skipped"
+near contract call-function as-transaction $INVOICE_CONTRACT set_listed json-args '{"invoice_id": "INV-000003", "seller": "'$CREATOR'"}' prepaid-gas '30 Tgas' attached-deposit '1 yoctoNEAR' sign-as $CREATOR network-config $NETWORK sign-with-keychain send || echo "Invoice 3 status update skipped"
 
 echo ""
 echo "Step 4: Listing invoices on marketplace..."